@@ -0,0 +1,98 @@
+use crate::{helper, CLIError};
+use bluetooth_mesh::beacon::{BeaconPDU, OOBInformation, URIHash, UnprovisionedDeviceBeacon};
+use bluetooth_mesh::stack::bearer::MESH_BEACON_AD_TYPE;
+use bluetooth_mesh::uri;
+use bluetooth_mesh::uuid::UUID;
+use std::convert::TryFrom;
+
+pub fn sub_command() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("beacon")
+        .about("Generate Mesh Beacon AD structures (as hex) to feed into other tools")
+        .subcommand(
+            clap::SubCommand::with_name("unprovisioned")
+                .about("Generate an Unprovisioned Device Beacon")
+                .arg(
+                    clap::Arg::with_name("uuid")
+                        .long("uuid")
+                        .value_name("UUID_HEX")
+                        .help("128-bit big endian device UUID hex")
+                        .required(true)
+                        .validator(helper::is_128_bit_hex_str_validator),
+                )
+                .arg(
+                    clap::Arg::with_name("uri")
+                        .long("uri")
+                        .value_name("URI")
+                        .help("URI to include the hash of"),
+                ),
+        )
+}
+pub fn beacon_matches(
+    parent_logger: &slog::Logger,
+    matches: &clap::ArgMatches,
+) -> Result<(), CLIError> {
+    let logger = parent_logger.new(o!());
+    debug!(logger, "beacon_sub_command");
+    match matches.subcommand() {
+        ("unprovisioned", Some(unprovisioned_matches)) => {
+            let uuid = UUID(
+                helper::hex_str_to_bytes::<[u8; 16]>(
+                    unprovisioned_matches
+                        .value_of("uuid")
+                        .expect("required by clap"),
+                )
+                .expect("validated by clap"),
+            );
+            let ad = unprovisioned_beacon_ad(uuid, unprovisioned_matches.value_of("uri"))?;
+            println!("{:x}", helper::HexSlice(&ad[..]));
+        }
+        ("", None) => error!(logger, "no_beacon_subcommand"),
+        _ => unreachable!("unhandled beacon subcommand"),
+    }
+    Ok(())
+}
+/// Packs an Unprovisioned Device Beacon into a full AD structure (length, AD type and beacon).
+pub fn unprovisioned_beacon_ad(uuid: UUID, uri: Option<&str>) -> Result<Vec<u8>, CLIError> {
+    let beacon = BeaconPDU::Unprovisioned(UnprovisionedDeviceBeacon {
+        uuid,
+        oob_information: OOBInformation::default(),
+        uri_hash: uri.map(|uri| URIHash(uri::uri_hash(uri))),
+    });
+    // Beacon type byte + beacon.
+    let beacon_len = 1 + beacon.byte_len();
+    let mut out = vec![0_u8; 2 + beacon_len];
+    out[0] = u8::try_from(beacon_len + 1).expect("beacons are small");
+    out[1] = MESH_BEACON_AD_TYPE;
+    beacon
+        .pack_into(&mut out[2..])
+        .map_err(|e| CLIError::OtherMessage(format!("unable to pack beacon: {:?}", e)))?;
+    Ok(out)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uuid() -> UUID {
+        UUID(helper::hex_str_to_bytes("70cf7c9732a345b691494810d2e9cbf4").expect("from spec 8.4.1"))
+    }
+    #[test]
+    fn test_unprovisioned_beacon_ad() {
+        let ad = unprovisioned_beacon_ad(uuid(), None).expect("packable beacon");
+        assert_eq!(
+            format!("{:x}", helper::HexSlice(&ad[..])),
+            "142b0070cf7c9732a345b691494810d2e9cbf40000"
+        );
+    }
+    #[test]
+    fn test_unprovisioned_beacon_ad_with_uri() {
+        let ad = unprovisioned_beacon_ad(
+            uuid(),
+            Some("https://www.example.com/mesh/products/light-switch-v3"),
+        )
+        .expect("packable beacon");
+        assert_eq!(
+            format!("{:x}", helper::HexSlice(&ad[..])),
+            "182b0070cf7c9732a345b691494810d2e9cbf40000d97478b3"
+        );
+    }
+}
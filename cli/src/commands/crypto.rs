@@ -193,7 +193,7 @@ pub fn crypto_matches(
             match netkeys_matches.subcommand() {
                 ("list", Some(list_matches)) => {
                     let print_nid = list_matches.is_present("nid");
-                    for (index, phase) in device_state.security_materials().net_key_map.map.iter() {
+                    for (index, phase) in device_state.security_materials().net_key_map.iter() {
                         let mut buf = String::with_capacity(20);
                         write!(
                             &mut buf,
@@ -298,8 +298,7 @@ pub fn crypto_matches(
             match appkey_matches.subcommand() {
                 ("list", Some(list_matches)) => {
                     let print_aid = list_matches.is_present("aid");
-                    for (index, appkey) in device_state.security_materials().app_key_map.map.iter()
-                    {
+                    for (index, appkey) in device_state.security_materials().app_key_map.iter() {
                         if print_aid {
                             println!(
                                 "net_index: {} app_index: {} aid: {}",
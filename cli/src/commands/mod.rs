@@ -1,7 +1,11 @@
+#[cfg(feature = "mesh")]
+pub mod beacon;
 pub mod ble;
 #[cfg(feature = "mesh")]
 pub mod crypto;
 #[cfg(feature = "mesh")]
+pub mod provisionee;
+#[cfg(feature = "mesh")]
 pub mod provisioner;
 #[cfg(feature = "mesh")]
 pub mod state;
@@ -0,0 +1,373 @@
+use crate::helper::tokio_runtime;
+use crate::{helper, CLIError};
+use bluetooth_mesh::beacon::{BeaconPDU, OOBInformation, UnprovisionedDeviceBeacon};
+use bluetooth_mesh::crypto::key::DevKey;
+use bluetooth_mesh::device_state::DeviceState;
+use bluetooth_mesh::mesh::IVUpdateFlag;
+use bluetooth_mesh::provisioning::bearer_control::{LinkAck, LinkOpen};
+use bluetooth_mesh::provisioning::data::{Flag, ProvisioningData};
+use bluetooth_mesh::provisioning::generic_bearer::GenericBearer;
+use bluetooth_mesh::provisioning::link::Link;
+use bluetooth_mesh::provisioning::pb_adv::{LinkID, TransactionNumber};
+use bluetooth_mesh::provisioning::protocol::{Capabilities, OOBConfig};
+use bluetooth_mesh::provisioning::provisionee::{Process, Stage};
+use bluetooth_mesh::provisioning::provisioner::Bearer;
+use bluetooth_mesh::provisioning::{bearer_control, generic, pb_adv};
+use bluetooth_mesh::stack::bearer::{IncomingMessage, OutgoingMessage, PBAdvBuf};
+use bluetooth_mesh::stack::bearers::advertiser::BufferedHCIAdvertiser;
+use bluetooth_mesh::uuid::UUID;
+use core::time::Duration;
+use driver_async::asyncs::sync::mpsc;
+use driver_async::asyncs::{task, time};
+
+/// How often the Unprovisioned Device Beacon is sent while waiting for a Link Open.
+const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn sub_command() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("provisionee")
+        .about("Provisionee Role for joining a network as a new Node")
+        .subcommand(
+            clap::SubCommand::with_name("listen")
+                .about(
+                    "advertise as an unprovisioned device and wait to be provisioned over PB-ADV.",
+                )
+                .arg(
+                    clap::Arg::with_name("source")
+                        .help("HCI source/sink (`bluez`/`usb`)")
+                        .short("s")
+                        .long("source")
+                        .value_name("SOURCE_NAME:ADAPTER_ID")
+                        .default_value("usb:0"),
+                )
+                .arg(
+                    clap::Arg::with_name("uuid")
+                        .long("uuid")
+                        .value_name("UUID_HEX")
+                        .help("128-bit big endian device UUID hex (saved in the device state)")
+                        .validator(helper::is_128_bit_hex_str_validator),
+                )
+                .arg(
+                    clap::Arg::with_name("oob_information")
+                        .long("oob_information")
+                        .value_name("OOB_INFO_BITS")
+                        .help("16-bit OOB Information field (saved in the device state)")
+                        .validator(helper::is_u16_validator),
+                ),
+        )
+}
+pub fn provisionee_matches(
+    logger: &slog::Logger,
+    device_state_path: &str,
+    matches: &clap::ArgMatches,
+) -> Result<(), CLIError> {
+    let mut runtime = tokio_runtime();
+    match matches.subcommand() {
+        ("listen", Some(listen_matches)) => {
+            let uuid = listen_matches.value_of("uuid").map(|uuid| {
+                UUID(helper::hex_str_to_bytes::<[u8; 16]>(uuid).expect("validated by clap"))
+            });
+            let oob_information = listen_matches
+                .value_of("oob_information")
+                .map(|oob| OOBInformation(oob.parse().expect("validated by clap")));
+            let mut device_state = helper::load_device_state(device_state_path)?;
+            if let Some(uuid) = uuid {
+                device_state.set_uuid(uuid);
+            }
+            if let Some(oob_information) = oob_information {
+                *device_state.oob_information_mut() = oob_information;
+            }
+            if uuid.is_some() || oob_information.is_some() {
+                helper::write_device_state(device_state_path, &device_state)?;
+            }
+            tokio::task::LocalSet::new().block_on(
+                &mut runtime,
+                listen(
+                    logger,
+                    listen_matches.value_of("source").expect("required by clap"),
+                    device_state,
+                    device_state_path,
+                ),
+            )
+        }
+        ("", None) => Err(CLIError::Clap(clap::Error::with_description(
+            "missing subcommand",
+            clap::ErrorKind::ArgumentNotFound,
+        ))),
+        _ => unreachable!("unhandled provisionee subcommand"),
+    }
+}
+/// Capabilities of this device: the element count from the device state and no OOB.
+pub fn capabilities(device_state: &DeviceState) -> Capabilities {
+    Capabilities::from_device(device_state.element_count(), &OOBConfig::default())
+}
+fn stage_name(stage: &Stage) -> &'static str {
+    match stage {
+        Stage::Pending => "pending",
+        Stage::Capabilities { .. } => "capabilities",
+        Stage::Started { .. } => "started",
+        Stage::PublicKeyDevice { .. } => "public_key_device",
+        Stage::Confirmation { .. } => "confirmation",
+        Stage::Distribute { .. } => "distribute",
+        Stage::Complete(_) => "complete",
+        Stage::Failed(_) => "failed",
+        Stage::Closed => "closed",
+    }
+}
+/// Runs the device side of provisioning until the provisioner's data is received.
+pub async fn provision_device(
+    logger: &slog::Logger,
+    process: &mut Process,
+) -> Result<(), CLIError> {
+    loop {
+        let stage = process
+            .next_stage()
+            .await
+            .map_err(|e| CLIError::OtherMessage(format!("provisioning error: {:?}", e)))?;
+        info!(logger, "provisionee_stage"; "stage" => stage_name(stage));
+        if stage.is_complete() {
+            return Ok(());
+        }
+    }
+}
+/// Device state of the new node. Keeps the element count, config states, UUID and OOB information
+/// of `device_state` but uses the address, NetKey and IV Index the provisioner assigned.
+pub fn provisioned_device_state(
+    mut device_state: DeviceState,
+    data: &ProvisioningData,
+    dev_key: DevKey,
+) -> Result<DeviceState, CLIError> {
+    let mut provisioned = DeviceState::try_new(data.element_address, device_state.element_count())
+        .map_err(|e| CLIError::OtherMessage(format!("bad assigned address: {}", e)))?;
+    *provisioned.config_states_mut() = core::mem::take(device_state.config_states_mut());
+    if let Some(uuid) = device_state.uuid() {
+        provisioned.set_uuid(uuid);
+    }
+    *provisioned.oob_information_mut() = device_state.oob_information();
+    let security_materials = provisioned.security_materials_mut();
+    security_materials.dev_key = dev_key;
+    security_materials.iv_index = data.iv_index;
+    security_materials.iv_update_flag = IVUpdateFlag(data.flags.get(Flag::IVUpdate));
+    security_materials
+        .net_key_map
+        .insert(data.net_key_index, &data.net_key);
+    Ok(provisioned)
+}
+/// Unprovisioned Device Beacon with the UUID and OOB information from the device state.
+pub fn unprovisioned_beacon(
+    device_state: &DeviceState,
+) -> Result<UnprovisionedDeviceBeacon, CLIError> {
+    device_state.unprovisioned_beacon().ok_or_else(|| {
+        CLIError::OtherMessage("no device UUID in the device state (use `--uuid`)".to_owned())
+    })
+}
+/// Waits for a Link Open for `uuid` and returns the Link ID the provisioner picked.
+async fn wait_for_link_open(
+    bearer_rx: &mut mpsc::Receiver<Result<IncomingMessage, btle::hci::adapter::Error>>,
+    uuid: &UUID,
+) -> Result<LinkID, CLIError> {
+    loop {
+        let msg = bearer_rx.recv().await.ok_or_else(|| {
+            CLIError::OtherMessage("early end on incoming advertisement stream".to_owned())
+        })??;
+        if let Some(incoming) = msg.pb_adv() {
+            if let generic::Control::BearerControl(bearer_control::PDU::LinkOpen(LinkOpen(
+                link_uuid,
+            ))) = incoming.pdu.generic_pdu.control
+            {
+                if &link_uuid == uuid {
+                    return Ok(incoming.pdu.link_id);
+                }
+            }
+        }
+    }
+}
+pub async fn listen(
+    logger: &slog::Logger,
+    which_adapter: &'_ str,
+    device_state: DeviceState,
+    device_state_path: &str,
+) -> Result<(), CLIError> {
+    const BEARER_CHANNEL_SIZE: usize = 16;
+    let unprovisioned_beacon = unprovisioned_beacon(&device_state)?;
+    let uuid = unprovisioned_beacon.uuid;
+    info!(logger, "opening_hci_adapter"; "adapter" => which_adapter);
+    let adapter = helper::hci_adapter(which_adapter).await?;
+    let (mut adapter, mut bearer_rx, mut bearer_tx) =
+        BufferedHCIAdvertiser::new_with_channel_size(adapter, BEARER_CHANNEL_SIZE);
+    let _adapter_task = task::spawn(async move {
+        adapter.run_loop_send_error().await;
+    });
+    let closed = || CLIError::OtherMessage("advertiser closed".to_owned());
+    let beacon = OutgoingMessage::Beacon(BeaconPDU::Unprovisioned(unprovisioned_beacon));
+    info!(logger, "advertising_unprovisioned_beacon"; "uuid" => %uuid);
+    let link_id = loop {
+        bearer_tx.send(beacon).await.map_err(|_| closed())?;
+        if let Ok(link_id) =
+            time::timeout(BEACON_INTERVAL, wait_for_link_open(&mut bearer_rx, &uuid)).await
+        {
+            break link_id?;
+        }
+    };
+    info!(logger, "link_opened"; "link_id" => ?link_id);
+    bearer_tx
+        .send(OutgoingMessage::PBAdv(pb_adv::PDU {
+            link_id,
+            transaction_number: TransactionNumber::new(0),
+            generic_pdu: generic::PDU {
+                control: generic::Control::BearerControl(bearer_control::PDU::LinkAck(LinkAck())),
+                payload: None,
+            },
+        }))
+        .await
+        .map_err(|_| closed())?;
+    let (device_tx, mut device_out) = mpsc::channel(Link::<PBAdvBuf>::CHANNEL_SIZE);
+    let (mut to_device, device_rx) = mpsc::channel(Link::<PBAdvBuf>::CHANNEL_SIZE);
+    // Forwards the device's PB-ADV PDUs to the bearer.
+    task::spawn(async move {
+        while let Some(pdu) = device_out.recv().await {
+            if bearer_tx.send(OutgoingMessage::PBAdv(pdu)).await.is_err() {
+                return;
+            }
+        }
+    });
+    // Forwards PB-ADV PDUs on our link to the device.
+    task::spawn(async move {
+        while let Some(Ok(msg)) = bearer_rx.recv().await {
+            if let Some(incoming) = msg.pb_adv() {
+                if incoming.pdu.link_id == link_id && to_device.send(incoming.pdu).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    let mut process = Process::new(
+        Bearer::new_generic(GenericBearer::new_provisionee(
+            link_id, device_tx, device_rx,
+        )),
+        capabilities(&device_state),
+    );
+    provision_device(logger, &mut process).await?;
+    let data = match process.stage() {
+        Stage::Complete(data) => data,
+        _ => unreachable!("provision_device only returns once complete"),
+    };
+    let dev_key = process.device_key().expect("known once complete");
+    let provisioned = provisioned_device_state(device_state, data, dev_key)?;
+    helper::write_device_state(device_state_path, &provisioned)?;
+    info!(logger, "provisioned";
+        "element_address" => format!("0x{:04X}", u16::from(data.element_address)),
+        "iv_index" => data.iv_index.0);
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bluetooth_mesh::address::UnicastAddress;
+    use bluetooth_mesh::beacon::OOBFlags;
+    use bluetooth_mesh::crypto::key::NetKey;
+    use bluetooth_mesh::mesh::{ElementCount, IVIndex, KeyIndex, NetKeyIndex};
+    use bluetooth_mesh::provisioning::data::Flags;
+    use bluetooth_mesh::provisioning::provisioner;
+    use slog::Drain;
+
+    #[test]
+    fn test_beacon_uses_configured_uuid() {
+        let path = std::env::temp_dir().join("mesh_cli_test_provisionee_uuid_device_state.json");
+        let path = path.to_str().expect("utf-8 temp path");
+        let uuid =
+            UUID(helper::hex_str_to_bytes("70cf7c9732a345b691494810d2e9cbf4").expect("valid uuid"));
+        let oob_information = OOBInformation::default().set(OOBFlags::OnBox);
+        let mut device_state = DeviceState::new(
+            UnicastAddress::new(1).expect("valid unicast address"),
+            ElementCount(1),
+        );
+        assert!(unprovisioned_beacon(&device_state).is_err());
+        device_state.set_uuid(uuid);
+        *device_state.oob_information_mut() = oob_information;
+        helper::write_device_state(path, &device_state).expect("write device state");
+
+        let loaded = helper::load_device_state(path).expect("load device state");
+        let beacon = unprovisioned_beacon(&loaded).expect("uuid configured");
+        assert_eq!(beacon.uuid, uuid);
+        assert_eq!(beacon.oob_information, oob_information);
+        assert_eq!(beacon.uri_hash, None);
+        std::fs::remove_file(path).expect("remove temp device state");
+    }
+    #[test]
+    fn test_provisionee_writes_device_state() {
+        let path = std::env::temp_dir().join("mesh_cli_test_provisionee_device_state.json");
+        let path = path.to_str().expect("utf-8 temp path");
+        let logger = slog::Logger::root(slog::Discard.fuse(), o!());
+        let unprovisioned = DeviceState::new(
+            UnicastAddress::new(1).expect("valid unicast address"),
+            ElementCount(2),
+        );
+        let (to_device, device_rx) = mpsc::channel(4);
+        let (device_tx, from_device) = mpsc::channel(4);
+        let mut device = Process::new(
+            Bearer::new_channel(device_rx, device_tx),
+            capabilities(&unprovisioned),
+        );
+        // Mock provisioner driving the other end of the bearer.
+        let mut provisioner =
+            provisioner::Process::new(Bearer::new_channel(from_device, to_device));
+        let mut flags = Flags::default();
+        flags.enable(Flag::IVUpdate);
+        let data = ProvisioningData {
+            net_key: NetKey::new_bytes([0x7D_u8; 16]),
+            net_key_index: NetKeyIndex(KeyIndex::new(0x0005)),
+            flags,
+            iv_index: IVIndex(0x1234_5678),
+            element_address: UnicastAddress::new(0x0B0C).expect("valid unicast address"),
+        };
+        let provisioner_side = async {
+            loop {
+                match provisioner.next_stage().await {
+                    Ok(provisioner::Stage::Distribute { .. }) => break,
+                    Ok(_) => (),
+                    Err(e) => panic!("provisioner error: {:?}", e),
+                }
+            }
+            provisioner
+                .distribute(&data)
+                .await
+                .expect("data distributed");
+            provisioner
+                .next_stage()
+                .await
+                .expect("device acknowledged data");
+        };
+        let (_, device_result) = tokio_runtime().block_on(futures_util::future::join(
+            provisioner_side,
+            provision_device(&logger, &mut device),
+        ));
+        device_result.expect("device provisioned");
+        let received = match device.stage() {
+            Stage::Complete(received) => received,
+            _ => panic!("expected device to be provisioned"),
+        };
+        let provisioned = provisioned_device_state(
+            unprovisioned,
+            received,
+            device.device_key().expect("provisioning complete"),
+        )
+        .expect("valid assigned address");
+        helper::write_device_state(path, &provisioned).expect("write device state");
+
+        let loaded = helper::load_device_state(path).expect("load device state");
+        assert_eq!(*loaded.unicast_range().start(), data.element_address);
+        assert_eq!(loaded.element_count(), ElementCount(2));
+        assert_eq!(loaded.iv_index(), data.iv_index);
+        assert_eq!(loaded.iv_update_flag(), IVUpdateFlag(true));
+        assert!(loaded
+            .security_materials()
+            .net_key_map
+            .get_keys(data.net_key_index)
+            .is_some());
+        assert_eq!(
+            loaded.security_materials().dev_key,
+            device.device_key().expect("provisioning complete")
+        );
+        std::fs::remove_file(path).expect("remove temp device state");
+    }
+}
@@ -2,58 +2,93 @@ use crate::{helper, CLIError};
 use bluetooth_mesh::address::{Address, UnicastAddress};
 use bluetooth_mesh::device_state;
 use bluetooth_mesh::mesh::ElementCount;
+use std::convert::TryFrom;
 use std::str::FromStr;
 
 pub fn sub_command() -> clap::App<'static, 'static> {
-    clap::SubCommand::with_name("state").subcommand(
-        clap::SubCommand::with_name("new")
-            .about("Generate a device state with desired parameters")
-            .arg(
-                clap::Arg::with_name("element_count")
-                    .short("c")
-                    .value_name("ELEMENT_COUNT")
-                    .required(true)
-                    .default_value("1")
-                    .validator(|count| {
-                        if let Ok(c) = usize::from_str(&count) {
-                            match c {
-                                1..=0xFF => Ok(()),
-                                _ => Err(format!(
-                                    "Invalid element count '{}'. Expected in range [1..0xFF]",
-                                    c
-                                )),
+    clap::SubCommand::with_name("state")
+        .subcommand(
+            clap::SubCommand::with_name("new")
+                .about("Generate a device state with desired parameters")
+                .arg(
+                    clap::Arg::with_name("element_count")
+                        .short("c")
+                        .value_name("ELEMENT_COUNT")
+                        .required(true)
+                        .default_value("1")
+                        .validator(|count| {
+                            if let Ok(c) = usize::from_str(&count) {
+                                match c {
+                                    1..=0xFF => Ok(()),
+                                    _ => Err(format!(
+                                        "Invalid element count '{}'. Expected in range [1..0xFF]",
+                                        c
+                                    )),
+                                }
+                            } else {
+                                Err(format!("Invalid element count '{}'. Not a number", count))
                             }
-                        } else {
-                            Err(format!("Invalid element count '{}'. Not a number", count))
-                        }
-                    }),
-            )
-            .arg(
-                clap::Arg::with_name("element_address")
-                    .short("a")
-                    .value_name("UNICAST_ADDRESS")
-                    .required(true)
-                    .default_value("1")
-                    .validator(|address| {
-                        let radix = if address.starts_with("0x") { 16 } else { 10 };
-                        if let Ok(a) = u16::from_str_radix(address.trim_start_matches("0x"), radix)
-                        {
-                            match Address::from(a) {
-                                Address::Unicast(_) => Ok(()),
-                                _ => Err(format!("Non-unicast address '{}' given", &address)),
+                        }),
+                )
+                .arg(
+                    clap::Arg::with_name("element_address")
+                        .short("a")
+                        .value_name("UNICAST_ADDRESS")
+                        .required(true)
+                        .default_value("1")
+                        .validator(|address| {
+                            let radix = if address.starts_with("0x") { 16 } else { 10 };
+                            if let Ok(a) =
+                                u16::from_str_radix(address.trim_start_matches("0x"), radix)
+                            {
+                                match Address::try_from(a) {
+                                    Ok(Address::Unicast(_)) => Ok(()),
+                                    _ => Err(format!("Non-unicast address '{}' given", &address)),
+                                }
+                            } else {
+                                Err(format!("Non-address '{}' given", &address))
                             }
-                        } else {
-                            Err(format!("Non-address '{}' given", &address))
-                        }
-                    }),
-            )
-            .arg(
-                clap::Arg::with_name("default_ttl")
-                    .short("t")
-                    .value_name("DEFAULT_TTL")
-                    .validator(helper::is_ttl),
-            ),
-    )
+                        }),
+                )
+                .arg(
+                    clap::Arg::with_name("default_ttl")
+                        .short("t")
+                        .value_name("DEFAULT_TTL")
+                        .validator(helper::is_ttl),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("verify")
+                .about("Check the device state for inconsistent keys, addresses and seq counters"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("nodes")
+                .about("Manage the nodes provisioned by this device")
+                .subcommand(
+                    clap::SubCommand::with_name("list")
+                        .about("List provisioned nodes with their address range and net keys"),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("remove")
+                        .about("Remove a provisioned node")
+                        .arg(
+                            clap::Arg::with_name("address")
+                                .value_name("PRIMARY_UNICAST_ADDRESS")
+                                .required(true)
+                                .validator(|address| match parse_unicast_address(&address) {
+                                    Some(_) => Ok(()),
+                                    None => {
+                                        Err(format!("Non-unicast address '{}' given", &address))
+                                    }
+                                }),
+                        ),
+                ),
+        )
+}
+/// Parses a decimal or `0x` prefixed hex unicast address.
+fn parse_unicast_address(address: &str) -> Option<UnicastAddress> {
+    let radix = if address.starts_with("0x") { 16 } else { 10 };
+    UnicastAddress::new(u16::from_str_radix(address.trim_start_matches("0x"), radix).ok()?)
 }
 pub fn state_matches(
     parent_logger: &slog::Logger,
@@ -69,12 +104,32 @@ pub fn state_matches(
                 (Some(element_count), Some(element_address)) => {
                     let count = ElementCount(element_count.parse().expect("checked by clap"));
                     let address =
-                        UnicastAddress::new(element_address.parse().expect("checked by clap"));
+                        UnicastAddress::new(element_address.parse().expect("checked by clap"))
+                            .expect("checked by clap");
                     generate(parent_logger, device_state_path, address, count)
                 }
                 _ => unreachable!("element count and element address should have default values"),
             }
         }
+        ("verify", Some(_)) => {
+            let device_state = helper::load_device_state(device_state_path)?;
+            let problems = device_state.verify();
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if problems.is_empty() {
+                println!("device state ok");
+                Ok(())
+            } else {
+                Err(CLIError::OtherMessage(format!(
+                    "{} problem(s) found in device state",
+                    problems.len()
+                )))
+            }
+        }
+        ("nodes", Some(nodes_matches)) => {
+            nodes_matches_handler(parent_logger, device_state_path, nodes_matches)
+        }
 
         ("", None) => Err(CLIError::Clap(clap::Error::with_description(
             "missing state subcommand",
@@ -90,9 +145,216 @@ pub fn generate(
     element_count: ElementCount,
 ) -> Result<(), CLIError> {
     let logger = parent_logger.new(o!("device_state_path" => device_state_path.to_owned()));
+    let device_state = device_state::DeviceState::try_new(primary_address, element_count)
+        .map_err(|e| CLIError::OtherMessage(e.to_string()))?;
     let f = helper::load_file(device_state_path, true, true)?;
     info!(logger, "found device_state");
-    let device_state = device_state::DeviceState::new(primary_address, element_count);
     serde_json::to_writer(f, &device_state).map_err(CLIError::SerdeJSON)?;
     Ok(())
 }
+pub fn nodes_matches_handler(
+    parent_logger: &slog::Logger,
+    device_state_path: &str,
+    nodes_matches: &clap::ArgMatches,
+) -> Result<(), CLIError> {
+    let logger = parent_logger.new(o!("device_state_path" => device_state_path.to_owned()));
+    match nodes_matches.subcommand() {
+        ("list", Some(_)) => {
+            let device_state = helper::load_device_state(device_state_path)?;
+            for line in node_lines(&device_state) {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        ("remove", Some(remove_matches)) => {
+            let address = parse_unicast_address(
+                remove_matches
+                    .value_of("address")
+                    .expect("required by clap"),
+            )
+            .expect("checked by clap");
+            remove_node(device_state_path, address)?;
+            info!(logger, "removed node"; "address" => u16::from(address));
+            Ok(())
+        }
+        ("", None) => Err(CLIError::Clap(clap::Error::with_description(
+            "missing nodes subcommand",
+            clap::ErrorKind::ArgumentNotFound,
+        ))),
+        _ => unreachable!("unhandled nodes subcommand"),
+    }
+}
+/// One line per provisioned node: `0x0002-0x0004 elements: 3 net_keys: [0, 1]`.
+pub fn node_lines(device_state: &device_state::DeviceState) -> Vec<String> {
+    device_state
+        .nodes()
+        .iter()
+        .map(|(address, node)| {
+            let start = u16::from(address);
+            let last = start + u16::from(node.element_count.0) - 1;
+            let net_keys = node
+                .net_keys
+                .iter()
+                .map(|index| u16::from(index.0).to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "0x{:04X}-0x{:04X} elements: {} net_keys: [{}]",
+                start, last, node.element_count.0, net_keys
+            )
+        })
+        .collect()
+}
+pub fn remove_node(device_state_path: &str, address: UnicastAddress) -> Result<(), CLIError> {
+    let mut device_state = helper::load_device_state(device_state_path)?;
+    if device_state.nodes_mut().remove(address).is_none() {
+        return Err(CLIError::OtherMessage(format!(
+            "no node with primary address 0x{:04X}",
+            u16::from(address)
+        )));
+    }
+    helper::write_device_state(device_state_path, &device_state)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bluetooth_mesh::crypto::key::{AppKey, DevKey};
+    use bluetooth_mesh::device_state::{NodeInfo, StateProblem};
+    use bluetooth_mesh::mesh::{
+        AppKeyIndex, ElementIndex, KeyIndex, NetKeyIndex, SequenceNumber, U24,
+    };
+    use bluetooth_mesh::random::Randomizable;
+
+    #[test]
+    fn test_list_and_remove_nodes() {
+        let path = std::env::temp_dir().join("mesh_cli_test_nodes_device_state.json");
+        let path = path.to_str().expect("utf-8 temp path");
+        let mut device_state = device_state::DeviceState::new(
+            UnicastAddress::new(1).expect("valid unicast address"),
+            ElementCount(1),
+        );
+        device_state.nodes_mut().insert(
+            UnicastAddress::new(0x0002).expect("valid unicast address"),
+            NodeInfo {
+                element_count: ElementCount(3),
+                net_keys: vec![NetKeyIndex(KeyIndex::new(0)), NetKeyIndex(KeyIndex::new(1))],
+                dev_key: Some(DevKey::random_secure()),
+            },
+        );
+        device_state.nodes_mut().insert(
+            UnicastAddress::new(0x0010).expect("valid unicast address"),
+            NodeInfo {
+                element_count: ElementCount(1),
+                net_keys: vec![NetKeyIndex(KeyIndex::new(0))],
+                dev_key: Some(DevKey::random_secure()),
+            },
+        );
+        helper::write_device_state(path, &device_state).expect("write device state");
+        // State files from before device keys were recorded have no `dev_key` for their nodes.
+        let mut json: serde_json::Value =
+            serde_json::from_reader(helper::load_file(path, false, false).expect("open"))
+                .expect("valid json");
+        for node in json["nodes"]
+            .as_object_mut()
+            .expect("nodes map")
+            .values_mut()
+        {
+            node.as_object_mut()
+                .expect("node object")
+                .remove("dev_key")
+                .expect("node has a dev_key");
+        }
+        serde_json::to_writer(helper::load_file(path, true, true).expect("open"), &json)
+            .expect("write json");
+
+        let loaded = helper::load_device_state(path).expect("load device state");
+        assert_eq!(
+            node_lines(&loaded),
+            vec![
+                "0x0002-0x0004 elements: 3 net_keys: [0, 1]".to_owned(),
+                "0x0010-0x0010 elements: 1 net_keys: [0]".to_owned(),
+            ]
+        );
+        assert!(loaded
+            .nodes()
+            .iter()
+            .all(|(_, node)| node.dev_key.is_none()));
+
+        remove_node(
+            path,
+            UnicastAddress::new(0x0002).expect("valid unicast address"),
+        )
+        .expect("node exists");
+        assert!(remove_node(
+            path,
+            UnicastAddress::new(0x0003).expect("valid unicast address")
+        )
+        .is_err());
+        let loaded = helper::load_device_state(path).expect("load device state");
+        assert_eq!(
+            node_lines(&loaded),
+            vec!["0x0010-0x0010 elements: 1 net_keys: [0]".to_owned()]
+        );
+        std::fs::remove_file(path).expect("remove temp device state");
+    }
+
+    #[test]
+    fn test_verify_broken_state() {
+        let path = std::env::temp_dir().join("mesh_cli_test_verify_device_state.json");
+        let path = path.to_str().expect("utf-8 temp path");
+        let mut device_state = device_state::DeviceState::new(
+            UnicastAddress::new(1).expect("valid unicast address"),
+            ElementCount(2),
+        );
+        assert!(device_state.verify().is_empty());
+        // AppKey bound to a NetKey that was never added.
+        device_state.security_materials_mut().app_key_map.insert(
+            NetKeyIndex(KeyIndex::new(5)),
+            AppKeyIndex(KeyIndex::new(1)),
+            AppKey::random_secure(),
+        );
+        // Node overlapping this device's second element.
+        device_state.nodes_mut().insert(
+            UnicastAddress::new(2).expect("valid unicast address"),
+            NodeInfo {
+                element_count: ElementCount(1),
+                net_keys: Vec::new(),
+                dev_key: Some(DevKey::random_secure()),
+            },
+        );
+        device_state
+            .seq_counter_mut(ElementIndex(0))
+            .set_seq(SequenceNumber(U24::max_value()));
+        helper::write_device_state(path, &device_state).expect("write device state");
+        // Drop the second seq counter from the saved file.
+        let mut json: serde_json::Value =
+            serde_json::from_reader(helper::load_file(path, false, false).expect("open"))
+                .expect("valid json");
+        json["seq_counters"]
+            .as_array_mut()
+            .expect("seq_counters array")
+            .pop();
+        serde_json::to_writer(helper::load_file(path, true, true).expect("open"), &json)
+            .expect("write json");
+
+        let loaded = helper::load_device_state(path).expect("load device state");
+        assert_eq!(
+            loaded.verify(),
+            vec![
+                StateProblem::MissingNetKey {
+                    app_key_index: AppKeyIndex(KeyIndex::new(1)),
+                    net_key_index: NetKeyIndex(KeyIndex::new(5)),
+                },
+                StateProblem::DuplicateElementAddress(
+                    UnicastAddress::new(2).expect("valid unicast address")
+                ),
+                StateProblem::SeqCounterCount {
+                    element_count: ElementCount(2),
+                    seq_counters: 1,
+                },
+                StateProblem::SeqExhausted(ElementIndex(0)),
+            ]
+        );
+        std::fs::remove_file(path).expect("remove temp device state");
+    }
+}
@@ -30,7 +30,9 @@ impl From<btle::hci::usb::Error> for CLIError {
 fn add_mesh_subcommands<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
     app.subcommand(commands::state::sub_command())
         .subcommand(commands::provisioner::sub_command())
+        .subcommand(commands::provisionee::sub_command())
         .subcommand(commands::crypto::sub_command())
+        .subcommand(commands::beacon::sub_command())
 }
 #[cfg(not(feature = "mesh"))]
 fn add_mesh_subcommands<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
@@ -109,6 +111,16 @@ fn main() {
                 get_device_state_path(),
                 prov_matches,
             )?,
+            #[cfg(feature = "mesh")]
+            ("provisionee", Some(prov_matches)) => commands::provisionee::provisionee_matches(
+                &root,
+                get_device_state_path(),
+                prov_matches,
+            )?,
+            #[cfg(feature = "mesh")]
+            ("beacon", Some(beacon_matches)) => {
+                commands::beacon::beacon_matches(&root, beacon_matches)?
+            }
             ("ble", Some(ble_matches)) => commands::ble::ble_matches(&root, ble_matches)?,
             _ => unreachable!("unhandled sub_command"),
         }
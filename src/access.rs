@@ -1,22 +1,42 @@
 //! Access Layer between Models and the rest of the stack (Transport, Network, etc). The most
 //! surface layer of the stack.
 use crate::bytes::ToFromBytesEndian;
+use crate::crypto::aes::MicSize;
+use crate::crypto::MIC;
 use crate::mesh::{CompanyID, ModelID};
+use crate::upper;
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::collections::BTreeMap;
 
+/// Largest Access Payload (opcode + parameters) that can be sent. 32 segments of 12 bytes
+/// (384 bytes) minus the 32-bit TransMIC. A 64-bit TransMIC lowers this by 4 bytes.
+pub const fn max_access_payload_len() -> usize {
+    upper::ENCRYPTED_APP_PAYLOAD_MAX_LEN - MIC::small_size()
+}
+/// Returns if an Access Payload of `packed_len` bytes still fits in an Upper Transport PDU once
+/// the `mic_size` TransMIC is appended.
+pub fn access_payload_fits(packed_len: usize, mic_size: MicSize) -> bool {
+    packed_len + mic_size.byte_size() <= max_access_payload_len() + MIC::small_size()
+}
+
+/// 16-bit Model ID assigned by the Bluetooth SIG.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
-pub struct SigModelID(u16);
+pub struct SigModelID(pub u16);
 impl SigModelID {
     pub const fn byte_len() -> usize {
         2
     }
 }
+/// 16-bit vendor assigned Model ID. Only unique together with the vendor's `CompanyID`.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
-pub struct VendorModelID(u16);
+pub struct VendorModelID(pub u16);
 impl VendorModelID {
     pub const fn byte_len() -> usize {
-        CompanyID::byte_len() + 2
+        2
     }
 }
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
@@ -94,17 +114,32 @@ impl Opcode {
             let vendor_opcode = VendorOpcode::new(bytes[0] & !0xC0);
             let company_id = CompanyID(u16::from_le_bytes([bytes[1], bytes[2]]));
             Ok(Opcode::Vendor(vendor_opcode, company_id))
-        } else if bytes[0] & 0x80 == 1 {
+        } else if bytes[0] & 0xC0 == 0x80 {
             if bytes.len() < 2 {
                 return Err(OpcodeConversationError(()));
             }
-            Ok(Opcode::SIG(SigOpcode::DoubleOctet(u16::from_le_bytes([
+            Ok(Opcode::SIG(SigOpcode::DoubleOctet(u16::from_be_bytes([
                 bytes[0], bytes[1],
             ]))))
         } else {
             Err(OpcodeConversationError(()))
         }
     }
+    /// Splits an Access PDU into its opcode and parameters. The opcode length comes from the
+    /// first byte (`0b0xxxxxxx` is 1 byte, `0b10xxxxxx` is 2 and `0b11xxxxxx` is 3).
+    pub fn split_from(access_pdu: &[u8]) -> Result<(Self, &[u8]), OpcodeConversationError> {
+        let opcode_len = match access_pdu.first() {
+            None => return Err(OpcodeConversationError(())),
+            Some(b) if b & 0x80 == 0 => 1,
+            Some(b) if b & 0xC0 == 0x80 => 2,
+            Some(_) => 3,
+        };
+        if access_pdu.len() < opcode_len {
+            return Err(OpcodeConversationError(()));
+        }
+        let (opcode, params) = access_pdu.split_at(opcode_len);
+        Ok((Self::unpack_from(opcode)?, params))
+    }
     pub fn pack_into(&self, buffer: &mut [u8]) -> Result<(), OpcodeConversationError> {
         match *self {
             Opcode::SIG(s) => match s {
@@ -124,7 +159,7 @@ impl Opcode {
                         return Err(OpcodeConversationError(()));
                     }
                     if d & 0xC000 == 0x8000 {
-                        buffer[..2].copy_from_slice(&d.to_le_bytes()[..]);
+                        buffer[..2].copy_from_slice(&d.to_be_bytes()[..]);
                         Ok(())
                     } else {
                         Err(OpcodeConversationError(()))
@@ -146,82 +181,206 @@ impl Opcode {
         }
     }
 }
+/// Identifies a model as either a SIG model (2 bytes on the wire) or a vendor model
+/// (`CompanyID` + `VendorModelID`, 4 bytes on the wire). Both are packed little endian.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
-pub struct ModelIdentifier {
-    model_id: ModelID,
-    company_id: Option<CompanyID>,
+pub enum ModelIdentifier {
+    Sig(SigModelID),
+    Vendor(CompanyID, VendorModelID),
 }
 impl ModelIdentifier {
     pub fn new_sig(sig_model_id: ModelID) -> ModelIdentifier {
-        ModelIdentifier {
-            model_id: sig_model_id,
-            company_id: None,
-        }
+        ModelIdentifier::Sig(SigModelID(sig_model_id.0))
     }
     /// Creates a new vendor model from a `ModelID` and Bluetooth `CompanyID`
     pub fn new_vendor(model_id: ModelID, company_id: CompanyID) -> ModelIdentifier {
-        ModelIdentifier {
-            model_id,
-            company_id: Some(company_id),
-        }
+        ModelIdentifier::Vendor(company_id, VendorModelID(model_id.0))
     }
     /// Returns the byte length of a vendor `ModelIdentifier`.
-    /// (`vendor_byte_len() == ModelID::byte_len() + CompanyID::byte_len()`)
+    /// (`vendor_byte_len() == CompanyID::byte_len() + VendorModelID::byte_len()`)
     pub const fn vendor_byte_len() -> usize {
-        ModelID::byte_len() + CompanyID::byte_len()
+        CompanyID::byte_len() + VendorModelID::byte_len()
     }
     /// Returns the byte length of a SIG `ModelIdentifier`.
-    /// (`sig_byte_len() == ModelID::byte_len()`)
+    /// (`sig_byte_len() == SigModelID::byte_len()`)
     pub const fn sig_byte_len() -> usize {
-        ModelID::byte_len()
+        SigModelID::byte_len()
     }
     pub fn byte_len(&self) -> usize {
-        if self.is_vendor() {
-            Self::vendor_byte_len()
-        } else {
-            Self::sig_byte_len()
+        match self {
+            ModelIdentifier::Sig(_) => Self::sig_byte_len(),
+            ModelIdentifier::Vendor(_, _) => Self::vendor_byte_len(),
         }
     }
-    /// Returns the `ModelID` of the model.
+    /// Returns the 16-bit `ModelID` of the model (SIG or vendor assigned).
     pub fn model_id(&self) -> ModelID {
-        self.model_id
+        match self {
+            ModelIdentifier::Sig(id) => ModelID(id.0),
+            ModelIdentifier::Vendor(_, id) => ModelID(id.0),
+        }
     }
     /// Returns the `CompanyID` of the vendor model or `None` if it's a SIG model.
     pub fn company_id(&self) -> Option<CompanyID> {
-        self.company_id
+        match self {
+            ModelIdentifier::Sig(_) => None,
+            ModelIdentifier::Vendor(company_id, _) => Some(*company_id),
+        }
     }
     /// Returns if the `ModelIdentifier` is a SIG model.
     pub fn is_sig(&self) -> bool {
-        self.company_id.is_none()
+        match self {
+            ModelIdentifier::Sig(_) => true,
+            ModelIdentifier::Vendor(_, _) => false,
+        }
     }
     /// Returns if the `ModelIdentifier` is a vendor model.
     pub fn is_vendor(&self) -> bool {
         !self.is_sig()
     }
     /// Packs the `ModelIdentifier` into a little endian byte buffer. The `buf` must have enough
-    /// room for the `ModelIdentifier`! (Usually 2 or 4 bytes).  
+    /// room for the `ModelIdentifier`! (2 bytes for SIG or 4 bytes for vendor models).
     /// # Panics
     /// Panics if `buf.len() < self.byte_len()`
     pub fn pack_into(&self, buf: &mut [u8]) {
         assert!(buf.len() >= self.byte_len());
-        (match self.company_id {
-            None => buf,
-            Some(company_id) => {
+        match self {
+            ModelIdentifier::Sig(id) => buf[..2].copy_from_slice(&id.0.to_le_bytes()),
+            ModelIdentifier::Vendor(company_id, id) => {
                 buf[..CompanyID::byte_len()].copy_from_slice(&company_id.to_bytes_le());
-                &mut buf[CompanyID::byte_len()..]
+                buf[CompanyID::byte_len()..Self::vendor_byte_len()]
+                    .copy_from_slice(&id.0.to_le_bytes());
             }
-        }[..VendorModelID::byte_len()])
-            .copy_from_slice(&self.model_id.to_bytes_le());
+        }
     }
+    /// Unpacks a SIG (`buf.len() == 2`) or vendor (`buf.len() == 4`) `ModelIdentifier`. Returns
+    /// `None` for any other length.
     pub fn unpack_from(buf: &[u8]) -> Option<Self> {
         match buf.len() {
-            4 => Some(Self::new_sig(ModelID::from_bytes_le(buf)?)),
-            8 => Some(Self::new_vendor(
-                ModelID::from_bytes_le(&buf[2..4])?,
+            2 => Some(ModelIdentifier::Sig(SigModelID(u16::from_le_bytes([
+                buf[0], buf[1],
+            ])))),
+            4 => Some(ModelIdentifier::Vendor(
                 CompanyID::from_bytes_le(&buf[..2])?,
+                VendorModelID(u16::from_le_bytes([buf[2], buf[3]])),
             )),
             _ => None,
         }
     }
 }
+/// Handler called by `DynDispatch` with the access message parameters (without the opcode).
+#[cfg(feature = "std")]
+pub type DynHandler<R> = Box<dyn Fn(&[u8]) -> R + Send + Sync>;
+/// Runtime registry of boxed handlers keyed by `Opcode`. Unlike
+/// `models::dispatch::MessageDispatch`, handlers get the raw parameters and decode (or not) them
+/// however they like.
+#[cfg(feature = "std")]
+pub struct DynDispatch<R> {
+    handlers: BTreeMap<Opcode, DynHandler<R>>,
+}
+#[cfg(feature = "std")]
+impl<R> DynDispatch<R> {
+    pub fn new() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+        }
+    }
+    /// Registers `handler` for `opcode`. Returns the handler it replaced (if any).
+    pub fn register(
+        &mut self,
+        opcode: Opcode,
+        handler: impl Fn(&[u8]) -> R + Send + Sync + 'static,
+    ) -> Option<DynHandler<R>> {
+        self.handlers.insert(opcode, Box::new(handler))
+    }
+    pub fn unregister(&mut self, opcode: Opcode) -> Option<DynHandler<R>> {
+        self.handlers.remove(&opcode)
+    }
+    pub fn is_registered(&self, opcode: Opcode) -> bool {
+        self.handlers.contains_key(&opcode)
+    }
+    /// Calls the handler registered for `opcode` with `payload`. Returns `None` if there isn't
+    /// one.
+    pub fn handle(&self, opcode: Opcode, payload: &[u8]) -> Option<R> {
+        self.handlers.get(&opcode).map(|handler| handler(payload))
+    }
+}
+#[cfg(feature = "std")]
+impl<R> Default for DynDispatch<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_access_payload_len() {
+        assert_eq!(max_access_payload_len(), 380);
+        assert!(access_payload_fits(380, MicSize::Small));
+        assert!(!access_payload_fits(381, MicSize::Small));
+        assert!(access_payload_fits(376, MicSize::Big));
+        assert!(!access_payload_fits(377, MicSize::Big));
+    }
+    #[test]
+    fn test_opcode_split_from() {
+        // Config Default TTL Status (0x800E) is sent big endian.
+        let opcode = Opcode::SIG(SigOpcode::DoubleOctet(0x800E));
+        let mut buf = [0_u8; 3];
+        opcode.pack_into(&mut buf[..2]).expect("valid opcode");
+        assert_eq!(buf[..2], [0x80, 0x0E]);
+        buf[2] = 0x05;
+        assert_eq!(Opcode::split_from(&buf), Ok((opcode, &buf[2..])));
+        let vendor = [0xC2, 0xF1, 0x05];
+        assert_eq!(
+            Opcode::split_from(&vendor),
+            Ok((
+                Opcode::Vendor(VendorOpcode::new(0x02), CompanyID(0x05F1)),
+                &[][..]
+            ))
+        );
+        assert!(Opcode::split_from(&vendor[..2]).is_err());
+        assert!(Opcode::split_from(&[]).is_err());
+    }
+    #[test]
+    fn test_sig_model_identifier_round_trip() {
+        let model = ModelIdentifier::Sig(SigModelID(0x1000));
+        assert_eq!(model.byte_len(), 2);
+        let mut buf = [0_u8; 2];
+        model.pack_into(&mut buf);
+        assert_eq!(buf, [0x00, 0x10]);
+        assert_eq!(ModelIdentifier::unpack_from(&buf), Some(model));
+        assert_eq!(model.company_id(), None);
+    }
+    #[test]
+    fn test_vendor_model_identifier_round_trip() {
+        let model = ModelIdentifier::Vendor(CompanyID(0x05F1), VendorModelID(0x0001));
+        assert_eq!(model.byte_len(), 4);
+        let mut buf = [0_u8; 4];
+        model.pack_into(&mut buf);
+        assert_eq!(buf, [0xF1, 0x05, 0x01, 0x00]);
+        assert_eq!(ModelIdentifier::unpack_from(&buf), Some(model));
+        assert_eq!(model.company_id(), Some(CompanyID(0x05F1)));
+        assert_eq!(ModelIdentifier::unpack_from(&buf[..3]), None);
+    }
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_dyn_dispatch_onoff_status() {
+        use crate::models::generics::onoff;
+        use crate::models::PackableMessage;
+        let mut dispatch = DynDispatch::new();
+        assert!(dispatch
+            .register(onoff::Status::opcode(), |payload: &[u8]| {
+                onoff::Status::unpack_from(payload)
+                    .ok()
+                    .map(|status| status.present_on_off)
+            })
+            .is_none());
+        assert_eq!(
+            dispatch.handle(onoff::Status::opcode(), &[0x01]),
+            Some(Some(true))
+        );
+        assert_eq!(dispatch.handle(onoff::Get::opcode(), &[]), None);
+    }
+}
@@ -45,30 +45,87 @@ pub struct UnicastAddress(u16);
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct GroupAddress(u16);
+const GROUP_RANGE_END: u16 = 0xFEFF;
 impl GroupAddress {
-    /// # Panics
-    /// Panics if `group_address` isn't a value group address.
-    pub fn new(group_address: u16) -> Self {
-        match Self::try_from(group_address) {
-            Ok(g) => g,
-            Err(_) => panic!("invalid group address given"),
+    /// Creates a new `GroupAddress`. Returns `None` unless `group_address` is a dynamic group
+    /// address (`0xC000-0xFEFF`) or one of the [`FixedGroup`] addresses (`0xFFFC-0xFFFF`).
+    /// The RFU range (`0xFF00-0xFFFB`) is rejected.
+    #[must_use]
+    pub fn new(group_address: u16) -> Option<GroupAddress> {
+        if (GROUP_BIT..=GROUP_RANGE_END).contains(&group_address)
+            || FixedGroup::from_u16(group_address).is_some()
+        {
+            Some(GroupAddress(group_address))
+        } else {
+            None
         }
     }
+    /// Returns the `FixedGroup` if the address is a fixed group address (all-nodes, etc).
+    #[must_use]
+    pub fn fixed_group(self) -> Option<FixedGroup> {
+        FixedGroup::from_u16(self.0)
+    }
+    #[must_use]
+    pub fn is_fixed_group(self) -> bool {
+        self.fixed_group().is_some()
+    }
     /// Group address corresponding to all proxies nodes.
     pub const fn all_proxies() -> GroupAddress {
-        GroupAddress(0xFFFC)
+        GroupAddress(FixedGroup::AllProxies as u16)
     }
     /// Group address corresponding to all friends nodes.
     pub const fn all_friends() -> GroupAddress {
-        GroupAddress(0xFFFD)
+        GroupAddress(FixedGroup::AllFriends as u16)
     }
     /// Group address corresponding to all relay nodes.
     pub const fn all_relays() -> GroupAddress {
-        GroupAddress(0xFFE)
+        GroupAddress(FixedGroup::AllRelays as u16)
     }
     /// Group address corresponding to all nodes.
     pub const fn all_nodes() -> GroupAddress {
-        GroupAddress(0xFFFF)
+        GroupAddress(FixedGroup::AllNodes as u16)
+    }
+}
+/// Fixed Group Addresses. Messages sent to these are delivered based on the features a node has
+/// enabled (except for `AllNodes` which every node receives).
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[repr(u16)]
+pub enum FixedGroup {
+    AllProxies = 0xFFFC,
+    AllFriends = 0xFFFD,
+    AllRelays = 0xFFFE,
+    AllNodes = 0xFFFF,
+}
+impl FixedGroup {
+    #[must_use]
+    pub fn from_u16(v: u16) -> Option<FixedGroup> {
+        match v {
+            0xFFFC => Some(FixedGroup::AllProxies),
+            0xFFFD => Some(FixedGroup::AllFriends),
+            0xFFFE => Some(FixedGroup::AllRelays),
+            0xFFFF => Some(FixedGroup::AllNodes),
+            _ => None,
+        }
+    }
+}
+impl From<FixedGroup> for u16 {
+    #[must_use]
+    fn from(g: FixedGroup) -> Self {
+        g as u16
+    }
+}
+impl From<FixedGroup> for GroupAddress {
+    #[must_use]
+    fn from(g: FixedGroup) -> Self {
+        GroupAddress(g.into())
+    }
+}
+impl TryFrom<GroupAddress> for FixedGroup {
+    type Error = AddressError;
+
+    fn try_from(g: GroupAddress) -> Result<Self, Self::Error> {
+        g.fixed_group().ok_or(AddressError(()))
     }
 }
 const VIRTUAL_ADDRESS_HASH_MAX: u16 = (1_u16 << 14) - 1;
@@ -136,17 +193,15 @@ impl From<&UUID> for VirtualAddress {
     }
 }
 impl UnicastAddress {
-    /// Creates a new `UnicastAddress`.
-    /// # Panics
-    /// Panics if the `u16` is not a valid `UnicastAddress`. (Panics if `u16==0 || u16&UNICAST_BIT!=0`)
+    /// Creates a new `UnicastAddress`. Returns `None` if `v` is `0x0000` (unassigned) or has the
+    /// top bit set (group or virtual address).
     #[must_use]
-    pub fn new(v: u16) -> UnicastAddress {
-        assert!(
-            (v & UNICAST_BIT) == 0 || v == 0,
-            "non unicast address '{}'",
-            v
-        );
-        UnicastAddress(v)
+    pub fn new(v: u16) -> Option<UnicastAddress> {
+        if v == 0 || v & UNICAST_BIT != 0 {
+            None
+        } else {
+            Some(UnicastAddress(v))
+        }
     }
     /// Creates a Unicast address by masking any u16 into it.
     /// # Panics
@@ -163,13 +218,7 @@ impl TryFrom<u16> for UnicastAddress {
     type Error = AddressError;
 
     fn try_from(v: u16) -> Result<UnicastAddress, Self::Error> {
-        if v == 0 {
-            Err(AddressError(()))
-        } else if v & UNICAST_BIT == 0 {
-            Ok(UnicastAddress(v))
-        } else {
-            Err(AddressError(()))
-        }
+        UnicastAddress::new(v).ok_or(AddressError(()))
     }
 }
 
@@ -177,11 +226,7 @@ impl TryFrom<u16> for GroupAddress {
     type Error = AddressError;
 
     fn try_from(v: u16) -> Result<GroupAddress, Self::Error> {
-        if v & 0xC000 == 0xC000 {
-            Ok(GroupAddress(v))
-        } else {
-            Err(AddressError(()))
-        }
+        GroupAddress::new(v).ok_or(AddressError(()))
     }
 }
 
@@ -292,9 +337,33 @@ impl Address {
             _ => None,
         }
     }
+    /// The exact 16-bit value of the address as it appears on the wire. Full `Virtual` addresses
+    /// are reduced to their hash since the Label UUID is never sent over the air.
+    #[must_use]
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            Address::Unassigned => 0,
+            Address::Unicast(u) => u.0,
+            Address::Group(g) => g.0,
+            Address::Virtual(v) => (v.0).0,
+            Address::VirtualHash(h) => h.0,
+        }
+    }
     #[must_use]
     pub fn value(&self) -> u16 {
-        self.into()
+        self.to_u16()
+    }
+}
+
+impl core::fmt::Display for Address {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Address::Unassigned => write!(f, "unassigned"),
+            Address::Unicast(u) => write!(f, "unicast(0x{:04X})", u.0),
+            Address::Group(g) => write!(f, "group(0x{:04X})", g.0),
+            Address::Virtual(v) => write!(f, "virtual(hash=0x{:04X})", (v.0).0),
+            Address::VirtualHash(h) => write!(f, "virtual(hash=0x{:04X})", h.0),
+        }
     }
 }
 
@@ -305,17 +374,19 @@ impl Default for Address {
     }
 }
 
-impl From<u16> for Address {
-    #[must_use]
-    fn from(v: u16) -> Address {
+impl TryFrom<u16> for Address {
+    type Error = AddressError;
+
+    /// Returns `AddressError` for the RFU group addresses (`0xFF00-0xFFFB`).
+    fn try_from(v: u16) -> Result<Address, Self::Error> {
         if v == 0 {
-            Address::Unassigned
+            Ok(Address::Unassigned)
         } else if v & GROUP_BIT == 0 {
-            Address::Unicast(UnicastAddress(v))
+            Ok(Address::Unicast(UnicastAddress(v)))
         } else if v & GROUP_BIT == GROUP_BIT {
-            Address::Group(GroupAddress(v))
+            Ok(Address::Group(GroupAddress::try_from(v)?))
         } else {
-            Address::VirtualHash(VirtualAddressHash(v))
+            Ok(Address::VirtualHash(VirtualAddressHash(v)))
         }
     }
 }
@@ -323,13 +394,7 @@ impl From<u16> for Address {
 impl From<&Address> for u16 {
     #[must_use]
     fn from(v: &Address) -> Self {
-        match v {
-            Address::Unassigned => 0,
-            Address::Unicast(u) => u.0,
-            Address::Group(g) => g.0,
-            Address::Virtual(v) => (v.0).0,
-            Address::VirtualHash(vh) => vh.0,
-        }
+        v.to_u16()
     }
 }
 
@@ -378,34 +443,22 @@ impl ToFromBytesEndian for Address {
 
     #[must_use]
     fn to_bytes_le(&self) -> Self::AsBytesType {
-        match self {
-            Address::Unassigned => 0_u16.to_bytes_le(),
-            Address::Unicast(u) => (u.0).to_bytes_le(),
-            Address::Group(g) => (g.0).to_bytes_le(),
-            Address::Virtual(v) => ((v.0).0).to_bytes_le(),
-            Address::VirtualHash(h) => (h.0).to_bytes_le(),
-        }
+        self.to_u16().to_bytes_le()
     }
 
     #[must_use]
     fn to_bytes_be(&self) -> Self::AsBytesType {
-        match self {
-            Address::Unassigned => 0_u16.to_bytes_be(),
-            Address::Unicast(u) => (u.0).to_bytes_be(),
-            Address::Group(g) => (g.0).to_bytes_be(),
-            Address::Virtual(v) => ((v.0).0).to_bytes_be(),
-            Address::VirtualHash(h) => (h.0).to_bytes_be(),
-        }
+        self.to_u16().to_bytes_be()
     }
 
     #[must_use]
     fn from_bytes_le(bytes: &[u8]) -> Option<Self> {
-        Some(u16::from_bytes_le(bytes)?.into())
+        u16::from_bytes_le(bytes)?.try_into().ok()
     }
 
     #[must_use]
     fn from_bytes_be(bytes: &[u8]) -> Option<Self> {
-        Some(u16::from_bytes_be(bytes)?.into())
+        u16::from_bytes_be(bytes)?.try_into().ok()
     }
 }
 
@@ -424,12 +477,12 @@ impl ToFromBytesEndian for UnicastAddress {
 
     #[must_use]
     fn from_bytes_le(bytes: &[u8]) -> Option<Self> {
-        u16::from_bytes_le(bytes)?.try_into().ok()
+        UnicastAddress::new(u16::from_bytes_le(bytes)?)
     }
 
     #[must_use]
     fn from_bytes_be(bytes: &[u8]) -> Option<Self> {
-        u16::from_bytes_be(bytes)?.try_into().ok()
+        UnicastAddress::new(u16::from_bytes_be(bytes)?)
     }
 }
 
@@ -479,3 +532,89 @@ impl ToFromBytesEndian for GroupAddress {
         u16::from_bytes_be(bytes)?.try_into().ok()
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_display() {
+        assert_eq!(format!("{}", Address::Unassigned), "unassigned");
+        assert_eq!(
+            format!("{}", Address::Unicast(UnicastAddress(0x0001))),
+            "unicast(0x0001)"
+        );
+        assert_eq!(
+            format!("{}", Address::Group(GroupAddress(0xC000))),
+            "group(0xC000)"
+        );
+        assert_eq!(
+            format!("{}", Address::VirtualHash(VirtualAddressHash(0x8ABC))),
+            "virtual(hash=0x8ABC)"
+        );
+        let virtual_address = VirtualAddress::new(&UUID([0x11_u8; 16]));
+        assert_eq!(
+            format!("{}", Address::Virtual(virtual_address)),
+            format!("virtual(hash=0x{:04X})", virtual_address.hash().0)
+        );
+    }
+
+    #[test]
+    fn test_address_to_u16() {
+        assert_eq!(Address::Unassigned.to_u16(), 0x0000);
+        assert_eq!(Address::Unicast(UnicastAddress(0x0001)).to_u16(), 0x0001);
+        assert_eq!(Address::Unicast(UnicastAddress(0x7FFF)).to_u16(), 0x7FFF);
+        assert_eq!(Address::Group(GroupAddress(0xC000)).to_u16(), 0xC000);
+        assert_eq!(Address::Group(GroupAddress(0xFFFF)).to_u16(), 0xFFFF);
+        assert_eq!(
+            Address::VirtualHash(VirtualAddressHash(0x8ABC)).to_u16(),
+            0x8ABC
+        );
+        let virtual_address = VirtualAddress::new(&UUID([0x11_u8; 16]));
+        let hash = virtual_address.hash().0;
+        assert_eq!(hash & 0xC000, 0x8000);
+        assert_eq!(Address::Virtual(virtual_address).to_u16(), hash);
+        for &v in &[0x0000_u16, 0x0001, 0x7FFF, 0x8ABC, 0xC000, 0xFFFF] {
+            let address = Address::try_from(v).expect("valid address");
+            assert_eq!(address.to_u16(), v);
+            assert_eq!(address.to_bytes_be(), v.to_be_bytes());
+            assert_eq!(address.to_bytes_le(), v.to_le_bytes());
+        }
+        // RFU group addresses.
+        for &v in &[0xFF00_u16, 0xFFFB] {
+            assert_eq!(Address::try_from(v), Err(AddressError(())));
+            assert_eq!(Address::from_bytes_be(&v.to_be_bytes()), None);
+        }
+    }
+
+    #[test]
+    fn test_unicast_address_new() {
+        assert_eq!(UnicastAddress::new(0x0000), None);
+        assert_eq!(UnicastAddress::new(0x0001), Some(UnicastAddress(0x0001)));
+        assert_eq!(UnicastAddress::new(0x7FFF), Some(UnicastAddress(0x7FFF)));
+        assert_eq!(UnicastAddress::new(0x8000), None);
+        assert_eq!(UnicastAddress::from_bytes_be(&[0x00, 0x00]), None);
+        assert_eq!(UnicastAddress::from_bytes_be(&[0xC0, 0x01]), None);
+    }
+
+    #[test]
+    fn test_group_address_new() {
+        assert_eq!(GroupAddress::new(0xC000), Some(GroupAddress(0xC000)));
+        assert_eq!(GroupAddress::new(0xFEFF), Some(GroupAddress(0xFEFF)));
+        assert_eq!(GroupAddress::new(0xBFFF), None);
+        assert_eq!(GroupAddress::new(0xFF00), None);
+        assert_eq!(GroupAddress::new(0xFFFB), None);
+
+        let all_nodes = GroupAddress::new(0xFFFF).expect("all-nodes is a valid group");
+        assert_eq!(all_nodes, GroupAddress::all_nodes());
+        assert_eq!(all_nodes.fixed_group(), Some(FixedGroup::AllNodes));
+        assert_eq!(
+            GroupAddress::from(FixedGroup::AllRelays),
+            GroupAddress::all_relays()
+        );
+        assert_eq!(u16::from(GroupAddress::all_relays()), 0xFFFE);
+        assert_eq!(
+            FixedGroup::try_from(GroupAddress(0xC123)),
+            Err(AddressError(()))
+        );
+    }
+}
@@ -1,12 +1,19 @@
 //! Bluetooth Mesh Beacon Layer. Currently only supports `SecureNetworkBeacon`s and
 //! `UnprovisionedDeviceBeacon`s.
 use crate::bytes::ToFromBytesEndian;
-use crate::crypto::{s1, NetworkID};
-use crate::mesh::IVIndex;
+use crate::crypto::aes::AESCipher;
+use crate::crypto::key::BeaconKey;
+use crate::crypto::{s1, KeyRefreshPhases, NetworkID};
+use crate::device_state::DeviceState;
+use crate::foundation::state::SecureNetworkBeaconState;
+use crate::mesh::{IVIndex, IVUpdateFlag, KeyRefreshFlag};
 use crate::uuid::UUID;
+use alloc::vec::Vec;
 use btle::le::advertisement::AdType;
 use btle::{ConversionError, PackError};
 use core::convert::{TryFrom, TryInto};
+use core::time::Duration;
+use driver_async::time::{Instant, InstantTrait};
 
 pub trait Beacon: Sized {
     fn byte_len(&self) -> usize;
@@ -41,6 +48,7 @@ impl From<OOBFlags> for u16 {
 }
 
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Default, Debug)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct OOBInformation(pub u16);
 impl OOBInformation {
     pub fn set(mut self, flag: OOBFlags) -> Self {
@@ -135,26 +143,59 @@ impl Beacon for UnprovisionedDeviceBeacon {
         }
     }
 }
-const SECURE_NETWORK_FLAGS_MAX: u8 = 0x03;
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
-pub struct SecureNetworkFlags(u8);
-impl From<SecureNetworkFlags> for u8 {
-    fn from(f: SecureNetworkFlags) -> Self {
+const BEACON_FLAGS_MAX: u8 = 0x03;
+/// Secure Network Beacon flags byte. Bit 0 is the Key Refresh flag and bit 1 the IV Update flag
+/// (the other bits are RFU).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Default)]
+pub struct BeaconFlags(u8);
+impl From<BeaconFlags> for u8 {
+    fn from(f: BeaconFlags) -> Self {
         f.0
     }
 }
-impl TryFrom<u8> for SecureNetworkFlags {
+impl TryFrom<u8> for BeaconFlags {
     type Error = ConversionError;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if value <= SECURE_NETWORK_FLAGS_MAX {
-            Ok(SecureNetworkFlags(value))
+        if value <= BEACON_FLAGS_MAX {
+            Ok(BeaconFlags(value))
         } else {
             Err(ConversionError(()))
         }
     }
 }
-pub enum SecureNetworkFlag {
+impl BeaconFlags {
+    pub fn new(key_refresh: bool, iv_update: bool) -> BeaconFlags {
+        BeaconFlags(
+            u8::from(key_refresh) << (BeaconFlag::KeyRefresh as u8)
+                | u8::from(iv_update) << (BeaconFlag::IVUpdate as u8),
+        )
+    }
+    /// Flags a node beacons for a subnet in `key_refresh_phase`. The Key Refresh flag is only
+    /// set in the second phase (when the new key is used to transmit).
+    pub fn from_states(
+        key_refresh_phase: KeyRefreshPhases,
+        iv_update: IVUpdateFlag,
+    ) -> BeaconFlags {
+        Self::new(key_refresh_phase == KeyRefreshPhases::Second, iv_update.0)
+    }
+    fn get(self, flag: BeaconFlag) -> bool {
+        self.0 & (1_u8 << (flag as u8)) != 0
+    }
+    pub fn key_refresh(self) -> bool {
+        self.get(BeaconFlag::KeyRefresh)
+    }
+    pub fn iv_update(self) -> bool {
+        self.get(BeaconFlag::IVUpdate)
+    }
+    pub fn key_refresh_flag(self) -> KeyRefreshFlag {
+        KeyRefreshFlag(self.key_refresh())
+    }
+    pub fn iv_update_flag(self) -> IVUpdateFlag {
+        IVUpdateFlag(self.iv_update())
+    }
+}
+pub enum BeaconFlag {
     KeyRefresh = 0x00,
     IVUpdate = 0x01,
 }
@@ -163,10 +204,28 @@ pub const AUTHENTICATION_VALUE_LEN: usize = 8;
 pub struct AuthenticationValue(pub [u8; AUTHENTICATION_VALUE_LEN]);
 impl AuthenticationValue {
     pub const BYTE_LEN: usize = AUTHENTICATION_VALUE_LEN;
+    /// `AES-CMAC(beacon_key, flags || network_id || iv_index)[0..8]`
+    pub fn calculate(
+        beacon_key: &BeaconKey,
+        flags: BeaconFlags,
+        network_id: NetworkID,
+        iv_index: IVIndex,
+    ) -> AuthenticationValue {
+        let cmac = AESCipher::from(beacon_key.key()).cmac_slice(&[
+            &[flags.0],
+            &network_id.0.to_be_bytes(),
+            &iv_index.to_bytes_be(),
+        ]);
+        AuthenticationValue(
+            cmac.as_ref()[..AUTHENTICATION_VALUE_LEN]
+                .try_into()
+                .expect("cmac is 16 bytes"),
+        )
+    }
 }
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct SecureNetworkBeacon {
-    pub flags: SecureNetworkFlags,
+    pub flags: BeaconFlags,
     pub network_id: NetworkID,
     pub iv_index: IVIndex,
     pub authentication_value: AuthenticationValue,
@@ -175,9 +234,25 @@ impl SecureNetworkBeacon {
     pub const BEACON_TYPE: BeaconType = BeaconType::SecureNetwork;
     pub const BYTE_LEN: usize =
         1 + NetworkID::BYTE_LEN + IVIndex::BYTE_LEN + AuthenticationValue::BYTE_LEN;
+    /// Creates a new beacon authenticated with `beacon_key`.
+    pub fn new(
+        beacon_key: &BeaconKey,
+        flags: BeaconFlags,
+        network_id: NetworkID,
+        iv_index: IVIndex,
+    ) -> SecureNetworkBeacon {
+        SecureNetworkBeacon {
+            flags,
+            network_id,
+            iv_index,
+            authentication_value: AuthenticationValue::calculate(
+                beacon_key, flags, network_id, iv_index,
+            ),
+        }
+    }
     pub fn unpack_from(buf: &[u8]) -> Result<SecureNetworkBeacon, PackError> {
         PackError::expect_length(Self::BYTE_LEN, buf)?;
-        let flags = SecureNetworkFlags::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        let flags = BeaconFlags::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
         let network_id = NetworkID(u64::from_be_bytes(
             (&buf[1..1 + NetworkID::BYTE_LEN])
                 .try_into()
@@ -221,12 +296,50 @@ impl From<BeaconType> for u8 {
         b as u8
     }
 }
+impl TryFrom<u8> for BeaconType {
+    type Error = BeaconError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(BeaconType::Unprovisioned),
+            0x01 => Ok(BeaconType::SecureNetwork),
+            _ => Err(BeaconError::UnknownType(value)),
+        }
+    }
+}
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum BeaconError {
+    /// Beacon payload is empty (missing the beacon type byte).
+    Empty,
+    /// Beacon type byte isn't `Unprovisioned` (`0x00`) or `SecureNetwork` (`0x01`).
+    UnknownType(u8),
+    /// Beacon type is known but its payload is malformed.
+    PackError(PackError),
+}
+impl From<PackError> for BeaconError {
+    fn from(e: PackError) -> Self {
+        BeaconError::PackError(e)
+    }
+}
+impl btle::error::Error for BeaconError {}
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub enum BeaconPDU {
     Unprovisioned(UnprovisionedDeviceBeacon),
     SecureNetwork(SecureNetworkBeacon),
 }
 impl BeaconPDU {
+    /// Parses a Mesh Beacon AD payload, dispatching on the leading beacon type byte.
+    pub fn parse(buf: &[u8]) -> Result<Self, BeaconError> {
+        let (&beacon_type, payload) = buf.split_first().ok_or(BeaconError::Empty)?;
+        Ok(match BeaconType::try_from(beacon_type)? {
+            BeaconType::Unprovisioned => {
+                BeaconPDU::Unprovisioned(UnprovisionedDeviceBeacon::unpack_from(payload)?)
+            }
+            BeaconType::SecureNetwork => {
+                BeaconPDU::SecureNetwork(SecureNetworkBeacon::unpack_from(payload)?)
+            }
+        })
+    }
     pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
         match buf.get(0).ok_or(PackError::BadLength {
             expected: 1,
@@ -292,6 +405,53 @@ impl btle::le::advertisement::UnpackableAdStructType for BeaconPDU {
         Self::unpack_from(buf)
     }
 }
+/// Decides when this node sends its Secure Network Beacons. While the
+/// `SecureNetworkBeaconState` is `Broadcasting`, `poll` yields one beacon per subnet (with the
+/// current Key Refresh/IV Update flags and IV Index) every `BEACON_INTERVAL`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SecureBeaconScheduler {
+    last_sent: Option<Instant>,
+}
+impl SecureBeaconScheduler {
+    pub const BEACON_INTERVAL: Duration = Duration::from_secs(10);
+    pub fn new() -> SecureBeaconScheduler {
+        SecureBeaconScheduler { last_sent: None }
+    }
+    /// Returns the beacons due at `now`. Returns no beacons if the node isn't broadcasting or
+    /// beacons were already sent less than `BEACON_INTERVAL` ago.
+    pub fn poll(&mut self, now: Instant, device_state: &DeviceState) -> Vec<SecureNetworkBeacon> {
+        if device_state.config_states().secure_network_beacon_state
+            != SecureNetworkBeaconState::Broadcasting
+        {
+            self.last_sent = None;
+            return Vec::new();
+        }
+        if let Some(last_sent) = self.last_sent {
+            match now.checked_duration_since(last_sent) {
+                Some(elapsed) if elapsed >= Self::BEACON_INTERVAL => (),
+                _ => return Vec::new(),
+            }
+        }
+        self.last_sent = Some(now);
+        let iv_update = device_state.iv_update_flag();
+        let iv_index = device_state.iv_index();
+        device_state
+            .security_materials()
+            .net_key_map
+            .iter()
+            .map(|(_, phase)| {
+                let flags = BeaconFlags::from_states(phase.phase(), iv_update);
+                let materials = phase.tx_key();
+                SecureNetworkBeacon::new(
+                    materials.beacon_key(),
+                    flags,
+                    materials.network_id(),
+                    iv_index,
+                )
+            })
+            .collect()
+    }
+}
 pub struct PackedBeacon {}
 impl AsRef<[u8]> for PackedBeacon {
     fn as_ref(&self) -> &[u8] {
@@ -328,6 +488,123 @@ mod test {
         assert_eq!(buf, expected);
     }
     #[test]
+    pub fn test_secure_beacon_scheduler() {
+        use crate::address::UnicastAddress;
+        use crate::beacon::SecureBeaconScheduler;
+        use crate::crypto::key::{Key, NetKey};
+        use crate::device_state::DeviceState;
+        use crate::foundation::state::SecureNetworkBeaconState;
+        use crate::mesh::{ElementCount, KeyIndex, NetKeyIndex};
+        use core::str::FromStr;
+        use core::time::Duration;
+        use driver_async::time::{Instant, InstantTrait};
+
+        let mut state = DeviceState::new(
+            UnicastAddress::new(1).expect("valid unicast address"),
+            ElementCount(1),
+        );
+        let net_key =
+            NetKey::new(Key::from_str("7dd7364cd842ad18c17c2b820c84c3d6").expect("sample key"));
+        state
+            .security_materials_mut()
+            .net_key_map
+            .insert(NetKeyIndex(KeyIndex::new(0)), &net_key);
+        let mut scheduler = SecureBeaconScheduler::new();
+        let now = Instant::now();
+
+        state.config_states_mut().secure_network_beacon_state =
+            SecureNetworkBeaconState::NotBroadcasting;
+        assert!(scheduler.poll(now, &state).is_empty());
+
+        state.config_states_mut().secure_network_beacon_state =
+            SecureNetworkBeaconState::Broadcasting;
+        let beacons = scheduler.poll(now, &state);
+        assert_eq!(beacons.len(), 1);
+        assert_eq!(beacons[0].iv_index, state.iv_index());
+        assert_eq!(
+            state
+                .security_materials()
+                .net_key_map
+                .matching_network_id(beacons[0].network_id),
+            Some(NetKeyIndex(KeyIndex::new(0)))
+        );
+        // Not due again until the interval passes.
+        assert!(scheduler
+            .poll(now + Duration::from_secs(5), &state)
+            .is_empty());
+        assert_eq!(
+            scheduler
+                .poll(now + SecureBeaconScheduler::BEACON_INTERVAL, &state)
+                .len(),
+            1
+        );
+
+        state.config_states_mut().secure_network_beacon_state =
+            SecureNetworkBeaconState::NotBroadcasting;
+        assert!(scheduler
+            .poll(now + Duration::from_secs(30), &state)
+            .is_empty());
+    }
+    #[test]
+    pub fn test_parse_beacon() {
+        use crate::beacon::{BeaconError, BeaconPDU, SecureNetworkBeacon};
+        let unprovisioned: [u8; 1 + UnprovisionedDeviceBeacon::min_len()] =
+            mesh::bytes_str_to_buf("0070cf7c9732a345b691494810d2e9cbf4a040")
+                .expect("from spec 8.4.1");
+        let beacon = BeaconPDU::parse(&unprovisioned[..]).expect("unprovisioned beacon");
+        assert_eq!(
+            beacon.unprovisioned().map(|b| b.oob_information),
+            Some(OOBInformation(0xA040))
+        );
+
+        let secure: [u8; 1 + SecureNetworkBeacon::BYTE_LEN] =
+            mesh::bytes_str_to_buf("01003ecaff672f673370123456788ea261582f364f6f")
+                .expect("from spec 8.4.3");
+        match BeaconPDU::parse(&secure[..]) {
+            Ok(BeaconPDU::SecureNetwork(b)) => assert_eq!(b.iv_index, mesh::IVIndex(0x1234_5678)),
+            _ => panic!("expected a secure network beacon"),
+        }
+
+        assert_eq!(BeaconPDU::parse(&[]), Err(BeaconError::Empty));
+        assert_eq!(
+            BeaconPDU::parse(&[0x02, 0x00]),
+            Err(BeaconError::UnknownType(0x02))
+        );
+        assert!(matches!(
+            BeaconPDU::parse(&unprovisioned[..5]),
+            Err(BeaconError::PackError(_))
+        ));
+    }
+    #[test]
+    pub fn test_beacon_flags() {
+        use crate::beacon::BeaconFlags;
+        use crate::crypto::KeyRefreshPhases;
+        use crate::mesh::IVUpdateFlag;
+        use core::convert::TryFrom;
+        for &(key_refresh, iv_update, byte) in &[
+            (false, false, 0x00_u8),
+            (true, false, 0x01),
+            (false, true, 0x02),
+            (true, true, 0x03),
+        ] {
+            let flags = BeaconFlags::new(key_refresh, iv_update);
+            assert_eq!(u8::from(flags), byte);
+            let parsed = BeaconFlags::try_from(byte).expect("valid flags");
+            assert_eq!(parsed, flags);
+            assert_eq!(parsed.key_refresh(), key_refresh);
+            assert_eq!(parsed.iv_update(), iv_update);
+        }
+        assert!(BeaconFlags::try_from(0x04).is_err());
+        assert_eq!(
+            BeaconFlags::from_states(KeyRefreshPhases::First, IVUpdateFlag(true)),
+            BeaconFlags::new(false, true)
+        );
+        assert_eq!(
+            BeaconFlags::from_states(KeyRefreshPhases::Second, IVUpdateFlag(false)),
+            BeaconFlags::new(true, false)
+        );
+    }
+    #[test]
     pub fn test_unprovisioned_with_uri() {
         // 0x17 is uri::URIName::https.
         let uri = "\x17//www.example.com/mesh/products/light-switch-v3";
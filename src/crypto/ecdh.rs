@@ -42,6 +42,18 @@ impl PrivateKey {
             .map_err(|_| Error::KeyGenerationProblem)?,
         })
     }
+    /// Private key with the fixed big endian scalar `bytes` (ex. the provisioning sample data).
+    /// Only for tests since the whole key exchange becomes predictable.
+    #[cfg(any(test, feature = "deterministic_ecdh"))]
+    pub fn from_fixed_bytes(bytes: &[u8; 32]) -> Result<PrivateKey, Error> {
+        Ok(PrivateKey {
+            key: ring::agreement::EphemeralPrivateKey::generate(
+                &ring::agreement::ECDH_P256,
+                &ring::test::rand::FixedSliceRandom { bytes: &bytes[..] },
+            )
+            .map_err(|_| Error::KeyGenerationProblem)?,
+        })
+    }
     pub fn public_key(&self) -> Result<DerivedPublicKey, Error> {
         Ok(DerivedPublicKey {
             key: self
@@ -1,4 +1,5 @@
 //! Collection of security materials (Keys, NID, AID, etc) used for encryption and decryption.
+use crate::address::UnicastAddress;
 use crate::crypto::key::{
     AppKey, BeaconKey, DevKey, EncryptionKey, IdentityKey, NetKey, PrivacyKey,
 };
@@ -87,6 +88,27 @@ impl NetworkSecurityMaterials {
     pub fn beacon_key(&self) -> &BeaconKey {
         &self.beacon_key
     }
+    /// Friendship security credentials for the friendship between `lpn_addr` and `friend_addr`
+    /// (Mesh Profile 3.8.6.3.4). Messages sent from the Friend Queue to the Low Power Node (and
+    /// the LPN's messages to its Friend) are encrypted with these instead of `network_keys()`.
+    /// See `friend::CredentialSelector` for picking them on send and
+    /// `StackInternals::decrypt_network_pdu` for trying them on receive.
+    pub fn friendship_keys(
+        &self,
+        lpn_addr: UnicastAddress,
+        friend_addr: UnicastAddress,
+        lpn_counter: u16,
+        friend_counter: u16,
+    ) -> NetworkKeys {
+        // P = 0x01 || LPNAddress || FriendAddress || LPNCounter || FriendCounter
+        let mut p = [0x01_u8; 9];
+        p[1..3].copy_from_slice(&u16::from(lpn_addr).to_be_bytes());
+        p[3..5].copy_from_slice(&u16::from(friend_addr).to_be_bytes());
+        p[5..7].copy_from_slice(&lpn_counter.to_be_bytes());
+        p[7..9].copy_from_slice(&friend_counter.to_be_bytes());
+        let (nid, encryption, privacy) = k2(self.net_key.key(), p);
+        NetworkKeys::new(nid, encryption, privacy)
+    }
 }
 impl NetworkSecurityMaterials {}
 impl From<&NetKey> for NetworkSecurityMaterials {
@@ -169,6 +191,27 @@ impl NetKeyMap {
             temp: None,
         }
     }
+    /// Iterates over every `NetKeyIndex` (in ascending order).
+    pub fn net_indexes(&self) -> impl Iterator<Item = NetKeyIndex> + '_ {
+        self.map.keys().copied()
+    }
+    /// Iterates over every subnet's `NetKeyIndex` and keys (in ascending `NetKeyIndex` order).
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (NetKeyIndex, &'_ KeyPhase<NetworkSecurityMaterials>)> {
+        self.map.iter().map(|(&index, phase)| (index, phase))
+    }
+    /// Returns the `NetKeyIndex` of the subnet whose (old or new) `NetworkSecurityMaterials`
+    /// have a `NetworkID` matching `network_id`. Used to tie a Secure Network Beacon to a subnet.
+    pub fn matching_network_id(&self, network_id: NetworkID) -> Option<NetKeyIndex> {
+        self.map
+            .iter()
+            .find_map(|(&index, phase)| match phase.rx_keys() {
+                (first, _) if first.network_id == network_id => Some(index),
+                (_, Some(second)) if second.network_id == network_id => Some(index),
+                _ => None,
+            })
+    }
     pub fn get_keys(&self, index: NetKeyIndex) -> Option<&KeyPhase<NetworkSecurityMaterials>> {
         self.map.get(&index)
     }
@@ -191,6 +234,46 @@ impl NetKeyMap {
     ) -> Option<KeyPhase<NetworkSecurityMaterials>> {
         self.map.insert(index, KeyPhase::Normal(new_key.into()))
     }
+    /// Removes the subnet under `index` along with every application key bound to it
+    /// (Mesh Core Spec 4.4.1.2.8, NetKey Delete). Returns the removed keys if `index` existed.
+    pub fn remove(
+        &mut self,
+        index: NetKeyIndex,
+        app_key_map: &mut AppKeyMap,
+    ) -> Option<KeyPhase<NetworkSecurityMaterials>> {
+        let removed = self.map.remove(&index)?;
+        app_key_map
+            .map
+            .retain(|_, materials| materials.net_key_index != index);
+        app_key_map.revoke_old_keys(index);
+        Some(removed)
+    }
+    /// Starts a Key Refresh on the subnet under `index` by moving it to `Phase1` with `new_key`.
+    /// Updating again with the same key is allowed (the update message may be retransmitted).
+    pub fn update(&mut self, index: NetKeyIndex, new_key: &NetKey) -> Result<(), KeyUpdateError> {
+        let phase = self
+            .map
+            .get_mut(&index)
+            .ok_or(KeyUpdateError::InvalidIndex)?;
+        let next = match phase {
+            KeyPhase::Normal(old) => KeyPhase::Phase1(KeyPair {
+                new: new_key.into(),
+                old: *old,
+            }),
+            KeyPhase::Phase1(pair) if pair.new.net_key() == new_key => return Ok(()),
+            _ => return Err(KeyUpdateError::CannotUpdate),
+        };
+        *phase = next;
+        Ok(())
+    }
+}
+/// Returned when a `NetKeyMap` or `AppKeyMap` key can't be updated.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum KeyUpdateError {
+    /// No key exists under the given index.
+    InvalidIndex,
+    /// The key is in a Key Refresh phase that doesn't allow an update.
+    CannotUpdate,
 }
 pub struct NIDFilterMap<
     'a,
@@ -245,23 +328,93 @@ impl ApplicationSecurityMaterials {
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct AppKeyMap {
     pub map: btree_map::BTreeMap<AppKeyIndex, ApplicationSecurityMaterials>,
+    /// Application keys replaced by `update` that are still in use until the Key Refresh of
+    /// their subnet completes (see `revoke_old_keys`).
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    pub old_map: btree_map::BTreeMap<AppKeyIndex, ApplicationSecurityMaterials>,
 }
 impl AppKeyMap {
     pub fn new() -> Self {
         Self {
             map: btree_map::BTreeMap::new(),
+            old_map: btree_map::BTreeMap::new(),
         }
     }
 
+    /// Iterates over every `AppKeyIndex` (in ascending order).
+    pub fn app_indexes(&self) -> impl Iterator<Item = AppKeyIndex> + '_ {
+        self.map.keys().copied()
+    }
+    /// Iterates over every `AppKeyIndex` and key (in ascending `AppKeyIndex` order).
+    pub fn iter(&self) -> impl Iterator<Item = (AppKeyIndex, &'_ ApplicationSecurityMaterials)> {
+        self.map
+            .iter()
+            .map(|(&index, materials)| (index, materials))
+    }
+    /// Returns the application keys bound to the subnet `net_index` (in ascending `AppKeyIndex`
+    /// order).
+    pub fn app_keys_for_net(
+        &self,
+        net_index: NetKeyIndex,
+    ) -> impl Iterator<Item = (AppKeyIndex, &'_ ApplicationSecurityMaterials)> {
+        self.iter()
+            .filter(move |(_, materials)| materials.net_key_index == net_index)
+    }
     pub fn get_key(&self, index: AppKeyIndex) -> Option<&ApplicationSecurityMaterials> {
         self.map.get(&index)
     }
     pub fn get_key_mut(&mut self, index: AppKeyIndex) -> Option<&mut ApplicationSecurityMaterials> {
         self.map.get_mut(&index)
     }
+    /// Returns the old application key under `index` if a Key Refresh is in progress.
+    pub fn get_old_key(&self, index: AppKeyIndex) -> Option<&ApplicationSecurityMaterials> {
+        self.old_map.get(&index)
+    }
+    /// Returns the application key to transmit with under `index` while its subnet is in the
+    /// Key Refresh `phase`. The old key is used until the new one is in use (`Second` phase).
+    pub fn tx_key(
+        &self,
+        index: AppKeyIndex,
+        phase: KeyRefreshPhases,
+    ) -> Option<&ApplicationSecurityMaterials> {
+        match phase {
+            KeyRefreshPhases::First => self.get_old_key(index).or_else(|| self.get_key(index)),
+            _ => self.get_key(index),
+        }
+    }
+    /// Removes the application key under `index` (AppKey Delete), including any old key kept
+    /// for a Key Refresh.
     pub fn remove_key(&mut self, index: AppKeyIndex) -> Option<ApplicationSecurityMaterials> {
+        self.old_map.remove(&index);
         self.map.remove(&index)
     }
+    /// Updates the application key under `index` to `new_key` (AppKey Update), keeping its subnet
+    /// binding. The old key is kept (and still accepted on receive) until `revoke_old_keys` is
+    /// called for the subnet. Updating again with the same key is allowed (the update message may
+    /// be retransmitted).
+    pub fn update(&mut self, index: AppKeyIndex, new_key: AppKey) -> Result<(), KeyUpdateError> {
+        let materials = self
+            .map
+            .get_mut(&index)
+            .ok_or(KeyUpdateError::InvalidIndex)?;
+        if self.old_map.contains_key(&index) {
+            return if materials.app_key == new_key {
+                Ok(())
+            } else {
+                Err(KeyUpdateError::CannotUpdate)
+            };
+        }
+        let new = ApplicationSecurityMaterials::new(new_key, materials.net_key_index);
+        let old = core::mem::replace(materials, new);
+        self.old_map.insert(index, old);
+        Ok(())
+    }
+    /// Drops the old application keys bound to the subnet `net_index` once its Key Refresh
+    /// completes (`Third` phase).
+    pub fn revoke_old_keys(&mut self, net_index: NetKeyIndex) {
+        self.old_map
+            .retain(|_, materials| materials.net_key_index != net_index);
+    }
     pub fn insert(
         &mut self,
         net_key_index: NetKeyIndex,
@@ -282,13 +435,16 @@ impl AppKeyMap {
         &self,
         aid_to_match: AID,
     ) -> impl Iterator<Item = (AppKeyIndex, &'_ ApplicationSecurityMaterials)> {
-        self.map.iter().filter_map(move |(&index, materials)| {
-            if materials.aid == aid_to_match {
-                Some((index, materials))
-            } else {
-                None
-            }
-        })
+        self.map
+            .iter()
+            .chain(self.old_map.iter())
+            .filter_map(move |(&index, materials)| {
+                if materials.aid == aid_to_match {
+                    Some((index, materials))
+                } else {
+                    None
+                }
+            })
     }
 }
 
@@ -300,3 +456,161 @@ pub struct SecurityMaterials {
     pub net_key_map: NetKeyMap,
     pub app_key_map: AppKeyMap,
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key::Key;
+    use crate::mesh::KeyIndex;
+    use core::str::FromStr;
+
+    #[test]
+    fn test_friendship_keys() {
+        // Sample k2 friendship credentials: LPN 0x0203, Friend 0x0405, LPNCounter 0x0607 and
+        // FriendCounter 0x0809.
+        let materials = NetworkSecurityMaterials::from(
+            &NetKey::from_hex("f7a2a44f8e8a8029064f173ddc1e2b00").expect("sample key"),
+        );
+        let keys = materials.friendship_keys(
+            UnicastAddress::from_mask_u16(0x0203),
+            UnicastAddress::from_mask_u16(0x0405),
+            0x0607,
+            0x0809,
+        );
+        assert_eq!(keys.nid(), NID::new(0x73));
+        assert_eq!(
+            keys.encryption_key(),
+            &EncryptionKey::from_hex("11efec0642774992510fb5929646df49").expect("sample key")
+        );
+        assert_eq!(
+            keys.privacy_key(),
+            &PrivacyKey::from_hex("d4d7cc0dfa772d836a8df9df5510d7a7").expect("sample key")
+        );
+        assert_ne!(&keys, materials.network_keys());
+    }
+    fn app_key(s: &str) -> AppKey {
+        AppKey::new(Key::from_str(s).expect("sample key"))
+    }
+    #[test]
+    fn test_app_keys_for_net() {
+        let net_0 = NetKeyIndex(KeyIndex::new(0));
+        let net_1 = NetKeyIndex(KeyIndex::new(1));
+        let mut map = AppKeyMap::new();
+        map.insert(
+            net_0,
+            AppKeyIndex(KeyIndex::new(2)),
+            app_key("63964771734fbd76e3b40519d1d94a48"),
+        );
+        map.insert(
+            net_1,
+            AppKeyIndex(KeyIndex::new(1)),
+            app_key("7dd7364cd842ad18c17c2b820c84c3d6"),
+        );
+        map.insert(
+            net_0,
+            AppKeyIndex(KeyIndex::new(0)),
+            app_key("9d6dd0e96eb25dc19a40ed9914f8f03f"),
+        );
+        let net_0_apps: Vec<AppKeyIndex> = map.app_keys_for_net(net_0).map(|(i, _)| i).collect();
+        assert_eq!(
+            net_0_apps,
+            vec![AppKeyIndex(KeyIndex::new(0)), AppKeyIndex(KeyIndex::new(2))]
+        );
+        let net_1_apps: Vec<AppKeyIndex> = map.app_keys_for_net(net_1).map(|(i, _)| i).collect();
+        assert_eq!(net_1_apps, vec![AppKeyIndex(KeyIndex::new(1))]);
+        assert_eq!(map.app_indexes().count(), 3);
+    }
+    #[test]
+    fn test_net_key_remove_cascades() {
+        let net_0 = NetKeyIndex(KeyIndex::new(0));
+        let net_1 = NetKeyIndex(KeyIndex::new(1));
+        let mut net_map = NetKeyMap::new();
+        net_map.insert(
+            net_0,
+            &NetKey::new(Key::from_str("7dd7364cd842ad18c17c2b820c84c3d6").expect("sample key")),
+        );
+        net_map.insert(
+            net_1,
+            &NetKey::new(Key::from_str("63964771734fbd76e3b40519d1d94a48").expect("sample key")),
+        );
+        let mut app_map = AppKeyMap::new();
+        app_map.insert(
+            net_0,
+            AppKeyIndex(KeyIndex::new(0)),
+            app_key("9d6dd0e96eb25dc19a40ed9914f8f03f"),
+        );
+        app_map.insert(
+            net_1,
+            AppKeyIndex(KeyIndex::new(1)),
+            app_key("63964771734fbd76e3b40519d1d94a48"),
+        );
+        assert!(net_map.remove(net_0, &mut app_map).is_some());
+        assert!(net_map.get_keys(net_0).is_none());
+        assert!(app_map.get_key(AppKeyIndex(KeyIndex::new(0))).is_none());
+        assert!(app_map.get_key(AppKeyIndex(KeyIndex::new(1))).is_some());
+        assert!(net_map.remove(net_0, &mut app_map).is_none());
+    }
+    #[test]
+    fn test_net_key_update() {
+        let index = NetKeyIndex(KeyIndex::new(0));
+        let new_key =
+            NetKey::new(Key::from_str("63964771734fbd76e3b40519d1d94a48").expect("sample key"));
+        let mut net_map = NetKeyMap::new();
+        assert_eq!(
+            net_map.update(index, &new_key),
+            Err(KeyUpdateError::InvalidIndex)
+        );
+        net_map.insert(
+            index,
+            &NetKey::new(Key::from_str("7dd7364cd842ad18c17c2b820c84c3d6").expect("sample key")),
+        );
+        assert_eq!(net_map.update(index, &new_key), Ok(()));
+        assert_eq!(net_map.update(index, &new_key), Ok(()));
+        assert_eq!(
+            net_map.get_keys(index).map(KeyPhase::phase),
+            Some(KeyRefreshPhases::First)
+        );
+    }
+    #[test]
+    fn test_app_key_update_keeps_old_key() {
+        let net_0 = NetKeyIndex(KeyIndex::new(0));
+        let index = AppKeyIndex(KeyIndex::new(0));
+        let old_key = app_key("9d6dd0e96eb25dc19a40ed9914f8f03f");
+        let new_key = app_key("63964771734fbd76e3b40519d1d94a48");
+        let mut app_map = AppKeyMap::new();
+        assert_eq!(
+            app_map.update(index, new_key),
+            Err(KeyUpdateError::InvalidIndex)
+        );
+        app_map.insert(net_0, index, old_key);
+        assert_eq!(app_map.update(index, new_key), Ok(()));
+        assert_eq!(app_map.update(index, new_key), Ok(()));
+        assert_eq!(
+            app_map.update(index, app_key("7dd7364cd842ad18c17c2b820c84c3d6")),
+            Err(KeyUpdateError::CannotUpdate)
+        );
+        // Both keys are accepted on receive while the refresh is in progress.
+        assert_eq!(app_map.matching_aid(old_key.aid()).count(), 1);
+        assert_eq!(app_map.matching_aid(new_key.aid()).count(), 1);
+        assert_eq!(
+            app_map
+                .tx_key(index, KeyRefreshPhases::First)
+                .map(|m| m.app_key),
+            Some(old_key)
+        );
+        assert_eq!(
+            app_map
+                .tx_key(index, KeyRefreshPhases::Second)
+                .map(|m| m.app_key),
+            Some(new_key)
+        );
+        app_map.revoke_old_keys(net_0);
+        assert!(app_map.get_old_key(index).is_none());
+        assert_eq!(app_map.matching_aid(old_key.aid()).count(), 0);
+        assert_eq!(
+            app_map
+                .tx_key(index, KeyRefreshPhases::First)
+                .map(|m| m.app_key),
+            Some(new_key)
+        );
+    }
+}
@@ -57,6 +57,15 @@ impl MIC {
             _ => None,
         }
     }
+    /// Splits the trailing big (8 byte) or small (4 byte) big endian `MIC` off `buf`. Returns
+    /// the data before the `MIC` and the `MIC` or `None` if `buf` is too short to hold the `MIC`.
+    #[must_use]
+    pub fn split_from(buf: &[u8], is_big: bool) -> Option<(&[u8], MIC)> {
+        let mic_size = if is_big { BIG_MIC_SIZE } else { SMALL_MIC_SIZE };
+        let data_len = buf.len().checked_sub(mic_size)?;
+        let (data, mic) = buf.split_at(data_len);
+        Some((data, MIC::try_from_bytes_be(mic)?))
+    }
     #[must_use]
     pub fn mic(&self) -> u64 {
         match self {
@@ -306,3 +315,26 @@ use crate::provisioning::confirmation::ConfirmationSalt;
 use core::fmt::{Display, Error, Formatter};
 pub use k_funcs::{k1, k2, k3, k4, s1};
 use std::convert::TryInto;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mic_split_from() {
+        let buf = [1_u8, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(
+            MIC::split_from(&buf, false),
+            Some((&buf[..6], MIC::Small(0x0708_090A)))
+        );
+        assert_eq!(
+            MIC::split_from(&buf, true),
+            Some((&buf[..2], MIC::Big(0x0304_0506_0708_090A)))
+        );
+        assert_eq!(
+            MIC::split_from(&buf[..4], false),
+            Some((&[][..], MIC::Small(0x0102_0304)))
+        );
+        assert_eq!(MIC::split_from(&buf[..3], false), None);
+        assert_eq!(MIC::split_from(&buf[..7], true), None);
+    }
+}
@@ -1,43 +1,222 @@
 //! Device State Manager used to storing device state and having an config client control it.
 use crate::access::ModelIdentifier;
-use crate::address::UnicastAddress;
+use crate::address::{FixedGroup, UnicastAddress};
+use crate::beacon::{OOBInformation, SecureNetworkBeacon, UnprovisionedDeviceBeacon};
 use crate::crypto::key::DevKey;
 use crate::crypto::materials::{AppKeyMap, NetKeyMap, SecurityMaterials};
+use crate::foundation::element::{ElementComposition, ElementsComposition, Location};
 use crate::foundation::publication::ModelPublishInfo;
 use crate::foundation::state::{
-    DefaultTTLState, GATTProxyState, NetworkTransmit, RelayState, SecureNetworkBeaconState,
+    DefaultTTLState, FriendState, GATTProxyState, NetworkTransmit, RelayRetransmit, RelayState,
+    SecureNetworkBeaconState,
 };
+use crate::foundation::{CompositionDataPage0, Features, ProductID, VersionID, CRPL};
 use crate::mesh::{
-    AppKeyIndex, ElementCount, ElementIndex, IVIndex, IVUpdateFlag, SequenceNumber, IVI, TTL, U24,
+    AppKeyIndex, CompanyID, ElementCount, ElementIndex, IVIndex, IVUpdateFlag, NetKeyIndex,
+    SequenceNumber, IVI, TTL, U24,
 };
 use crate::random::Randomizable;
+use crate::uuid::UUID;
 
 use crate::lower::SegO;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 use core::convert::TryFrom;
-use core::ops::Range;
+use core::ops::{Range, RangeInclusive};
 use core::sync::atomic::Ordering;
 
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModelInfo {
     pub publish: Option<ModelPublishInfo>,
     pub app_key: Vec<AppKeyIndex>,
 }
+impl ModelInfo {
+    /// Applies the publication from a Config Model Publication Set. An `Unassigned` publish
+    /// address clears (disables) publication.
+    pub fn set_publication(&mut self, publication: ModelPublishInfo) {
+        self.publish = if publication.is_enabled() {
+            Some(publication)
+        } else {
+            None
+        };
+    }
+}
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
-pub struct Models(BTreeMap<ModelIdentifier, ModelInfo>);
+pub struct Models(BTreeMap<(ElementIndex, ModelIdentifier), ModelInfo>);
+impl Models {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, element_index: ElementIndex, model: ModelIdentifier) -> Option<&ModelInfo> {
+        self.0.get(&(element_index, model))
+    }
+    /// Publication of `model` on `element_index`. `None` if it doesn't publish.
+    pub fn publication(
+        &self,
+        element_index: ElementIndex,
+        model: ModelIdentifier,
+    ) -> Option<&ModelPublishInfo> {
+        self.get(element_index, model)?.publish.as_ref()
+    }
+    /// Applies the publication from a Config Model Publication Set to `model` on
+    /// `element_index` (see `ModelInfo::set_publication`).
+    pub fn set_publication(
+        &mut self,
+        element_index: ElementIndex,
+        model: ModelIdentifier,
+        publication: ModelPublishInfo,
+    ) {
+        self.0
+            .entry((element_index, model))
+            .or_default()
+            .set_publication(publication)
+    }
+}
 
+/// A node provisioned by this device (when acting as a provisioner).
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeInfo {
+    pub element_count: ElementCount,
+    pub net_keys: Vec<NetKeyIndex>,
+    /// Device key from provisioning. Needed to configure the node. `None` for nodes saved before
+    /// device keys were recorded.
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    pub dev_key: Option<DevKey>,
+}
+/// Nodes provisioned by this device keyed by their primary `UnicastAddress`.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeList(BTreeMap<UnicastAddress, NodeInfo>);
+impl NodeList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Inserts the node with the `primary_address`, returning the old `NodeInfo` if one existed.
+    pub fn insert(&mut self, primary_address: UnicastAddress, node: NodeInfo) -> Option<NodeInfo> {
+        self.0.insert(primary_address, node)
+    }
+    pub fn remove(&mut self, primary_address: UnicastAddress) -> Option<NodeInfo> {
+        self.0.remove(&primary_address)
+    }
+    pub fn get(&self, primary_address: UnicastAddress) -> Option<&NodeInfo> {
+        self.0.get(&primary_address)
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Returns the unicast address range `[primary_address..primary_address+element_count)` of
+    /// the node with the `primary_address`.
+    pub fn unicast_range(&self, primary_address: UnicastAddress) -> Option<Range<u16>> {
+        let node = self.get(primary_address)?;
+        let start = u16::from(primary_address);
+        Some(start..start + u16::from(node.element_count.0))
+    }
+    /// Returns the `(primary_address, node)` of the node that has an element with `address`.
+    pub fn node_with_element(
+        &self,
+        address: UnicastAddress,
+    ) -> Option<(UnicastAddress, &NodeInfo)> {
+        let (&primary_address, node) = self.0.range(..=address).next_back()?;
+        if u16::from(address) - u16::from(primary_address) < u16::from(node.element_count.0) {
+            Some((primary_address, node))
+        } else {
+            None
+        }
+    }
+    /// Device key of the node that has an element with `address`.
+    pub fn device_key(&self, address: UnicastAddress) -> Option<&DevKey> {
+        self.node_with_element(address)
+            .and_then(|(_, node)| node.dev_key.as_ref())
+    }
+    /// Iterates over `(primary_address, node)` ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = (UnicastAddress, &NodeInfo)> {
+        self.0.iter().map(|(&address, node)| (address, node))
+    }
+}
+/// Models registered on each of the node's elements (primary element first). Used to generate
+/// the Composition Data so it always matches the models actually running.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct Elements(Vec<ElementComposition>);
+impl Elements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a new element with no models and returns its index.
+    /// # Panics
+    /// Panics if there are already 255 elements.
+    pub fn push_element(&mut self, location: Location) -> ElementIndex {
+        let index = u8::try_from(self.0.len()).expect("too many elements");
+        self.0.push(ElementComposition::new_empty(location));
+        ElementIndex(index)
+    }
+    pub fn element_count(&self) -> ElementCount {
+        ElementCount(u8::try_from(self.0.len()).expect("too many elements"))
+    }
+    pub fn element(&self, element_index: ElementIndex) -> Option<&ElementComposition> {
+        self.0.get(usize::from(element_index.0))
+    }
+    /// Registers `model` on the element. Returns `false` if the element already has the model.
+    /// # Panics
+    /// Panics if the element doesn't exist or already has 255 SIG or vendor models.
+    pub fn register_model(&mut self, element_index: ElementIndex, model: ModelIdentifier) -> bool {
+        let element = &mut self.0[usize::from(element_index.0)];
+        if element.sig_models.contains(&model) || element.vendor_models.contains(&model) {
+            false
+        } else {
+            element.add_model(model);
+            true
+        }
+    }
+    /// Builds Composition Data Page 0 from the registered elements and models.
+    pub fn to_composition_page0(
+        &self,
+        cid: CompanyID,
+        pid: ProductID,
+        vid: VersionID,
+        crpl: CRPL,
+        features: Features,
+    ) -> CompositionDataPage0 {
+        CompositionDataPage0::new(
+            cid,
+            pid,
+            vid,
+            crpl,
+            features,
+            ElementsComposition::new(self.0.clone()),
+        )
+    }
+}
 #[derive(Default, Debug)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConfigStates {
     pub relay_state: RelayState,
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    pub relay_retransmit: RelayRetransmit,
     pub gatt_proxy_state: GATTProxyState,
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    pub friend_state: FriendState,
     pub secure_network_beacon_state: SecureNetworkBeaconState,
     pub default_ttl: DefaultTTLState,
     pub network_transmit: NetworkTransmit,
 }
+impl ConfigStates {
+    /// Returns if messages sent to the `fixed_group` address are for this node. All-nodes is
+    /// always accepted while the others depend on the matching feature being enabled.
+    pub fn accepts_fixed_group(&self, fixed_group: FixedGroup) -> bool {
+        match fixed_group {
+            FixedGroup::AllNodes => true,
+            FixedGroup::AllRelays => self.relay_state.is_enabled(),
+            FixedGroup::AllFriends => self.friend_state.is_enabled(),
+            FixedGroup::AllProxies => self.gatt_proxy_state.is_enabled(),
+        }
+    }
+}
 
 /// Contains all the persistant Bluetooth Mesh device data. This struct needs to be serialized/saved
 /// somehow when the program shuts down or you will lose all your crypto keys. Normal operations
@@ -54,20 +233,165 @@ pub struct DeviceState {
     config_states: ConfigStates,
 
     security_materials: SecurityMaterials,
+
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    nodes: NodeList,
+
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    uuid: Option<UUID>,
+    #[cfg_attr(feature = "serde-1", serde(default))]
+    oob_information: OOBInformation,
+
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    subnet_beacons: SubnetBeacons,
+}
+/// The furthest an observed `IVIndex` may be ahead of ours and still be accepted (Mesh Core
+/// Spec 3.10.5).
+pub const IV_INDEX_MAX_JUMP: u32 = 42;
+/// IV Index state last observed from a subnet's Secure Network Beacon.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ObservedBeacon {
+    pub iv_index: IVIndex,
+    pub iv_update_flag: IVUpdateFlag,
+}
+impl ObservedBeacon {
+    pub fn from_beacon(beacon: &SecureNetworkBeacon) -> Self {
+        Self {
+            iv_index: beacon.iv_index,
+            iv_update_flag: beacon.flags.iv_update_flag(),
+        }
+    }
+    /// Orders IV Index states by how far along the IV Update procedure they are.
+    /// `(n, Normal) < (n+1, In Progress) < (n+1, Normal)`.
+    pub fn progress(&self) -> u64 {
+        u64::from(self.iv_index.0) * 2 + 1 - u64::from(bool::from(self.iv_update_flag))
+    }
+}
+/// The IV Index is global to the node (shared by every subnet) but each subnet beacons
+/// independently. `SubnetBeacons` remembers the last beacon seen on each subnet so IV Update
+/// transitions can be driven by the most advanced subnet.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct SubnetBeacons(BTreeMap<NetKeyIndex, ObservedBeacon>);
+impl SubnetBeacons {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, net_key_index: NetKeyIndex) -> Option<&ObservedBeacon> {
+        self.0.get(&net_key_index)
+    }
+    /// Records `observed` for the subnet and returns the previous observation (if any).
+    pub fn insert(
+        &mut self,
+        net_key_index: NetKeyIndex,
+        observed: ObservedBeacon,
+    ) -> Option<ObservedBeacon> {
+        self.0.insert(net_key_index, observed)
+    }
+    pub fn remove(&mut self, net_key_index: NetKeyIndex) -> Option<ObservedBeacon> {
+        self.0.remove(&net_key_index)
+    }
+    /// Returns the subnet observation furthest along the IV Update procedure.
+    pub fn most_advanced(&self) -> Option<(NetKeyIndex, &ObservedBeacon)> {
+        self.0
+            .iter()
+            .max_by_key(|(_, observed)| observed.progress())
+            .map(|(&index, observed)| (index, observed))
+    }
 }
 
+/// An inconsistency found in a `DeviceState` by [`DeviceState::verify`]. Usually caused by a
+/// corrupted or hand edited saved state.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum StateProblem {
+    /// The AppKey is bound to a NetKey that doesn't exist.
+    MissingNetKey {
+        app_key_index: AppKeyIndex,
+        net_key_index: NetKeyIndex,
+    },
+    /// The unicast address is assigned to more than one element (this node's or a provisioned
+    /// node's).
+    DuplicateElementAddress(UnicastAddress),
+    /// There isn't exactly one `SeqCounter` per element.
+    SeqCounterCount {
+        element_count: ElementCount,
+        seq_counters: usize,
+    },
+    /// The element's `SeqCounter` has run out of Sequence Numbers.
+    SeqExhausted(ElementIndex),
+}
+impl core::fmt::Display for StateProblem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StateProblem::MissingNetKey {
+                app_key_index,
+                net_key_index,
+            } => write!(
+                f,
+                "app key {} references missing net key {}",
+                u16::from(app_key_index.0),
+                u16::from(net_key_index.0)
+            ),
+            StateProblem::DuplicateElementAddress(address) => {
+                write!(f, "duplicate element address 0x{:04X}", u16::from(*address))
+            }
+            StateProblem::SeqCounterCount {
+                element_count,
+                seq_counters,
+            } => write!(
+                f,
+                "{} seq counters for {} elements",
+                seq_counters, element_count.0
+            ),
+            StateProblem::SeqExhausted(element_index) => {
+                write!(f, "element {} seq counter exhausted", element_index.0)
+            }
+        }
+    }
+}
+/// Returned by [`DeviceState::try_new`] when the node's elements can't be addressed.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum ElementCountError {
+    /// A node has at least the primary element.
+    Zero,
+    /// The last element's address (`primary_address + element_count - 1`) isn't unicast.
+    NotUnicast,
+}
+impl core::fmt::Display for ElementCountError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ElementCountError::Zero => f.write_str("zero element count"),
+            ElementCountError::NotUnicast => {
+                f.write_str("primary address + element count crosses out of the unicast range")
+            }
+        }
+    }
+}
 impl DeviceState {
     /// Generates a new `DeviceState`. `SecurityMaterials` will be new random keys.
     /// # Panics
-    /// Panics if `element_count == 0 || (primary_address + element_count).is_not_unicast()`
+    /// Panics if `element_count == 0` or the last element address isn't unicast. See
+    /// [`DeviceState::try_new`].
     pub fn new(primary_address: UnicastAddress, element_count: ElementCount) -> Self {
-        assert_ne!(element_count.0, 0, "zero element_count given");
-        assert!(
-            UnicastAddress::try_from(u16::from(primary_address) + u16::from(element_count.0))
-                .is_ok(),
-            "primary_address + element_count is non-unicast"
-        );
-        Self {
+        match Self::try_new(primary_address, element_count) {
+            Ok(state) => state,
+            Err(e) => panic!("invalid element count: {}", e),
+        }
+    }
+    /// Generates a new `DeviceState` like [`DeviceState::new`]. Returns an error if
+    /// `element_count` is 0 or the element addresses (`primary_address` up to
+    /// `primary_address + element_count - 1`) cross into the group address space.
+    pub fn try_new(
+        primary_address: UnicastAddress,
+        element_count: ElementCount,
+    ) -> Result<Self, ElementCountError> {
+        if element_count.0 == 0 {
+            return Err(ElementCountError::Zero);
+        }
+        let last_address = u16::from(primary_address) + u16::from(element_count.0) - 1;
+        if UnicastAddress::try_from(last_address).is_err() {
+            return Err(ElementCountError::NotUnicast);
+        }
+        Ok(Self {
             element_count,
             element_address: primary_address,
             seq_counters: core::iter::repeat(SeqCounter::default())
@@ -83,16 +407,64 @@ impl DeviceState {
                 net_key_map: NetKeyMap::new(),
                 app_key_map: AppKeyMap::new(),
             },
-        }
+            nodes: NodeList::new(),
+            uuid: None,
+            oob_information: OOBInformation::default(),
+            subnet_beacons: SubnetBeacons::new(),
+        })
     }
-    /// Returns the assigned unicast address range.
-    pub fn unicast_range(&self) -> Range<UnicastAddress> {
-        Range {
-            start: self.element_address,
-            end: UnicastAddress::new(
-                u16::from(self.element_address) + u16::from(self.element_count.0),
-            ),
+    /// Checks the state for inconsistencies (AppKeys bound to missing NetKeys, element addresses
+    /// assigned twice and out of range `SeqCounter`s). Returns every problem found.
+    pub fn verify(&self) -> Vec<StateProblem> {
+        let mut problems = Vec::new();
+        let net_key_map = &self.security_materials.net_key_map;
+        for (app_key_index, materials) in self.security_materials.app_key_map.iter() {
+            if net_key_map.get_keys(materials.net_key_index).is_none() {
+                problems.push(StateProblem::MissingNetKey {
+                    app_key_index,
+                    net_key_index: materials.net_key_index,
+                });
+            }
         }
+        let mut seen = BTreeSet::new();
+        let mut duplicates = BTreeSet::new();
+        let ranges = core::iter::once((self.element_address, self.element_count)).chain(
+            self.nodes
+                .iter()
+                .map(|(address, node)| (address, node.element_count)),
+        );
+        for (primary_address, element_count) in ranges {
+            for offset in 0..u16::from(element_count.0) {
+                if let Some(address) = UnicastAddress::new(u16::from(primary_address) + offset) {
+                    if !seen.insert(address) {
+                        duplicates.insert(address);
+                    }
+                }
+            }
+        }
+        problems.extend(
+            duplicates
+                .into_iter()
+                .map(StateProblem::DuplicateElementAddress),
+        );
+        if self.seq_counters.len() != usize::from(self.element_count.0) {
+            problems.push(StateProblem::SeqCounterCount {
+                element_count: self.element_count,
+                seq_counters: self.seq_counters.len(),
+            });
+        }
+        for (index, counter) in self.seq_counters.iter().enumerate() {
+            if counter.next.load(Ordering::SeqCst) >= U24::max_value().value() {
+                problems.push(StateProblem::SeqExhausted(ElementIndex(index as u8)));
+            }
+        }
+        problems
+    }
+    /// Returns the assigned unicast address range (primary address to last element address,
+    /// inclusive). Inclusive because the last element can be `0x7FFF`.
+    pub fn unicast_range(&self) -> RangeInclusive<UnicastAddress> {
+        let last = u16::from(self.element_address) + u16::from(self.element_count.0) - 1;
+        self.element_address..=UnicastAddress::new(last).expect("checked in DeviceState::new")
     }
     /// Returns the numbers of elements.
     pub fn element_count(&self) -> ElementCount {
@@ -112,26 +484,31 @@ impl DeviceState {
     /// and its primary unicast address is `0x0002`, then it owns the range `[0x0002..0x0007]`.
     /// If `unicast_address` is not in that range, this returns `None`.
     pub fn element_index(&self, unicast_address: UnicastAddress) -> Option<ElementIndex> {
-        let range = self.unicast_range();
-        if range.contains(&unicast_address) {
-            Some(ElementIndex(
-                u8::try_from(u16::from(range.start) - u16::from(unicast_address))
-                    .expect("too many elements"),
-            ))
-        } else {
-            None
-        }
+        self.unicast_addresses()
+            .position(|address| address == unicast_address)
+            .map(|index| ElementIndex(u8::try_from(index).expect("too many elements")))
+    }
+    /// Every unicast address owned by this node (one per element) in element order, starting
+    /// with the primary address.
+    pub fn unicast_addresses(&self) -> impl Iterator<Item = UnicastAddress> {
+        let primary = u16::from(self.element_address);
+        (primary..primary + u16::from(self.element_count.0)).map(UnicastAddress::from_mask_u16)
     }
     /// IVIndex used for transmitting.
     pub fn tx_iv_index(&self) -> IVIndex {
         self.security_materials.iv_index
     }
-    /// IVIndex used for receiving. Will return `None` if no matching `IVIndex` can be found.
-    /// See [`IVIndex::matching_flags`] for more.
+    /// IVIndex used for receiving a PDU with `ivi`. A matching IVI means the current `IVIndex`,
+    /// otherwise the PDU is from the previous one. The stored `IVIndex` is already incremented
+    /// while an IV Update is in progress, so the `IVUpdateFlag` never makes the next `IVIndex`
+    /// valid. Returns `None` (instead of underflowing) for a mismatched IVI at `IVIndex(0)`.
     pub fn rx_iv_index(&self, ivi: IVI) -> Option<IVIndex> {
-        self.security_materials
-            .iv_index
-            .matching_flags(ivi, self.security_materials.iv_update_flag)
+        let current = self.security_materials.iv_index;
+        if current.ivi() == ivi {
+            Some(current)
+        } else {
+            current.prev()
+        }
     }
     pub fn iv_index(&self) -> IVIndex {
         self.security_materials.iv_index
@@ -145,6 +522,49 @@ impl DeviceState {
     pub fn iv_update_flag_mut(&mut self) -> &mut IVUpdateFlag {
         &mut self.security_materials.iv_update_flag
     }
+    /// Last Secure Network Beacon state seen on each subnet.
+    pub fn subnet_beacons(&self) -> &SubnetBeacons {
+        &self.subnet_beacons
+    }
+    /// Records a (already authenticated) Secure Network Beacon against the subnet with the
+    /// matching `NetworkID`. If the most advanced subnet is now ahead of the node's shared
+    /// `IVIndex`/`IVUpdateFlag` (by no more than `IV_INDEX_MAX_JUMP`), the node adopts it.
+    /// Returns the `NetKeyIndex` of the beacon's subnet or `None` if no subnet matches.
+    pub fn observe_beacon(&mut self, beacon: &SecureNetworkBeacon) -> Option<NetKeyIndex> {
+        let net_key_index = self
+            .security_materials
+            .net_key_map
+            .matching_network_id(beacon.network_id)?;
+        self.subnet_beacons
+            .insert(net_key_index, ObservedBeacon::from_beacon(beacon));
+        if let Some((_, &advanced)) = self.subnet_beacons.most_advanced() {
+            self.observe_beacon_iv(advanced.iv_index, advanced.iv_update_flag);
+        }
+        Some(net_key_index)
+    }
+    /// Applies the IV Index acceptance rules to an `IVIndex`/`IVUpdateFlag` pair from an
+    /// authenticated beacon. The node adopts it (and returns `true`) only if it's further along
+    /// the IV Update procedure than the current state and no more than `IV_INDEX_MAX_JUMP` ahead.
+    /// Older (behind) states are ignored.
+    pub fn observe_beacon_iv(&mut self, iv_index: IVIndex, update_flag: IVUpdateFlag) -> bool {
+        let current = ObservedBeacon {
+            iv_index: self.iv_index(),
+            iv_update_flag: self.iv_update_flag(),
+        };
+        let observed = ObservedBeacon {
+            iv_index,
+            iv_update_flag: update_flag,
+        };
+        if observed.progress() > current.progress()
+            && iv_index.0 - current.iv_index.0 <= IV_INDEX_MAX_JUMP
+        {
+            self.security_materials.iv_index = iv_index;
+            self.security_materials.iv_update_flag = update_flag;
+            true
+        } else {
+            false
+        }
+    }
     /// The security materials that contains all the required crypto materials for encrypting and
     /// decrypting messages/PDU. Normal operation only requires an immutable reference.
     pub fn security_materials(&self) -> &SecurityMaterials {
@@ -176,12 +596,49 @@ impl DeviceState {
     pub fn config_states(&self) -> &ConfigStates {
         &self.config_states
     }
+    /// Publication and AppKey bindings of the node's models.
+    pub fn models(&self) -> &Models {
+        &self.models
+    }
+    pub fn models_mut(&mut self) -> &mut Models {
+        &mut self.models
+    }
     pub fn config_states_mut(&mut self) -> &mut ConfigStates {
         &mut self.config_states
     }
+    /// Nodes provisioned by this device.
+    pub fn nodes(&self) -> &NodeList {
+        &self.nodes
+    }
+    pub fn nodes_mut(&mut self) -> &mut NodeList {
+        &mut self.nodes
+    }
     pub fn default_ttl(&self) -> TTL {
         TTL::new(self.config_states.default_ttl.into())
     }
+    /// Device UUID advertised while unprovisioned. `None` until one is set.
+    pub fn uuid(&self) -> Option<UUID> {
+        self.uuid
+    }
+    pub fn set_uuid(&mut self, uuid: UUID) {
+        self.uuid = Some(uuid)
+    }
+    /// Where the provisioner can find this device's OOB data (advertised while unprovisioned).
+    pub fn oob_information(&self) -> OOBInformation {
+        self.oob_information
+    }
+    pub fn oob_information_mut(&mut self) -> &mut OOBInformation {
+        &mut self.oob_information
+    }
+    /// Unprovisioned Device Beacon for the configured UUID and OOB information. `None` if no
+    /// UUID is set.
+    pub fn unprovisioned_beacon(&self) -> Option<UnprovisionedDeviceBeacon> {
+        Some(UnprovisionedDeviceBeacon {
+            uuid: self.uuid?,
+            oob_information: self.oob_information,
+            uri_hash: None,
+        })
+    }
 }
 
 #[derive(Default)]
@@ -199,7 +656,7 @@ impl DeviceStateBuilder {
     }
     pub fn new_provisioner() -> Self {
         Self {
-            element_address: Some(UnicastAddress::new(1)),
+            element_address: UnicastAddress::new(1),
             element_count: Some(ElementCount(1)),
             seq_counters: Some(vec![SeqCounter::default()]),
             models: None,
@@ -227,6 +684,10 @@ impl DeviceStateBuilder {
             models: self.models?,
             config_states: self.config_states?,
             security_materials: self.security_materials?,
+            nodes: NodeList::new(),
+            uuid: None,
+            oob_information: OOBInformation::default(),
+            subnet_beacons: SubnetBeacons::new(),
         })
     }
 }
@@ -282,46 +743,108 @@ impl Iterator for SeqRange {
 /// Atomic SeqCounter so no PDUs get the same SeqNumber. Sequence Numbers are a finite resource
 /// (only 24-bits) that only get reset every IVIndex update. Also segmented PDUs require sequential
 /// Sequence Number.
+///
+/// To avoid persisting the counter after every message, applications can reserve blocks of
+/// Sequence Numbers with `allocate_block` and only persist `persisted_value()` (the top of the
+/// block). Once a block has been allocated, `inc_seq` only issues Sequence Numbers from inside the
+/// reserved block and returns `None` when a new block is needed. After a reboot,
+/// `SeqCounter::restore` resumes from the persisted value so no Sequence Number is ever reused.
 #[derive(Default, Debug)]
-pub struct SeqCounter(core::sync::atomic::AtomicU32);
+pub struct SeqCounter {
+    next: core::sync::atomic::AtomicU32,
+    reserved_top: core::sync::atomic::AtomicU32,
+    uses_blocks: core::sync::atomic::AtomicBool,
+}
 impl SeqCounter {
     pub fn new(start_seq: SequenceNumber) -> Self {
-        Self(core::sync::atomic::AtomicU32::new(start_seq.0.value()))
+        Self {
+            next: core::sync::atomic::AtomicU32::new(start_seq.0.value()),
+            reserved_top: core::sync::atomic::AtomicU32::new(start_seq.0.value()),
+            uses_blocks: core::sync::atomic::AtomicBool::new(false),
+        }
+    }
+    /// Restores a `SeqCounter` after a reboot from the last persisted block top. No Sequence
+    /// Numbers are reserved yet so `allocate_block` must be called (and its `persisted_value()`
+    /// persisted) before `inc_seq` issues anything.
+    pub fn restore(persisted: SequenceNumber) -> Self {
+        Self {
+            next: core::sync::atomic::AtomicU32::new(persisted.0.value()),
+            reserved_top: core::sync::atomic::AtomicU32::new(persisted.0.value()),
+            uses_blocks: core::sync::atomic::AtomicBool::new(true),
+        }
     }
     /// Allocates a or some SequenceNumbers and increments the internal counter by amount. Allocating
     /// `amount` Sequence Numbers is useful for Segmented Transport PDUs.
-    /// Returns `None` if `SequenceNumber` is at its max or will overflow.
+    /// Returns `None` if `SequenceNumber` is at its max or will overflow or, when blocks are being
+    /// used, if the reserved block doesn't have `amount` Sequence Numbers left (call
+    /// `allocate_block` and try again).
     pub fn inc_seq(&self, amount: u32) -> Option<SeqRange> {
-        let next = self
-            .0
-            .fetch_add(amount, core::sync::atomic::Ordering::SeqCst);
-        if next >= U24::max_value().value() {
-            // Overflow of Seq Number
-            self.0.store(
-                U24::max_value().value(),
-                core::sync::atomic::Ordering::SeqCst,
-            );
-            None
+        let limit = if self.uses_blocks.load(Ordering::SeqCst) {
+            self.reserved_top.load(Ordering::SeqCst)
         } else {
-            Some(SeqRange(next..next + amount))
+            U24::max_value().value()
+        };
+        self.next
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| {
+                next.checked_add(amount).filter(|&end| end <= limit)
+            })
+            .ok()
+            .map(|next| SeqRange(next..next + amount))
+    }
+    /// Reserves the next `size` Sequence Numbers (starting from the current counter) and moves
+    /// `persisted_value()` to the top of the block. The application should persist
+    /// `persisted_value()` before issuing any Sequence Number from the returned range.
+    /// Returns `None` if the Sequence Numbers are exhausted.
+    pub fn allocate_block(&self, size: u32) -> Option<SeqRange> {
+        let start = self.next.load(Ordering::SeqCst);
+        if start >= U24::max_value().value() {
+            return None;
         }
+        let top = start.saturating_add(size).min(U24::max_value().value());
+        self.reserved_top.fetch_max(top, Ordering::SeqCst);
+        self.uses_blocks.store(true, Ordering::SeqCst);
+        Some(SeqRange(start..top))
+    }
+    /// Value to persist. Every Sequence Number issued so far (and every one still reserved) is
+    /// below this value. This is the top of the last allocated block or, if blocks aren't used,
+    /// the next Sequence Number to be issued.
+    pub fn persisted_value(&self) -> SequenceNumber {
+        if self.uses_blocks.load(Ordering::SeqCst) {
+            SequenceNumber(U24::new(self.reserved_top.load(Ordering::SeqCst)))
+        } else {
+            self.check()
+        }
+    }
+    /// Number of reserved Sequence Numbers left before a new block should be allocated.
+    pub fn reserved_remaining(&self) -> u32 {
+        self.reserved_top
+            .load(Ordering::SeqCst)
+            .saturating_sub(self.next.load(Ordering::SeqCst))
     }
     /// Set the atomic sequence number. This should only really be called when initally setuping up
     /// the `SeqCounter` or reseting it. Setting `SeqCounter` to an older value may cause PDUs to be
     /// dropped by message recipients.
     pub fn set_seq(&mut self, new_seq: SequenceNumber) {
-        *self.0.get_mut() = new_seq.0.value()
+        *self.next.get_mut() = new_seq.0.value();
+        let reserved_top = self.reserved_top.get_mut();
+        *reserved_top = (*reserved_top).max(new_seq.0.value());
     }
     pub fn check(&self) -> SequenceNumber {
-        SequenceNumber(U24::new(self.0.load(Ordering::SeqCst)))
+        SequenceNumber(U24::new(self.next.load(Ordering::SeqCst)))
     }
 }
 
 impl Clone for SeqCounter {
     fn clone(&self) -> Self {
-        SeqCounter(core::sync::atomic::AtomicU32::new(
-            self.0.load(Ordering::SeqCst),
-        ))
+        SeqCounter {
+            next: core::sync::atomic::AtomicU32::new(self.next.load(Ordering::SeqCst)),
+            reserved_top: core::sync::atomic::AtomicU32::new(
+                self.reserved_top.load(Ordering::SeqCst),
+            ),
+            uses_blocks: core::sync::atomic::AtomicBool::new(
+                self.uses_blocks.load(Ordering::SeqCst),
+            ),
+        }
     }
 }
 #[cfg(feature = "serde-1")]
@@ -330,6 +853,8 @@ impl<'de> serde::Deserialize<'de> for SeqCounter {
     where
         D: serde::Deserializer<'de>,
     {
+        // Everything issued before serializing is below the persisted value so it's safe to
+        // resume from it. Applications using blocks should `allocate_block` again after loading.
         Ok(SeqCounter::new(SequenceNumber::deserialize(deserializer)?))
     }
 }
@@ -342,6 +867,201 @@ impl serde::Serialize for SeqCounter {
     where
         S: serde::Serializer,
     {
-        self.check().serialize(serializer)
+        self.persisted_value().serialize(serializer)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon::{AuthenticationValue, BeaconFlags};
+    use crate::crypto::key::{Key, NetKey};
+    use crate::crypto::NetworkID;
+    use crate::mesh::KeyIndex;
+    use core::str::FromStr;
+
+    #[test]
+    fn test_unicast_addresses() {
+        let primary = UnicastAddress::new(0x0002).expect("valid unicast address");
+        let device_state = DeviceState::new(primary, ElementCount(4));
+        let addresses: Vec<UnicastAddress> = device_state.unicast_addresses().collect();
+        assert_eq!(
+            addresses,
+            [0x0002_u16, 0x0003, 0x0004, 0x0005]
+                .iter()
+                .map(|&address| UnicastAddress::new(address).expect("valid unicast address"))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            device_state.element_index(addresses[3]),
+            Some(ElementIndex(3))
+        );
+        assert_eq!(
+            device_state.element_index(UnicastAddress::new(0x0006).expect("valid address")),
+            None
+        );
+    }
+    #[test]
+    fn test_elements_to_composition_page0() {
+        use crate::access::ModelIdentifier;
+        use crate::mesh::ModelID;
+
+        let mut elements = Elements::new();
+        let primary = elements.push_element(Location::Main);
+        let secondary = elements.push_element(Location::Numbered(2));
+        assert_eq!(elements.element_count(), ElementCount(2));
+        let config_server = ModelIdentifier::new_sig(ModelID(0x0000));
+        let onoff_server = ModelIdentifier::new_sig(ModelID(0x1000));
+        let vendor = ModelIdentifier::new_vendor(ModelID(0x0001), CompanyID(0x05F1));
+        assert!(elements.register_model(primary, config_server));
+        assert!(elements.register_model(primary, onoff_server));
+        assert!(elements.register_model(primary, vendor));
+        assert!(!elements.register_model(primary, onoff_server));
+        assert!(elements.register_model(secondary, onoff_server));
+        let page0 = elements.to_composition_page0(
+            CompanyID(0x05F1),
+            ProductID(0x0001),
+            VersionID(0x0002),
+            CRPL(0x0020),
+            Features::default(),
+        );
+        let composition = page0.elements().elements();
+        assert_eq!(composition.len(), 2);
+        assert_eq!((composition[0].num_s(), composition[0].num_v()), (2, 1));
+        assert_eq!((composition[1].num_s(), composition[1].num_v()), (1, 0));
+        assert_eq!(composition[1].location, Location::Numbered(2));
+    }
+    #[test]
+    fn test_try_new_element_count() {
+        let primary = |address: u16| UnicastAddress::new(address).expect("valid unicast address");
+        assert_eq!(
+            DeviceState::try_new(primary(0x0001), ElementCount(0)).err(),
+            Some(ElementCountError::Zero)
+        );
+        // The last element would be 0x7F80 + 0x81 - 1 = 0x8000 (the first group address).
+        assert_eq!(
+            DeviceState::try_new(primary(0x7F80), ElementCount(0x81)).err(),
+            Some(ElementCountError::NotUnicast)
+        );
+        let state = DeviceState::try_new(primary(0x7F80), ElementCount(0x80))
+            .expect("last element is 0x7FFF");
+        assert_eq!(state.element_count(), ElementCount(0x80));
+        assert_eq!(state.unicast_range(), primary(0x7F80)..=primary(0x7FFF));
+        let state =
+            DeviceState::try_new(primary(0x7FFF), ElementCount(1)).expect("last element is 0x7FFF");
+        assert_eq!(state.unicast_range(), primary(0x7FFF)..=primary(0x7FFF));
+    }
+    #[test]
+    fn test_subnet_beacon_advances_iv_index() {
+        let mut state = DeviceState::new(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            ElementCount(1),
+        );
+        let key_a =
+            NetKey::new(Key::from_str("7dd7364cd842ad18c17c2b820c84c3d6").expect("sample key"));
+        let key_b =
+            NetKey::new(Key::from_str("63964771734fbd76e3b40519d1d94a48").expect("sample key"));
+        let index_a = NetKeyIndex(KeyIndex::new(0));
+        let index_b = NetKeyIndex(KeyIndex::new(1));
+        state
+            .security_materials_mut()
+            .net_key_map
+            .insert(index_a, &key_a);
+        state
+            .security_materials_mut()
+            .net_key_map
+            .insert(index_b, &key_b);
+        let beacon = |key: &NetKey, iv_index: u32, flags: u8| SecureNetworkBeacon {
+            flags: BeaconFlags::try_from(flags).expect("valid flags"),
+            network_id: NetworkID::from(key),
+            iv_index: IVIndex(iv_index),
+            authentication_value: AuthenticationValue([0_u8; 8]),
+        };
+        // Subnet A is still on the current IV Index.
+        assert_eq!(state.observe_beacon(&beacon(&key_a, 0, 0)), Some(index_a));
+        assert_eq!(state.iv_index(), IVIndex(0));
+        // Subnet B has started an IV Update.
+        assert_eq!(
+            state.observe_beacon(&beacon(&key_b, 1, 0b10)),
+            Some(index_b)
+        );
+        assert_eq!(state.iv_index(), IVIndex(1));
+        assert_eq!(state.iv_update_flag(), IVUpdateFlag(true));
+        // An older beacon from subnet A doesn't roll the shared IV Index back.
+        state.observe_beacon(&beacon(&key_a, 0, 0));
+        assert_eq!(state.iv_index(), IVIndex(1));
+        assert_eq!(
+            state.subnet_beacons().most_advanced().map(|(i, _)| i),
+            Some(index_b)
+        );
+    }
+    #[test]
+    fn test_observe_beacon_iv() {
+        let mut state = DeviceState::new(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            ElementCount(1),
+        );
+        *state.iv_index_mut() = IVIndex(10);
+        // +1 in Normal Operation.
+        assert!(state.observe_beacon_iv(IVIndex(11), IVUpdateFlag(false)));
+        assert_eq!(state.iv_index(), IVIndex(11));
+        assert_eq!(state.iv_update_flag(), IVUpdateFlag(false));
+        // More than `IV_INDEX_MAX_JUMP` ahead.
+        assert!(!state.observe_beacon_iv(IVIndex(11 + 43), IVUpdateFlag(false)));
+        assert_eq!(state.iv_index(), IVIndex(11));
+        // Behind.
+        assert!(!state.observe_beacon_iv(IVIndex(10), IVUpdateFlag(false)));
+        assert!(!state.observe_beacon_iv(IVIndex(11), IVUpdateFlag(true)));
+        assert_eq!(state.iv_index(), IVIndex(11));
+        assert_eq!(state.iv_update_flag(), IVUpdateFlag(false));
+    }
+    #[test]
+    fn test_rx_iv_index() {
+        let mut state = DeviceState::new(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            ElementCount(1),
+        );
+        assert_eq!(state.rx_iv_index(IVI(false)), Some(IVIndex(0)));
+        // No IV Index before 0.
+        assert_eq!(state.rx_iv_index(IVI(true)), None);
+        *state.iv_update_flag_mut() = IVUpdateFlag(true);
+        assert_eq!(state.rx_iv_index(IVI(true)), None);
+        *state.iv_index_mut() = IVIndex(5);
+        assert_eq!(state.rx_iv_index(IVI(true)), Some(IVIndex(5)));
+        assert_eq!(state.rx_iv_index(IVI(false)), Some(IVIndex(4)));
+        *state.iv_update_flag_mut() = IVUpdateFlag(false);
+        assert_eq!(state.rx_iv_index(IVI(false)), Some(IVIndex(4)));
+    }
+    #[test]
+    fn test_seq_counter_block_survives_reboot() {
+        let counter = SeqCounter::new(SequenceNumber(U24::new(0)));
+        let block = counter.allocate_block(100).expect("seqs left");
+        assert_eq!(block.seqs_lefts(), 100);
+        assert_eq!(counter.persisted_value(), SequenceNumber(U24::new(100)));
+        // Persist only the block top then issue some of the reserved seqs.
+        let persisted = counter.persisted_value();
+        let issued: Vec<SequenceNumber> = (0..10)
+            .flat_map(|_| counter.inc_seq(3).expect("seqs left"))
+            .collect();
+        assert_eq!(counter.reserved_remaining(), 70);
+        // Issuing past the block needs a new block first.
+        assert!(counter.inc_seq(71).is_none());
+        let issued: Vec<SequenceNumber> = issued
+            .into_iter()
+            .chain(counter.inc_seq(70).expect("seqs left in the block"))
+            .collect();
+        assert!(counter.inc_seq(1).is_none());
+        assert_eq!(counter.check(), SequenceNumber(U24::new(100)));
+        // "Reboot"
+        let rebooted = SeqCounter::restore(persisted);
+        assert!(rebooted.inc_seq(1).is_none());
+        rebooted.allocate_block(100).expect("seqs left");
+        assert_eq!(rebooted.persisted_value(), SequenceNumber(U24::new(200)));
+        let next = rebooted
+            .inc_seq(1)
+            .expect("seqs left")
+            .next()
+            .expect("one seq");
+        assert!(issued.iter().all(|&seq| seq < next));
+        assert_eq!(rebooted.reserved_remaining(), 99);
     }
 }
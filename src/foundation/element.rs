@@ -1,8 +1,7 @@
 //use crate::access::ModelIdentifier;
-use crate::access::{ModelIdentifier, SigModelID, VendorModelID};
+use crate::access::ModelIdentifier;
 
 use crate::bytes::ToFromBytesEndian;
-use crate::mesh::ModelID;
 
 use alloc::vec::Vec;
 use core::convert::TryInto;
@@ -39,8 +38,8 @@ impl ElementComposition {
         Location::byte_len()
             + 1
             + 1
-            + self.sig_models.len() * SigModelID::byte_len()
-            + self.vendor_models.len() * VendorModelID::byte_len()
+            + self.sig_models.len() * ModelIdentifier::sig_byte_len()
+            + self.vendor_models.len() * ModelIdentifier::vendor_byte_len()
     }
     pub const fn min_byte_len() -> usize {
         Location::byte_len() + 1 + 1
@@ -60,7 +59,7 @@ impl ElementComposition {
                 None
             } else {
                 let mut sig_models = Vec::new();
-                let mut pos = 3;
+                let mut pos = Self::min_byte_len();
                 for _ in 0..num_s {
                     sig_models.push(ModelIdentifier::unpack_from(
                         &buf[pos..pos + ModelIdentifier::sig_byte_len()],
@@ -89,13 +88,12 @@ impl ElementComposition {
         buf[0..2].copy_from_slice(&self.location.to_bytes_le());
         buf[2] = self.num_s();
         buf[3] = self.num_v();
-        let mut position = 0_usize;
+        let mut position = Self::min_byte_len();
         for model in self.sig_models.iter() {
             // This could be change to a debug_assert.
             assert!(model.is_sig(), "non SIG model in sig_models");
-            buf[position..position + ModelID::byte_size()]
-                .copy_from_slice(&model.model_id().to_bytes_le());
-            position += ModelID::byte_size();
+            model.pack_into(&mut buf[position..position + ModelIdentifier::sig_byte_len()]);
+            position += ModelIdentifier::sig_byte_len();
         }
         for model in self.vendor_models.iter() {
             assert!(model.is_vendor(), "SIG model in vendor_models");
@@ -237,6 +235,14 @@ impl ToFromBytesEndian for Location {
 #[derive(Clone, Ord, PartialOrd, PartialEq, Debug, Hash, Eq)]
 pub struct ElementsComposition(Vec<ElementComposition>);
 impl ElementsComposition {
+    #[must_use]
+    pub fn new(elements: Vec<ElementComposition>) -> Self {
+        ElementsComposition(elements)
+    }
+    #[must_use]
+    pub fn elements(&self) -> &[ElementComposition] {
+        &self.0
+    }
     #[must_use]
     pub fn byte_len(&self) -> usize {
         self.0.iter().map(ElementComposition::byte_len).sum()
@@ -5,6 +5,7 @@ use crate::foundation::element::ElementsComposition;
 use crate::mesh::CompanyID;
 use crate::upper::AppPayload;
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 
@@ -146,7 +147,7 @@ impl ToFromBytesEndian for Features {
         Some(Features(u16::from_bytes_be(bytes)?))
     }
 }
-#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Default)]
 pub struct Features(u16);
 impl Features {
     pub const fn byte_len() -> usize {
@@ -203,6 +204,26 @@ pub struct CompositionDataPage0 {
     elements: ElementsComposition,
 }
 impl CompositionDataPage0 {
+    pub fn new(
+        cid: CompanyID,
+        pid: ProductID,
+        vid: VersionID,
+        crpl: CRPL,
+        features: Features,
+        elements: ElementsComposition,
+    ) -> Self {
+        CompositionDataPage0 {
+            cid,
+            pid,
+            vid,
+            crpl,
+            features,
+            elements,
+        }
+    }
+    pub fn elements(&self) -> &ElementsComposition {
+        &self.elements
+    }
     pub fn byte_len(&self) -> usize {
         CompanyID::byte_len()
             + ProductID::byte_len()
@@ -237,3 +258,66 @@ impl CompositionDataPage0 {
         AppPayload::new(buf)
     }
 }
+/// A single page of Composition Data. Only Page 0 is currently defined but other pages (1, 128,
+/// etc) can be added as new variants.
+#[derive(Clone, Ord, PartialOrd, PartialEq, Debug, Hash, Eq)]
+pub enum CompositionDataPage {
+    Page0(CompositionDataPage0),
+}
+impl CompositionDataPage {
+    pub fn page_number(&self) -> u8 {
+        match self {
+            CompositionDataPage::Page0(_) => 0,
+        }
+    }
+    pub fn byte_len(&self) -> usize {
+        match self {
+            CompositionDataPage::Page0(page) => page.byte_len(),
+        }
+    }
+    pub fn pack_into(&self, buf: &mut [u8]) {
+        match self {
+            CompositionDataPage::Page0(page) => page.pack_into(buf),
+        }
+    }
+}
+impl From<CompositionDataPage0> for CompositionDataPage {
+    fn from(page: CompositionDataPage0) -> Self {
+        CompositionDataPage::Page0(page)
+    }
+}
+/// Every Composition Data page a node has, keyed by page number. Page 0 is always present.
+#[derive(Clone, Ord, PartialOrd, PartialEq, Debug, Hash, Eq)]
+pub struct CompositionDataPages(BTreeMap<u8, CompositionDataPage>);
+impl CompositionDataPages {
+    pub fn new(page0: CompositionDataPage0) -> Self {
+        let mut pages = BTreeMap::new();
+        pages.insert(0, CompositionDataPage::Page0(page0));
+        CompositionDataPages(pages)
+    }
+    /// Inserts (or replaces) a page, returning the old page with the same page number.
+    pub fn insert(&mut self, page: CompositionDataPage) -> Option<CompositionDataPage> {
+        self.0.insert(page.page_number(), page)
+    }
+    pub fn get(&self, page_number: u8) -> Option<&CompositionDataPage> {
+        self.0.get(&page_number)
+    }
+    pub fn page0(&self) -> &CompositionDataPage0 {
+        match self.get(0).expect("page 0 always exists") {
+            CompositionDataPage::Page0(page) => page,
+        }
+    }
+    pub fn page_numbers(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.keys().copied()
+    }
+    /// Returns the page a Composition Data Get for `requested` should respond with. That's the
+    /// first page `>= requested` or the highest page if `requested` is past every page.
+    pub fn select(&self, requested: u8) -> &CompositionDataPage {
+        self.0
+            .range(requested..)
+            .next()
+            .or_else(|| self.0.iter().next_back())
+            .map(|(_, page)| page)
+            .expect("page 0 always exists")
+    }
+}
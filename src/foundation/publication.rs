@@ -1,9 +1,11 @@
 use crate::address::{Address, VirtualAddress};
 use crate::bytes::ToFromBytesEndian;
+use crate::friend::Credentials;
 use crate::mesh::{AppKeyIndex, KeyIndex, TransmitInterval, TTL};
 use crate::uuid::UUID;
 use core::convert::TryInto;
 use core::time;
+use driver_async::time::{Instant, InstantTrait};
 
 /// 2-bit Step Resoution used for `PublishPeriod`, etc.
 #[derive(Copy, Clone, Ord, PartialOrd, Debug, Hash, Eq, PartialEq)]
@@ -30,24 +32,28 @@ impl From<StepResolution> for u8 {
     }
 }
 const STEPS_MAX: u8 = 0x3F;
-/// 6-bit Steps for Periods.
+/// 6-bit Steps for Periods. `0` steps means periodic publishing is disabled.
 #[derive(Copy, Clone, Ord, PartialOrd, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Steps(u8);
 impl Steps {
     /// # Panics
-    /// Panics if `steps == 0` or `steps > STEPS_MAX`
+    /// Panics if `steps > STEPS_MAX`
     pub fn new(steps: u8) -> Self {
-        assert!(steps != 0 && steps <= STEPS_MAX);
+        assert!(steps <= STEPS_MAX);
         Self(steps)
     }
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
 }
 impl From<Steps> for u8 {
     fn from(s: Steps) -> Self {
         s.0
     }
 }
-
+/// Publish Period. Packed into one byte as the 6-bit number of steps followed by the 2-bit
+/// `StepResolution` in the upper bits.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct PublishPeriod {
@@ -55,19 +61,32 @@ pub struct PublishPeriod {
     pub steps: Steps,
 }
 impl PublishPeriod {
+    /// Periodic publishing disabled (`0` steps).
+    pub const DISABLED: PublishPeriod = PublishPeriod {
+        resolution: StepResolution::Milliseconds100,
+        steps: Steps(0),
+    };
     pub fn new(resolution: StepResolution, steps: Steps) -> Self {
         Self { resolution, steps }
     }
+    pub fn is_periodic(&self) -> bool {
+        !self.steps.is_zero()
+    }
     pub fn to_milliseconds(&self) -> u32 {
         self.resolution.to_milliseconds() * u32::from(self.steps.0)
     }
-    pub fn to_duration(&self) -> time::Duration {
-        time::Duration::from_millis(self.to_milliseconds().into())
+    /// Time between periodic publications. `None` if periodic publishing is disabled.
+    pub fn as_duration(&self) -> Option<time::Duration> {
+        if self.is_periodic() {
+            Some(time::Duration::from_millis(self.to_milliseconds().into()))
+        } else {
+            None
+        }
     }
-    pub fn packed(&self) -> u8 {
+    pub fn to_byte(&self) -> u8 {
         u8::from(self.steps) | u8::from(self.resolution) << 6
     }
-    pub fn unpack(b: u8) -> Self {
+    pub fn from_byte(b: u8) -> Self {
         let steps = Steps::new(b & STEPS_MAX);
         let resolution = match b >> 6 {
             0b00 => StepResolution::Milliseconds100,
@@ -81,12 +100,12 @@ impl PublishPeriod {
 }
 impl From<PublishPeriod> for u8 {
     fn from(p: PublishPeriod) -> Self {
-        p.packed()
+        p.to_byte()
     }
 }
-impl From<PublishPeriod> for time::Duration {
-    fn from(p: PublishPeriod) -> Self {
-        p.to_duration()
+impl From<u8> for PublishPeriod {
+    fn from(b: u8) -> Self {
+        PublishPeriod::from_byte(b)
     }
 }
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
@@ -107,6 +126,8 @@ impl From<PublishRetransmit> for u8 {
 pub struct ModelPublishInfo {
     pub address: Address,
     pub app_key_index: AppKeyIndex,
+    /// Publish with the friendship credentials (`true`) or the master credentials (`false`).
+    /// Packed in bit 12 of the AppKeyIndex field.
     pub credential_flag: bool,
     pub ttl: Option<TTL>, // None means default TTL
     pub period: PublishPeriod,
@@ -116,6 +137,14 @@ pub struct ModelPublishInfo {
 impl ModelPublishInfo {
     pub const NON_VIRTUAL_LEN: usize = 7;
     pub const VIRTUAL_LEN: usize = 7 + 14;
+    /// An `Unassigned` publish address means publication is disabled.
+    pub fn is_enabled(&self) -> bool {
+        self.address != Address::Unassigned
+    }
+    /// Network credentials published messages are sent with.
+    pub fn credentials(&self) -> Credentials {
+        self.credential_flag.into()
+    }
     pub fn byte_len(&self) -> usize {
         if self.address.is_full_virtual() {
             Self::VIRTUAL_LEN
@@ -143,7 +172,7 @@ impl ModelPublishInfo {
                     app_key_index: AppKeyIndex(KeyIndex::new_masked(index)),
                     credential_flag,
                     ttl: publish_ttl,
-                    period: PublishPeriod::unpack(buf[5]),
+                    period: PublishPeriod::from_byte(buf[5]),
                     retransmit: PublishRetransmit::from(buf[6]),
                 })
             }
@@ -154,7 +183,7 @@ impl ModelPublishInfo {
                 let publish_ttl = if buf[18] == 0xFF {
                     None
                 } else {
-                    if buf[0] & 0x80 != 0 {
+                    if buf[18] & 0x80 != 0 {
                         return None;
                     }
                     Some(TTL::from_masked_u8(buf[18]))
@@ -164,7 +193,7 @@ impl ModelPublishInfo {
                     app_key_index: AppKeyIndex(KeyIndex::new_masked(index)),
                     credential_flag,
                     ttl: publish_ttl,
-                    period: PublishPeriod::unpack(buf[19]),
+                    period: PublishPeriod::from_byte(buf[19]),
                     retransmit: PublishRetransmit::from(buf[20]),
                 })
             }
@@ -176,17 +205,145 @@ impl ModelPublishInfo {
             buf.len() >= self.byte_len(),
             "not enough room for publication"
         );
-        let address = u16::from(&self.address);
-        let _pos = match &self.address {
+        let pos = match &self.address {
             Address::Virtual(va) => {
                 buf[..16].copy_from_slice(va.uuid().as_ref());
                 16
             }
-            _ => {
-                buf[..2].copy_from_slice(&address.to_le_bytes());
+            address => {
+                buf[..2].copy_from_slice(&u16::from(address).to_le_bytes());
                 2
             }
         };
-        unimplemented!()
+        let mut index = u16::from(self.app_key_index.0);
+        if self.credential_flag {
+            index |= 1 << 12;
+        }
+        buf[pos..pos + 2].copy_from_slice(&index.to_le_bytes());
+        buf[pos + 2] = self.ttl.map_or(0xFF, u8::from);
+        buf[pos + 3] = self.period.to_byte();
+        buf[pos + 4] = self.retransmit.into();
+    }
+}
+/// Periodic publication timer for one model. Only runs while publication is enabled (publish
+/// address isn't `Unassigned`).
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PublishTimer {
+    period: Option<time::Duration>,
+    next_publish: Option<Instant>,
+}
+impl PublishTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Restarts the timer for the new `publication` (from a Config Model Publication Set).
+    /// `None`, an `Unassigned` publish address or a zero `PublishPeriod` stops the timer.
+    pub fn set_publication(&mut self, publication: Option<&ModelPublishInfo>, now: Instant) {
+        self.period = publication
+            .filter(|publication| publication.is_enabled())
+            .and_then(|publication| publication.period.as_duration());
+        self.next_publish = self.period.map(|period| now + period);
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.period.is_some()
+    }
+    /// When the next periodic publication is due. `None` if publication is disabled.
+    pub fn next_publish(&self) -> Option<Instant> {
+        self.next_publish
+    }
+    /// Returns `true` if a publication is due at `now` (and schedules the next one).
+    pub fn poll(&mut self, now: Instant) -> bool {
+        match (self.period, self.next_publish) {
+            (Some(period), Some(next)) if now.checked_duration_since(next).is_some() => {
+                self.next_publish = Some(now + period);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::UnicastAddress;
+    use crate::device_state::ModelInfo;
+    use crate::mesh::{TransmitCount, TransmitSteps};
+    use alloc::vec::Vec;
+
+    fn publication(address: Address) -> ModelPublishInfo {
+        ModelPublishInfo {
+            address,
+            app_key_index: AppKeyIndex(KeyIndex::new(0)),
+            credential_flag: false,
+            ttl: None,
+            period: PublishPeriod::new(StepResolution::Second1, Steps::new(5)),
+            retransmit: PublishRetransmit(TransmitInterval::new(
+                TransmitCount::new(0),
+                TransmitSteps::new(0),
+            )),
+        }
+    }
+    #[test]
+    fn test_publish_period_bytes() {
+        let period = PublishPeriod::from_byte(0b10_000011);
+        assert_eq!(
+            period,
+            PublishPeriod::new(StepResolution::Second10, Steps::new(3))
+        );
+        assert_eq!(period.as_duration(), Some(time::Duration::from_secs(30)));
+        assert_eq!(period.to_byte(), 0b10_000011);
+        assert_eq!(
+            PublishPeriod::from_byte(0b00_000101).as_duration(),
+            Some(time::Duration::from_millis(500))
+        );
+        assert_eq!(
+            PublishPeriod::from_byte(0b11_000001).as_duration(),
+            Some(time::Duration::from_secs(600))
+        );
+        // Zero steps disables periodic publishing, whatever the resolution.
+        assert_eq!(PublishPeriod::from_byte(0b01_000000).as_duration(), None);
+        assert_eq!(PublishPeriod::DISABLED.to_byte(), 0);
+    }
+    #[test]
+    fn test_publish_info_pack_round_trip() {
+        let mut info = publication(Address::Unicast(
+            UnicastAddress::new(0x0010).expect("valid unicast address"),
+        ));
+        info.credential_flag = true;
+        info.ttl = Some(TTL::new(7));
+        let mut buf = [0_u8; ModelPublishInfo::NON_VIRTUAL_LEN];
+        info.pack_into(&mut buf);
+        assert_eq!(buf, [0x10, 0x00, 0x00, 0x10, 0x07, 0b01_000101, 0x00]);
+        assert_eq!(ModelPublishInfo::unpack(&buf), Some(info));
+        assert_eq!(info.credentials(), Credentials::Friendship);
+    }
+    #[test]
+    fn test_unassigned_stops_publishing() {
+        let now = Instant::now();
+        let enabled = publication(Address::Unicast(
+            UnicastAddress::new(0x0010).expect("valid unicast address"),
+        ));
+        let mut model = ModelInfo {
+            publish: None,
+            app_key: Vec::new(),
+        };
+        model.set_publication(enabled);
+        let mut timer = PublishTimer::new();
+        timer.set_publication(model.publish.as_ref(), now);
+        assert_eq!(
+            timer.next_publish(),
+            Some(now + time::Duration::from_secs(5))
+        );
+        assert!(timer.poll(now + time::Duration::from_secs(5)));
+
+        model.set_publication(publication(Address::Unassigned));
+        assert_eq!(model.publish, None);
+        timer.set_publication(model.publish.as_ref(), now);
+        assert!(!timer.is_enabled());
+        assert_eq!(timer.next_publish(), None);
+        assert!(!timer.poll(now + time::Duration::from_secs(60)));
+        // Even if the unassigned publication is handed over as is.
+        timer.set_publication(Some(&publication(Address::Unassigned)), now);
+        assert!(!timer.is_enabled());
     }
 }
@@ -36,10 +36,23 @@ impl RelayState {
     pub fn is_enabled(self) -> bool {
         self == RelayState::Enabled
     }
+    /// Returns if the node has the Relay feature.
+    pub fn is_supported(self) -> bool {
+        self != RelayState::NotSupported
+    }
 }
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct RelayRetransmit(pub TransmitInterval);
+impl Default for RelayRetransmit {
+    /// Relay each PDU once (no retransmissions).
+    fn default() -> Self {
+        RelayRetransmit(TransmitInterval::new(
+            TransmitCount::new(0),
+            TransmitSteps::new(0),
+        ))
+    }
+}
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
@@ -98,6 +111,15 @@ impl Default for GATTProxyState {
         GATTProxyState::Disabled
     }
 }
+impl GATTProxyState {
+    pub fn is_enabled(self) -> bool {
+        self == GATTProxyState::Enabled
+    }
+    /// Returns if the node has the Proxy feature.
+    pub fn is_supported(self) -> bool {
+        self != GATTProxyState::NotSupported
+    }
+}
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
@@ -138,6 +160,16 @@ impl From<FriendState> for u8 {
     }
 }
 
+impl Default for FriendState {
+    fn default() -> FriendState {
+        FriendState::Disabled
+    }
+}
+impl FriendState {
+    pub fn is_enabled(self) -> bool {
+        self == FriendState::Enabled
+    }
+}
 impl TryFrom<u8> for FriendState {
     type Error = FoundationStateError;
 
@@ -247,3 +279,94 @@ impl Default for NetworkTransmit {
         })
     }
 }
+/// Heartbeat Publication Count Log. The number of Heartbeat messages left to publish.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub enum CountLog {
+    /// `0x00`. Heartbeat messages aren't published.
+    Disabled,
+    /// `0x01..=0x11`. `2^(n-1)` Heartbeat messages are published.
+    Log(u8),
+    /// `0xFF`. Heartbeat messages are published indefinitely.
+    Indefinite,
+}
+impl CountLog {
+    pub const MAX_LOG: u8 = 0x11;
+    /// Number of Heartbeat messages to publish. `None` if `Indefinite`.
+    pub fn count(self) -> Option<u32> {
+        match self {
+            CountLog::Disabled => Some(0),
+            CountLog::Log(log) => Some(1_u32 << (log - 1)),
+            CountLog::Indefinite => None,
+        }
+    }
+}
+impl Default for CountLog {
+    fn default() -> Self {
+        CountLog::Disabled
+    }
+}
+impl From<CountLog> for u8 {
+    fn from(count_log: CountLog) -> Self {
+        match count_log {
+            CountLog::Disabled => 0x00,
+            CountLog::Log(log) => log,
+            CountLog::Indefinite => 0xFF,
+        }
+    }
+}
+impl TryFrom<u8> for CountLog {
+    type Error = FoundationStateError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(CountLog::Disabled),
+            0x01..=CountLog::MAX_LOG => Ok(CountLog::Log(value)),
+            0xFF => Ok(CountLog::Indefinite),
+            _ => Err(FoundationStateError(())),
+        }
+    }
+}
+/// Heartbeat Publication Period Log. The time between publishing Heartbeat messages.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub enum PeriodLog {
+    /// `0x00`. Heartbeat messages aren't published periodically.
+    Disabled,
+    /// `0x01..=0x11`. Heartbeat messages are published every `2^(n-1)` seconds.
+    Log(u8),
+}
+impl PeriodLog {
+    pub const MAX_LOG: u8 = 0x11;
+    /// Time between Heartbeat messages. `None` if `Disabled`.
+    pub fn period(self) -> Option<core::time::Duration> {
+        match self {
+            PeriodLog::Disabled => None,
+            PeriodLog::Log(log) => Some(core::time::Duration::from_secs(1_u64 << (log - 1))),
+        }
+    }
+}
+impl Default for PeriodLog {
+    fn default() -> Self {
+        PeriodLog::Disabled
+    }
+}
+impl From<PeriodLog> for u8 {
+    fn from(period_log: PeriodLog) -> Self {
+        match period_log {
+            PeriodLog::Disabled => 0x00,
+            PeriodLog::Log(log) => log,
+        }
+    }
+}
+impl TryFrom<u8> for PeriodLog {
+    type Error = FoundationStateError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PeriodLog::Disabled),
+            0x01..=PeriodLog::MAX_LOG => Ok(PeriodLog::Log(value)),
+            _ => Err(FoundationStateError(())),
+        }
+    }
+}
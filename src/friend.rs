@@ -1,6 +1,10 @@
 //! Optional Bluetooth Mesh Friends feature.
-use crate::address::UnicastAddress;
-use crate::mesh::{IVIndex, IVUpdateFlag, KeyRefreshFlag, U24};
+use crate::address::{Address, UnicastAddress};
+use crate::crypto::materials::{NetworkKeys, NetworkSecurityMaterials};
+use crate::mesh::{IVIndex, IVUpdateFlag, KeyRefreshFlag, NetKeyIndex, U24};
+use crate::timestamp::TimestampTrait;
+use alloc::vec::Vec;
+use core::time::Duration;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct Flags(u8);
@@ -10,10 +14,90 @@ pub struct FSN(bool);
 pub struct MD(u8);
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct Criteria(u8);
+impl Criteria {
+    pub fn new(
+        rssi_factor: RSSIFactor,
+        receive_window_factor: ReceiveWindowFactor,
+        min_queue_size_log: MinQueueSizeLog,
+    ) -> Self {
+        Self(
+            ((rssi_factor as u8) << 5)
+                | ((receive_window_factor as u8) << 3)
+                | min_queue_size_log as u8,
+        )
+    }
+    pub fn rssi_factor(self) -> RSSIFactor {
+        match (self.0 >> 5) & 0b11 {
+            0b00 => RSSIFactor::Factor1,
+            0b01 => RSSIFactor::Factor2,
+            0b10 => RSSIFactor::Factor3,
+            _ => RSSIFactor::Factor4,
+        }
+    }
+    pub fn receive_window_factor(self) -> ReceiveWindowFactor {
+        match (self.0 >> 3) & 0b11 {
+            0b00 => ReceiveWindowFactor::Window1,
+            0b01 => ReceiveWindowFactor::Window2,
+            0b10 => ReceiveWindowFactor::Window3,
+            _ => ReceiveWindowFactor::Window4,
+        }
+    }
+    pub fn min_queue_size_log(self) -> MinQueueSizeLog {
+        match self.0 & 0b111 {
+            0b000 => MinQueueSizeLog::Prohibited,
+            0b001 => MinQueueSizeLog::N2,
+            0b010 => MinQueueSizeLog::N4,
+            0b011 => MinQueueSizeLog::N8,
+            0b100 => MinQueueSizeLog::N16,
+            0b101 => MinQueueSizeLog::N32,
+            0b110 => MinQueueSizeLog::N64,
+            _ => MinQueueSizeLog::N128,
+        }
+    }
+}
+impl From<Criteria> for u8 {
+    fn from(c: Criteria) -> Self {
+        c.0
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct ReceiveDelay(u8);
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct PollTimeout(U24);
+impl PollTimeout {
+    pub fn new(timeout: U24) -> Self {
+        Self(timeout)
+    }
+    /// PollTimeout is in units of 100 milliseconds.
+    pub fn to_duration(self) -> Duration {
+        Duration::from_millis(u64::from(self.0.value()) * 100)
+    }
+}
+/// Friend node side Poll Timeout timer. Restarted by every Friend Poll (or Friend Subscription
+/// List message) from the Low Power Node. If it expires, the friendship is terminated.
+#[derive(Copy, Clone, Debug)]
+pub struct PollTimer<T: TimestampTrait> {
+    timeout: Duration,
+    deadline: T,
+}
+impl<T: TimestampTrait> PollTimer<T> {
+    pub fn new(poll_timeout: PollTimeout, now: T) -> Self {
+        let timeout = poll_timeout.to_duration();
+        Self {
+            timeout,
+            deadline: now + timeout,
+        }
+    }
+    pub fn deadline(&self) -> T {
+        self.deadline
+    }
+    pub fn poll_received(&mut self, now: T) {
+        self.deadline = now + self.timeout;
+    }
+    pub fn is_expired(&self, now: T) -> bool {
+        now.reached(self.deadline)
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct LPNCounter(u16);
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -23,6 +107,12 @@ pub enum RSSIFactor {
     Factor3 = 0b10,
     Factor4 = 0b11,
 }
+impl RSSIFactor {
+    /// Factor multiplied by 10 (`1.0`, `1.5`, `2.0` or `2.5`).
+    pub fn tenths(self) -> i32 {
+        10 + 5 * self as i32
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub enum ReceiveWindowFactor {
     Window1 = 0b00,
@@ -30,6 +120,12 @@ pub enum ReceiveWindowFactor {
     Window3 = 0b10,
     Window4 = 0b11,
 }
+impl ReceiveWindowFactor {
+    /// Factor multiplied by 10 (`1.0`, `1.5`, `2.0` or `2.5`).
+    pub fn tenths(self) -> i32 {
+        10 + 5 * self as i32
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub enum MinQueueSizeLog {
     Prohibited = 0b000,
@@ -41,6 +137,15 @@ pub enum MinQueueSizeLog {
     N64 = 0b110,
     N128 = 0b111,
 }
+impl MinQueueSizeLog {
+    /// Minimum number of messages the Friend Queue must hold. `None` if `Prohibited`.
+    pub fn min_queue_size(self) -> Option<u8> {
+        match self {
+            MinQueueSizeLog::Prohibited => None,
+            _ => Some(1_u8 << (self as u8)),
+        }
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct FriendPoll {
     fsn: FSN,
@@ -71,3 +176,223 @@ pub struct FriendClearConfirm {
     address: UnicastAddress,
     counter: LPNCounter,
 }
+/// Friend Offer received by a Low Power Node in response to its Friend Request.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct FriendOffer {
+    /// Address of the Friend node that sent the offer.
+    pub src: UnicastAddress,
+    /// Receive Window in milliseconds.
+    pub receive_window: u8,
+    pub queue_size: u8,
+    pub subscription_list_size: u8,
+    /// RSSI (in dBm) measured by the Friend node for the Friend Request.
+    pub rssi: i8,
+    pub friend_counter: u16,
+}
+impl FriendOffer {
+    /// Weighted delay of the offer (`ReceiveWindowFactor * ReceiveWindow - RSSIFactor * RSSI`) in
+    /// tenths of a millisecond. Lower is better.
+    pub fn delay_tenths(&self, weights: Criteria) -> i32 {
+        weights.receive_window_factor().tenths() * i32::from(self.receive_window)
+            - weights.rssi_factor().tenths() * i32::from(self.rssi)
+    }
+}
+/// Picks the best Friend Offer by the LPN's `weights` (the `Criteria` it sent in the Friend
+/// Request). Offers with a Friend Queue smaller than `MinQueueSizeLog` are ignored. The lowest
+/// weighted delay wins and ties go to the larger Friend Queue, then the larger Subscription List.
+pub fn select_offer(offers: &[FriendOffer], weights: Criteria) -> Option<&FriendOffer> {
+    let min_queue_size = weights.min_queue_size_log().min_queue_size()?;
+    offers
+        .iter()
+        .filter(|offer| offer.queue_size >= min_queue_size)
+        .min_by_key(|offer| {
+            (
+                offer.delay_tenths(weights),
+                core::cmp::Reverse(offer.queue_size),
+                core::cmp::Reverse(offer.subscription_list_size),
+            )
+        })
+}
+/// Network security credentials a PDU is encrypted with (the publication Credential Flag).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum Credentials {
+    /// Master security credentials derived from the NetKey.
+    Master,
+    /// Friendship security credentials of the node's friendship on the subnet.
+    Friendship,
+}
+impl Default for Credentials {
+    fn default() -> Self {
+        Credentials::Master
+    }
+}
+impl From<bool> for Credentials {
+    fn from(credential_flag: bool) -> Self {
+        if credential_flag {
+            Credentials::Friendship
+        } else {
+            Credentials::Master
+        }
+    }
+}
+impl From<Credentials> for bool {
+    fn from(credentials: Credentials) -> Self {
+        credentials == Credentials::Friendship
+    }
+}
+/// Friendship between a Low Power Node and its Friend node on the `net_key_index` subnet.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct Friendship {
+    pub net_key_index: NetKeyIndex,
+    pub lpn: UnicastAddress,
+    pub friend: UnicastAddress,
+    pub lpn_counter: u16,
+    pub friend_counter: u16,
+}
+impl Friendship {
+    /// The other node of the friendship if `address` is one of them.
+    pub fn peer_of(&self, address: UnicastAddress) -> Option<UnicastAddress> {
+        if address == self.lpn {
+            Some(self.friend)
+        } else if address == self.friend {
+            Some(self.lpn)
+        } else {
+            None
+        }
+    }
+    pub fn network_keys(&self, net_sm: &NetworkSecurityMaterials) -> NetworkKeys {
+        net_sm.friendship_keys(self.lpn, self.friend, self.lpn_counter, self.friend_counter)
+    }
+}
+/// Picks the network credentials for outgoing PDUs. PDUs between the two nodes of a friendship
+/// use the friendship credentials. A Low Power Node also uses them for PDUs sent with
+/// `Credentials::Friendship` (ex. a publication with the Credential Flag set) so its Friend
+/// relays them. Everything else uses the master credentials.
+#[derive(Clone, Debug, Default)]
+pub struct CredentialSelector {
+    friendships: Vec<Friendship>,
+}
+impl CredentialSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn friendships(&self) -> &[Friendship] {
+        &self.friendships
+    }
+    /// Adds an established friendship, replacing the old one with the same LPN on the subnet.
+    pub fn add_friendship(&mut self, friendship: Friendship) {
+        self.remove_friendship(friendship.net_key_index, friendship.lpn);
+        self.friendships.push(friendship);
+    }
+    /// Removes the friendship with `lpn` on the subnet (ex. after a Friend Clear).
+    pub fn remove_friendship(
+        &mut self,
+        net_key_index: NetKeyIndex,
+        lpn: UnicastAddress,
+    ) -> Option<Friendship> {
+        let index = self
+            .friendships
+            .iter()
+            .position(|f| f.net_key_index == net_key_index && f.lpn == lpn)?;
+        Some(self.friendships.remove(index))
+    }
+    /// Friendship whose credentials encrypt a PDU from `src` to `dst`. `None` means the master
+    /// credentials.
+    pub fn select(
+        &self,
+        net_key_index: NetKeyIndex,
+        src: UnicastAddress,
+        dst: Address,
+        credentials: Credentials,
+    ) -> Option<&Friendship> {
+        let mut on_subnet = self
+            .friendships
+            .iter()
+            .filter(|f| f.net_key_index == net_key_index);
+        if let Some(dst) = dst.unicast() {
+            if let Some(friendship) = on_subnet.clone().find(|f| f.peer_of(src) == Some(dst)) {
+                return Some(friendship);
+            }
+        }
+        match credentials {
+            Credentials::Master => None,
+            Credentials::Friendship => on_subnet.find(|f| f.lpn == src),
+        }
+    }
+    /// `NetworkKeys` to encrypt a PDU from `src` to `dst` with (see `select`).
+    pub fn network_keys(
+        &self,
+        net_key_index: NetKeyIndex,
+        net_sm: &NetworkSecurityMaterials,
+        src: UnicastAddress,
+        dst: Address,
+        credentials: Credentials,
+    ) -> NetworkKeys {
+        match self.select(net_key_index, src, dst, credentials) {
+            Some(friendship) => friendship.network_keys(net_sm),
+            None => *net_sm.network_keys(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::Timestamp;
+
+    fn offer(src: u16, queue_size: u8) -> FriendOffer {
+        FriendOffer {
+            src: UnicastAddress::from_mask_u16(src),
+            receive_window: 20,
+            queue_size,
+            subscription_list_size: 8,
+            rssi: -60,
+            friend_counter: 0,
+        }
+    }
+    #[test]
+    fn test_poll_timer() {
+        let start = Timestamp::from_micros(1_000);
+        let mut timer = PollTimer::new(PollTimeout::new(U24::new(10)), start);
+        assert!(!timer.is_expired(start + Duration::from_millis(999)));
+        timer.poll_received(start + Duration::from_millis(500));
+        assert!(!timer.is_expired(start + Duration::from_millis(1_499)));
+        assert!(timer.is_expired(start + Duration::from_millis(1_500)));
+    }
+    #[test]
+    fn test_criteria_pack() {
+        let criteria = Criteria::new(
+            RSSIFactor::Factor3,
+            ReceiveWindowFactor::Window2,
+            MinQueueSizeLog::N16,
+        );
+        assert_eq!(u8::from(criteria), 0b0100_1100);
+        assert_eq!(criteria.rssi_factor(), RSSIFactor::Factor3);
+        assert_eq!(
+            criteria.receive_window_factor(),
+            ReceiveWindowFactor::Window2
+        );
+        assert_eq!(criteria.min_queue_size_log(), MinQueueSizeLog::N16);
+    }
+    #[test]
+    fn test_select_offer_queue_size() {
+        let weights = Criteria::new(
+            RSSIFactor::Factor1,
+            ReceiveWindowFactor::Window1,
+            MinQueueSizeLog::N4,
+        );
+        let offers = [offer(0x0010, 4), offer(0x0020, 16)];
+        assert_eq!(
+            select_offer(&offers, weights).map(|o| o.src),
+            Some(UnicastAddress::from_mask_u16(0x0020))
+        );
+        // A queue smaller than the minimum disqualifies an offer, even with a better delay.
+        let mut close = offer(0x0030, 2);
+        close.rssi = -20;
+        let offers = [close, offer(0x0010, 4)];
+        assert_eq!(
+            select_offer(&offers, weights).map(|o| o.src),
+            Some(UnicastAddress::from_mask_u16(0x0010))
+        );
+        assert_eq!(select_offer(&offers[..1], weights), None);
+    }
+}
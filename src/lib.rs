@@ -47,6 +47,7 @@ pub mod reassembler;
 pub mod replay;
 pub mod segmenter;
 pub mod upper;
+pub mod uri;
 
 pub mod device_state;
 pub mod friend;
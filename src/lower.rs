@@ -36,10 +36,16 @@ impl SeqZero {
         assert!(seq_zero <= SEQ_ZERO_MAX);
         SeqZero(seq_zero)
     }
-    pub fn original_seq(&self, seq: SequenceNumber) -> SequenceNumber {
-        SequenceNumber(U24::new(
-            (u32::from(seq.0) & !u32::from(SEQ_ZERO_MAX)) & u32::from(self.0),
-        ))
+    /// Reconstructs the full sequence number of the transfer's first segment (the `SeqAuth`
+    /// sequence number) from a segment received with `received_seq`. It's the closest sequence
+    /// number `<= received_seq` whose lower 13 bits equal `SeqZero`, so it works across a
+    /// `SeqZero` wrap as long as `received_seq` is within 8191 of the first segment. Saturates at
+    /// 0 if `SeqZero` can't come before `received_seq`.
+    #[must_use]
+    pub fn to_full_seq(&self, received_seq: SequenceNumber) -> SequenceNumber {
+        let seq = received_seq.0.value();
+        let delta = seq.wrapping_sub(u32::from(self.0)) & u32::from(SEQ_ZERO_MAX);
+        SequenceNumber(U24::new(seq.saturating_sub(delta)))
     }
 }
 impl From<SequenceNumber> for SeqZero {
@@ -71,7 +77,7 @@ impl SeqAuth {
         }
     }
     pub fn from_seq_zero(seq_zero: SeqZero, seq: SequenceNumber, iv_index: IVIndex) -> Self {
-        SeqAuth::new(seq_zero.original_seq(seq), iv_index)
+        SeqAuth::new(seq_zero.to_full_seq(seq), iv_index)
     }
     pub fn valid_seq(&self, new_seq: SequenceNumber) -> bool {
         new_seq >= self.first_seq && (new_seq - self.first_seq) < 8192
@@ -175,7 +181,7 @@ impl BlockAck {
     /// bits that were 1 that are now 0, it is invalid (`false`).
     pub fn is_new(self, maybe_new: Self) -> bool {
         // maybe_new can only have more new bits set than self.
-        maybe_new > self && ((maybe_new.0 & self.0) == maybe_new.0)
+        maybe_new != self && ((maybe_new.0 & self.0) == self.0)
     }
     pub const fn cancel() -> Self {
         BlockAck::new()
@@ -255,8 +261,12 @@ impl SegmentHeader {
 /// |  1  |  1  | Segmented Control		|
 ///
 ///
+/// Largest Upper Transport Access PDU (encrypted access payload + 32-bit TransMIC) that fits in
+/// an Unsegmented Access PDU.
 const UNSEGMENTED_ACCESS_PDU_MAX_LEN: usize = 15;
-const UNSEGMENTED_ACCESS_PDU_MIN_LEN: usize = 5;
+/// Smallest Upper Transport Access PDU. An access payload is at least a 1 octet opcode (no
+/// parameters) so after encryption it's the opcode + the 32-bit TransMIC.
+const UNSEGMENTED_ACCESS_PDU_MIN_LEN: usize = 1 + MIC::small_size();
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
 pub struct UnsegmentedAccessPDU {
     aid: Option<AID>,
@@ -264,13 +274,16 @@ pub struct UnsegmentedAccessPDU {
     access_pdu_len: usize,
 }
 impl UnsegmentedAccessPDU {
+    /// `data` is the Upper Transport Access PDU (encrypted access payload + TransMIC).
     /// # Panics
-    /// Panics if `data.len() > UNSEGMENTED_ACCESS_PDU_LEN` (15)
+    /// Panics if `data.len() > UNSEGMENTED_ACCESS_PDU_MAX_LEN` (15) or
+    /// `data.len() < UNSEGMENTED_ACCESS_PDU_MIN_LEN` (5, opcode + TransMIC).
     pub fn new(aid: Option<AID>, data: &[u8]) -> UnsegmentedAccessPDU {
         assert!(data.len() <= UNSEGMENTED_ACCESS_PDU_MAX_LEN);
         assert!(data.len() >= UNSEGMENTED_ACCESS_PDU_MIN_LEN);
         let len = data.len();
-        let buf = [0_u8; UNSEGMENTED_ACCESS_PDU_MAX_LEN];
+        let mut buf = [0_u8; UNSEGMENTED_ACCESS_PDU_MAX_LEN];
+        buf[..len].copy_from_slice(data);
         UnsegmentedAccessPDU {
             aid,
             access_pdu_buf: buf,
@@ -281,6 +294,16 @@ impl UnsegmentedAccessPDU {
     pub const fn max_len() -> usize {
         UNSEGMENTED_ACCESS_PDU_MAX_LEN + 1
     }
+    /// Largest Upper Transport PDU (including the TransMIC) an Unsegmented Access PDU can hold.
+    #[must_use]
+    pub const fn max_upper_pdu_len() -> usize {
+        UNSEGMENTED_ACCESS_PDU_MAX_LEN
+    }
+    /// Smallest Upper Transport PDU (opcode + TransMIC) an Unsegmented Access PDU can hold.
+    #[must_use]
+    pub const fn min_upper_pdu_len() -> usize {
+        UNSEGMENTED_ACCESS_PDU_MIN_LEN
+    }
     #[must_use]
     pub fn upper_pdu_len(&self) -> usize {
         self.access_pdu_len
@@ -315,15 +338,15 @@ impl UnsegmentedAccessPDU {
     }
     #[must_use]
     pub fn unpack_from(bytes: &[u8]) -> Option<Self> {
-        if SEG::new_upper_masked(bytes[0]).0
-            || bytes.len() > UNSEGMENTED_ACCESS_PDU_MAX_LEN + 1
-            || bytes.len() < UNSEGMENTED_ACCESS_PDU_MIN_LEN
+        if bytes.len() > UNSEGMENTED_ACCESS_PDU_MAX_LEN + 1
+            || bytes.len() < UNSEGMENTED_ACCESS_PDU_MIN_LEN + 1
+            || SEG::new_upper_masked(bytes[0]).0
         {
             None
         } else {
             let akf = AKF::from(bytes[0] & 0x40 != 0);
             let aid = AID::new_masked(bytes[0]);
-            if !bool::from(akf) && u8::from(aid) == 0 {
+            if !bool::from(akf) && u8::from(aid) != 0 {
                 // 0 AKF Flag with a non-zero AID.
                 return None;
             }
@@ -333,10 +356,9 @@ impl UnsegmentedAccessPDU {
     }
     #[must_use]
     pub fn mic(&self) -> MIC {
-        MIC::try_from_bytes_be(
-            &self.access_pdu_buf[self.access_pdu_len - MIC::small_size()..self.access_pdu_len],
-        )
-        .expect("all access PDUs have small MIC")
+        MIC::split_from(self.upper_pdu(), false)
+            .expect("all access PDUs have small MIC")
+            .1
     }
 }
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
@@ -356,7 +378,7 @@ impl SegmentedAccessPDU {
         seg_n: SegN,
         data: &[u8],
     ) -> Self {
-        assert!(data.len() < Self::max_seg_len());
+        assert!(data.len() <= Self::max_seg_len());
         let mut buf = [0_u8; SegmentedAccessPDU::max_seg_len()];
         buf[..data.len()].copy_from_slice(data);
         Self {
@@ -620,7 +642,7 @@ impl SegmentAckPDU {
     }
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
+#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash)]
 pub enum PDU {
     UnsegmentedAccess(UnsegmentedAccessPDU),
     SegmentedAccess(SegmentedAccessPDU),
@@ -689,6 +711,39 @@ impl PDU {
         }
     }
 }
+/// Only prints the header fields (no raw segment/payload buffers) to keep logs readable.
+impl core::fmt::Debug for PDU {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PDU::UnsegmentedAccess(p) => f
+                .debug_struct("UnsegmentedAccess")
+                .field("aid", &p.aid())
+                .field("len", &p.len())
+                .finish(),
+            PDU::SegmentedAccess(p) => f
+                .debug_struct("SegmentedAccess")
+                .field("aid", &p.aid())
+                .field("seq_zero", &u16::from(p.segment_header.seq_zero))
+                .field("seg_o", &u8::from(p.segment_header.seg_o))
+                .field("seg_n", &u8::from(p.segment_header.seg_n))
+                .field("len", &p.segment_len())
+                .finish(),
+            PDU::UnsegmentedControl(p) => f
+                .debug_struct("UnsegmentedControl")
+                .field("opcode", &p.opcode())
+                .field("len", &p.len())
+                .finish(),
+            PDU::SegmentedControl(p) => f
+                .debug_struct("SegmentedControl")
+                .field("opcode", &p.opcode)
+                .field("seq_zero", &u16::from(p.segment_header.seq_zero))
+                .field("seg_o", &u8::from(p.segment_header.seg_o))
+                .field("seg_n", &u8::from(p.segment_header.seg_n))
+                .field("len", &p.segment_len())
+                .finish(),
+        }
+    }
+}
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash, Debug)]
 pub struct PDUBytes {
     buf: [u8; PDU::max_len()],
@@ -791,3 +846,64 @@ impl From<SegmentedPDU> for PDU {
         (&pdu).into()
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_zero_to_full_seq() {
+        let seq = |seq: u32| SequenceNumber(U24::new(seq));
+        // Same 8192 block.
+        assert_eq!(SeqZero::new(0x0010).to_full_seq(seq(0x4015)), seq(0x4010));
+        assert_eq!(SeqZero::new(0x0015).to_full_seq(seq(0x4015)), seq(0x4015));
+        // First segment before the SeqZero wrap, later segment after it.
+        assert_eq!(SeqZero::new(0x1FFE).to_full_seq(seq(0x6001)), seq(0x5FFE));
+        assert_eq!(SeqZero::new(0x1FFF).to_full_seq(seq(0x6000)), seq(0x5FFF));
+        // Furthest back the reassembly window reaches.
+        assert_eq!(SeqZero::new(0x0001).to_full_seq(seq(0x6000)), seq(0x4001));
+        assert_eq!(
+            SeqAuth::from_seq_zero(SeqZero::new(0x1FFE), seq(0x6001), IVIndex(3)),
+            SeqAuth::new(seq(0x5FFE), IVIndex(3))
+        );
+        // SeqZero can't come before sequence number 0.
+        assert_eq!(SeqZero::new(0x0005).to_full_seq(seq(0x0002)), seq(0));
+    }
+
+    #[test]
+    fn test_block_ack_is_new() {
+        let mut acked = BlockAck::ZERO;
+        acked.set(0);
+        acked.set(2);
+        // More segments acked.
+        let mut more = acked;
+        more.set(1);
+        assert!(acked.is_new(more));
+        assert!(acked.is_new(BlockAck::new_all_acked(SegO::new(3))));
+        // Same ack again isn't new.
+        assert!(!acked.is_new(acked));
+        // Acks can't clear segments that were already acked.
+        let mut cleared = BlockAck::ZERO;
+        cleared.set(0);
+        assert!(!acked.is_new(cleared));
+        let mut moved = BlockAck::ZERO;
+        moved.set(0);
+        moved.set(1);
+        moved.set(3);
+        assert!(!acked.is_new(moved));
+    }
+    #[test]
+    fn test_segmented_access_debug() {
+        let pdu = PDU::SegmentedAccess(SegmentedAccessPDU::new(
+            Some(AID::new_masked(0x12)),
+            SZMIC(false),
+            SeqZero::new(0x100),
+            SegO::new(1),
+            SegN::new(3),
+            &[0xAA; 8],
+        ));
+        assert_eq!(
+            format!("{:?}", pdu),
+            "SegmentedAccess { aid: Some(AID(18)), seq_zero: 256, seg_o: 1, seg_n: 3, len: 8 }"
+        );
+    }
+}
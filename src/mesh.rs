@@ -202,19 +202,41 @@ impl U24 {
     pub const fn max_value() -> U24 {
         U24(U24_MAX)
     }
+    /// `self + rhs` modulo 2^24.
+    #[must_use]
+    pub const fn wrapping_add(self, rhs: U24) -> U24 {
+        U24::new_masked(self.0.wrapping_add(rhs.0))
+    }
+    /// `self - rhs` modulo 2^24.
+    #[must_use]
+    pub const fn wrapping_sub(self, rhs: U24) -> U24 {
+        U24::new_masked(self.0.wrapping_sub(rhs.0))
+    }
+    /// `self + rhs` or `None` if the sum doesn't fit in 24 bits.
+    #[must_use]
+    pub fn checked_add(self, rhs: U24) -> Option<U24> {
+        U24::try_from(self.0 + rhs.0).ok()
+    }
+    /// `self - rhs` or `None` if `rhs` is bigger than `self`.
+    #[must_use]
+    pub fn checked_sub(self, rhs: U24) -> Option<U24> {
+        self.0.checked_sub(rhs.0).map(U24)
+    }
 }
+/// Wraps around modulo 2^24 (in debug and release builds) like `U24::wrapping_add`.
 impl core::ops::Add for U24 {
     type Output = U24;
 
     fn add(self, rhs: Self) -> Self::Output {
-        U24::new_masked(self.0 + rhs.0)
+        self.wrapping_add(rhs)
     }
 }
+/// Wraps around modulo 2^24 (in debug and release builds) like `U24::wrapping_sub`.
 impl core::ops::Sub for U24 {
     type Output = U24;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        U24::new_masked(self.0 - rhs.0)
+        self.wrapping_sub(rhs)
     }
 }
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
@@ -618,4 +640,38 @@ mod tests {
     fn test_ttl_out_of_range() {
         let _ = TTL::new(128);
     }
+    #[test]
+    fn test_u24_byte_order() {
+        let cases: [(U24, [u8; 3], [u8; 3]); 4] = [
+            (U24::new(0), [0x00, 0x00, 0x00], [0x00, 0x00, 0x00]),
+            (U24::new(0x01_02_03), [0x01, 0x02, 0x03], [0x03, 0x02, 0x01]),
+            (U24::new(0xAB_00_CD), [0xAB, 0x00, 0xCD], [0xCD, 0x00, 0xAB]),
+            (U24::max_value(), [0xFF, 0xFF, 0xFF], [0xFF, 0xFF, 0xFF]),
+        ];
+        for &(value, be, le) in cases.iter() {
+            assert_eq!(value.to_bytes_be(), be);
+            assert_eq!(value.to_bytes_le(), le);
+            assert_eq!(U24::from_bytes_be(&be), Some(value));
+            assert_eq!(U24::from_bytes_le(&le), Some(value));
+        }
+        // Reading with the wrong endianness swaps the bytes.
+        assert_eq!(
+            U24::from_bytes_le(&[0x01, 0x02, 0x03]),
+            Some(U24::new(0x03_02_01))
+        );
+        assert_eq!(U24::from_bytes_be(&[0x01, 0x02]), None);
+        assert_eq!(U24::from_bytes_le(&[0x01, 0x02, 0x03, 0x04]), None);
+    }
+    #[test]
+    fn test_u24_wrapping_arithmetic() {
+        let near_max = U24::new(U24_MAX - 1);
+        assert_eq!(near_max + near_max, U24::new(U24_MAX - 2));
+        assert_eq!(U24::max_value() + U24::new(1), U24::new(0));
+        assert_eq!(U24::new(0) - U24::new(1), U24::max_value());
+        assert_eq!(U24::new(5) - U24::new(3), U24::new(2));
+        assert_eq!(near_max.checked_add(near_max), None);
+        assert_eq!(near_max.checked_add(U24::new(1)), Some(U24::max_value()));
+        assert_eq!(U24::new(0).checked_sub(U24::new(1)), None);
+        assert_eq!(U24::new(5).checked_sub(U24::new(5)), Some(U24::new(0)));
+    }
 }
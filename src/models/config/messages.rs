@@ -1,23 +1,117 @@
 pub mod beacon {
+    use crate::access::Opcode;
     use crate::foundation::state::SecureNetworkBeaconState;
+    use crate::models::config::ConfigOpcode;
+    use crate::models::{MessagePackError, PackableMessage};
+    use core::convert::TryInto;
 
     #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
     pub struct Get;
+    impl PackableMessage for Get {
+        fn opcode() -> Opcode {
+            ConfigOpcode::BeaconGet.into()
+        }
+
+        fn message_size(&self) -> usize {
+            0
+        }
+
+        fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            Ok(())
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.is_empty() {
+                Ok(Get)
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
     #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
     pub struct Set(pub SecureNetworkBeaconState);
+    impl PackableMessage for Set {
+        fn opcode() -> Opcode {
+            ConfigOpcode::BeaconSet.into()
+        }
+
+        fn message_size(&self) -> usize {
+            1
+        }
+
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            if buffer.is_empty() {
+                Err(MessagePackError::SmallBuffer)
+            } else {
+                buffer[0] = self.0.into();
+                Ok(())
+            }
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.len() == 1 {
+                Ok(Set(buffer[0]
+                    .try_into()
+                    .map_err(|_| MessagePackError::BadBytes)?))
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
     #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
     pub struct Status(pub SecureNetworkBeaconState);
+    impl PackableMessage for Status {
+        fn opcode() -> Opcode {
+            ConfigOpcode::BeaconStatus.into()
+        }
+
+        fn message_size(&self) -> usize {
+            1
+        }
+
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            if buffer.is_empty() {
+                Err(MessagePackError::SmallBuffer)
+            } else {
+                buffer[0] = self.0.into();
+                Ok(())
+            }
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.len() == 1 {
+                Ok(Status(
+                    buffer[0]
+                        .try_into()
+                        .map_err(|_| MessagePackError::BadBytes)?,
+                ))
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
 }
 
 pub mod composition_data {
-    use crate::foundation::CompositionDataPage0;
+    use crate::foundation::{CompositionDataPage, CompositionDataPages};
 
     #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
-    pub struct Get(u8);
+    pub struct Get(pub u8);
     #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
     pub struct Status {
-        page_number: u8,
-        page: CompositionDataPage0,
+        pub page_number: u8,
+        pub page: CompositionDataPage,
+    }
+    impl Status {
+        /// Builds the response to `get` from the node's `pages`. The page number in the `Status`
+        /// is the page actually returned (which may differ from the one requested).
+        pub fn respond(pages: &CompositionDataPages, get: Get) -> Status {
+            let page = pages.select(get.0).clone();
+            Status {
+                page_number: page.page_number(),
+                page,
+            }
+        }
     }
 }
 pub mod default_ttl {
@@ -115,6 +209,7 @@ pub mod default_ttl {
 }
 pub mod gatt_proxy {
     use crate::access::Opcode;
+    use crate::device_state::ConfigStates;
     use crate::foundation::state::GATTProxyState;
     use crate::models::config::ConfigOpcode;
     use crate::models::{MessagePackError, PackableMessage};
@@ -146,6 +241,17 @@ pub mod gatt_proxy {
     }
     #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
     pub struct Set(pub GATTProxyState);
+    impl Set {
+        /// Applies the Set to `config_states` and returns the Status to respond with. A node
+        /// without the Proxy feature keeps reporting `NotSupported`. Setting `NotSupported`
+        /// is prohibited so it's ignored as well.
+        pub fn apply(self, config_states: &mut ConfigStates) -> Status {
+            if config_states.gatt_proxy_state.is_supported() && self.0.is_supported() {
+                config_states.gatt_proxy_state = self.0;
+            }
+            Status(config_states.gatt_proxy_state)
+        }
+    }
     impl PackableMessage for Set {
         fn opcode() -> Opcode {
             ConfigOpcode::GATTProxySet.into()
@@ -207,8 +313,161 @@ pub mod gatt_proxy {
         }
     }
 }
+pub mod heartbeat_publication {
+    use crate::access::Opcode;
+    use crate::address::{Address, ADDRESS_LEN};
+    use crate::bytes::ToFromBytesEndian;
+    use crate::foundation::state::{CountLog, PeriodLog};
+    use crate::foundation::{Features, StatusCode};
+    use crate::mesh::{KeyIndex, NetKeyIndex, TTL};
+    use crate::models::config::ConfigOpcode;
+    use crate::models::{MessagePackError, PackableMessage};
+    use core::convert::TryInto;
+
+    /// Heartbeat Publication parameters shared by the Set and Status messages.
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct HeartbeatPublication {
+        /// Unassigned, Unicast or Group destination for the Heartbeat messages.
+        pub destination: Address,
+        pub count_log: CountLog,
+        pub period_log: PeriodLog,
+        pub ttl: TTL,
+        /// Features that trigger a Heartbeat message when they change.
+        pub features: Features,
+        pub net_key_index: NetKeyIndex,
+    }
+    impl HeartbeatPublication {
+        pub const BYTE_LEN: usize = ADDRESS_LEN + 1 + 1 + 1 + Features::byte_len() + 2;
+        pub fn pack_into(&self, buffer: &mut [u8]) {
+            assert!(
+                buffer.len() >= Self::BYTE_LEN,
+                "not enough room for heartbeat publication"
+            );
+            buffer[..2].copy_from_slice(&u16::from(&self.destination).to_le_bytes());
+            buffer[2] = self.count_log.into();
+            buffer[3] = self.period_log.into();
+            buffer[4] = self.ttl.into();
+            buffer[5..7].copy_from_slice(&self.features.to_bytes_le());
+            buffer[7..9].copy_from_slice(&u16::from(self.net_key_index.0).to_le_bytes());
+        }
+        pub fn unpack(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.len() != Self::BYTE_LEN {
+                return Err(MessagePackError::BadLength);
+            }
+            let destination =
+                Address::from_bytes_le(&buffer[..2]).ok_or(MessagePackError::BadBytes)?;
+            if destination.is_virtual() {
+                return Err(MessagePackError::BadBytes);
+            }
+            Ok(HeartbeatPublication {
+                destination,
+                count_log: buffer[2]
+                    .try_into()
+                    .map_err(|_| MessagePackError::BadBytes)?,
+                period_log: buffer[3]
+                    .try_into()
+                    .map_err(|_| MessagePackError::BadBytes)?,
+                ttl: buffer[4]
+                    .try_into()
+                    .map_err(|_| MessagePackError::BadBytes)?,
+                features: Features::from_bytes_le(&buffer[5..7])
+                    .ok_or(MessagePackError::BadBytes)?,
+                net_key_index: NetKeyIndex(
+                    KeyIndex::new_maybe(
+                        u16::from_bytes_le(&buffer[7..9]).expect("hard coded length"),
+                    )
+                    .ok_or(MessagePackError::BadBytes)?,
+                ),
+            })
+        }
+    }
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct Get;
+    impl PackableMessage for Get {
+        fn opcode() -> Opcode {
+            ConfigOpcode::HeartbeatPublicationGet.into()
+        }
+
+        fn message_size(&self) -> usize {
+            0
+        }
+
+        fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            Ok(())
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.is_empty() {
+                Ok(Get)
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct Set(pub HeartbeatPublication);
+    impl PackableMessage for Set {
+        fn opcode() -> Opcode {
+            ConfigOpcode::HeartbeatPublicationSet.into()
+        }
+
+        fn message_size(&self) -> usize {
+            HeartbeatPublication::BYTE_LEN
+        }
+
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            if buffer.len() < self.message_size() {
+                Err(MessagePackError::SmallBuffer)
+            } else {
+                self.0.pack_into(buffer);
+                Ok(())
+            }
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            Ok(Set(HeartbeatPublication::unpack(buffer)?))
+        }
+    }
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct Status {
+        pub status_code: StatusCode,
+        pub publication: HeartbeatPublication,
+    }
+    impl PackableMessage for Status {
+        fn opcode() -> Opcode {
+            ConfigOpcode::HeartbeatPublicationStatus.into()
+        }
+
+        fn message_size(&self) -> usize {
+            1 + HeartbeatPublication::BYTE_LEN
+        }
+
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            if buffer.len() < self.message_size() {
+                Err(MessagePackError::SmallBuffer)
+            } else {
+                buffer[0] = self.status_code.into();
+                self.publication.pack_into(&mut buffer[1..]);
+                Ok(())
+            }
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.is_empty() {
+                return Err(MessagePackError::BadLength);
+            }
+            Ok(Status {
+                status_code: buffer[0]
+                    .try_into()
+                    .map_err(|_| MessagePackError::BadBytes)?,
+                publication: HeartbeatPublication::unpack(&buffer[1..])?,
+            })
+        }
+    }
+}
 pub mod relay {
     use crate::access::Opcode;
+    use crate::device_state::ConfigStates;
     use crate::foundation::state::{RelayRetransmit, RelayState};
     use crate::models::config::ConfigOpcode;
     use crate::models::{MessagePackError, PackableMessage};
@@ -239,6 +498,18 @@ pub mod relay {
     }
     #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
     pub struct Set(pub RelayState, pub RelayRetransmit);
+    impl Set {
+        /// Applies the Set to `config_states` and returns the Status to respond with. A node
+        /// without the Relay feature keeps reporting `NotSupported` (and its current
+        /// retransmit state). Setting `NotSupported` is prohibited so it's ignored as well.
+        pub fn apply(self, config_states: &mut ConfigStates) -> Status {
+            if config_states.relay_state.is_supported() && self.0.is_supported() {
+                config_states.relay_state = self.0;
+                config_states.relay_retransmit = self.1;
+            }
+            Status(config_states.relay_state, config_states.relay_retransmit)
+        }
+    }
     impl PackableMessage for Set {
         fn opcode() -> Opcode {
             ConfigOpcode::RelaySet.into()
@@ -314,6 +585,101 @@ pub mod relay {
         }
     }
 }
+pub mod network_transmit {
+    use crate::access::Opcode;
+    use crate::device_state::ConfigStates;
+    use crate::foundation::state::NetworkTransmit;
+    use crate::models::config::ConfigOpcode;
+    use crate::models::{MessagePackError, PackableMessage};
+
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct Get;
+    impl PackableMessage for Get {
+        fn opcode() -> Opcode {
+            ConfigOpcode::NetworkTransmitGet.into()
+        }
+
+        fn message_size(&self) -> usize {
+            0
+        }
+
+        fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            Ok(())
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.is_empty() {
+                Ok(Get)
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct Set(pub NetworkTransmit);
+    impl Set {
+        /// Applies the Set to `config_states` and returns the Status to respond with. Every
+        /// Network PDU sent afterwards uses the new count and interval.
+        pub fn apply(self, config_states: &mut ConfigStates) -> Status {
+            config_states.network_transmit = self.0;
+            Status(config_states.network_transmit)
+        }
+    }
+    impl PackableMessage for Set {
+        fn opcode() -> Opcode {
+            ConfigOpcode::NetworkTransmitSet.into()
+        }
+
+        fn message_size(&self) -> usize {
+            1
+        }
+
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            if buffer.len() < self.message_size() {
+                Err(MessagePackError::SmallBuffer)
+            } else {
+                buffer[0] = (self.0).0.into();
+                Ok(())
+            }
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.len() == 1 {
+                Ok(Set(NetworkTransmit(buffer[0].into())))
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
+    #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+    pub struct Status(pub NetworkTransmit);
+    impl PackableMessage for Status {
+        fn opcode() -> Opcode {
+            ConfigOpcode::NetworkTransmitStatus.into()
+        }
+
+        fn message_size(&self) -> usize {
+            1
+        }
+
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            if buffer.len() < self.message_size() {
+                Err(MessagePackError::SmallBuffer)
+            } else {
+                buffer[0] = (self.0).0.into();
+                Ok(())
+            }
+        }
+
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.len() == 1 {
+                Ok(Status(NetworkTransmit(buffer[0].into())))
+            } else {
+                Err(MessagePackError::BadLength)
+            }
+        }
+    }
+}
 pub mod model_publication {
     use crate::access::{ModelIdentifier, Opcode};
     use crate::address::{Address, UnicastAddress, ADDRESS_LEN};
@@ -350,7 +716,7 @@ pub mod model_publication {
         }
 
         fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
-            const SIG_LEN: usize = ADDRESS_LEN + ModelIdentifier::vendor_byte_len();
+            const SIG_LEN: usize = ADDRESS_LEN + ModelIdentifier::sig_byte_len();
             const VENDOR_LEN: usize = ADDRESS_LEN + ModelIdentifier::vendor_byte_len();
             if buffer.len() == SIG_LEN || buffer.len() == VENDOR_LEN {
                 Ok(Get {
@@ -396,9 +762,8 @@ pub mod model_publication {
         }
 
         fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
-            const SIG_LEN: usize = ADDRESS_LEN
-                + ModelPublishInfo::NON_VIRTUAL_LEN
-                + ModelIdentifier::vendor_byte_len();
+            const SIG_LEN: usize =
+                ADDRESS_LEN + ModelPublishInfo::NON_VIRTUAL_LEN + ModelIdentifier::sig_byte_len();
             const VENDOR_LEN: usize = ADDRESS_LEN
                 + ModelPublishInfo::NON_VIRTUAL_LEN
                 + ModelIdentifier::vendor_byte_len();
@@ -453,7 +818,7 @@ pub mod model_publication {
 
         fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
             const SIG_LEN: usize =
-                ADDRESS_LEN + ModelPublishInfo::VIRTUAL_LEN + ModelIdentifier::vendor_byte_len();
+                ADDRESS_LEN + ModelPublishInfo::VIRTUAL_LEN + ModelIdentifier::sig_byte_len();
             const VENDOR_LEN: usize =
                 ADDRESS_LEN + ModelPublishInfo::VIRTUAL_LEN + ModelIdentifier::vendor_byte_len();
             if buffer.len() == SIG_LEN || buffer.len() == VENDOR_LEN {
@@ -518,7 +883,7 @@ pub mod model_publication {
             const SIG_LEN: usize = 1
                 + ADDRESS_LEN
                 + ModelPublishInfo::NON_VIRTUAL_LEN
-                + ModelIdentifier::vendor_byte_len();
+                + ModelIdentifier::sig_byte_len();
             const VENDOR_LEN: usize = 1
                 + ADDRESS_LEN
                 + ModelPublishInfo::NON_VIRTUAL_LEN
@@ -686,3 +1051,242 @@ pub mod app_key_list {
         pub indexes: Vec<NetKeyIndex>,
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::{
+        beacon, composition_data, gatt_proxy, heartbeat_publication, model_publication, relay,
+    };
+    use crate::access::ModelIdentifier;
+    use crate::address::{Address, GroupAddress, UnicastAddress};
+    use crate::foundation::publication::{
+        ModelPublishInfo, PublishPeriod, PublishRetransmit, StepResolution, Steps,
+    };
+    use crate::foundation::state::{CountLog, PeriodLog, SecureNetworkBeaconState};
+    use crate::foundation::{FeatureFlags, Features, StatusCode};
+    use crate::friend::Credentials;
+    use crate::mesh::{
+        AppKeyIndex, CompanyID, ElementIndex, KeyIndex, ModelID, NetKeyIndex, TransmitCount,
+        TransmitInterval, TransmitSteps, TTL,
+    };
+    use crate::models::PackableMessage;
+    use crate::stack::messages::OutgoingMessage;
+
+    #[test]
+    fn test_beacon_messages_round_trip() {
+        for &state in &[
+            SecureNetworkBeaconState::NotBroadcasting,
+            SecureNetworkBeaconState::Broadcasting,
+        ] {
+            let mut buf = [0_u8; 1];
+            beacon::Set(state)
+                .pack_into(&mut buf)
+                .ok()
+                .expect("buffer fits Set");
+            assert_eq!(buf[0], u8::from(state));
+            assert_eq!(
+                beacon::Set::unpack_from(&buf).ok(),
+                Some(beacon::Set(state))
+            );
+            beacon::Status(state)
+                .pack_into(&mut buf)
+                .ok()
+                .expect("buffer fits Status");
+            assert_eq!(
+                beacon::Status::unpack_from(&buf).ok(),
+                Some(beacon::Status(state))
+            );
+        }
+        assert!(beacon::Set::unpack_from(&[0x02]).is_err());
+        assert!(beacon::Status::unpack_from(&[]).is_err());
+        assert_eq!(beacon::Get::unpack_from(&[]).ok(), Some(beacon::Get));
+        assert!(beacon::Get::unpack_from(&[0x00]).is_err());
+    }
+
+    #[test]
+    fn test_heartbeat_publication_indefinite_count() {
+        let mut features = Features::default();
+        features.set(FeatureFlags::Relay);
+        let set = heartbeat_publication::Set(heartbeat_publication::HeartbeatPublication {
+            destination: Address::Group(GroupAddress::new(0xC001).expect("valid group address")),
+            count_log: CountLog::Indefinite,
+            period_log: PeriodLog::Log(0x03),
+            ttl: TTL::new(7),
+            features,
+            net_key_index: NetKeyIndex(KeyIndex::new(0x0123)),
+        });
+        let mut buf = [0_u8; heartbeat_publication::HeartbeatPublication::BYTE_LEN];
+        set.pack_into(&mut buf).ok().expect("buffer fits Set");
+        assert_eq!(buf, [0x01, 0xC0, 0xFF, 0x03, 0x07, 0x01, 0x00, 0x23, 0x01]);
+        let unpacked = heartbeat_publication::Set::unpack_from(&buf)
+            .ok()
+            .expect("valid Set");
+        assert_eq!(unpacked, set);
+        assert_eq!(unpacked.0.count_log.count(), None);
+        assert_eq!(
+            unpacked.0.period_log.period(),
+            Some(core::time::Duration::from_secs(4))
+        );
+        // 0x12..=0xFE are prohibited Count Log values.
+        buf[2] = 0x12;
+        assert!(heartbeat_publication::Set::unpack_from(&buf).is_err());
+    }
+    #[test]
+    fn test_heartbeat_publication_disabled_period() {
+        let buf = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let status = heartbeat_publication::Status::unpack_from(&buf)
+            .ok()
+            .expect("valid Status");
+        assert_eq!(status.status_code, StatusCode::Ok);
+        assert_eq!(status.publication.destination, Address::Unassigned);
+        assert_eq!(status.publication.count_log, CountLog::Disabled);
+        assert_eq!(status.publication.count_log.count(), Some(0));
+        assert_eq!(status.publication.period_log, PeriodLog::Disabled);
+        assert_eq!(status.publication.period_log.period(), None);
+        let mut packed = [0xAA_u8; 10];
+        status
+            .pack_into(&mut packed)
+            .ok()
+            .expect("buffer fits Status");
+        assert_eq!(packed, buf);
+        // Period Log values above 0x11 are prohibited.
+        let mut bad_period = buf;
+        bad_period[4] = 0x12;
+        assert!(heartbeat_publication::Status::unpack_from(&bad_period).is_err());
+    }
+
+    fn publication_get_round_trip(model_identifier: ModelIdentifier, expected_len: usize) {
+        let get = model_publication::Get {
+            element_address: UnicastAddress::new(0x0102).expect("valid unicast address"),
+            model_identifier,
+        };
+        assert_eq!(get.message_size(), expected_len);
+        let mut buf = [0_u8; 6];
+        get.pack_into(&mut buf[..expected_len])
+            .ok()
+            .expect("buffer fits Get");
+        let unpacked = model_publication::Get::unpack_from(&buf[..expected_len])
+            .ok()
+            .expect("valid Get");
+        assert_eq!(unpacked, get);
+    }
+    #[test]
+    fn test_sig_model_publication_get() {
+        publication_get_round_trip(ModelIdentifier::new_sig(ModelID(0x1000)), 4);
+        let unpacked = model_publication::Get::unpack_from(&[0x02, 0x01, 0x00, 0x10])
+            .ok()
+            .expect("valid SIG Get");
+        assert!(unpacked.model_identifier.is_sig());
+        assert_eq!(unpacked.model_identifier.model_id(), ModelID(0x1000));
+    }
+    #[test]
+    fn test_vendor_model_publication_get() {
+        publication_get_round_trip(
+            ModelIdentifier::new_vendor(ModelID(0x0001), CompanyID(0x05F1)),
+            6,
+        );
+        let unpacked = model_publication::Get::unpack_from(&[0x02, 0x01, 0xF1, 0x05, 0x01, 0x00])
+            .ok()
+            .expect("valid vendor Get");
+        assert_eq!(
+            unpacked.model_identifier.company_id(),
+            Some(CompanyID(0x05F1))
+        );
+        assert_eq!(unpacked.model_identifier.model_id(), ModelID(0x0001));
+        assert!(model_publication::Get::unpack_from(&[0x02, 0x01, 0xF1]).is_err());
+    }
+    #[test]
+    fn test_model_publication_set_friendship_credentials() {
+        let set = model_publication::NonVirtualSet {
+            element_address: UnicastAddress::new(0x0102).expect("valid unicast address"),
+            publication: ModelPublishInfo {
+                address: Address::Unicast(UnicastAddress::new(0x0010).expect("valid address")),
+                app_key_index: AppKeyIndex(KeyIndex::new(0x0123)),
+                credential_flag: true,
+                ttl: Some(TTL::new(5)),
+                period: PublishPeriod::new(StepResolution::Second1, Steps::new(5)),
+                retransmit: PublishRetransmit(TransmitInterval::new(
+                    TransmitCount::new(1),
+                    TransmitSteps::new(2),
+                )),
+            },
+            model_identifier: ModelIdentifier::new_sig(ModelID(0x1000)),
+        };
+        let mut buf = [0_u8; 11];
+        assert_eq!(set.message_size(), buf.len());
+        set.pack_into(&mut buf).ok().expect("buffer fits Set");
+        // AppKeyIndex 0x123 with the Credential Flag (bit 12) set.
+        assert_eq!(&buf[4..6], &[0x23, 0x11]);
+        let unpacked = model_publication::NonVirtualSet::unpack_from(&buf)
+            .ok()
+            .expect("valid Set");
+        assert_eq!(unpacked, set);
+        assert!(unpacked.publication.credential_flag);
+        // Messages published with this publication use the friendship credentials.
+        let msg = OutgoingMessage::from_publication(
+            ElementIndex(0),
+            &unpacked.publication,
+            &model_publication::Get {
+                element_address: set.element_address,
+                model_identifier: set.model_identifier,
+            },
+        )
+        .ok()
+        .expect("message packs");
+        assert_eq!(msg.credentials, Credentials::Friendship);
+        assert_eq!(msg.dst, unpacked.publication.address);
+        assert_eq!(msg.ttl, Some(TTL::new(5)));
+    }
+    #[test]
+    fn test_relay_set_not_supported() {
+        use crate::device_state::ConfigStates;
+        use crate::foundation::state::{GATTProxyState, RelayRetransmit, RelayState};
+        use crate::mesh::{TransmitCount, TransmitInterval, TransmitSteps};
+
+        let retransmit = RelayRetransmit(TransmitInterval::new(
+            TransmitCount::new(2),
+            TransmitSteps::new(4),
+        ));
+        let mut config_states = ConfigStates::default();
+        assert_eq!(
+            relay::Set(RelayState::Enabled, retransmit).apply(&mut config_states),
+            relay::Status(RelayState::Enabled, retransmit)
+        );
+        config_states.relay_state = RelayState::NotSupported;
+        config_states.relay_retransmit = RelayRetransmit::default();
+        assert_eq!(
+            relay::Set(RelayState::Enabled, retransmit).apply(&mut config_states),
+            relay::Status(RelayState::NotSupported, RelayRetransmit::default())
+        );
+        assert_eq!(config_states.relay_state, RelayState::NotSupported);
+
+        config_states.gatt_proxy_state = GATTProxyState::NotSupported;
+        assert_eq!(
+            gatt_proxy::Set(GATTProxyState::Enabled).apply(&mut config_states),
+            gatt_proxy::Status(GATTProxyState::NotSupported)
+        );
+    }
+    #[test]
+    fn test_composition_data_get_missing_page() {
+        use crate::foundation::element::{ElementComposition, ElementsComposition, Location};
+        use crate::foundation::{
+            CompositionDataPage, CompositionDataPage0, CompositionDataPages, Features, ProductID,
+            VersionID, CRPL,
+        };
+        let mut element = ElementComposition::new_empty(Location::Main);
+        element.add_model(ModelIdentifier::new_sig(ModelID(0x0000)));
+        let page0 = CompositionDataPage0::new(
+            CompanyID(0x05F1),
+            ProductID(0x0001),
+            VersionID(0x0002),
+            CRPL(0x0020),
+            Features::default(),
+            ElementsComposition::new(vec![element]),
+        );
+        let pages = CompositionDataPages::new(page0.clone());
+        let status = composition_data::Status::respond(&pages, composition_data::Get(5));
+        assert_eq!(status.page_number, 0);
+        assert_eq!(status.page, CompositionDataPage::Page0(page0.clone()));
+        let status = composition_data::Status::respond(&pages, composition_data::Get(0));
+        assert_eq!(status.page, CompositionDataPage::Page0(page0));
+    }
+}
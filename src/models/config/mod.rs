@@ -109,6 +109,7 @@ impl TryFrom<Opcode> for ConfigOpcode {
                     0x01 => Ok(ConfigOpcode::AppKeyUpdate),
                     0x02 => Ok(ConfigOpcode::CompositionDataStatus),
                     0x03 => Ok(ConfigOpcode::ModelPublicationSet),
+                    0x06 => Ok(ConfigOpcode::HeartbeatPublicationStatus),
 
                     _ => Err(OpcodeConversationError(())),
                 },
@@ -0,0 +1,118 @@
+//! Access message dispatch table. Maps each registered `PackableMessage` opcode to a handler that
+//! gets the decoded message.
+use crate::access::Opcode;
+use crate::models::{MessagePackError, PackableMessage};
+use alloc::boxed::Box;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+
+/// Returned by `MessageDispatch::register` when another handler already claimed the opcode.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct OpcodeCollision(pub Opcode);
+/// Returned when an access payload can't be dispatched.
+pub enum DispatchError {
+    /// No handler is registered for the payload's opcode.
+    UnknownOpcode,
+    /// The handler's message couldn't be unpacked from the payload.
+    PackError(MessagePackError),
+}
+impl From<MessagePackError> for DispatchError {
+    fn from(e: MessagePackError) -> Self {
+        DispatchError::PackError(e)
+    }
+}
+type Handler<'a> = Box<dyn FnMut(&[u8]) -> Result<(), MessagePackError> + 'a>;
+/// Opcode to handler table. Every opcode can only be claimed by one handler.
+#[derive(Default)]
+pub struct MessageDispatch<'a> {
+    handlers: BTreeMap<Opcode, Handler<'a>>,
+}
+impl<'a> MessageDispatch<'a> {
+    pub fn new() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+        }
+    }
+    /// Registers `handler` for messages with `M::opcode()`. Returns `OpcodeCollision` (and leaves
+    /// the existing handler in place) if the opcode is already registered.
+    pub fn register<M: PackableMessage + 'a>(
+        &mut self,
+        mut handler: impl FnMut(M) + 'a,
+    ) -> Result<(), OpcodeCollision> {
+        match self.handlers.entry(M::opcode()) {
+            Entry::Occupied(_) => Err(OpcodeCollision(M::opcode())),
+            Entry::Vacant(v) => {
+                v.insert(Box::new(move |payload: &[u8]| {
+                    handler(M::unpack_from(payload)?);
+                    Ok(())
+                }));
+                Ok(())
+            }
+        }
+    }
+    pub fn is_registered(&self, opcode: Opcode) -> bool {
+        self.handlers.contains_key(&opcode)
+    }
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+    /// Decodes the access payload (opcode followed by the message) with the handler registered
+    /// for its opcode and calls the handler.
+    /// Returns `DispatchError::PackError(MessagePackError::BadLength)` if the payload doesn't start
+    /// with a valid opcode.
+    pub fn dispatch(&mut self, access_payload: &[u8]) -> Result<(), DispatchError> {
+        let (opcode, parameters) =
+            Opcode::split_from(access_payload).map_err(|_| MessagePackError::BadLength)?;
+        match self.handlers.get_mut(&opcode) {
+            Some(handler) => handler(parameters).map_err(DispatchError::PackError),
+            None => Err(DispatchError::UnknownOpcode),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::generics::onoff;
+
+    #[test]
+    fn test_register_opcode_collision() {
+        let mut dispatch = MessageDispatch::new();
+        assert_eq!(dispatch.register(|_: onoff::Get| ()), Ok(()));
+        assert_eq!(
+            dispatch.register(|_: onoff::Get| ()),
+            Err(OpcodeCollision(onoff::Get::opcode()))
+        );
+        assert_eq!(dispatch.len(), 1);
+    }
+    #[test]
+    fn test_dispatch_to_registered_handler() {
+        let mut received = None;
+        {
+            let mut dispatch = MessageDispatch::new();
+            assert_eq!(
+                dispatch.register(|set: onoff::Set| received = Some(set)),
+                Ok(())
+            );
+            let set = onoff::Set {
+                on_off: true,
+                tid: 3,
+                transition: None,
+            };
+            let mut buf = [0_u8; 4];
+            assert!(set.pack_with_opcode(&mut buf).is_ok());
+            assert!(dispatch.dispatch(&buf).is_ok());
+            match dispatch.dispatch(&[0x00]) {
+                Err(DispatchError::UnknownOpcode) => (),
+                _ => panic!("expected UnknownOpcode"),
+            }
+            match dispatch.dispatch(&[]) {
+                Err(DispatchError::PackError(MessagePackError::BadLength)) => (),
+                _ => panic!("expected BadLength"),
+            }
+        }
+        assert_eq!(received.map(|set| set.tid), Some(3));
+    }
+}
@@ -0,0 +1,175 @@
+//! Generic Battery messages.
+use crate::access::{Opcode, SigOpcode};
+use crate::bytes::ToFromBytesEndian;
+use crate::mesh::U24;
+use crate::models::{MessagePackError, PackableMessage};
+
+/// Battery level reported when it's unknown.
+const LEVEL_UNKNOWN: u8 = 0xFF;
+/// Max battery level (percent).
+pub const LEVEL_MAX: u8 = 100;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Get;
+impl PackableMessage for Get {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8223).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(Get)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+/// Generic Battery Flags. Presence (bits 0-1), Indicator (bits 2-3), Charging (bits 4-5) and
+/// Serviceability (bits 6-7). `0b11` in a field means unknown.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BatteryFlags(pub u8);
+impl BatteryFlags {
+    pub const UNKNOWN: BatteryFlags = BatteryFlags(0xFF);
+}
+fn pack_time(time: Option<U24>) -> Result<[u8; 3], MessagePackError> {
+    match time {
+        // `0xFFFFFF` is reserved for unknown.
+        Some(time) if time == U24::max_value() => Err(MessagePackError::BadState),
+        time => Ok(time.unwrap_or_else(U24::max_value).to_bytes_le()),
+    }
+}
+fn unpack_time(buffer: &[u8]) -> Option<U24> {
+    let time = U24::from_bytes_le(buffer).expect("hard coded length");
+    if time == U24::max_value() {
+        None
+    } else {
+        Some(time)
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Status {
+    /// Battery level in percent (`0..=100`). `None` if unknown.
+    pub level: Option<u8>,
+    /// Minutes until the battery is discharged. `None` if unknown.
+    pub time_to_discharge: Option<U24>,
+    /// Minutes until the battery is charged. `None` if unknown.
+    pub time_to_charge: Option<U24>,
+    pub flags: BatteryFlags,
+}
+impl Status {
+    pub const BYTE_LEN: usize = 1 + 3 + 3 + 1;
+}
+impl PackableMessage for Status {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8224).into()
+    }
+
+    fn message_size(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        buffer[0] = match self.level {
+            Some(level) if level > LEVEL_MAX => return Err(MessagePackError::BadState),
+            Some(level) => level,
+            None => LEVEL_UNKNOWN,
+        };
+        buffer[1..4].copy_from_slice(&pack_time(self.time_to_discharge)?);
+        buffer[4..7].copy_from_slice(&pack_time(self.time_to_charge)?);
+        buffer[7] = self.flags.0;
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.len() != Self::BYTE_LEN {
+            return Err(MessagePackError::BadLength);
+        }
+        let level = match buffer[0] {
+            LEVEL_UNKNOWN => None,
+            level if level <= LEVEL_MAX => Some(level),
+            _ => return Err(MessagePackError::BadBytes),
+        };
+        Ok(Status {
+            level,
+            time_to_discharge: unpack_time(&buffer[1..4]),
+            time_to_charge: unpack_time(&buffer[4..7]),
+            flags: BatteryFlags(buffer[7]),
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_battery_status() {
+        let mut buf = [0_u8; 2 + Status::BYTE_LEN];
+        assert_eq!(Get.packed_len(), 2);
+        assert!(Get.pack_with_opcode(&mut buf[..2]).is_ok());
+        assert_eq!(buf[..2], [0x82, 0x23]);
+
+        let status = Status {
+            level: Some(85),
+            time_to_discharge: Some(U24::new(0x01_2345)),
+            time_to_charge: Some(U24::new(0)),
+            flags: BatteryFlags(0b0101_1010),
+        };
+        assert_eq!(status.packed_len(), buf.len());
+        assert!(status.pack_with_opcode(&mut buf).is_ok());
+        assert_eq!(
+            buf,
+            [
+                0x82,
+                0x24,
+                85,
+                0x45,
+                0x23,
+                0x01,
+                0x00,
+                0x00,
+                0x00,
+                0b0101_1010
+            ]
+        );
+        assert_eq!(Status::unpack_from(&buf[2..]).ok(), Some(status));
+
+        // Every field unknown.
+        let unknown = Status {
+            level: None,
+            time_to_discharge: None,
+            time_to_charge: None,
+            flags: BatteryFlags::UNKNOWN,
+        };
+        assert!(unknown.pack_with_opcode(&mut buf).is_ok());
+        assert_eq!(buf[2..], [0xFF; Status::BYTE_LEN]);
+        assert_eq!(Status::unpack_from(&buf[2..]).ok(), Some(unknown));
+
+        // Battery levels 0x65..=0xFE are prohibited.
+        assert!(Status::unpack_from(&[0x65, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(Status {
+            level: Some(101),
+            ..status
+        }
+        .pack_into(&mut buf[2..])
+        .is_err());
+        // 0xFFFFFF can't be sent as a known time.
+        assert!(Status {
+            time_to_charge: Some(U24::max_value()),
+            ..status
+        }
+        .pack_into(&mut buf[2..])
+        .is_err());
+        assert!(Status::unpack_from(&buf[2..9]).is_err());
+    }
+}
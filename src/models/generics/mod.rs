@@ -1 +1,3 @@
-
+pub mod battery;
+pub mod onoff;
+pub mod property;
@@ -0,0 +1,136 @@
+//! Generic OnOff messages.
+use crate::access::{Opcode, SigOpcode};
+use crate::models::transition::{Transition, TransitionTime};
+use crate::models::{MessagePackError, PackableMessage};
+
+fn bool_from_byte(b: u8) -> Result<bool, MessagePackError> {
+    match b {
+        0x00 => Ok(false),
+        0x01 => Ok(true),
+        _ => Err(MessagePackError::BadBytes),
+    }
+}
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Get;
+impl PackableMessage for Get {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8201).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(Get)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Set {
+    pub on_off: bool,
+    pub tid: u8,
+    pub transition: Option<Transition>,
+}
+impl PackableMessage for Set {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8202).into()
+    }
+
+    fn message_size(&self) -> usize {
+        2 + self.transition.map_or(0, |_| Transition::BYTE_LEN)
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            buffer[0] = self.on_off.into();
+            buffer[1] = self.tid;
+            if let Some(transition) = self.transition {
+                transition.pack_into(&mut buffer[2..4]);
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let transition = match buffer.len() {
+            2 => None,
+            4 => Some(Transition::unpack_from(&buffer[2..4])),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(Set {
+            on_off: bool_from_byte(buffer[0])?,
+            tid: buffer[1],
+            transition,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetUnacknowledged(pub Set);
+impl PackableMessage for SetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8203).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(SetUnacknowledged(Set::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Status {
+    pub present_on_off: bool,
+    /// Target OnOff and Remaining Time if a transition is in progress.
+    pub target: Option<(bool, TransitionTime)>,
+}
+impl PackableMessage for Status {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8204).into()
+    }
+
+    fn message_size(&self) -> usize {
+        1 + self.target.map_or(0, |_| 2)
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            buffer[0] = self.present_on_off.into();
+            if let Some((target, remaining)) = self.target {
+                buffer[1] = target.into();
+                buffer[2] = remaining.into();
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let target = match buffer.len() {
+            1 => None,
+            3 => Some((bool_from_byte(buffer[1])?, TransitionTime(buffer[2]))),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(Status {
+            present_on_off: bool_from_byte(buffer[0])?,
+            target,
+        })
+    }
+}
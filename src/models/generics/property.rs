@@ -0,0 +1,475 @@
+//! Generic User/Admin/Manufacturer Property messages. Property values are checked against the
+//! `properties` registry so known properties always have the right length.
+use crate::access::{Opcode, SigOpcode};
+use crate::models::{MessagePackError, PackableMessage};
+use crate::properties::PropertyId;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+/// Who can read and/or write a property through the Generic User Property messages.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum PropertyAccess {
+    NotUserAccessible = 0x00,
+    Read = 0x01,
+    Write = 0x02,
+    ReadWrite = 0x03,
+}
+impl From<PropertyAccess> for u8 {
+    fn from(access: PropertyAccess) -> Self {
+        access as u8
+    }
+}
+impl TryFrom<u8> for PropertyAccess {
+    type Error = MessagePackError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PropertyAccess::NotUserAccessible),
+            0x01 => Ok(PropertyAccess::Read),
+            0x02 => Ok(PropertyAccess::Write),
+            0x03 => Ok(PropertyAccess::ReadWrite),
+            _ => Err(MessagePackError::BadBytes),
+        }
+    }
+}
+fn check_value_len(id: PropertyId, value: &[u8]) -> Result<(), MessagePackError> {
+    match id.format() {
+        Some(format) if !format.is_valid_len(value.len()) => Err(MessagePackError::BadLength),
+        _ => Ok(()),
+    }
+}
+fn pack_id(id: PropertyId, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+    if id.is_prohibited() {
+        return Err(MessagePackError::BadState);
+    }
+    buffer[..PropertyId::BYTE_LEN].copy_from_slice(&id.to_bytes_le());
+    Ok(())
+}
+fn unpack_id(buffer: &[u8]) -> Result<PropertyId, MessagePackError> {
+    if buffer.len() < PropertyId::BYTE_LEN {
+        return Err(MessagePackError::BadLength);
+    }
+    let id = PropertyId::from_bytes_le([buffer[0], buffer[1]]);
+    if id.is_prohibited() {
+        Err(MessagePackError::BadBytes)
+    } else {
+        Ok(id)
+    }
+}
+/// Property ID of a User/Admin/Manufacturer Property Get.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PropertyGet(pub PropertyId);
+impl PropertyGet {
+    fn message_size(&self) -> usize {
+        PropertyId::BYTE_LEN
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        pack_id(self.0, buffer)
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.len() != PropertyId::BYTE_LEN {
+            return Err(MessagePackError::BadLength);
+        }
+        Ok(PropertyGet(unpack_id(buffer)?))
+    }
+}
+/// Property ID and value of a User Property Set.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PropertyValue {
+    pub id: PropertyId,
+    pub value: Vec<u8>,
+}
+impl PropertyValue {
+    fn message_size(&self) -> usize {
+        PropertyId::BYTE_LEN + self.value.len()
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        check_value_len(self.id, &self.value).map_err(|_| MessagePackError::BadState)?;
+        pack_id(self.id, buffer)?;
+        buffer[PropertyId::BYTE_LEN..self.message_size()].copy_from_slice(&self.value);
+        Ok(())
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let id = unpack_id(buffer)?;
+        let value = &buffer[PropertyId::BYTE_LEN..];
+        check_value_len(id, value)?;
+        Ok(PropertyValue {
+            id,
+            value: value.to_vec(),
+        })
+    }
+}
+/// Property ID, user access and value of an Admin Property Set.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct AccessPropertyValue {
+    pub id: PropertyId,
+    pub access: PropertyAccess,
+    pub value: Vec<u8>,
+}
+impl AccessPropertyValue {
+    fn message_size(&self) -> usize {
+        PropertyId::BYTE_LEN + 1 + self.value.len()
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        check_value_len(self.id, &self.value).map_err(|_| MessagePackError::BadState)?;
+        pack_id(self.id, buffer)?;
+        buffer[PropertyId::BYTE_LEN] = self.access.into();
+        buffer[PropertyId::BYTE_LEN + 1..self.message_size()].copy_from_slice(&self.value);
+        Ok(())
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let id = unpack_id(buffer)?;
+        let access = PropertyAccess::try_from(
+            *buffer
+                .get(PropertyId::BYTE_LEN)
+                .ok_or(MessagePackError::BadLength)?,
+        )?;
+        let value = &buffer[PropertyId::BYTE_LEN + 1..];
+        check_value_len(id, value)?;
+        Ok(AccessPropertyValue {
+            id,
+            access,
+            value: value.to_vec(),
+        })
+    }
+}
+/// Property ID and user access of a Manufacturer Property Set (the value is read only).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PropertyAccessSet {
+    pub id: PropertyId,
+    pub access: PropertyAccess,
+}
+impl PropertyAccessSet {
+    fn message_size(&self) -> usize {
+        PropertyId::BYTE_LEN + 1
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        pack_id(self.id, buffer)?;
+        buffer[PropertyId::BYTE_LEN] = self.access.into();
+        Ok(())
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.len() != PropertyId::BYTE_LEN + 1 {
+            return Err(MessagePackError::BadLength);
+        }
+        Ok(PropertyAccessSet {
+            id: unpack_id(buffer)?,
+            access: PropertyAccess::try_from(buffer[PropertyId::BYTE_LEN])?,
+        })
+    }
+}
+/// Property ID followed by the user access and value if the property exists on the server.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PropertyStatus {
+    pub id: PropertyId,
+    pub value: Option<(PropertyAccess, Vec<u8>)>,
+}
+impl PropertyStatus {
+    fn message_size(&self) -> usize {
+        PropertyId::BYTE_LEN + self.value.as_ref().map_or(0, |(_, value)| 1 + value.len())
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        pack_id(self.id, buffer)?;
+        if let Some((access, value)) = &self.value {
+            check_value_len(self.id, value).map_err(|_| MessagePackError::BadState)?;
+            buffer[PropertyId::BYTE_LEN] = (*access).into();
+            buffer[PropertyId::BYTE_LEN + 1..self.message_size()].copy_from_slice(value);
+        }
+        Ok(())
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let id = unpack_id(buffer)?;
+        if buffer.len() == PropertyId::BYTE_LEN {
+            return Ok(PropertyStatus { id, value: None });
+        }
+        let value = AccessPropertyValue::unpack_from(buffer)?;
+        Ok(PropertyStatus {
+            id,
+            value: Some((value.access, value.value)),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct UserGet(pub PropertyGet);
+impl PackableMessage for UserGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x822F).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(UserGet(PropertyGet::unpack_from(buffer)?))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct UserSet(pub PropertyValue);
+impl PackableMessage for UserSet {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x4C).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(UserSet(PropertyValue::unpack_from(buffer)?))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct UserSetUnacknowledged(pub PropertyValue);
+impl PackableMessage for UserSetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x4D).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(UserSetUnacknowledged(PropertyValue::unpack_from(buffer)?))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct UserStatus(pub PropertyStatus);
+impl PackableMessage for UserStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x4E).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(UserStatus(PropertyStatus::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct AdminGet(pub PropertyGet);
+impl PackableMessage for AdminGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x822D).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(AdminGet(PropertyGet::unpack_from(buffer)?))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct AdminSet(pub AccessPropertyValue);
+impl PackableMessage for AdminSet {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x48).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(AdminSet(AccessPropertyValue::unpack_from(buffer)?))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct AdminSetUnacknowledged(pub AccessPropertyValue);
+impl PackableMessage for AdminSetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x49).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(AdminSetUnacknowledged(AccessPropertyValue::unpack_from(
+            buffer,
+        )?))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct AdminStatus(pub PropertyStatus);
+impl PackableMessage for AdminStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x4A).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(AdminStatus(PropertyStatus::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ManufacturerGet(pub PropertyGet);
+impl PackableMessage for ManufacturerGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x822B).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(ManufacturerGet(PropertyGet::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ManufacturerSet(pub PropertyAccessSet);
+impl PackableMessage for ManufacturerSet {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x44).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(ManufacturerSet(PropertyAccessSet::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ManufacturerSetUnacknowledged(pub PropertyAccessSet);
+impl PackableMessage for ManufacturerSetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x45).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(ManufacturerSetUnacknowledged(
+            PropertyAccessSet::unpack_from(buffer)?,
+        ))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ManufacturerStatus(pub PropertyStatus);
+impl PackableMessage for ManufacturerStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x46).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(ManufacturerStatus(PropertyStatus::unpack_from(buffer)?))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_property_get_status() {
+        let get = UserGet(PropertyGet(PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL));
+        let mut buf = [0_u8; 4];
+        assert_eq!(get.packed_len(), 4);
+        assert!(get.pack_with_opcode(&mut buf).is_ok());
+        assert_eq!(buf[2..], [0x4E, 0x00]);
+        assert_eq!(UserGet::unpack_from(&buf[2..]).ok(), Some(get));
+
+        let status = UserStatus(PropertyStatus {
+            id: PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL,
+            value: Some((PropertyAccess::Read, vec![0x10, 0x27, 0x00])),
+        });
+        let mut buf = [0_u8; 7];
+        assert_eq!(status.packed_len(), buf.len());
+        assert!(status.pack_with_opcode(&mut buf).is_ok());
+        assert_eq!(buf, [0x4E, 0x4E, 0x00, 0x01, 0x10, 0x27, 0x00]);
+        assert_eq!(UserStatus::unpack_from(&buf[1..]).ok(), Some(status));
+
+        // Registry says Present Ambient Light Level is 3 bytes.
+        assert!(UserStatus::unpack_from(&[0x4E, 0x00, 0x01, 0x10, 0x27]).is_err());
+        // Unknown property on the server.
+        assert_eq!(
+            UserStatus::unpack_from(&[0x4E, 0x00]).ok(),
+            Some(UserStatus(PropertyStatus {
+                id: PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL,
+                value: None,
+            }))
+        );
+    }
+}
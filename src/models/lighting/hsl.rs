@@ -0,0 +1,550 @@
+//! Light HSL messages (HSL, Hue, Saturation, Target and Default).
+use crate::access::{Opcode, SigOpcode};
+use crate::models::transition::{Transition, TransitionTime};
+use crate::models::{MessagePackError, PackableMessage};
+
+fn u16_at(buffer: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([buffer[pos], buffer[pos + 1]])
+}
+/// Light HSL Lightness, Hue and Saturation.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct HSL {
+    pub lightness: u16,
+    pub hue: u16,
+    pub saturation: u16,
+}
+impl HSL {
+    pub const BYTE_LEN: usize = 6;
+    pub fn new(lightness: u16, hue: u16, saturation: u16) -> Self {
+        Self {
+            lightness,
+            hue,
+            saturation,
+        }
+    }
+    pub fn pack_into(&self, buffer: &mut [u8]) {
+        buffer[0..2].copy_from_slice(&self.lightness.to_le_bytes());
+        buffer[2..4].copy_from_slice(&self.hue.to_le_bytes());
+        buffer[4..6].copy_from_slice(&self.saturation.to_le_bytes());
+    }
+    pub fn unpack_from(buffer: &[u8]) -> Self {
+        Self::new(u16_at(buffer, 0), u16_at(buffer, 2), u16_at(buffer, 4))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Get;
+impl PackableMessage for Get {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x826D).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(Get)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Set {
+    pub hsl: HSL,
+    pub tid: u8,
+    pub transition: Option<Transition>,
+}
+impl PackableMessage for Set {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8276).into()
+    }
+
+    fn message_size(&self) -> usize {
+        HSL::BYTE_LEN + 1 + self.transition.map_or(0, |_| Transition::BYTE_LEN)
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            self.hsl.pack_into(&mut buffer[..HSL::BYTE_LEN]);
+            buffer[HSL::BYTE_LEN] = self.tid;
+            if let Some(transition) = self.transition {
+                transition.pack_into(&mut buffer[HSL::BYTE_LEN + 1..HSL::BYTE_LEN + 3]);
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let transition = match buffer.len() {
+            7 => None,
+            9 => Some(Transition::unpack_from(&buffer[7..9])),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(Set {
+            hsl: HSL::unpack_from(buffer),
+            tid: buffer[HSL::BYTE_LEN],
+            transition,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetUnacknowledged(pub Set);
+impl PackableMessage for SetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8277).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(SetUnacknowledged(Set::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Status {
+    pub hsl: HSL,
+    /// Remaining Time if a transition is in progress.
+    pub remaining_time: Option<TransitionTime>,
+}
+impl PackableMessage for Status {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8278).into()
+    }
+
+    fn message_size(&self) -> usize {
+        HSL::BYTE_LEN + self.remaining_time.map_or(0, |_| 1)
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            self.hsl.pack_into(&mut buffer[..HSL::BYTE_LEN]);
+            if let Some(remaining) = self.remaining_time {
+                buffer[HSL::BYTE_LEN] = remaining.into();
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let remaining_time = match buffer.len() {
+            6 => None,
+            7 => Some(TransitionTime(buffer[6])),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(Status {
+            hsl: HSL::unpack_from(buffer),
+            remaining_time,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TargetGet;
+impl PackableMessage for TargetGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8279).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(TargetGet)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+/// Target HSL values (same layout as `Status`).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TargetStatus(pub Status);
+impl PackableMessage for TargetStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x827A).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(TargetStatus(Status::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DefaultGet;
+impl PackableMessage for DefaultGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x827B).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(DefaultGet)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DefaultStatus(pub HSL);
+impl PackableMessage for DefaultStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x827C).into()
+    }
+
+    fn message_size(&self) -> usize {
+        HSL::BYTE_LEN
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            self.0.pack_into(buffer);
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.len() == HSL::BYTE_LEN {
+            Ok(DefaultStatus(HSL::unpack_from(buffer)))
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+/// Hue or Saturation Set body.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ComponentSet {
+    pub value: u16,
+    pub tid: u8,
+    pub transition: Option<Transition>,
+}
+impl ComponentSet {
+    fn message_size(&self) -> usize {
+        3 + self.transition.map_or(0, |_| Transition::BYTE_LEN)
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            buffer[0..2].copy_from_slice(&self.value.to_le_bytes());
+            buffer[2] = self.tid;
+            if let Some(transition) = self.transition {
+                transition.pack_into(&mut buffer[3..5]);
+            }
+            Ok(())
+        }
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let transition = match buffer.len() {
+            3 => None,
+            5 => Some(Transition::unpack_from(&buffer[3..5])),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(ComponentSet {
+            value: u16_at(buffer, 0),
+            tid: buffer[2],
+            transition,
+        })
+    }
+}
+/// Hue or Saturation Status body.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ComponentStatus {
+    pub present: u16,
+    /// Target value and Remaining Time if a transition is in progress.
+    pub target: Option<(u16, TransitionTime)>,
+}
+impl ComponentStatus {
+    fn message_size(&self) -> usize {
+        2 + self.target.map_or(0, |_| 3)
+    }
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            buffer[0..2].copy_from_slice(&self.present.to_le_bytes());
+            if let Some((target, remaining)) = self.target {
+                buffer[2..4].copy_from_slice(&target.to_le_bytes());
+                buffer[4] = remaining.into();
+            }
+            Ok(())
+        }
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let target = match buffer.len() {
+            2 => None,
+            5 => Some((u16_at(buffer, 2), TransitionTime(buffer[4]))),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(ComponentStatus {
+            present: u16_at(buffer, 0),
+            target,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct HueGet;
+impl PackableMessage for HueGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x826E).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(HueGet)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct HueSet(pub ComponentSet);
+impl PackableMessage for HueSet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x826F).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(HueSet(ComponentSet::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct HueSetUnacknowledged(pub ComponentSet);
+impl PackableMessage for HueSetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8270).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(HueSetUnacknowledged(ComponentSet::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct HueStatus(pub ComponentStatus);
+impl PackableMessage for HueStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8271).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(HueStatus(ComponentStatus::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SaturationGet;
+impl PackableMessage for SaturationGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8272).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(SaturationGet)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SaturationSet(pub ComponentSet);
+impl PackableMessage for SaturationSet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8273).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(SaturationSet(ComponentSet::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SaturationSetUnacknowledged(pub ComponentSet);
+impl PackableMessage for SaturationSetUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8274).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(SaturationSetUnacknowledged(ComponentSet::unpack_from(
+            buffer,
+        )?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SaturationStatus(pub ComponentStatus);
+impl PackableMessage for SaturationStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8275).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(SaturationStatus(ComponentStatus::unpack_from(buffer)?))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<M: PackableMessage>(message: &M) -> Option<M> {
+        let mut buf = vec![0_u8; message.message_size()];
+        message.pack_into(&mut buf).ok()?;
+        M::unpack_from(&buf).ok()
+    }
+    #[test]
+    fn test_hsl_set_round_trip() {
+        let hsl = HSL::new(0x1234, 0xABCD, 0x8000);
+        let set = Set {
+            hsl,
+            tid: 7,
+            transition: None,
+        };
+        assert_eq!(set.message_size(), 7);
+        assert_eq!(round_trip(&set), Some(set));
+        let with_transition = SetUnacknowledged(Set {
+            transition: Some(Transition::new(TransitionTime(0x41), 10)),
+            ..set
+        });
+        assert_eq!(with_transition.message_size(), 9);
+        let mut buf = [0_u8; 9];
+        assert!(with_transition.pack_into(&mut buf).is_ok());
+        assert_eq!(buf, [0x34, 0x12, 0xCD, 0xAB, 0x00, 0x80, 7, 0x41, 10]);
+        assert_eq!(round_trip(&with_transition), Some(with_transition));
+        assert!(Set::unpack_from(&buf[..8]).is_err());
+    }
+    #[test]
+    fn test_hsl_status_round_trip() {
+        let status = Status {
+            hsl: HSL::new(1, 2, 3),
+            remaining_time: Some(TransitionTime(0x05)),
+        };
+        assert_eq!(round_trip(&status), Some(status));
+        let target = TargetStatus(Status {
+            remaining_time: None,
+            ..status
+        });
+        assert_eq!(target.message_size(), 6);
+        assert_eq!(round_trip(&target), Some(target));
+        let default = DefaultStatus(HSL::new(0xFFFF, 0, 0x7FFF));
+        assert_eq!(round_trip(&default), Some(default));
+        assert!(DefaultStatus::unpack_from(&[0_u8; 7]).is_err());
+    }
+    #[test]
+    fn test_hue_saturation_round_trip() {
+        let hue = HueSet(ComponentSet {
+            value: 0x4000,
+            tid: 1,
+            transition: Some(Transition::new(TransitionTime(0x0A), 0)),
+        });
+        assert_eq!(round_trip(&hue), Some(hue));
+        let saturation = SaturationStatus(ComponentStatus {
+            present: 0x1000,
+            target: Some((0x2000, TransitionTime(0x03))),
+        });
+        assert_eq!(saturation.message_size(), 5);
+        assert_eq!(round_trip(&saturation), Some(saturation));
+        let present_only = HueStatus(ComponentStatus {
+            present: 0x1000,
+            target: None,
+        });
+        assert_eq!(round_trip(&present_only), Some(present_only));
+    }
+}
@@ -1 +1 @@
-
+pub mod hsl;
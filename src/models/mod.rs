@@ -1,13 +1,19 @@
 use crate::access::Opcode;
 
 pub mod config;
+pub mod dispatch;
 pub mod generics;
 pub mod lighting;
+pub mod scenes;
 pub mod sensors;
 pub mod state;
+pub mod tid;
 pub mod time;
+pub mod transition;
+pub mod vendor;
 
 /// Error when trying to pack a message into a byte buffer.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 pub enum MessagePackError {
     /// Byte Buffer too small to fit the whole message.
     SmallBuffer,
@@ -18,6 +24,18 @@ pub enum MessagePackError {
     /// Message can't be packed because the object is in a bad state.
     BadState,
 }
+impl core::fmt::Display for MessagePackError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            MessagePackError::SmallBuffer => "buffer too small to fit the message",
+            MessagePackError::BadLength => "message has a bad length",
+            MessagePackError::BadBytes => "message bytes are invalid",
+            MessagePackError::BadState => "message is in a bad state to pack",
+        })
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for MessagePackError {}
 
 /// An Access Message that can be packed into a (little endian) byte buffer.
 /// If a message comes in that matches `Opcode`, the stack will try to decode it with
@@ -29,7 +47,16 @@ pub trait PackableMessage: Sized {
     /// Pack the message into the byte buffer (without the opcode). If the length of the buffer is
     /// too small or the object is in a bad state, return `MessagePackError`.
     fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError>;
+    /// Bytes needed to fit the entire message including the opcode.
+    fn packed_len(&self) -> usize {
+        Self::opcode().byte_len() + self.message_size()
+    }
+    /// Pack the opcode followed by the message into the byte buffer. Returns
+    /// `MessagePackError::SmallBuffer` if `buffer.len() < self.packed_len()`.
     fn pack_with_opcode(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.packed_len() {
+            return Err(MessagePackError::SmallBuffer);
+        }
         let opcode = Self::opcode();
         let opcode_len = opcode.byte_len();
         self.pack_into(&mut buffer[opcode_len..opcode_len + self.message_size()])?;
@@ -41,4 +68,65 @@ pub trait PackableMessage: Sized {
     /// Unpack the message from the byte buffer (without the opcode). Make sure to check for a valid
     /// message or return a `MessagePackError` otherwise.
     fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError>;
+    /// Unpack the message from a byte buffer starting with the opcode. Returns
+    /// `MessagePackError::BadBytes` if the buffer doesn't start with `Self::opcode()`.
+    fn unpack_with_opcode(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let (opcode, parameters) =
+            Opcode::split_from(buffer).map_err(|_| MessagePackError::BadLength)?;
+        if opcode == Self::opcode() {
+            Self::unpack_from(parameters)
+        } else {
+            Err(MessagePackError::BadBytes)
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::generics::onoff;
+
+    #[test]
+    fn test_pack_with_opcode_small_buffer() {
+        let set = onoff::Set {
+            on_off: true,
+            tid: 1,
+            transition: None,
+        };
+        assert_eq!(set.packed_len(), 2 + 2);
+        let mut buf = [0_u8; 3];
+        match set.pack_with_opcode(&mut buf) {
+            Err(MessagePackError::SmallBuffer) => (),
+            _ => panic!("expected SmallBuffer"),
+        }
+        let mut buf = [0_u8; 4];
+        assert!(set.pack_with_opcode(&mut buf).is_ok());
+        assert_eq!(onoff::Set::unpack_with_opcode(&buf).ok(), Some(set));
+        match onoff::Get::unpack_with_opcode(&buf) {
+            Err(MessagePackError::BadBytes) => (),
+            _ => panic!("expected BadBytes"),
+        }
+        match onoff::Set::unpack_with_opcode(&[]) {
+            Err(MessagePackError::BadLength) => (),
+            _ => panic!("expected BadLength"),
+        }
+    }
+    #[test]
+    fn test_message_pack_error_display() {
+        assert_eq!(
+            format!("{}", MessagePackError::SmallBuffer),
+            "buffer too small to fit the message"
+        );
+        assert_eq!(
+            format!("{}", MessagePackError::BadLength),
+            "message has a bad length"
+        );
+        assert_eq!(
+            format!("{}", MessagePackError::BadBytes),
+            "message bytes are invalid"
+        );
+        assert_eq!(
+            format!("{}", MessagePackError::BadState),
+            "message is in a bad state to pack"
+        );
+    }
 }
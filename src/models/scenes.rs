@@ -0,0 +1,401 @@
+//! Scene Server/Setup Server messages. Scene numbers are 16-bit and `0x0000` is prohibited.
+use crate::access::{Opcode, SigOpcode};
+use crate::models::transition::{Transition, TransitionTime};
+use crate::models::{MessagePackError, PackableMessage};
+use alloc::vec::Vec;
+
+/// Non-zero 16-bit Scene Number.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct SceneNumber(u16);
+impl SceneNumber {
+    pub const BYTE_LEN: usize = 2;
+    /// Returns `None` for the prohibited scene number `0x0000`.
+    pub fn new(number: u16) -> Option<SceneNumber> {
+        if number == 0 {
+            None
+        } else {
+            Some(SceneNumber(number))
+        }
+    }
+    pub fn value(self) -> u16 {
+        self.0
+    }
+    fn pack_into(self, buffer: &mut [u8]) {
+        buffer[..Self::BYTE_LEN].copy_from_slice(&self.0.to_le_bytes());
+    }
+    fn unpack_from(buffer: &[u8]) -> Result<SceneNumber, MessagePackError> {
+        SceneNumber::new(u16::from_le_bytes([buffer[0], buffer[1]]))
+            .ok_or(MessagePackError::BadBytes)
+    }
+}
+impl From<SceneNumber> for u16 {
+    fn from(number: SceneNumber) -> Self {
+        number.0
+    }
+}
+/// Current scene field of a status. `0x0000` means no scene is active.
+fn pack_current_scene(scene: Option<SceneNumber>, buffer: &mut [u8]) {
+    buffer[..SceneNumber::BYTE_LEN].copy_from_slice(&scene.map_or(0, u16::from).to_le_bytes());
+}
+fn unpack_current_scene(buffer: &[u8]) -> Option<SceneNumber> {
+    SceneNumber::new(u16::from_le_bytes([buffer[0], buffer[1]]))
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum StatusCode {
+    Success = 0x00,
+    RegisterFull = 0x01,
+    NotFound = 0x02,
+}
+impl StatusCode {
+    fn from_u8(code: u8) -> Result<StatusCode, MessagePackError> {
+        match code {
+            0x00 => Ok(StatusCode::Success),
+            0x01 => Ok(StatusCode::RegisterFull),
+            0x02 => Ok(StatusCode::NotFound),
+            _ => Err(MessagePackError::BadBytes),
+        }
+    }
+}
+impl From<StatusCode> for u8 {
+    fn from(code: StatusCode) -> Self {
+        code as u8
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Get;
+impl PackableMessage for Get {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8241).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(Get)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Recall {
+    pub scene: SceneNumber,
+    pub tid: u8,
+    pub transition: Option<Transition>,
+}
+impl PackableMessage for Recall {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8242).into()
+    }
+
+    fn message_size(&self) -> usize {
+        SceneNumber::BYTE_LEN + 1 + self.transition.map_or(0, |_| Transition::BYTE_LEN)
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            self.scene.pack_into(buffer);
+            buffer[2] = self.tid;
+            if let Some(transition) = self.transition {
+                transition.pack_into(&mut buffer[3..5]);
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let transition = match buffer.len() {
+            3 => None,
+            5 => Some(Transition::unpack_from(&buffer[3..5])),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(Recall {
+            scene: SceneNumber::unpack_from(buffer)?,
+            tid: buffer[2],
+            transition,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RecallUnacknowledged(pub Recall);
+impl PackableMessage for RecallUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8243).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(RecallUnacknowledged(Recall::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Status {
+    pub status_code: StatusCode,
+    pub current_scene: Option<SceneNumber>,
+    /// Target Scene and Remaining Time if a transition is in progress.
+    pub target: Option<(Option<SceneNumber>, TransitionTime)>,
+}
+impl PackableMessage for Status {
+    fn opcode() -> Opcode {
+        SigOpcode::SingleOctet(0x5E).into()
+    }
+
+    fn message_size(&self) -> usize {
+        3 + self.target.map_or(0, |_| 3)
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            buffer[0] = self.status_code.into();
+            pack_current_scene(self.current_scene, &mut buffer[1..3]);
+            if let Some((target, remaining)) = self.target {
+                pack_current_scene(target, &mut buffer[3..5]);
+                buffer[5] = remaining.into();
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        let target = match buffer.len() {
+            3 => None,
+            6 => Some((
+                unpack_current_scene(&buffer[3..5]),
+                TransitionTime(buffer[5]),
+            )),
+            _ => return Err(MessagePackError::BadLength),
+        };
+        Ok(Status {
+            status_code: StatusCode::from_u8(buffer[0])?,
+            current_scene: unpack_current_scene(&buffer[1..3]),
+            target,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RegisterGet;
+impl PackableMessage for RegisterGet {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8244).into()
+    }
+
+    fn message_size(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, _buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Ok(())
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.is_empty() {
+            Ok(RegisterGet)
+        } else {
+            Err(MessagePackError::BadLength)
+        }
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RegisterStatus {
+    pub status_code: StatusCode,
+    pub current_scene: Option<SceneNumber>,
+    pub scenes: Vec<SceneNumber>,
+}
+impl PackableMessage for RegisterStatus {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8245).into()
+    }
+
+    fn message_size(&self) -> usize {
+        3 + self.scenes.len() * SceneNumber::BYTE_LEN
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            buffer[0] = self.status_code.into();
+            pack_current_scene(self.current_scene, &mut buffer[1..3]);
+            for (scene, chunk) in self
+                .scenes
+                .iter()
+                .zip(buffer[3..].chunks_exact_mut(SceneNumber::BYTE_LEN))
+            {
+                scene.pack_into(chunk);
+            }
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.len() < 3 || (buffer.len() - 3) % SceneNumber::BYTE_LEN != 0 {
+            return Err(MessagePackError::BadLength);
+        }
+        Ok(RegisterStatus {
+            status_code: StatusCode::from_u8(buffer[0])?,
+            current_scene: unpack_current_scene(&buffer[1..3]),
+            scenes: buffer[3..]
+                .chunks_exact(SceneNumber::BYTE_LEN)
+                .map(SceneNumber::unpack_from)
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Store(pub SceneNumber);
+impl PackableMessage for Store {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8246).into()
+    }
+
+    fn message_size(&self) -> usize {
+        SceneNumber::BYTE_LEN
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.message_size() {
+            Err(MessagePackError::SmallBuffer)
+        } else {
+            self.0.pack_into(buffer);
+            Ok(())
+        }
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        if buffer.len() != SceneNumber::BYTE_LEN {
+            return Err(MessagePackError::BadLength);
+        }
+        Ok(Store(SceneNumber::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct StoreUnacknowledged(pub Store);
+impl PackableMessage for StoreUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x8247).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(StoreUnacknowledged(Store::unpack_from(buffer)?))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Delete(pub SceneNumber);
+impl PackableMessage for Delete {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x829E).into()
+    }
+
+    fn message_size(&self) -> usize {
+        SceneNumber::BYTE_LEN
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        Store(self.0).pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(Delete(Store::unpack_from(buffer)?.0))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DeleteUnacknowledged(pub Delete);
+impl PackableMessage for DeleteUnacknowledged {
+    fn opcode() -> Opcode {
+        SigOpcode::DoubleOctet(0x829F).into()
+    }
+
+    fn message_size(&self) -> usize {
+        self.0.message_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        self.0.pack_into(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        Ok(DeleteUnacknowledged(Delete::unpack_from(buffer)?))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(number: u16) -> SceneNumber {
+        SceneNumber::new(number).expect("non-zero scene number")
+    }
+    #[test]
+    fn test_recall_with_transition() {
+        let recall = Recall {
+            scene: scene(0x0102),
+            tid: 0x33,
+            transition: Some(Transition::new(TransitionTime(0x54), 0x02)),
+        };
+        let mut buf = [0_u8; 5];
+        assert_eq!(recall.message_size(), buf.len());
+        assert!(recall.pack_into(&mut buf).is_ok());
+        assert_eq!(buf, [0x02, 0x01, 0x33, 0x54, 0x02]);
+        assert_eq!(Recall::unpack_from(&buf).ok(), Some(recall));
+        assert_eq!(
+            RecallUnacknowledged::unpack_from(&buf[..3]).ok(),
+            Some(RecallUnacknowledged(Recall {
+                transition: None,
+                ..recall
+            }))
+        );
+        // Scene 0x0000 is prohibited.
+        assert!(Recall::unpack_from(&[0x00, 0x00, 0x33]).is_err());
+        assert_eq!(SceneNumber::new(0), None);
+    }
+    #[test]
+    fn test_register_status_multiple_scenes() {
+        let status = RegisterStatus {
+            status_code: StatusCode::Success,
+            current_scene: Some(scene(2)),
+            scenes: vec![scene(1), scene(2), scene(0x1234)],
+        };
+        let mut buf = vec![0_u8; status.message_size()];
+        assert!(status.pack_into(&mut buf).is_ok());
+        assert_eq!(buf, [0x00, 0x02, 0x00, 0x01, 0x00, 0x02, 0x00, 0x34, 0x12]);
+        assert_eq!(RegisterStatus::unpack_from(&buf).ok(), Some(status));
+        // Odd trailing byte and a prohibited scene in the register.
+        assert!(RegisterStatus::unpack_from(&buf[..8]).is_err());
+        assert!(RegisterStatus::unpack_from(&[0x00, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert_eq!(
+            RegisterStatus::unpack_from(&[0x02, 0x00, 0x00]).ok(),
+            Some(RegisterStatus {
+                status_code: StatusCode::NotFound,
+                current_scene: None,
+                scenes: Vec::new(),
+            })
+        );
+    }
+}
@@ -13,3 +13,84 @@ pub trait StateEndpoint {
         Ok(())
     }
 }
+
+/// State binding from the Mesh Model spec. When the source state (`Self`) changes, the bound
+/// state is updated to match so the node's states stay coherent.
+pub trait Binding<Bound: State>: State {
+    fn bind(&self, bound: &mut Bound);
+}
+/// Generic OnOff state.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct GenericOnOff(pub bool);
+impl State for GenericOnOff {}
+/// Generic Level state (signed 16-bit).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct GenericLevel(pub i16);
+impl State for GenericLevel {}
+/// Light Lightness Actual state. `0` is off.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LightLightness(pub u16);
+impl State for LightLightness {}
+/// Generic OnOff is on when Light Lightness Actual is non-zero.
+impl Binding<GenericOnOff> for LightLightness {
+    fn bind(&self, bound: &mut GenericOnOff) {
+        bound.0 = self.0 != 0;
+    }
+}
+/// Generic Level = Light Lightness Actual - 32768.
+impl Binding<GenericLevel> for LightLightness {
+    fn bind(&self, bound: &mut GenericLevel) {
+        bound.0 = (i32::from(self.0) - 32768) as i16;
+    }
+}
+/// Light Lightness with its bound Generic OnOff and Generic Level states.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LightLightnessStates {
+    lightness: LightLightness,
+    on_off: GenericOnOff,
+    level: GenericLevel,
+}
+impl LightLightnessStates {
+    pub fn new(lightness: LightLightness) -> Self {
+        let mut states = Self::default();
+        states.set_lightness(lightness);
+        states
+    }
+    pub fn lightness(&self) -> LightLightness {
+        self.lightness
+    }
+    pub fn on_off(&self) -> GenericOnOff {
+        self.on_off
+    }
+    pub fn level(&self) -> GenericLevel {
+        self.level
+    }
+    /// Sets Light Lightness Actual and updates the bound states.
+    pub fn set_lightness(&mut self, lightness: LightLightness) {
+        self.lightness = lightness;
+        lightness.bind(&mut self.on_off);
+        lightness.bind(&mut self.level);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lightness_binding() {
+        let mut states = LightLightnessStates::new(LightLightness(0));
+        assert_eq!(states.on_off(), GenericOnOff(false));
+        assert_eq!(states.level(), GenericLevel(i16::min_value()));
+
+        states.set_lightness(LightLightness(1));
+        assert_eq!(states.on_off(), GenericOnOff(true));
+        assert_eq!(states.level(), GenericLevel(-32767));
+
+        states.set_lightness(LightLightness(0xFFFF));
+        assert_eq!(states.on_off(), GenericOnOff(true));
+        assert_eq!(states.level(), GenericLevel(i16::max_value()));
+
+        states.set_lightness(LightLightness(0));
+        assert_eq!(states.on_off(), GenericOnOff(false));
+    }
+}
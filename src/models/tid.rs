@@ -0,0 +1,76 @@
+//! Transaction Identifier (TID) used by Generic and Lighting Set/Move messages so a server can
+//! tell a new transaction apart from a retransmission of the last one.
+use core::time::Duration;
+use driver_async::time::{Instant, InstantTrait};
+
+/// Tracks the TID for one client/server pair. Clients call `next()` for every new transaction
+/// (and reuse `current()` for retransmissions). Servers call `same_transaction` to check if an
+/// incoming TID is a repeat of the last transaction seen within `Tid::WINDOW`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct Tid {
+    current: u8,
+    last_seen: Option<(u8, Instant)>,
+}
+impl Tid {
+    /// Messages with the same TID within this window belong to the same transaction.
+    pub const WINDOW: Duration = Duration::from_secs(6);
+    pub fn new(start: u8) -> Tid {
+        Tid {
+            current: start,
+            last_seen: None,
+        }
+    }
+    /// TID of the current (last started) transaction.
+    pub fn current(&self) -> u8 {
+        self.current
+    }
+    /// Starts a new transaction and returns its TID.
+    pub fn next(&mut self) -> u8 {
+        self.current = self.current.wrapping_add(1);
+        self.current
+    }
+    /// Returns `true` if `tid` is the last observed TID and it was observed less than
+    /// `Tid::WINDOW` before `now`.
+    pub fn same_transaction(&self, tid: u8, now: Instant) -> bool {
+        match self.last_seen {
+            Some((last_tid, last_time)) => {
+                last_tid == tid
+                    && now
+                        .checked_duration_since(last_time)
+                        .map_or(true, |elapsed| elapsed < Self::WINDOW)
+            }
+            None => false,
+        }
+    }
+    /// Records `tid` as observed at `now`. Returns `true` if it's a new transaction (not a
+    /// repeat of the last one).
+    pub fn observe(&mut self, tid: u8, now: Instant) -> bool {
+        let new_transaction = !self.same_transaction(tid, now);
+        if new_transaction {
+            self.last_seen = Some((tid, now));
+        }
+        new_transaction
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tid_next_and_window() {
+        let mut tid = Tid::new(0xFF);
+        let first = tid.next();
+        let second = tid.next();
+        assert_ne!(first, second);
+        assert_eq!(first, 0x00);
+        assert_eq!(tid.current(), second);
+
+        let now = Instant::now();
+        assert!(tid.observe(second, now));
+        assert!(tid.same_transaction(second, now + Duration::from_secs(5)));
+        assert!(!tid.observe(second, now + Duration::from_secs(5)));
+        assert!(!tid.same_transaction(first, now + Duration::from_secs(1)));
+        assert!(!tid.same_transaction(second, now + Tid::WINDOW));
+        assert!(tid.observe(second, now + Duration::from_secs(7)));
+    }
+}
@@ -0,0 +1,42 @@
+//! Generic Default Transition Time and the optional Transition/Delay fields that trail most
+//! Generic and Lighting Set messages.
+
+/// Generic Transition Time (6-bit steps + 2-bit step resolution). `0x3F` steps means unknown.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct TransitionTime(pub u8);
+impl TransitionTime {
+    pub const UNKNOWN_STEPS: u8 = 0x3F;
+    pub fn steps(self) -> u8 {
+        self.0 & 0x3F
+    }
+    pub fn is_unknown(self) -> bool {
+        self.steps() == Self::UNKNOWN_STEPS
+    }
+}
+impl From<TransitionTime> for u8 {
+    fn from(t: TransitionTime) -> Self {
+        t.0
+    }
+}
+/// Optional Transition Time and Delay (in 5 millisecond steps) of a Set message.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct Transition {
+    pub transition_time: TransitionTime,
+    pub delay: u8,
+}
+impl Transition {
+    pub const BYTE_LEN: usize = 2;
+    pub fn new(transition_time: TransitionTime, delay: u8) -> Self {
+        Self {
+            transition_time,
+            delay,
+        }
+    }
+    pub fn pack_into(&self, buffer: &mut [u8]) {
+        buffer[0] = self.transition_time.into();
+        buffer[1] = self.delay;
+    }
+    pub fn unpack_from(buffer: &[u8]) -> Self {
+        Self::new(TransitionTime(buffer[0]), buffer[1])
+    }
+}
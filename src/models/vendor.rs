@@ -0,0 +1,100 @@
+//! Scaffolding for vendor models. Vendor messages always use a 3-octet opcode made of a 6-bit
+//! `VendorOpcode` and the vendor's `CompanyID`. Implement `VendorMessage` (only the payload) and
+//! the message gets a matching `PackableMessage` implementation for free.
+use crate::access::{Opcode, VendorOpcode};
+use crate::mesh::CompanyID;
+use crate::models::{MessagePackError, PackableMessage};
+
+/// Access message of a vendor model. Only the payload has to be packed/unpacked, the opcode is
+/// always `Opcode::Vendor(Self::vendor_opcode(), Self::COMPANY_ID)`.
+pub trait VendorMessage: Sized {
+    /// Company ID of the vendor defining the message.
+    const COMPANY_ID: CompanyID;
+    /// 6-bit opcode of the message within the vendor's opcode space.
+    fn vendor_opcode() -> VendorOpcode;
+    /// Bytes need to fit the payload (excluding the opcode).
+    fn payload_size(&self) -> usize;
+    /// Pack the payload into the byte buffer (without the opcode).
+    fn pack_payload(&self, buffer: &mut [u8]) -> Result<(), MessagePackError>;
+    /// Unpack the payload from the byte buffer (without the opcode).
+    fn unpack_payload(buffer: &[u8]) -> Result<Self, MessagePackError>;
+}
+impl<M: VendorMessage> PackableMessage for M {
+    fn opcode() -> Opcode {
+        Opcode::Vendor(M::vendor_opcode(), M::COMPANY_ID)
+    }
+
+    fn message_size(&self) -> usize {
+        self.payload_size()
+    }
+
+    fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+        if buffer.len() < self.payload_size() {
+            return Err(MessagePackError::SmallBuffer);
+        }
+        self.pack_payload(buffer)
+    }
+
+    fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+        M::unpack_payload(buffer)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example vendor message setting a 16-bit level on a fictional vendor model.
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    struct LevelSet {
+        level: u16,
+        ack: bool,
+    }
+    impl VendorMessage for LevelSet {
+        const COMPANY_ID: CompanyID = CompanyID(0x05F1);
+
+        fn vendor_opcode() -> VendorOpcode {
+            VendorOpcode::new(0x01)
+        }
+
+        fn payload_size(&self) -> usize {
+            3
+        }
+
+        fn pack_payload(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            buffer[..2].copy_from_slice(&self.level.to_le_bytes());
+            buffer[2] = self.ack.into();
+            Ok(())
+        }
+
+        fn unpack_payload(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            if buffer.len() != 3 {
+                return Err(MessagePackError::BadLength);
+            }
+            let ack = match buffer[2] {
+                0 => false,
+                1 => true,
+                _ => return Err(MessagePackError::BadBytes),
+            };
+            Ok(LevelSet {
+                level: u16::from_le_bytes([buffer[0], buffer[1]]),
+                ack,
+            })
+        }
+    }
+    #[test]
+    fn test_vendor_message_round_trip() {
+        let set = LevelSet {
+            level: 0x1234,
+            ack: true,
+        };
+        assert_eq!(set.packed_len(), 3 + 3);
+        let mut buf = [0_u8; 6];
+        assert!(set.pack_with_opcode(&mut buf).is_ok());
+        assert_eq!(buf, [0xC1, 0xF1, 0x05, 0x34, 0x12, 0x01]);
+        assert_eq!(LevelSet::unpack_with_opcode(&buf).ok(), Some(set));
+        match LevelSet::unpack_with_opcode(&[0xC2, 0xF1, 0x05, 0x34, 0x12, 0x01]) {
+            Err(MessagePackError::BadBytes) => (),
+            _ => panic!("expected BadBytes for a different vendor opcode"),
+        }
+    }
+}
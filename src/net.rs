@@ -145,7 +145,7 @@ impl EncryptedData<'_> {
     #[must_use]
     pub fn packed_privacy_random(&self, dst: Address, iv_index: IVIndex) -> PackedPrivacy {
         let mut privacy_random_buf = [0_u8; PRIVACY_RANDOM_LEN + MIC::max_len()];
-        privacy_random_buf[..ADDRESS_LEN].copy_from_slice(&dst.value().to_le_bytes());
+        privacy_random_buf[..ADDRESS_LEN].copy_from_slice(&dst.to_u16().to_le_bytes());
         privacy_random_buf[ADDRESS_LEN..ADDRESS_LEN + self.data.len()].copy_from_slice(self.data());
         if self.data.len() < PRIVACY_RANDOM_LEN - ADDRESS_LEN {
             self.mic.be_pack_into(
@@ -279,8 +279,14 @@ impl fmt::Display for Header {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(
             f,
-            "(ivi:{} nid:{} ttl:{} ctl:{} seq:{} src:{:?} dst:{:?}",
-            self.ivi.0, self.nid, self.ttl, self.ctl.0, self.seq, self.src, self.dst
+            "(ivi:{} nid:{} ttl:{} ctl:{} seq:{} src:{} dst:{}",
+            self.ivi.0,
+            self.nid,
+            self.ttl,
+            self.ctl.0,
+            self.seq,
+            Address::Unicast(self.src),
+            self.dst
         )
     }
 }
@@ -298,7 +304,7 @@ impl TryFrom<RawAdStructureBuffer<&[u8]>> for EncryptedPDU<StaticEncryptedPDUBuf
 }
 pub const ENCRYPTED_PDU_MAX_SIZE: usize = TRANSPORT_PDU_MAX_LEN + PDU_HEADER_LEN + 4;
 pub type StaticEncryptedPDUBuf = StaticBuf<u8, [u8; ENCRYPTED_DATA_MAX_LEN]>;
-const MIN_ENCRYPTED_PDU_LEN: usize = PDU_HEADER_LEN + MIC::small_size();
+const MIN_ENCRYPTED_PDU_LEN: usize = PDU_HEADER_LEN + TRANSPORT_PDU_MIN_LEN + MIC::small_size();
 const MAX_ENCRYPTED_PDU_LEN: usize = ENCRYPTED_PDU_MAX_SIZE;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
@@ -318,6 +324,12 @@ impl<Buf: AsRef<[u8]>> EncryptedPDU<Buf> {
         }
         Some(EncryptedPDU(data))
     }
+    /// Wraps `data` without checking its length. Only for testing how oversized or malformed
+    /// PDUs are handled.
+    #[cfg(test)]
+    pub(crate) fn new_unchecked(data: Buf) -> EncryptedPDU<Buf> {
+        EncryptedPDU(data)
+    }
     #[must_use]
     pub fn len(&self) -> usize {
         self.0.as_ref().len()
@@ -374,16 +386,14 @@ impl<Buf: AsRef<[u8]>> EncryptedPDU<Buf> {
         if iv_index.ivi() != self.ivi() {
             return Err(NetworkDataError::BadIVI);
         }
-        let pecb = self
-            .privacy_random()
-            .pack_with_iv(iv_index)
-            .encrypt_with(keys.privacy_key());
+        let pecb = self.compute_pecb(keys, iv_index);
         let deobfuscated = self
             .header()
             .deobfuscate(pecb)
             .ok_or(NetworkDataError::BadSrc)?;
         let nonce = deobfuscated.nonce(iv_index);
         let private_header = deobfuscated.private_header(self.ivi(), self.nid());
+        self.check_mic_len(private_header.ctl())?;
         let encrypted_data = self.encrypted_data(private_header.ctl());
         let decrypted_data = encrypted_data
             .try_decrypt(keys, &nonce)
@@ -397,6 +407,27 @@ impl<Buf: AsRef<[u8]>> EncryptedPDU<Buf> {
             .ok_or(NetworkDataError::BadTransportPDU)?;
         Ok(PDU::new(&header, &payload))
     }
+    /// Computes the `PECB` that (de)obfuscates the header from the privacy random (encrypted DST
+    /// and transport PDU), `iv_index` and the privacy key. Doesn't decrypt anything.
+    #[must_use]
+    pub fn compute_pecb(&self, keys: &NetworkKeys, iv_index: IVIndex) -> PECB {
+        self.privacy_random()
+            .pack_with_iv(iv_index)
+            .encrypt_with(keys.privacy_key())
+    }
+    /// Returns a copy of the PDU (same SEQ and SRC) with the TTL set to `ttl` for relaying.
+    /// The Network Nonce includes the TTL so the payload can't be reused as is. It's encrypted
+    /// again with the new nonce and the header is obfuscated with the new `PECB`.
+    pub fn reobfuscate_with_ttl(
+        &self,
+        keys: &NetworkKeys,
+        iv_index: IVIndex,
+        ttl: TTL,
+    ) -> Result<EncryptedPDU<StaticEncryptedPDUBuf>, NetworkDataError> {
+        let mut pdu = self.try_decrypt(keys, iv_index)?;
+        pdu.header.ttl = ttl;
+        Ok(pdu.encrypt(keys, iv_index)?)
+    }
     /// Returns the `ObfuscatedHeader`.
     #[must_use]
     pub fn header(&self) -> ObfuscatedHeader {
@@ -406,20 +437,33 @@ impl<Buf: AsRef<[u8]>> EncryptedPDU<Buf> {
                 .expect("obfuscated header should always exist"),
         )
     }
+    /// Checks the PDU is long enough for the header, at least 1 byte of Transport PDU and the
+    /// `MIC` size (8 bytes for control, 4 for access) implied by `ctl`. `mic` and
+    /// `encrypted_data` panic if this fails.
+    pub fn check_mic_len(&self, ctl: CTL) -> Result<(), NetworkDataError> {
+        let mic_size = if bool::from(ctl) {
+            MIC::big_size()
+        } else {
+            MIC::small_size()
+        };
+        if self.len() < PDU_HEADER_LEN + TRANSPORT_PDU_MIN_LEN + mic_size {
+            Err(NetworkDataError::BadTransportPDU)
+        } else {
+            Ok(())
+        }
+    }
     /// Returns the `MIC` based on the `CTL` bit. If `CTL == 1`, `MIC::byte_len() == 8` else if
     /// `CTL == 0`, `MIC::byte_len() == 4`.
     pub fn mic(&self, ctl: CTL) -> MIC {
-        let mic_size = if bool::from(ctl) { 8 } else { 4 };
-        MIC::try_from_bytes_be(&self.0.as_ref()[self.0.as_ref().len() - mic_size..])
+        MIC::split_from(self.0.as_ref(), bool::from(ctl))
             .expect("every PDU has a MIC")
+            .1
     }
 
     pub fn encrypted_data(&self, ctl: CTL) -> EncryptedData {
-        let mic = self.mic(ctl);
-        EncryptedData::new(
-            &self.0.as_ref()[OBFUSCATED_LEN..self.0.as_ref().len() - mic.byte_size()],
-            mic,
-        )
+        let (data, mic) =
+            MIC::split_from(self.0.as_ref(), bool::from(ctl)).expect("every PDU has a MIC");
+        EncryptedData::new(&data[OBFUSCATED_LEN..], mic)
     }
     /// Converts the reference EncryptedPDU into a owned byte array.
     pub fn to_owned<NewBuf: Storage<u8>>(&self) -> EncryptedPDU<NewBuf> {
@@ -471,6 +515,18 @@ pub enum PDUEncryptError {
     WrongNID,
     WrongIVI,
     BadDst,
+    /// The header's `CTL` doesn't match the payload kind (control or access).
+    CtlMismatch,
+}
+impl From<PDUEncryptError> for NetworkDataError {
+    fn from(e: PDUEncryptError) -> Self {
+        match e {
+            PDUEncryptError::WrongNID => NetworkDataError::DifferentNID,
+            PDUEncryptError::WrongIVI => NetworkDataError::BadIVI,
+            PDUEncryptError::BadDst => NetworkDataError::BadDst,
+            PDUEncryptError::CtlMismatch => NetworkDataError::BadTransportPDU,
+        }
+    }
 }
 impl PDU {
     #[must_use]
@@ -507,7 +563,8 @@ impl PDU {
             mic: None,
         }
     }
-    /// Encrypts the PDU. Ignores the IVI, NID, and CTL.
+    /// Encrypts the PDU. Ignores the IVI and NID. Returns `PDUEncryptError::CtlMismatch` if the
+    /// header's `CTL` doesn't match the payload.
     #[must_use]
     pub fn encrypt(
         &self,
@@ -518,6 +575,8 @@ impl PDU {
             || (self.payload.is_control() && self.header.dst.is_virtual())
         {
             Err(PDUEncryptError::BadDst)
+        } else if bool::from(self.header.ctl) != self.payload.is_control() {
+            Err(PDUEncryptError::CtlMismatch)
         } else {
             let deobfuscated = self.header.deobfuscated();
             let unencrypted = self.decrypted_data();
@@ -732,7 +791,11 @@ impl From<PackedPrivacy> for [u8; PACKED_PRIVACY_LEN] {
 }
 #[cfg(test)]
 mod tests {
-    use super::Header;
+    use super::*;
+    use crate::control::ControlOpcode;
+    use crate::lower::{UnsegmentedAccessPDU, UnsegmentedControlPDU};
+    use crate::mesh::U24;
+    use crate::stack::test_helpers;
 
     /*
     /// Generates a random Network PDU Header. Helpful for testing.
@@ -757,4 +820,83 @@ mod tests {
     fn test_random_headers_to_from_bytes() {
         for _i in 0..10 {}
     }
+    #[test]
+    fn test_mic_len_for_ctl() {
+        // Header, 1 byte access Transport PDU and a 4 byte MIC is too short for a control PDU.
+        let access = EncryptedPDU::new([0_u8; 14]).expect("valid encrypted pdu length");
+        assert!(access.check_mic_len(CTL(false)).is_ok());
+        match access.check_mic_len(CTL(true)) {
+            Err(NetworkDataError::BadTransportPDU) => (),
+            _ => panic!("expected bad transport pdu"),
+        }
+        // Header, 1 byte control Transport PDU and an 8 byte MIC is a 5 byte access PDU.
+        let control = EncryptedPDU::new([0_u8; 18]).expect("valid encrypted pdu length");
+        assert!(control.check_mic_len(CTL(true)).is_ok());
+        assert!(control.check_mic_len(CTL(false)).is_ok());
+        assert!(control.mic(CTL(true)).is_big());
+        assert!(!control.mic(CTL(false)).is_big());
+        // No room for any Transport PDU.
+        assert!(EncryptedPDU::new([0_u8; 13]).is_none());
+    }
+    #[test]
+    fn test_encrypt_ctl_mismatch() {
+        let keys = NetworkKeys::from(&test_helpers::net_key());
+        let iv_index = IVIndex(0x1234_5678);
+        let mut pdu = PDU {
+            header: Header {
+                ivi: iv_index.ivi(),
+                nid: keys.nid(),
+                ctl: CTL(false),
+                ttl: TTL::new(5),
+                seq: SequenceNumber(U24::new(0x0000_0010)),
+                src: UnicastAddress::from_mask_u16(0x1201),
+                dst: Address::Unicast(UnicastAddress::from_mask_u16(0x0003)),
+            },
+            payload: lower::PDU::UnsegmentedControl(UnsegmentedControlPDU::new(
+                ControlOpcode::FriendPoll,
+                &[0x01],
+            )),
+        };
+        assert_eq!(
+            pdu.encrypt(&keys, iv_index).err(),
+            Some(PDUEncryptError::CtlMismatch)
+        );
+        pdu.header.ctl = CTL(true);
+        assert!(pdu.encrypt(&keys, iv_index).is_ok());
+    }
+    #[test]
+    fn test_reobfuscate_with_ttl() {
+        let keys = NetworkKeys::from(&test_helpers::net_key());
+        let iv_index = IVIndex(0x1234_5678);
+        let header = Header {
+            ivi: iv_index.ivi(),
+            nid: keys.nid(),
+            ctl: CTL(false),
+            ttl: TTL::new(5),
+            seq: SequenceNumber(U24::new(0x0003_0201)),
+            src: UnicastAddress::from_mask_u16(0x1201),
+            dst: Address::Unicast(UnicastAddress::from_mask_u16(0x0003)),
+        };
+        let pdu = PDU {
+            header,
+            payload: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0x5A; 8])),
+        };
+        let encrypted = pdu.encrypt(&keys, iv_index).expect("pdu encrypts");
+        let relayed = match encrypted.reobfuscate_with_ttl(&keys, iv_index, TTL::new(4)) {
+            Ok(relayed) => relayed,
+            Err(_) => panic!("relaying should re-encrypt the pdu"),
+        };
+        assert_ne!(
+            encrypted.compute_pecb(&keys, iv_index),
+            relayed.compute_pecb(&keys, iv_index)
+        );
+        let decrypted = match relayed.try_decrypt(&keys, iv_index) {
+            Ok(decrypted) => decrypted,
+            Err(_) => panic!("relayed pdu should decrypt"),
+        };
+        assert_eq!(decrypted.header.ttl, TTL::new(4));
+        assert_eq!(decrypted.header.seq, header.seq);
+        assert_eq!(decrypted.header.src, header.src);
+        assert!(decrypted.payload == pdu.payload);
+    }
 }
@@ -1,3 +1,6 @@
 //! Bluetooth Properties (From GATT).
 pub mod characteristics;
+pub mod property;
 pub mod value;
+
+pub use property::{format, PropertyFormat, PropertyId, PropertyKind};
@@ -0,0 +1,174 @@
+//! Mesh Device Property IDs and the format of their values.
+
+/// Mesh Device Property ID. `0x0000` is prohibited. Only some of the standardized IDs have
+/// constants (and a `PropertyFormat`) but any other ID can still be made with `PropertyId(id)`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct PropertyId(pub u16);
+impl PropertyId {
+    pub const BYTE_LEN: usize = 2;
+    pub const PROHIBITED: PropertyId = PropertyId(0x0000);
+    pub const AVERAGE_AMBIENT_TEMPERATURE_IN_A_PERIOD_OF_DAY: PropertyId = PropertyId(0x0001);
+    pub const AVERAGE_INPUT_CURRENT: PropertyId = PropertyId(0x0002);
+    pub const AVERAGE_INPUT_VOLTAGE: PropertyId = PropertyId(0x0003);
+    pub const AVERAGE_OUTPUT_CURRENT: PropertyId = PropertyId(0x0004);
+    pub const AVERAGE_OUTPUT_VOLTAGE: PropertyId = PropertyId(0x0005);
+    pub const CENTER_BEAM_INTENSITY_AT_FULL_POWER: PropertyId = PropertyId(0x0006);
+    pub const CHROMATICITY_TOLERANCE: PropertyId = PropertyId(0x0007);
+    pub const COLOR_RENDERING_INDEX_R9: PropertyId = PropertyId(0x0008);
+    pub const COLOR_RENDERING_INDEX_RA: PropertyId = PropertyId(0x0009);
+    pub const DEVICE_APPEARANCE: PropertyId = PropertyId(0x000A);
+    pub const DEVICE_COUNTRY_OF_ORIGIN: PropertyId = PropertyId(0x000B);
+    pub const DEVICE_DATE_OF_MANUFACTURE: PropertyId = PropertyId(0x000C);
+    pub const DEVICE_ENERGY_USE_SINCE_TURN_ON: PropertyId = PropertyId(0x000D);
+    pub const DEVICE_FIRMWARE_REVISION: PropertyId = PropertyId(0x000E);
+    pub const DEVICE_GLOBAL_TRADE_ITEM_NUMBER: PropertyId = PropertyId(0x000F);
+    pub const DEVICE_HARDWARE_REVISION: PropertyId = PropertyId(0x0010);
+    pub const DEVICE_MANUFACTURER_NAME: PropertyId = PropertyId(0x0011);
+    pub const DEVICE_MODEL_NUMBER: PropertyId = PropertyId(0x0012);
+    pub const DEVICE_OPERATING_TEMPERATURE_RANGE_SPECIFICATION: PropertyId = PropertyId(0x0013);
+    pub const DEVICE_OPERATING_TEMPERATURE_STATISTICAL_VALUES: PropertyId = PropertyId(0x0014);
+    pub const DEVICE_POWER_RANGE_SPECIFICATION: PropertyId = PropertyId(0x0016);
+    pub const DEVICE_RUNTIME_SINCE_TURN_ON: PropertyId = PropertyId(0x0017);
+    pub const DEVICE_RUNTIME_WARRANTY: PropertyId = PropertyId(0x0018);
+    pub const DEVICE_SERIAL_NUMBER: PropertyId = PropertyId(0x0019);
+    pub const DEVICE_SOFTWARE_REVISION: PropertyId = PropertyId(0x001A);
+    pub const MOTION_SENSED: PropertyId = PropertyId(0x0042);
+    pub const PEOPLE_COUNT: PropertyId = PropertyId(0x004C);
+    pub const PRESENT_AMBIENT_LIGHT_LEVEL: PropertyId = PropertyId(0x004E);
+    pub const PRESENT_AMBIENT_TEMPERATURE: PropertyId = PropertyId(0x004F);
+
+    pub fn is_prohibited(self) -> bool {
+        self == Self::PROHIBITED
+    }
+    /// Format of the property's value or `None` if the ID isn't in the table.
+    pub fn format(self) -> Option<PropertyFormat> {
+        format(self)
+    }
+    pub fn to_bytes_le(self) -> [u8; Self::BYTE_LEN] {
+        self.0.to_le_bytes()
+    }
+    pub fn from_bytes_le(bytes: [u8; Self::BYTE_LEN]) -> PropertyId {
+        PropertyId(u16::from_le_bytes(bytes))
+    }
+}
+impl From<PropertyId> for u16 {
+    fn from(id: PropertyId) -> Self {
+        id.0
+    }
+}
+impl From<u16> for PropertyId {
+    fn from(id: u16) -> Self {
+        PropertyId(id)
+    }
+}
+/// Type of a property value's characteristic.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum PropertyKind {
+    Unsigned,
+    Signed,
+    /// Fixed length UTF-8 string (zero padded).
+    FixedString,
+    /// Several fields packed together (ex. a value with a sensing duration).
+    Structure,
+}
+/// Byte length and type of a property value.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PropertyFormat {
+    pub kind: PropertyKind,
+    pub len: usize,
+}
+impl PropertyFormat {
+    pub const fn new(kind: PropertyKind, len: usize) -> PropertyFormat {
+        PropertyFormat { kind, len }
+    }
+    /// Returns if a marshalled value of `len` bytes fits this format.
+    pub fn is_valid_len(self, len: usize) -> bool {
+        self.len == len
+    }
+}
+/// Looks up the format of a property value. Returns `None` for unknown (or prohibited) IDs.
+pub fn format(id: PropertyId) -> Option<PropertyFormat> {
+    use PropertyKind::{FixedString, Signed, Structure, Unsigned};
+    let (kind, len) = match id {
+        // Temperature 8 In A Period Of Day
+        PropertyId::AVERAGE_AMBIENT_TEMPERATURE_IN_A_PERIOD_OF_DAY => (Structure, 3),
+        // Average Current/Voltage
+        PropertyId::AVERAGE_INPUT_CURRENT
+        | PropertyId::AVERAGE_INPUT_VOLTAGE
+        | PropertyId::AVERAGE_OUTPUT_CURRENT
+        | PropertyId::AVERAGE_OUTPUT_VOLTAGE => (Structure, 3),
+        // Luminous Intensity
+        PropertyId::CENTER_BEAM_INTENSITY_AT_FULL_POWER => (Unsigned, 2),
+        PropertyId::CHROMATICITY_TOLERANCE => (Unsigned, 1),
+        // CIE 13.3-1995 Color Rendering Index
+        PropertyId::COLOR_RENDERING_INDEX_R9 | PropertyId::COLOR_RENDERING_INDEX_RA => (Signed, 1),
+        // GAP Appearance and Country Code
+        PropertyId::DEVICE_APPEARANCE | PropertyId::DEVICE_COUNTRY_OF_ORIGIN => (Unsigned, 2),
+        // Date UTC and Energy
+        PropertyId::DEVICE_DATE_OF_MANUFACTURE | PropertyId::DEVICE_ENERGY_USE_SINCE_TURN_ON => {
+            (Unsigned, 3)
+        }
+        PropertyId::DEVICE_FIRMWARE_REVISION | PropertyId::DEVICE_SOFTWARE_REVISION => {
+            (FixedString, 8)
+        }
+        PropertyId::DEVICE_GLOBAL_TRADE_ITEM_NUMBER => (Unsigned, 6),
+        PropertyId::DEVICE_HARDWARE_REVISION | PropertyId::DEVICE_SERIAL_NUMBER => {
+            (FixedString, 16)
+        }
+        PropertyId::DEVICE_MANUFACTURER_NAME => (FixedString, 36),
+        PropertyId::DEVICE_MODEL_NUMBER => (FixedString, 24),
+        // Temperature Range (min, max)
+        PropertyId::DEVICE_OPERATING_TEMPERATURE_RANGE_SPECIFICATION => (Structure, 4),
+        // Temperature Statistics (average, std dev, min, max, sensing duration)
+        PropertyId::DEVICE_OPERATING_TEMPERATURE_STATISTICAL_VALUES => (Structure, 9),
+        // Power Specification (min, typical, max)
+        PropertyId::DEVICE_POWER_RANGE_SPECIFICATION => (Structure, 9),
+        // Time Hour 24
+        PropertyId::DEVICE_RUNTIME_SINCE_TURN_ON | PropertyId::DEVICE_RUNTIME_WARRANTY => {
+            (Unsigned, 3)
+        }
+        // Percentage 8
+        PropertyId::MOTION_SENSED => (Unsigned, 1),
+        // Count 16
+        PropertyId::PEOPLE_COUNT => (Unsigned, 2),
+        // Illuminance
+        PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL => (Unsigned, 3),
+        // Temperature 8
+        PropertyId::PRESENT_AMBIENT_TEMPERATURE => (Signed, 1),
+        _ => return None,
+    };
+    Some(PropertyFormat::new(kind, len))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_property_formats() {
+        assert_eq!(
+            format(PropertyId(0x0042)),
+            Some(PropertyFormat::new(PropertyKind::Unsigned, 1))
+        );
+        assert_eq!(
+            PropertyId::PRESENT_AMBIENT_LIGHT_LEVEL
+                .format()
+                .map(|f| f.len),
+            Some(3)
+        );
+        assert_eq!(
+            PropertyId::DEVICE_MANUFACTURER_NAME.format(),
+            Some(PropertyFormat::new(PropertyKind::FixedString, 36))
+        );
+        assert!(PropertyId::PRESENT_AMBIENT_TEMPERATURE
+            .format()
+            .expect("known property")
+            .is_valid_len(1));
+        assert!(!PropertyId::PEOPLE_COUNT
+            .format()
+            .expect("known property")
+            .is_valid_len(3));
+        assert_eq!(format(PropertyId::PROHIBITED), None);
+        assert_eq!(format(PropertyId(0xFFFF)), None);
+    }
+}
@@ -87,6 +87,26 @@ pub struct AuthValue(pub [u8; AUTH_VALUE_LEN]);
 impl AuthValue {
     pub const ZEROED: AuthValue = AuthValue([0_u8; AUTH_VALUE_LEN]);
     pub const DEFAULT: AuthValue = Self::ZEROED;
+    /// Numeric (Output/Input Numeric) OOB value. Stored big endian in the last bytes with the
+    /// rest zeroed.
+    pub fn from_numeric(value: u32) -> AuthValue {
+        let mut out = AuthValue::ZEROED;
+        out.0[AUTH_VALUE_LEN - 4..].copy_from_slice(&value.to_be_bytes());
+        out
+    }
+    /// Alphanumeric (Output/Input Alphanumeric) OOB value. ASCII uppercased, left justified
+    /// and zero padded. Returns `None` if `value` has more than `AUTH_VALUE_LEN` characters or
+    /// any that aren't ASCII alphanumeric.
+    pub fn from_alphanumeric(value: &str) -> Option<AuthValue> {
+        if value.len() > AUTH_VALUE_LEN || !value.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let mut out = AuthValue::ZEROED;
+        for (o, b) in out.0.iter_mut().zip(value.bytes()) {
+            *o = b.to_ascii_uppercase();
+        }
+        Some(out)
+    }
 }
 impl AsRef<[u8]> for AuthValue {
     fn as_ref(&self) -> &[u8] {
@@ -125,8 +145,102 @@ impl AsRef<[u8]> for ConfirmationKey {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::foundation::state::AttentionTimer;
+    use crate::mesh::{bytes_str_to_buf, ElementCount};
+    use crate::provisioning::protocol::{
+        Algorithms, AlgorithmsFlags, AuthenticationMethod, Capabilities, InputOOBOptions, Invite,
+        OutputOOBOptions, PublicKey, PublicKeyOption, PublicKeyType, Start, StaticOOBOption,
+    };
+
+    fn hex<T: Default + AsMut<[u8]>>(s: &str) -> T {
+        bytes_str_to_buf(s).expect("from spec")
+    }
+    /// Mesh Profile Spec 8.7 provisioning sample data.
+    fn sample_input() -> Input {
+        Input {
+            invite: Invite(AttentionTimer::default()),
+            capabilities: Capabilities {
+                num_elements: ElementCount(1),
+                algorithms: Algorithms(1),
+                pub_key_option: PublicKeyOption::NoKey,
+                static_oob_option: StaticOOBOption::NoStaticOOB,
+                output_oob_size: None,
+                output_oob_action: OutputOOBOptions(0),
+                input_oob_size: None,
+                input_oob_action: InputOOBOptions(0),
+            },
+            start: Start {
+                algorithm: AlgorithmsFlags::FIPSP256,
+                public_key_type: PublicKeyType::NotAvailable,
+                auth_method: AuthenticationMethod::NoOOB,
+            },
+            provisioner_public_key: PublicKey {
+                x: hex("2c31a47b5779809ef44cb5eaaf5c3e43d5f8faad4a8794cb987e9b03745c78dd"),
+                y: hex("919512183898dfbecd52e2408e43871fd021109117bd3ed4eaf8437743715d4f"),
+            },
+            device_public_key: PublicKey {
+                x: hex("f465e43ff23d3f1b9dc7dfc04da8758184dbc966204796eccf0d6cf5e16500cc"),
+                y: hex("0201d048bcbbd899eeefc424164e33c201c2b010ca6b4d43a8a155cad8ecb279"),
+            },
+        }
+    }
+    #[test]
+    pub fn test_sample_confirmation() {
+        let salt = sample_input().salt();
+        assert_eq!(
+            salt,
+            ConfirmationSalt(
+                Salt::from_hex("5faabe187337c71cc6c973369dcaa79a").expect("from spec")
+            )
+        );
+        let ecdh_secret = ECDHSecret::new_bytes(hex(
+            "ab85843a2f6d883f62e5684b38e307335fe6e1945ecd19604105c6f23221eb69",
+        ));
+        let key = ConfirmationKey::from_salt_and_secret(&salt, &ecdh_secret);
+        assert_eq!(
+            key,
+            ConfirmationKey(Key::from_hex("e31fe046c68ec339c425fc6629f0336f").expect("from spec"))
+        );
+        // The sample data uses No OOB authentication so the AuthValue is zeroed.
+        let auth_value = AuthValue::from_numeric(0);
+        assert_eq!(auth_value, AuthValue::ZEROED);
+        assert_eq!(
+            key.confirm_random(
+                &Random(hex("8b19ac31d58b124c946209b5db1021b9")),
+                &auth_value
+            ),
+            Confirmation(hex("b38a114dfdca1fe153bd2c1e0dc46ac2"))
+        );
+        assert_eq!(
+            key.confirm_random(
+                &Random(hex("55a2a2bca04cd32ff6f346bd0a0c1a3a")),
+                &auth_value
+            ),
+            Confirmation(hex("eeba521c196b52cc2e37aa40329f554e"))
+        );
+    }
     #[test]
     pub fn test_input_len() {
         assert_eq!(DEVICE_KEY_POS + protocol::PublicKey::BYTE_LEN, INPUT_LEN)
     }
+    #[test]
+    pub fn test_auth_value_numeric() {
+        assert_eq!(AuthValue::from_numeric(0), AuthValue::ZEROED);
+        let mut expected = AuthValue::ZEROED;
+        expected.0[12..].copy_from_slice(&[0x00, 0x01, 0xE2, 0x40]);
+        assert_eq!(AuthValue::from_numeric(123_456), expected);
+    }
+    #[test]
+    pub fn test_auth_value_alphanumeric() {
+        let mut expected = AuthValue::ZEROED;
+        expected.0[..6].copy_from_slice(b"A1B2C3");
+        assert_eq!(AuthValue::from_alphanumeric("a1B2c3"), Some(expected));
+        assert_eq!(AuthValue::from_alphanumeric(""), Some(AuthValue::ZEROED));
+        assert_eq!(
+            AuthValue::from_alphanumeric("0123456789ABCDEF"),
+            Some(AuthValue(*b"0123456789ABCDEF"))
+        );
+        assert_eq!(AuthValue::from_alphanumeric("0123456789ABCDEFG"), None);
+        assert_eq!(AuthValue::from_alphanumeric("AB-12"), None);
+    }
 }
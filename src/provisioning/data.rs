@@ -1,20 +1,26 @@
 use crate::address::{UnicastAddress, ADDRESS_LEN};
 use crate::bytes::ToFromBytesEndian;
 use crate::crypto::aes::{AESCipher, MicSize};
-use crate::crypto::key::{NetKey, SessionKey, KEY_LEN};
+use crate::crypto::key::{DevKey, NetKey, SessionKey, KEY_LEN};
 use crate::crypto::nonce::SessionNonce;
 use crate::crypto::{ECDHSecret, ProvisioningSalt};
 use crate::mesh::{IVIndex, KeyIndex, NetKeyIndex};
 use crate::provisioning::protocol::EncryptedProvisioningData;
 use btle::{ConversionError, PackError};
 use core::convert::TryFrom;
+#[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
 pub struct SessionSecurityMaterials {
     pub key: SessionKey,
     pub nonce: SessionNonce,
+    pub dev_key: DevKey,
 }
 impl SessionSecurityMaterials {
-    pub fn new(key: SessionKey, nonce: SessionNonce) -> SessionSecurityMaterials {
-        SessionSecurityMaterials { key, nonce }
+    pub fn new(key: SessionKey, nonce: SessionNonce, dev_key: DevKey) -> SessionSecurityMaterials {
+        SessionSecurityMaterials {
+            key,
+            nonce,
+            dev_key,
+        }
     }
     pub fn from_secret_salt(
         secret: &ECDHSecret,
@@ -23,8 +29,14 @@ impl SessionSecurityMaterials {
         SessionSecurityMaterials {
             key: SessionKey::from_secret_salt(secret, salt),
             nonce: SessionNonce::from_secret_salt(secret, salt),
+            dev_key: DevKey::from_salt_and_secret(salt, secret),
         }
     }
+    /// Device Key of the newly provisioned node (`k1(ECDHSecret, ProvisioningSalt, "prdk")`).
+    /// Both the provisioner and the device derive the same key.
+    pub fn device_key(&self) -> DevKey {
+        self.dev_key
+    }
 }
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
 #[repr(u8)]
@@ -139,3 +151,30 @@ impl ProvisioningData {
         ))
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Salt;
+    use crate::mesh::bytes_str_to_buf;
+
+    #[test]
+    fn test_session_materials() {
+        // Mesh Profile Spec 8.7 sample data.
+        let secret = ECDHSecret::new_bytes(
+            bytes_str_to_buf("ab85843a2f6d883f62e5684b38e307335fe6e1945ecd19604105c6f23221eb69")
+                .expect("from spec"),
+        );
+        let salt = ProvisioningSalt(
+            Salt::from_hex("a21c7d45f201cf9489a2fb57145015b4").expect("from spec"),
+        );
+        let materials = SessionSecurityMaterials::from_secret_salt(&secret, &salt);
+        assert_eq!(
+            materials.key,
+            SessionKey::from_hex("c80253af86b33dfa450bbdb2a191fea3").expect("from spec")
+        );
+        assert_eq!(
+            materials.device_key(),
+            DevKey::from_hex("0520adad5e0142aa3e325087b4ec16d8").expect("from spec")
+        );
+    }
+}
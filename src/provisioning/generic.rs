@@ -1,6 +1,7 @@
 //! Generic Provisioning PDUs should be sent with delays of 20-50 milliseconds between them
 use super::bearer_control;
 
+use crate::provisioning::pb_adv::TransactionNumber;
 use crate::provisioning::protocol;
 use btle::bytes::Storage;
 use btle::PackError;
@@ -128,7 +129,8 @@ pub fn fcs_calc(data: &[u8]) -> FCS {
     for &b in data {
         fcs = FCS_TABLE[usize::from(fcs ^ b)]
     }
-    FCS(fcs)
+    // Ones complement of the running CRC.
+    FCS(0xFF - fcs)
 }
 pub fn fcs_check(fcs: FCS, data: &[u8]) -> bool {
     let mut fcs_check = 0xFF;
@@ -342,15 +344,34 @@ impl<T: AsRef<[u8]>> core::fmt::Debug for PDU<T> {
 pub const PDU_MTU: u16 = 24;
 pub const MAX_START_DATA_LEN: u16 = PDU_MTU - 4;
 pub const MAX_CONTINUATION_DATA_LEN: u16 = PDU_MTU - 1;
-pub const MAX_PDU_LEN: u16 = PDU_MTU * (SegmentIndex::MAX_SEGMENTS - 1) as u16 + MAX_START_DATA_LEN;
+pub const MAX_PDU_LEN: u16 =
+    MAX_CONTINUATION_DATA_LEN * (SegmentIndex::MAX_SEGMENTS - 1) as u16 + MAX_START_DATA_LEN;
+/// Offset of the first byte of segment `seg_i` in the reassembled data.
+const fn segment_offset(seg_i: u8) -> usize {
+    if seg_i == 0 {
+        0
+    } else {
+        MAX_START_DATA_LEN as usize + MAX_CONTINUATION_DATA_LEN as usize * (seg_i as usize - 1)
+    }
+}
+/// Maximum data length of segment `seg_i`.
+const fn segment_max_len(seg_i: u8) -> usize {
+    if seg_i == 0 {
+        MAX_START_DATA_LEN as usize
+    } else {
+        MAX_CONTINUATION_DATA_LEN as usize
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct SegmentGenerator<B> {
     data: B,
     fcs: FCS,
 }
 impl<B: AsRef<[u8]>> SegmentGenerator<B> {
+    /// # Panics
+    /// Panics if `data.len() > MAX_PDU_LEN`.
     pub fn new(data: B) -> SegmentGenerator<B> {
-        assert!(data.as_ref().len() <= usize::from(PDU_MTU));
+        assert!(data.as_ref().len() <= usize::from(MAX_PDU_LEN));
         SegmentGenerator {
             fcs: fcs_calc(data.as_ref()),
             data,
@@ -399,6 +420,67 @@ impl<B: AsRef<[u8]>> SegmentGenerator<B> {
         // Constructor insures `.len()` fits in an `u16`.
         self.data.as_ref().len() as u16
     }
+    /// Frames every segment of the `transaction_number` transaction into a Generic Provisioning
+    /// `PDU`. The first PDU is a `TransactionStart` (carrying `seg_n`, total length and FCS)
+    /// followed by `TransactionContinuation`s.
+    pub fn pdus<Buf: Storage<u8>>(
+        &self,
+        transaction_number: TransactionNumber,
+    ) -> SegmentPDUs<'_, B, Buf> {
+        SegmentPDUs {
+            generator: self,
+            transaction_number,
+            next_seg: 0,
+            _buf: core::marker::PhantomData,
+        }
+    }
+}
+/// Iterator over the Generic Provisioning `PDU`s of one transaction (see
+/// [`SegmentGenerator::pdus`]).
+pub struct SegmentPDUs<'a, B, Buf> {
+    generator: &'a SegmentGenerator<B>,
+    transaction_number: TransactionNumber,
+    next_seg: u8,
+    _buf: core::marker::PhantomData<Buf>,
+}
+impl<'a, B: AsRef<[u8]>, Buf> SegmentPDUs<'a, B, Buf> {
+    /// Transaction Number the bearer (PB-ADV) should send every PDU with.
+    pub fn transaction_number(&self) -> TransactionNumber {
+        self.transaction_number
+    }
+}
+impl<'a, B: AsRef<[u8]>, Buf: Storage<u8>> Iterator for SegmentPDUs<'a, B, Buf> {
+    type Item = PDU<Buf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let seg_n = self.generator.seg_n();
+        if self.next_seg > seg_n.0 {
+            return None;
+        }
+        let seg_i = SegmentIndex::new(self.next_seg);
+        self.next_seg += 1;
+        let control = if seg_i == SegmentIndex::ZERO {
+            Control::TransactionStart(TransactionStartPDU::new(
+                seg_n,
+                self.generator.data_len(),
+                self.generator.fcs,
+            ))
+        } else {
+            Control::TransactionContinuation(TransactionContinuationPDU::new(seg_i))
+        };
+        let data = self
+            .generator
+            .get_segment_data(seg_i)
+            .expect("seg_i is always <= seg_n");
+        Some(PDU {
+            control,
+            payload: if data.is_empty() {
+                None
+            } else {
+                Some(Buf::from_slice(data))
+            },
+        })
+    }
 }
 impl<B: AsRef<[u8]>> core::fmt::Debug for SegmentGenerator<B> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -418,14 +500,15 @@ impl<B: AsRef<[u8]>> core::hash::Hash for SegmentGenerator<B> {
 pub struct Reassembler<B> {
     data: B,
     fcs: FCS,
-    seg_i: SegmentIndex,
+    /// Number of segments inserted so far (the next expected segment index).
+    segs_received: u8,
     seg_n: SegmentIndex,
 }
 impl<B: AsRef<[u8]>> core::hash::Hash for Reassembler<B> {
     fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         state.write(self.data.as_ref());
         state.write_u8(self.fcs.0);
-        state.write_u8(self.seg_i.0);
+        state.write_u8(self.segs_received);
         state.write_u8(self.seg_n.0);
     }
 }
@@ -437,10 +520,15 @@ pub enum ReassembleError {
     SegmentSkipped,
     DataUnderflow,
     DataOverflow,
-    FCSMismatch,
+    /// The FCS sent in the Transaction Start doesn't match the FCS calculated over the data.
+    FCSMismatch {
+        expected: FCS,
+        calculated: FCS,
+    },
     PackError(PackError),
 }
 impl<B: AsRef<[u8]> + AsMut<[u8]>> Reassembler<B> {
+    /// Creates a `Reassembler` that already has segments `0..seg_i` inserted.
     pub fn new_started(
         data: B,
         fcs: FCS,
@@ -451,10 +539,11 @@ impl<B: AsRef<[u8]> + AsMut<[u8]>> Reassembler<B> {
             data.as_ref().len() < (u16::MAX as usize),
             "data.len() overflows a u16"
         );
+        assert!(seg_i <= seg_n, "seg_i overflow");
         Reassembler {
             data,
             fcs,
-            seg_i,
+            segs_received: seg_i.0,
             seg_n,
         }
     }
@@ -492,21 +581,18 @@ impl<B: AsRef<[u8]> + AsMut<[u8]>> Reassembler<B> {
         }
     }
     pub fn seg_n(&self) -> SegmentIndex {
-        self.seg_i
+        self.seg_n
     }
+    /// Next expected segment index (or `seg_n` once every segment is in).
     pub fn seg_i(&self) -> SegmentIndex {
-        self.seg_i
+        SegmentIndex(self.segs_received.min(self.seg_n.0))
     }
+    /// Number of data bytes received so far.
     pub fn data_index(&self) -> u16 {
-        match self.seg_i {
-            SegmentIndex(0) => 0,
-            SegmentIndex(1) => MAX_START_DATA_LEN,
-            SegmentIndex(i) => MAX_CONTINUATION_DATA_LEN * u16::from(i - 1) + MAX_START_DATA_LEN,
-        }
+        segment_offset(self.segs_received).min(self.data.as_ref().len()) as u16
     }
     pub fn is_done(&self) -> bool {
-        debug_assert!(self.seg_i <= self.seg_n, "seg_i overflow");
-        self.seg_i == self.seg_n
+        self.segs_received > self.seg_n.0
     }
     pub fn current_data(&self) -> &[u8] {
         &self.data.as_ref()[..self.data_index() as usize]
@@ -524,67 +610,124 @@ impl<B: AsRef<[u8]> + AsMut<[u8]>> Reassembler<B> {
     pub fn into_inner(self) -> B {
         self.data
     }
-    pub fn finish_data(self) -> Result<B, ReassembleError> {
+    /// Checks the FCS of the finished data. Returns `NotFinished` if segments are still missing
+    /// or `FCSMismatch` (with both FCS values) if the data is corrupt.
+    pub fn verify_fcs(&self) -> Result<(), ReassembleError> {
         if self.fcs_matches().ok_or(ReassembleError::NotFinished)? {
-            Ok(self.data)
+            Ok(())
         } else {
-            Err(ReassembleError::FCSMismatch)
+            Err(ReassembleError::FCSMismatch {
+                expected: self.fcs,
+                calculated: fcs_calc(self.data.as_ref()),
+            })
         }
     }
+    pub fn finish_data(self) -> Result<B, ReassembleError> {
+        self.verify_fcs()?;
+        Ok(self.data)
+    }
     pub fn finish_data_ref(&self) -> Result<&[u8], ReassembleError> {
-        if self.fcs_matches().ok_or(ReassembleError::NotFinished)? {
-            Ok(self.data.as_ref())
-        } else {
-            Err(ReassembleError::FCSMismatch)
-        }
+        self.verify_fcs()?;
+        Ok(self.data.as_ref())
     }
     pub fn finish_pdu(&self) -> Result<protocol::PDU, ReassembleError> {
         let data = self.finish_data_ref()?;
         protocol::PDU::unpack_raw(data).map_err(ReassembleError::PackError)
     }
+    /// Inserts the data of segment `seg_i`. Segments must be inserted in order and every segment
+    /// but the last must be full.
     pub fn insert(
         &mut self,
         segment_data: &[u8],
         seg_i: SegmentIndex,
     ) -> Result<(), ReassembleError> {
-        if self.seg_n >= seg_i {
+        if seg_i > self.seg_n {
             return Err(ReassembleError::TooManySegments);
         }
-        if self.seg_i > seg_i {
+        if seg_i.0 < self.segs_received {
             return Err(ReassembleError::SegmentRepeat);
         }
-        if self.seg_i < seg_i {
+        if seg_i.0 > self.segs_received {
             return Err(ReassembleError::SegmentSkipped);
         }
-        if self.seg_i == SegmentIndex::ZERO {
-            if segment_data.len() > usize::from(MAX_START_DATA_LEN) {
-                return Err(ReassembleError::DataOverflow);
-            }
-            if self.seg_n != SegmentIndex(1) && segment_data.len() < usize::from(MAX_START_DATA_LEN)
-            {
-                return Err(ReassembleError::DataUnderflow);
-            }
-            if segment_data.len() < self.data.as_ref().len() {
-                return Err(ReassembleError::DataUnderflow);
-            }
-            self.data.as_mut()[..usize::from(MAX_START_DATA_LEN)]
-                .copy_from_slice(segment_data.as_ref());
-        } else {
-            if segment_data.len() > usize::from(MAX_CONTINUATION_DATA_LEN) {
-                return Err(ReassembleError::DataOverflow);
-            }
-            if self.seg_n != seg_i && segment_data.len() < usize::from(MAX_CONTINUATION_DATA_LEN) {
-                return Err(ReassembleError::DataUnderflow);
-            }
-            let index = usize::from(MAX_START_DATA_LEN)
-                + usize::from(seg_i.0) * usize::from(MAX_CONTINUATION_DATA_LEN);
-            if index + segment_data.len() < self.data.as_ref().len() {
+        let total_len = self.data.as_ref().len();
+        let index = segment_offset(seg_i.0);
+        let end = index + segment_data.len();
+        if segment_data.len() > segment_max_len(seg_i.0) || end > total_len {
+            return Err(ReassembleError::DataOverflow);
+        }
+        if seg_i == self.seg_n {
+            if end < total_len {
                 return Err(ReassembleError::DataUnderflow);
             }
-            self.data.as_mut()[index..index + usize::from(MAX_CONTINUATION_DATA_LEN)]
-                .copy_from_slice(segment_data.as_ref());
+        } else if segment_data.len() < segment_max_len(seg_i.0) {
+            return Err(ReassembleError::DataUnderflow);
         }
-        self.seg_i = seg_i;
+        self.data.as_mut()[index..end].copy_from_slice(segment_data);
+        self.segs_received += 1;
         Ok(())
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+
+    #[test]
+    fn test_segment_generator_pdus_reassemble() {
+        let data: Vec<u8> = (0..48_u8).collect();
+        let generator = SegmentGenerator::new(&data[..]);
+        let transaction_number = TransactionNumber::new_provisioner();
+        let segments = generator.pdus(transaction_number);
+        assert_eq!(segments.transaction_number(), transaction_number);
+        let pdus: Vec<PDU<Box<[u8]>>> = segments.collect();
+        assert_eq!(pdus.len(), 3);
+        let start = match pdus[0].control {
+            Control::TransactionStart(start) => start,
+            _ => panic!("first PDU must be a TransactionStart"),
+        };
+        assert_eq!(start.seg_n, SegmentIndex::new(2));
+        assert_eq!(start.total_length, 48);
+        assert_eq!(start.fcs, fcs_calc(&data));
+        let mut reassembler = Reassembler::<Box<[u8]>>::from_start(
+            start,
+            pdus[0].payload.as_ref().map_or(&[][..], AsRef::as_ref),
+        )
+        .expect("valid start");
+        for (i, pdu) in pdus.iter().enumerate().skip(1) {
+            match pdu.control {
+                Control::TransactionContinuation(con) => {
+                    assert_eq!(con.seg_i, SegmentIndex::new(i as u8));
+                    reassembler
+                        .insert(
+                            pdu.payload.as_ref().map_or(&[][..], AsRef::as_ref),
+                            con.seg_i,
+                        )
+                        .expect("in order segment");
+                }
+                _ => panic!("expected a TransactionContinuation"),
+            }
+        }
+        assert!(reassembler.is_done());
+        assert_eq!(reassembler.finish_data_ref(), Ok(&data[..]));
+    }
+    #[test]
+    fn test_verify_fcs_mismatch() {
+        let data = [0x01_u8, 0x02, 0x03, 0x04];
+        let fcs = fcs_calc(&data[..]);
+        let mut reassembler = Reassembler::new([0_u8; 4], fcs, SegmentIndex::ZERO);
+        let mut corrupted = data;
+        corrupted[2] ^= 0xFF;
+        reassembler
+            .insert(&corrupted[..], SegmentIndex::ZERO)
+            .expect("single segment");
+        assert_eq!(
+            reassembler.verify_fcs(),
+            Err(ReassembleError::FCSMismatch {
+                expected: fcs,
+                calculated: fcs_calc(&corrupted[..]),
+            })
+        );
+        assert_ne!(fcs, fcs_calc(&corrupted[..]));
+    }
+}
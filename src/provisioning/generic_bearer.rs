@@ -1 +1,331 @@
+//! Generic Provisioning bearer. Segments outgoing provisioning PDUs, reassembles incoming ones
+//! and handles Transaction Acknowledgments (and retransmissions) so the provisioning state
+//! machines only deal with whole `protocol::PDU`s.
+use crate::asyncs::sync::mpsc;
+use crate::asyncs::time;
+use crate::provisioning::generic::{
+    Control, ReassembleError, Reassembler, SegmentGenerator, TransactionAcknowledgmentPDU,
+};
+use crate::provisioning::link::LinkError;
+use crate::provisioning::pb_adv::{LinkID, TransactionNumber};
+use crate::provisioning::{bearer_control, generic, pb_adv, protocol};
+use alloc::boxed::Box;
+use btle::bytes::Storage;
+use core::time::Duration;
+use driver_async::time::{Instant, InstantTrait};
 
+pub struct GenericBearer<B: Storage<u8>> {
+    link_id: LinkID,
+    my_transaction_number: TransactionNumber,
+    other_transaction_number: TransactionNumber,
+    reassembler: Option<Reassembler<Box<[u8]>>>,
+    outgoing: mpsc::Sender<pb_adv::PDU<B>>,
+    incoming: mpsc::Receiver<pb_adv::PDU<B>>,
+}
+impl<B: Storage<u8>> GenericBearer<B> {
+    /// How long to wait for a Transaction Acknowledgment before retransmitting every segment.
+    pub const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(500);
+    /// How long to keep retransmitting a transaction before giving up.
+    pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+    pub fn new(
+        link_id: LinkID,
+        my_transaction_number: TransactionNumber,
+        other_transaction_number: TransactionNumber,
+        outgoing: mpsc::Sender<pb_adv::PDU<B>>,
+        incoming: mpsc::Receiver<pb_adv::PDU<B>>,
+    ) -> Self {
+        Self {
+            link_id,
+            my_transaction_number,
+            other_transaction_number,
+            reassembler: None,
+            outgoing,
+            incoming,
+        }
+    }
+    pub fn new_provisioner(
+        link_id: LinkID,
+        outgoing: mpsc::Sender<pb_adv::PDU<B>>,
+        incoming: mpsc::Receiver<pb_adv::PDU<B>>,
+    ) -> Self {
+        Self::new(
+            link_id,
+            TransactionNumber::new_provisioner(),
+            TransactionNumber::new_provisionee(),
+            outgoing,
+            incoming,
+        )
+    }
+    pub fn new_provisionee(
+        link_id: LinkID,
+        outgoing: mpsc::Sender<pb_adv::PDU<B>>,
+        incoming: mpsc::Receiver<pb_adv::PDU<B>>,
+    ) -> Self {
+        Self::new(
+            link_id,
+            TransactionNumber::new_provisionee(),
+            TransactionNumber::new_provisioner(),
+            outgoing,
+            incoming,
+        )
+    }
+    pub fn link_id(&self) -> LinkID {
+        self.link_id
+    }
+    pub fn my_transaction_number(&self) -> TransactionNumber {
+        self.my_transaction_number
+    }
+    pub fn other_transaction_number(&self) -> TransactionNumber {
+        self.other_transaction_number
+    }
+    async fn send_generic(
+        &mut self,
+        transaction_number: TransactionNumber,
+        generic_pdu: generic::PDU<B>,
+    ) -> Result<(), LinkError> {
+        let pdu = pb_adv::PDU {
+            link_id: self.link_id,
+            transaction_number,
+            generic_pdu,
+        };
+        self.outgoing
+            .send(pdu)
+            .await
+            .map_err(|_| LinkError::ChannelClosed)
+    }
+    async fn send_ack(&mut self, transaction_number: TransactionNumber) -> Result<(), LinkError> {
+        self.send_generic(
+            transaction_number,
+            generic::PDU {
+                control: Control::TransactionAcknowledgement(TransactionAcknowledgmentPDU::new()),
+                payload: None,
+            },
+        )
+        .await
+    }
+    /// Segments and sends `pdu`, retransmitting every segment each `RETRANSMIT_INTERVAL` until
+    /// the other side acknowledges the transaction.
+    pub async fn send(&mut self, pdu: &protocol::PDU) -> Result<(), LinkError> {
+        let mut buf = [0_u8; protocol::PDU::MAX_BYTE_LEN];
+        let buf = &mut buf[..pdu.byte_len()];
+        pdu.pack_raw(buf).map_err(LinkError::PDUPackError)?;
+        let generator = SegmentGenerator::new(&buf[..]);
+        let transaction_number = self.my_transaction_number;
+        let start = Instant::now();
+        loop {
+            for segment in generator.pdus(transaction_number) {
+                self.send_generic(transaction_number, segment).await?;
+            }
+            if self.wait_for_ack(Self::RETRANSMIT_INTERVAL).await? {
+                self.my_transaction_number.increment();
+                return Ok(());
+            }
+            if Instant::now()
+                .checked_duration_since(start)
+                .map_or(false, |elapsed| elapsed >= Self::TRANSACTION_TIMEOUT)
+            {
+                return Err(LinkError::TimedOut);
+            }
+        }
+    }
+    /// Returns `Ok(true)` if an ack for our current transaction arrives before `timeout`.
+    async fn wait_for_ack(&mut self, timeout: Duration) -> Result<bool, LinkError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = match Instant::now().checked_duration_until(deadline) {
+                Some(remaining) => remaining,
+                None => return Ok(false),
+            };
+            let incoming = match time::timeout(remaining, self.incoming.recv()).await {
+                Ok(incoming) => incoming.ok_or(LinkError::ChannelClosed)?,
+                Err(_) => return Ok(false),
+            };
+            if incoming.link_id != self.link_id {
+                continue;
+            }
+            match incoming.generic_pdu.control {
+                Control::TransactionAcknowledgement(_) => {
+                    if incoming.transaction_number == self.my_transaction_number {
+                        return Ok(true);
+                    }
+                }
+                Control::BearerControl(bearer_control::PDU::LinkClose(close)) => {
+                    return Err(LinkError::Closed(close.0))
+                }
+                Control::TransactionStart(_) | Control::TransactionContinuation(_) => {
+                    // The other side didn't get our last ack and is retransmitting.
+                    if incoming.transaction_number == self.other_transaction_number.prev() {
+                        self.send_ack(incoming.transaction_number).await?;
+                    }
+                }
+                Control::BearerControl(_) => (),
+            }
+        }
+    }
+    /// Waits up to `timeout` for a whole provisioning PDU, acknowledging it once reassembled.
+    pub async fn recv(&mut self, timeout: Duration) -> Result<protocol::PDU, LinkError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = Instant::now()
+                .checked_duration_until(deadline)
+                .ok_or(LinkError::TimedOut)?;
+            let incoming = time::timeout(remaining, self.incoming.recv())
+                .await
+                .map_err(|_| LinkError::TimedOut)?
+                .ok_or(LinkError::ChannelClosed)?;
+            if let Some(pdu) = self.handle_incoming(incoming.as_ref()).await? {
+                return Ok(pdu);
+            }
+        }
+    }
+    async fn handle_incoming(
+        &mut self,
+        incoming: pb_adv::PDU<&[u8]>,
+    ) -> Result<Option<protocol::PDU>, LinkError> {
+        if incoming.link_id != self.link_id {
+            return Ok(None);
+        }
+        let payload = incoming.generic_pdu.payload.unwrap_or(&[]);
+        match incoming.generic_pdu.control {
+            Control::BearerControl(bearer_control::PDU::LinkClose(close)) => {
+                return Err(LinkError::Closed(close.0))
+            }
+            Control::TransactionStart(_) | Control::TransactionContinuation(_)
+                if incoming.transaction_number == self.other_transaction_number.prev() =>
+            {
+                // Already reassembled this transaction but our ack got lost.
+                self.send_ack(incoming.transaction_number).await?;
+                return Ok(None);
+            }
+            _ if incoming.transaction_number != self.other_transaction_number => return Ok(None),
+            Control::TransactionStart(start) => {
+                // A repeated Start is part of a retransmission we're already reassembling.
+                if self.reassembler.is_none() {
+                    match Reassembler::from_start(start, payload) {
+                        Ok(reassembler) => self.reassembler = Some(reassembler),
+                        Err(_) => return Ok(None),
+                    }
+                }
+            }
+            Control::TransactionContinuation(continuation) => match self.reassembler.as_mut() {
+                // Repeated or skipped segments are dropped. The sender retransmits the whole
+                // transaction until it's acked so the missing segment will come around again.
+                Some(reassembler) => {
+                    let _ = reassembler.insert(payload, continuation.seg_i);
+                }
+                None => return Ok(None),
+            },
+            Control::TransactionAcknowledgement(_) | Control::BearerControl(_) => return Ok(None),
+        }
+        let result = match self.reassembler.as_ref() {
+            Some(reassembler) if reassembler.is_done() => reassembler.finish_pdu(),
+            _ => return Ok(None),
+        };
+        self.reassembler = None;
+        match result {
+            Ok(pdu) => {
+                let transaction_number = self.other_transaction_number;
+                self.send_ack(transaction_number).await?;
+                self.other_transaction_number.increment();
+                Ok(Some(pdu))
+            }
+            // Corrupt transaction. Don't ack it and wait for the retransmission.
+            Err(ReassembleError::FCSMismatch { .. }) => Ok(None),
+            Err(e) => Err(LinkError::ReassembleError(e)),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::ecdh;
+    use crate::mesh::ElementCount;
+    use crate::provisioning::protocol::{
+        Algorithms, Capabilities, InputOOBOptions, OutputOOBOptions, PublicKeyOption,
+        StaticOOBOption, PDU,
+    };
+    use crate::provisioning::provisioner::{Bearer, Process, Stage};
+    use crate::stack::bearer::PBAdvBuf;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[tokio::test]
+    async fn test_provisioner_over_lossy_generic_bearer() {
+        let link_id = LinkID::new(0x1234_5678);
+        let (provisioner_tx, mut lossy_rx) = mpsc::channel::<pb_adv::PDU<PBAdvBuf>>(32);
+        let (mut lossy_tx, device_rx) = mpsc::channel(32);
+        let (device_tx, provisioner_rx) = mpsc::channel(32);
+        // Drops the first Transaction Continuation from the provisioner (part of its Public Key).
+        let lossy = async move {
+            let mut dropped = false;
+            while let Some(pdu) = lossy_rx.recv().await {
+                if !dropped {
+                    if let Control::TransactionContinuation(_) = pdu.generic_pdu.control {
+                        dropped = true;
+                        continue;
+                    }
+                }
+                if lossy_tx.send(pdu).await.is_err() {
+                    break;
+                }
+            }
+            dropped
+        };
+        let device = async move {
+            let mut bearer = GenericBearer::new_provisionee(link_id, device_tx, device_rx);
+            match bearer.recv(TIMEOUT).await.expect("invite") {
+                PDU::Invite(_) => (),
+                pdu => panic!("expected invite got {:?}", pdu),
+            }
+            let capabilities = Capabilities {
+                num_elements: ElementCount(1),
+                algorithms: Algorithms(1),
+                pub_key_option: PublicKeyOption::NoKey,
+                static_oob_option: StaticOOBOption::NoStaticOOB,
+                output_oob_size: None,
+                output_oob_action: OutputOOBOptions(0),
+                input_oob_size: None,
+                input_oob_action: InputOOBOptions(0),
+            };
+            bearer
+                .send(&PDU::Capabilities(capabilities))
+                .await
+                .expect("capabilities");
+            match bearer.recv(TIMEOUT).await.expect("start") {
+                PDU::Start(_) => (),
+                pdu => panic!("expected start got {:?}", pdu),
+            }
+            let provisioner_public_key = match bearer.recv(TIMEOUT).await.expect("public key") {
+                PDU::PublicKey(key) => key,
+                pdu => panic!("expected public key got {:?}", pdu),
+            };
+            let private_key = ecdh::PrivateKey::new().expect("private key");
+            let public_key = (&private_key.public_key().expect("public key")).into();
+            bearer
+                .send(&PDU::PublicKey(public_key))
+                .await
+                .expect("device public key");
+            (provisioner_public_key, public_key)
+        };
+        let provisioner =
+            async move {
+                let mut process = Process::new(Bearer::new_generic(
+                    GenericBearer::new_provisioner(link_id, provisioner_tx, provisioner_rx),
+                ));
+                loop {
+                    if let Stage::PublicKeyDevice {
+                        provisioner_public_key,
+                        device_public_key,
+                        ..
+                    } = process.next_stage().await.expect("next stage")
+                    {
+                        return (*provisioner_public_key, *device_public_key);
+                    }
+                }
+            };
+        let (dropped, device_keys, provisioner_keys) =
+            futures_util::future::join3(lossy, device, provisioner).await;
+        assert!(dropped, "lossy mock never dropped a continuation");
+        assert_eq!(device_keys, provisioner_keys);
+    }
+}
@@ -13,6 +13,7 @@ pub mod link;
 pub mod pb_adv;
 pub mod pb_gatt;
 pub mod protocol;
+pub mod provisionee;
 pub mod provisioner;
 
 pub enum Error {
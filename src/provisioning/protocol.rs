@@ -105,6 +105,30 @@ impl PDU {
             Opcode::Failed => Ok(PDU::Failed(Failed::unpack(buf)?)),
         }
     }
+    /// Largest packed PDU (opcode + `PublicKey`).
+    pub const MAX_BYTE_LEN: usize = 1 + PublicKey::BYTE_LEN;
+    /// Length of the packed PDU including the opcode byte.
+    pub fn byte_len(&self) -> usize {
+        1 + match self {
+            PDU::Invite(_) => Invite::BYTE_LEN,
+            PDU::Capabilities(_) => Capabilities::BYTE_LEN,
+            PDU::Start(_) => Start::BYTE_LEN,
+            PDU::PublicKey(_) => PublicKey::BYTE_LEN,
+            PDU::InputComplete(_) => InputComplete::BYTE_LEN,
+            PDU::Confirm(_) => Confirmation::BYTE_LEN,
+            PDU::Random(_) => Random::BYTE_LEN,
+            PDU::Data(_) => EncryptedProvisioningData::BYTE_LEN,
+            PDU::Complete(_) => Complete::BYTE_LEN,
+            PDU::Failed(_) => Failed::BYTE_LEN,
+        }
+    }
+    /// Packs the opcode followed by the PDU. Inverse of `PDU::unpack_raw`.
+    pub fn pack_raw(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self.opcode().into();
+        self.pack(&mut buf[1..])?;
+        Ok(())
+    }
     pub fn unpack_raw(buf: &[u8]) -> Result<PDU, PackError> {
         let opcode_padding = *buf.as_ref().get(0).ok_or(PackError::BadLength {
             expected: 1,
@@ -269,6 +293,13 @@ pub enum OutputOOBAction {
     OutputNumeric = 0x3,
     OutputAlphanumeric = 0x4,
 }
+impl OutputOOBAction {
+    /// Returns if the OOB value is alphanumeric (`size` characters) instead of numeric
+    /// (`size` digits).
+    pub fn is_alphanumeric(self) -> bool {
+        self == OutputOOBAction::OutputAlphanumeric
+    }
+}
 impl From<OutputOOBAction> for u8 {
     fn from(action: OutputOOBAction) -> Self {
         action as u8
@@ -294,6 +325,22 @@ impl OutputOOBOptions {
     pub const fn is_zero(self) -> bool {
         self.0 == 0
     }
+    pub fn supports(self, action: OutputOOBAction) -> bool {
+        self.0 & (1_u16 << u8::from(action)) != 0
+    }
+    /// Every supported `OutputOOBAction` (in ascending bit order).
+    pub fn actions(self) -> impl Iterator<Item = OutputOOBAction> {
+        [
+            OutputOOBAction::Blink,
+            OutputOOBAction::Beep,
+            OutputOOBAction::Vibrate,
+            OutputOOBAction::OutputNumeric,
+            OutputOOBAction::OutputAlphanumeric,
+        ]
+        .iter()
+        .copied()
+        .filter(move |&action| self.supports(action))
+    }
 }
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Hash)]
 pub struct OOBSize(u8);
@@ -308,6 +355,10 @@ impl OOBSize {
             Err(_) => panic!("bad OOB size"),
         }
     }
+    /// Largest numeric OOB value with `size` digits (`10^size - 1`).
+    pub fn max_value(self) -> u32 {
+        10_u32.pow(self.0.into()) - 1
+    }
 }
 impl TryFrom<u8> for OOBSize {
     type Error = PackError;
@@ -345,6 +396,13 @@ impl TryFrom<u8> for InputOOBAction {
         }
     }
 }
+impl InputOOBAction {
+    /// Returns if the OOB value is alphanumeric (`size` characters) instead of numeric
+    /// (`size` digits).
+    pub fn is_alphanumeric(self) -> bool {
+        self == InputOOBAction::InputAlphanumeric
+    }
+}
 impl From<InputOOBAction> for u8 {
     fn from(action: InputOOBAction) -> Self {
         action as u8
@@ -383,6 +441,35 @@ pub enum AuthenticationMethod {
     OutputOOB(OutputOOBAction, OOBSize),
     InputOOB(InputOOBAction, OOBSize),
 }
+impl AuthenticationMethod {
+    /// Output OOB with a `size` digit/character value. `None` if `size` isn't in `1..=8`.
+    pub fn output_oob(action: OutputOOBAction, size: u8) -> Option<AuthenticationMethod> {
+        Some(AuthenticationMethod::OutputOOB(
+            action,
+            OOBSize::try_from(size).ok()?,
+        ))
+    }
+    /// Input OOB with a `size` digit/character value. `None` if `size` isn't in `1..=8`.
+    pub fn input_oob(action: InputOOBAction, size: u8) -> Option<AuthenticationMethod> {
+        Some(AuthenticationMethod::InputOOB(
+            action,
+            OOBSize::try_from(size).ok()?,
+        ))
+    }
+    /// Largest OOB value for numeric Output/Input OOB actions. `None` for alphanumeric actions,
+    /// No OOB and Static OOB.
+    pub fn max_value(&self) -> Option<u32> {
+        match self {
+            AuthenticationMethod::OutputOOB(action, size) if !action.is_alphanumeric() => {
+                Some(size.max_value())
+            }
+            AuthenticationMethod::InputOOB(action, size) if !action.is_alphanumeric() => {
+                Some(size.max_value())
+            }
+            _ => None,
+        }
+    }
+}
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Hash)]
 pub struct InputOOBOptions(pub u16);
 
@@ -390,6 +477,40 @@ impl InputOOBOptions {
     pub const fn is_zero(self) -> bool {
         self.0 == 0
     }
+    pub fn supports(self, action: InputOOBAction) -> bool {
+        self.0 & (1_u16 << u8::from(action)) != 0
+    }
+    /// Every supported `InputOOBAction` (in ascending bit order).
+    pub fn actions(self) -> impl Iterator<Item = InputOOBAction> {
+        [
+            InputOOBAction::Push,
+            InputOOBAction::Twist,
+            InputOOBAction::InputNumber,
+            InputOOBAction::InputAlphanumeric,
+        ]
+        .iter()
+        .copied()
+        .filter(move |&action| self.supports(action))
+    }
+}
+/// OOB methods a device supports for authenticating during provisioning.
+/// The default is no OOB public key, no static OOB and no input or output OOB.
+#[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Hash)]
+pub struct OOBConfig {
+    pub pub_key_option: PublicKeyOption,
+    pub static_oob_option: StaticOOBOption,
+    pub output_oob: Option<(OOBSize, OutputOOBOptions)>,
+    pub input_oob: Option<(OOBSize, InputOOBOptions)>,
+}
+impl Default for OOBConfig {
+    fn default() -> Self {
+        OOBConfig {
+            pub_key_option: PublicKeyOption::NoKey,
+            static_oob_option: StaticOOBOption::NoStaticOOB,
+            output_oob: None,
+            input_oob: None,
+        }
+    }
 }
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Hash)]
 pub struct Capabilities {
@@ -402,6 +523,97 @@ pub struct Capabilities {
     pub input_oob_size: Option<OOBSize>,
     pub input_oob_action: InputOOBOptions,
 }
+impl Capabilities {
+    /// Capabilities of a device with `element_count` elements supporting the `oob_config` OOB
+    /// methods. FIPS P-256 is the only algorithm. Input/Output OOB without any actions isn't
+    /// advertised.
+    pub fn from_device(element_count: ElementCount, oob_config: &OOBConfig) -> Capabilities {
+        let (output_oob_size, output_oob_action) = match oob_config.output_oob {
+            Some((size, actions)) if !actions.is_zero() => (Some(size), actions),
+            _ => (None, OutputOOBOptions(0)),
+        };
+        let (input_oob_size, input_oob_action) = match oob_config.input_oob {
+            Some((size, actions)) if !actions.is_zero() => (Some(size), actions),
+            _ => (None, InputOOBOptions(0)),
+        };
+        Capabilities {
+            num_elements: element_count,
+            algorithms: Algorithms(1 << (AlgorithmsFlags::FIPSP256 as u16)),
+            pub_key_option: oob_config.pub_key_option,
+            static_oob_option: oob_config.static_oob_option,
+            output_oob_size,
+            output_oob_action,
+            input_oob_size,
+            input_oob_action,
+        }
+    }
+    /// Every `AuthenticationMethod` the device supports. `NoOOB` is always supported.
+    pub fn oob_methods(&self) -> Vec<AuthenticationMethod> {
+        let mut methods = vec![AuthenticationMethod::NoOOB];
+        if self.static_oob_option == StaticOOBOption::StaticOOBAvailable {
+            methods.push(AuthenticationMethod::StaticOOB);
+        }
+        if let Some(size) = self.output_oob_size {
+            methods.extend(
+                self.output_oob_action
+                    .actions()
+                    .map(|action| AuthenticationMethod::OutputOOB(action, size)),
+            );
+        }
+        if let Some(size) = self.input_oob_size {
+            methods.extend(
+                self.input_oob_action
+                    .actions()
+                    .map(|action| AuthenticationMethod::InputOOB(action, size)),
+            );
+        }
+        methods
+    }
+    /// Human readable summary of the capabilities (for provisioner tools to display).
+    #[cfg(feature = "std")]
+    pub fn describe(&self) -> String {
+        let algorithms = if self.algorithms.0 & 1 << (AlgorithmsFlags::FIPSP256 as u16) != 0 {
+            "FIPS P-256"
+        } else {
+            "none"
+        };
+        let public_key = match self.pub_key_option {
+            PublicKeyOption::NoKey => "in-band",
+            PublicKeyOption::OOBKey => "OOB available",
+        };
+        let static_oob = match self.static_oob_option {
+            StaticOOBOption::NoStaticOOB => "no",
+            StaticOOBOption::StaticOOBAvailable => "yes",
+        };
+        let actions = |size: Option<OOBSize>, actions: Vec<String>| match size {
+            Some(size) if !actions.is_empty() => {
+                format!("{} (size {})", actions.join(", "), u8::from(size))
+            }
+            _ => "none".to_owned(),
+        };
+        format!(
+            "elements: {}, algorithms: {}, public key: {}, static OOB: {}, output OOB: {}, input OOB: {}",
+            self.num_elements.0,
+            algorithms,
+            public_key,
+            static_oob,
+            actions(
+                self.output_oob_size,
+                self.output_oob_action
+                    .actions()
+                    .map(|action| format!("{:?}", action))
+                    .collect()
+            ),
+            actions(
+                self.input_oob_size,
+                self.input_oob_action
+                    .actions()
+                    .map(|action| format!("{:?}", action))
+                    .collect()
+            ),
+        )
+    }
+}
 impl ProtocolPDU for Capabilities {
     const OPCODE: Opcode = Opcode::Capabilities;
 
@@ -413,7 +625,7 @@ impl ProtocolPDU for Capabilities {
             if self.output_oob_action.is_zero() && self.output_oob_size.is_some() {
                 (self.output_oob_action, None)
             } else {
-                (self.output_oob_action, self.input_oob_size)
+                (self.output_oob_action, self.output_oob_size)
             };
         let (in_oob_action, in_oob_size) =
             if self.input_oob_action.is_zero() && self.input_oob_size.is_some() {
@@ -503,10 +715,9 @@ impl ProtocolPDU for EncryptedProvisioningData {
         Self: Sized,
     {
         PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let (data, mic) = MIC::split_from(buf, true).expect("MIC should be here");
         let mut out = [0_u8; ENCRYPTED_PROVISIONING_DATA_LEN];
-        out.copy_from_slice(&buf[..ENCRYPTED_PROVISIONING_DATA_LEN]);
-        let mic = MIC::try_from_bytes_be(&buf[ENCRYPTED_PROVISIONING_DATA_LEN..])
-            .expect("MIC should be here");
+        out.copy_from_slice(data);
         Ok(EncryptedProvisioningData { data: out, mic })
     }
 }
@@ -573,10 +784,12 @@ impl ProtocolPDU for Start {
                 }
             }
             AuthenticationMethodTypes::OutputOOB => {
-                AuthenticationMethod::OutputOOB(buf[3].try_into()?, buf[4].try_into()?)
+                AuthenticationMethod::output_oob(buf[3].try_into()?, buf[4])
+                    .ok_or_else(|| PackError::bad_index(4))?
             }
             AuthenticationMethodTypes::InputOOB => {
-                AuthenticationMethod::InputOOB(buf[3].try_into()?, buf[4].try_into()?)
+                AuthenticationMethod::input_oob(buf[3].try_into()?, buf[4])
+                    .ok_or_else(|| PackError::bad_index(4))?
             }
         };
         Ok(Self {
@@ -767,3 +980,87 @@ impl ProtocolPDU for Random {
         Ok(out)
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_output_and_static_oob() {
+        // 2 elements, FIPS P-256, no OOB public key, static OOB, 4 digit Blink/OutputNumeric.
+        let buf = [
+            0x02, 0x00, 0x01, 0x00, 0x01, 0x04, 0x00, 0x09, 0x00, 0x00, 0x00,
+        ];
+        let capabilities = Capabilities::unpack(&buf).expect("valid capabilities");
+        let size = OOBSize::new(4);
+        assert_eq!(
+            capabilities.oob_methods(),
+            vec![
+                AuthenticationMethod::NoOOB,
+                AuthenticationMethod::StaticOOB,
+                AuthenticationMethod::OutputOOB(OutputOOBAction::Blink, size),
+                AuthenticationMethod::OutputOOB(OutputOOBAction::OutputNumeric, size),
+            ]
+        );
+        #[cfg(feature = "std")]
+        assert_eq!(
+            capabilities.describe(),
+            "elements: 2, algorithms: FIPS P-256, public key: in-band, static OOB: yes, \
+             output OOB: Blink, OutputNumeric (size 4), input OOB: none"
+        );
+    }
+    #[test]
+    fn test_oob_size_for_action() {
+        let numeric = AuthenticationMethod::output_oob(OutputOOBAction::OutputNumeric, 3)
+            .expect("valid OOB size");
+        assert_eq!(numeric.max_value(), Some(999));
+        assert_eq!(
+            AuthenticationMethod::input_oob(InputOOBAction::Push, 8)
+                .expect("valid OOB size")
+                .max_value(),
+            Some(99_999_999)
+        );
+        assert_eq!(
+            AuthenticationMethod::input_oob(InputOOBAction::InputAlphanumeric, 3)
+                .expect("valid OOB size")
+                .max_value(),
+            None
+        );
+        assert_eq!(
+            AuthenticationMethod::output_oob(OutputOOBAction::OutputNumeric, 0),
+            None
+        );
+        assert_eq!(
+            AuthenticationMethod::input_oob(InputOOBAction::Twist, 9),
+            None
+        );
+        // Start PDU (without the opcode) with Output OOB, OutputNumeric and a zero size.
+        assert!(Start::unpack(&[0x00, 0x00, 0x02, 0x03, 0x00]).is_err());
+        assert!(Start::unpack(&[0x00, 0x00, 0x02, 0x03, 0x03]).is_ok());
+    }
+    #[test]
+    fn test_capabilities_from_device_output_oob() {
+        let oob_config = OOBConfig {
+            output_oob: Some((
+                OOBSize::new(6),
+                OutputOOBOptions(
+                    1 << u8::from(OutputOOBAction::Beep)
+                        | 1 << u8::from(OutputOOBAction::OutputNumeric),
+                ),
+            )),
+            // No actions so input OOB isn't advertised.
+            input_oob: Some((OOBSize::new(2), InputOOBOptions(0))),
+            ..OOBConfig::default()
+        };
+        let capabilities = Capabilities::from_device(ElementCount(3), &oob_config);
+        let mut buf = [0_u8; Capabilities::BYTE_LEN];
+        capabilities.pack(&mut buf).expect("buffer fits");
+        assert_eq!(
+            buf,
+            [0x03, 0x00, 0x01, 0x00, 0x00, 0x06, 0x00, 0x0A, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            Capabilities::unpack(&buf).expect("valid capabilities"),
+            capabilities
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Device (Provisionee) side of the Provisioning Protocol.
+use crate::crypto::key::DevKey;
+use crate::crypto::{ecdh, ECDHSecret, ProvisioningSalt};
+use crate::foundation::state::AttentionTimer;
+use crate::provisioning::confirmation;
+use crate::provisioning::confirmation::{AuthValue, ConfirmationKey, ConfirmationSalt};
+use crate::provisioning::data::{ProvisioningData, SessionSecurityMaterials};
+use crate::provisioning::protocol::{
+    Capabilities, Complete, Confirmation, ErrorCode, Failed, Invite, PublicKey, Random, Start, PDU,
+};
+use crate::provisioning::provisioner::{Bearer, ProvisionerError};
+use alloc::boxed::Box;
+use core::time::Duration;
+
+/// Physically gets the attention of a person (blink an LED, beep, etc) while provisioning.
+/// Called with the `AttentionTimer` from the provisioner's `Invite` and again with an off
+/// (`0`) timer once provisioning completes, fails or times out.
+pub trait AttentionHandler {
+    fn set_attention(&mut self, timer: AttentionTimer);
+}
+impl<F: FnMut(AttentionTimer)> AttentionHandler for F {
+    fn set_attention(&mut self, timer: AttentionTimer) {
+        self(timer)
+    }
+}
+pub enum Stage {
+    /// Waiting for an `Invite` from the provisioner.
+    Pending,
+    /// `Capabilities` sent, waiting for `Start`.
+    Capabilities {
+        invite: Invite,
+    },
+    Started {
+        invite: Invite,
+        start: Start,
+    },
+    /// Public Keys exchanged, waiting for the provisioner's `Confirmation`.
+    PublicKeyDevice {
+        ecdh_secret: ECDHSecret,
+        confirmation_key: ConfirmationKey,
+        confirmation_salt: ConfirmationSalt,
+        device_random: Random,
+    },
+    /// `Confirmation` exchanged, waiting for the provisioner's `Random`.
+    Confirmation {
+        ecdh_secret: ECDHSecret,
+        confirmation_key: ConfirmationKey,
+        confirmation_salt: ConfirmationSalt,
+        device_random: Random,
+        provisioner_confirmation: Confirmation,
+    },
+    /// Waiting for the encrypted `ProvisioningData`.
+    Distribute {
+        security_materials: SessionSecurityMaterials,
+    },
+    Complete(ProvisioningData),
+    Failed(Failed),
+    Closed,
+}
+impl Stage {
+    pub fn is_complete(&self) -> bool {
+        match self {
+            Stage::Complete(_) => true,
+            _ => false,
+        }
+    }
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Stage::Complete(_) | Stage::Failed(_) | Stage::Closed => true,
+            _ => false,
+        }
+    }
+}
+pub struct Process {
+    stage: Stage,
+    attention_on: bool,
+    device_key: Option<DevKey>,
+    pub capabilities: Capabilities,
+    pub auth_value: AuthValue,
+    pub attention_handler: Option<Box<dyn AttentionHandler>>,
+    pub bearer: Bearer,
+}
+impl Process {
+    pub const TIMEOUT: Duration = Duration::from_secs(30);
+    pub fn new(bearer: Bearer, capabilities: Capabilities) -> Process {
+        Process {
+            stage: Stage::Pending,
+            attention_on: false,
+            device_key: None,
+            capabilities,
+            auth_value: AuthValue::DEFAULT,
+            attention_handler: None,
+            bearer,
+        }
+    }
+    pub fn with_attention_handler<H: AttentionHandler + 'static>(mut self, handler: H) -> Self {
+        self.attention_handler = Some(Box::new(handler));
+        self
+    }
+    pub fn stage(&self) -> &'_ Stage {
+        &self.stage
+    }
+    /// Device Key of the new node. Only known once provisioning is complete.
+    pub fn device_key(&self) -> Option<DevKey> {
+        self.device_key
+    }
+    fn set_attention(&mut self, timer: AttentionTimer) {
+        self.attention_on = timer.is_on();
+        if let Some(handler) = self.attention_handler.as_mut() {
+            handler.set_attention(timer);
+        }
+    }
+    fn clear_attention(&mut self) {
+        if self.attention_on {
+            self.set_attention(AttentionTimer::default());
+        }
+    }
+    pub async fn fail(&mut self, reason: ErrorCode) -> Result<(), ProvisionerError> {
+        self.stage = Stage::Failed(Failed(reason));
+        self.clear_attention();
+        self.bearer.send(&PDU::Failed(Failed(reason))).await?;
+        self.bearer.close().await
+    }
+    async fn fail_with<T>(&mut self, reason: ErrorCode) -> Result<T, ProvisionerError> {
+        self.fail(reason).await?;
+        Err(ProvisionerError::Failed(reason))
+    }
+    async fn recv(&mut self) -> Result<PDU, ProvisionerError> {
+        match self.bearer.recv(Self::TIMEOUT).await {
+            Ok(PDU::Failed(failed)) => {
+                self.stage = Stage::Failed(failed);
+                Err(ProvisionerError::Failed(failed.0))
+            }
+            Ok(pdu) => Ok(pdu),
+            // PDUs that don't decode (ex. a `Start` with an unsupported algorithm) are
+            // `InvalidFormat` so the provisioner gets told why provisioning failed.
+            Err(ProvisionerError::PackError(_)) => self.fail_with(ErrorCode::InvalidFormat).await,
+            Err(e) => Err(e),
+        }
+    }
+    /// Runs the next step of provisioning. The attention timer is cleared once provisioning
+    /// is complete or any error (including a timeout) ends it.
+    pub async fn next_stage(&mut self) -> Result<&Stage, ProvisionerError> {
+        if let Err(e) = self.advance().await {
+            self.clear_attention();
+            return Err(e);
+        }
+        if self.stage.is_complete() {
+            self.clear_attention();
+        }
+        Ok(&self.stage)
+    }
+    async fn advance(&mut self) -> Result<(), ProvisionerError> {
+        match &self.stage {
+            Stage::Failed(reason) => return Err(ProvisionerError::Failed(reason.0)),
+            Stage::Complete(_) | Stage::Closed => return Err(ProvisionerError::Closed),
+            Stage::Pending => {
+                let invite = match self.recv().await? {
+                    PDU::Invite(invite) => invite,
+                    _ => return self.fail_with(ErrorCode::UnexpectedPDU).await,
+                };
+                self.set_attention(invite.0);
+                let capabilities = self.capabilities;
+                self.bearer.send(&PDU::Capabilities(capabilities)).await?;
+                self.stage = Stage::Capabilities { invite };
+            }
+            Stage::Capabilities { invite } => {
+                let invite = *invite;
+                let start = match self.recv().await? {
+                    PDU::Start(start) => start,
+                    _ => return self.fail_with(ErrorCode::UnexpectedPDU).await,
+                };
+                self.stage = Stage::Started { invite, start };
+            }
+            Stage::Started { invite, start } => {
+                let invite = *invite;
+                let start = *start;
+                let provisioner_public_key = match self.recv().await? {
+                    PDU::PublicKey(key) => key,
+                    _ => return self.fail_with(ErrorCode::UnexpectedPDU).await,
+                };
+                let private_key = ecdh::PrivateKey::new()?;
+                let device_public_key: PublicKey = (&private_key.public_key()?).into();
+                self.bearer.send(&PDU::PublicKey(device_public_key)).await?;
+                let ecdh_secret =
+                    private_key.agree(&provisioner_public_key, |s| ECDHSecret::new(s))?;
+                let confirmation_salt = confirmation::Input {
+                    invite,
+                    capabilities: self.capabilities,
+                    start,
+                    provisioner_public_key,
+                    device_public_key,
+                }
+                .salt();
+                self.stage = Stage::PublicKeyDevice {
+                    confirmation_key: ConfirmationKey::from_salt_and_secret(
+                        &confirmation_salt,
+                        &ecdh_secret,
+                    ),
+                    ecdh_secret,
+                    confirmation_salt,
+                    device_random: Random::new_rand(),
+                };
+            }
+            Stage::PublicKeyDevice {
+                ecdh_secret,
+                confirmation_key,
+                confirmation_salt,
+                device_random,
+            } => {
+                let ecdh_secret = *ecdh_secret;
+                let confirmation_key = *confirmation_key;
+                let confirmation_salt = *confirmation_salt;
+                let device_random = *device_random;
+                let provisioner_confirmation = match self.recv().await? {
+                    PDU::Confirm(confirmation) => confirmation,
+                    _ => return self.fail_with(ErrorCode::UnexpectedPDU).await,
+                };
+                let confirmation =
+                    confirmation_key.confirm_random(&device_random, &self.auth_value);
+                self.bearer.send(&PDU::Confirm(confirmation)).await?;
+                self.stage = Stage::Confirmation {
+                    ecdh_secret,
+                    confirmation_key,
+                    confirmation_salt,
+                    device_random,
+                    provisioner_confirmation,
+                };
+            }
+            Stage::Confirmation {
+                ecdh_secret,
+                confirmation_key,
+                confirmation_salt,
+                device_random,
+                provisioner_confirmation,
+            } => {
+                let ecdh_secret = *ecdh_secret;
+                let confirmation_key = *confirmation_key;
+                let confirmation_salt = *confirmation_salt;
+                let device_random = *device_random;
+                let provisioner_confirmation = *provisioner_confirmation;
+                let provisioner_random = match self.recv().await? {
+                    PDU::Random(random) => random,
+                    _ => return self.fail_with(ErrorCode::UnexpectedPDU).await,
+                };
+                if provisioner_confirmation
+                    != confirmation_key.confirm_random(&provisioner_random, &self.auth_value)
+                {
+                    self.fail(ErrorCode::ConfirmationFailed).await?;
+                    return Err(ProvisionerError::ProvisionerConfirmationMismatch);
+                }
+                self.bearer.send(&PDU::Random(device_random)).await?;
+                let provisioning_salt = ProvisioningSalt::from_randoms(
+                    &confirmation_salt,
+                    &provisioner_random,
+                    &device_random,
+                );
+                self.stage = Stage::Distribute {
+                    security_materials: SessionSecurityMaterials::from_secret_salt(
+                        &ecdh_secret,
+                        &provisioning_salt,
+                    ),
+                };
+            }
+            Stage::Distribute { security_materials } => {
+                let security_materials = *security_materials;
+                let encrypted = match self.recv().await? {
+                    PDU::Data(data) => data,
+                    _ => return self.fail_with(ErrorCode::UnexpectedPDU).await,
+                };
+                let data = match ProvisioningData::decrypt(&security_materials, encrypted) {
+                    Some(Ok(data)) => data,
+                    Some(Err(_)) => return self.fail_with(ErrorCode::InvalidFormat).await,
+                    None => return self.fail_with(ErrorCode::DecryptionFailed).await,
+                };
+                self.bearer.send(&PDU::Complete(Complete())).await?;
+                self.device_key = Some(security_materials.device_key());
+                self.stage = Stage::Complete(data);
+            }
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asyncs::sync::mpsc;
+    use crate::mesh::ElementCount;
+    use crate::provisioning::generic::SegmentGenerator;
+    use crate::provisioning::generic_bearer::GenericBearer;
+    use crate::provisioning::pb_adv;
+    use crate::provisioning::protocol::{
+        Algorithms, InputOOBOptions, OutputOOBOptions, PublicKeyOption, StaticOOBOption,
+    };
+    use crate::stack::bearer::PBAdvBuf;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            num_elements: ElementCount(1),
+            algorithms: Algorithms(1),
+            pub_key_option: PublicKeyOption::NoKey,
+            static_oob_option: StaticOOBOption::NoStaticOOB,
+            output_oob_size: None,
+            output_oob_action: OutputOOBOptions(0),
+            input_oob_size: None,
+            input_oob_action: InputOOBOptions(0),
+        }
+    }
+    #[tokio::test]
+    async fn test_attention_handler_invite() {
+        let (mut to_device, device_rx) = mpsc::channel(4);
+        let (device_tx, mut from_device) = mpsc::channel(4);
+        let capabilities = capabilities();
+        let attention = Rc::new(RefCell::new(Vec::new()));
+        let handler_attention = attention.clone();
+        let mut process = Process::new(Bearer::new_channel(device_rx, device_tx), capabilities)
+            .with_attention_handler(move |timer| handler_attention.borrow_mut().push(timer));
+        to_device
+            .send(PDU::Invite(Invite(AttentionTimer::new(5))))
+            .await
+            .ok()
+            .expect("device channel open");
+        match process.next_stage().await.expect("invite handled") {
+            Stage::Capabilities { invite } => assert_eq!(invite.0, AttentionTimer::new(5)),
+            _ => panic!("expected capabilities stage"),
+        }
+        assert_eq!(*attention.borrow(), vec![AttentionTimer::new(5)]);
+        match from_device.recv().await {
+            Some(PDU::Capabilities(_)) => (),
+            _ => panic!("expected capabilities"),
+        }
+        // Provisioning ending early (closed link) turns attention back off.
+        drop(to_device);
+        assert!(process.next_stage().await.is_err());
+        assert_eq!(
+            *attention.borrow(),
+            vec![AttentionTimer::new(5), AttentionTimer::default()]
+        );
+    }
+    #[tokio::test]
+    async fn test_start_unsupported_algorithm() {
+        let link_id = pb_adv::LinkID::new(0x0102_0304);
+        let (to_device, device_rx) = mpsc::channel::<pb_adv::PDU<PBAdvBuf>>(8);
+        let (device_tx, from_device) = mpsc::channel(8);
+        let mut raw_to_device = to_device.clone();
+        let mut provisioner = GenericBearer::new_provisioner(link_id, to_device, from_device);
+        let mut process = Process::new(
+            Bearer::new_generic(GenericBearer::new_provisionee(
+                link_id, device_tx, device_rx,
+            )),
+            capabilities(),
+        );
+        process.stage = Stage::Capabilities {
+            invite: Invite(AttentionTimer::default()),
+        };
+        // Start PDU (opcode 0x02) with the algorithm byte set to 0x01.
+        let start = [0x02_u8, 0x01, 0x00, 0x00, 0x00, 0x00];
+        let transaction_number = pb_adv::TransactionNumber::new_provisioner();
+        for generic_pdu in SegmentGenerator::new(&start[..]).pdus(transaction_number) {
+            raw_to_device
+                .send(pb_adv::PDU {
+                    link_id,
+                    transaction_number,
+                    generic_pdu,
+                })
+                .await
+                .ok()
+                .expect("device channel open");
+        }
+        let (device_result, provisioner_received) =
+            futures_util::future::join(process.next_stage(), provisioner.recv(Process::TIMEOUT))
+                .await;
+        match device_result {
+            Err(ProvisionerError::Failed(ErrorCode::InvalidFormat)) => (),
+            _ => panic!("expected invalid format failure"),
+        }
+        match provisioner_received {
+            Ok(PDU::Failed(Failed(ErrorCode::InvalidFormat))) => (),
+            _ => panic!("expected failed pdu"),
+        }
+    }
+}
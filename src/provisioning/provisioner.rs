@@ -1,12 +1,16 @@
+use crate::address::UnicastAddress;
 use crate::crypto::{ecdh, ECDHSecret, ProvisioningSalt};
 use crate::foundation::state::AttentionTimer;
 use crate::provisioning::confirmation::{AuthValue, ConfirmationKey, ConfirmationSalt};
 use crate::provisioning::data::{ProvisioningData, SessionSecurityMaterials};
+use crate::provisioning::generic_bearer::GenericBearer;
+use crate::provisioning::link::LinkError;
 use crate::provisioning::protocol::{
     AuthenticationMethod, Capabilities, Confirmation, ErrorCode, Failed, InputOOBAction, Invite,
     OOBSize, OutputOOBAction, PublicKey, PublicKeyType, Random, Start, PDU,
 };
-use crate::provisioning::{confirmation, protocol};
+use crate::provisioning::{confirmation, generic, protocol};
+use crate::stack::bearer::PBAdvBuf;
 use btle::PackError;
 use core::time::Duration;
 use driver_async::asyncs::sync::mpsc;
@@ -19,7 +23,9 @@ pub enum ProvisionerError {
     TimedOut,
     PrivateKeyMissing,
     OOBPublicKeyMissing,
+    CantSetOutputOOB,
     DeviceConfirmationMismatch,
+    ProvisionerConfirmationMismatch,
     CantDistributeYet,
     ECDH(ecdh::Error),
     PackError(PackError),
@@ -31,6 +37,20 @@ impl From<PackError> for ProvisionerError {
         ProvisionerError::PackError(e)
     }
 }
+impl From<LinkError> for ProvisionerError {
+    fn from(e: LinkError) -> Self {
+        match e {
+            LinkError::TimedOut => ProvisionerError::TimedOut,
+            LinkError::Closed(_) => ProvisionerError::Closed,
+            LinkError::PDUPackError(e)
+            | LinkError::ReassembleError(generic::ReassembleError::PackError(e)) => {
+                ProvisionerError::PackError(e)
+            }
+            LinkError::ReassembleError(_) => ProvisionerError::Failed(ErrorCode::InvalidFormat),
+            _ => ProvisionerError::ChannelClosed,
+        }
+    }
+}
 impl From<ecdh::Error> for ProvisionerError {
     fn from(e: ecdh::Error) -> Self {
         ProvisionerError::ECDH(e)
@@ -149,6 +169,17 @@ pub enum Stage {
     Distribute {
         security_materials: SessionSecurityMaterials,
     },
+    /// `ProvisioningData` sent, waiting for the device's `Complete`.
+    DataSent {
+        security_materials: SessionSecurityMaterials,
+        element_address: UnicastAddress,
+    },
+    /// Device acknowledged the `ProvisioningData`. It's now a node with its primary element at
+    /// `element_address`.
+    Complete {
+        security_materials: SessionSecurityMaterials,
+        element_address: UnicastAddress,
+    },
     Closed,
     Failed(Failed),
 }
@@ -159,6 +190,12 @@ impl Stage {
             _ => false,
         }
     }
+    pub fn is_complete(&self) -> bool {
+        match self {
+            Stage::Complete { .. } => true,
+            _ => false,
+        }
+    }
     pub fn failed_reason(&self) -> Option<ErrorCode> {
         match self {
             Stage::Failed(reason) => Some(reason.0),
@@ -166,31 +203,57 @@ impl Stage {
         }
     }
 }
-pub struct Bearer {
-    in_bearer: mpsc::Receiver<PDU>,
-    out_bearer: mpsc::Sender<PDU>,
+pub enum Bearer {
+    /// Whole provisioning PDUs passed over channels (segmentation is handled elsewhere).
+    Channel {
+        in_bearer: mpsc::Receiver<PDU>,
+        out_bearer: mpsc::Sender<PDU>,
+    },
+    /// PB-ADV Generic Provisioning. Segmentation and acks are handled by the `GenericBearer`.
+    Generic(GenericBearer<PBAdvBuf>),
 }
 impl Bearer {
+    pub fn new_channel(in_bearer: mpsc::Receiver<PDU>, out_bearer: mpsc::Sender<PDU>) -> Bearer {
+        Bearer::Channel {
+            in_bearer,
+            out_bearer,
+        }
+    }
+    pub fn new_generic(bearer: GenericBearer<PBAdvBuf>) -> Bearer {
+        Bearer::Generic(bearer)
+    }
     pub async fn close(&mut self) -> Result<(), ProvisionerError> {
         Ok(())
     }
     pub async fn recv(&mut self, timeout: Duration) -> Result<PDU, ProvisionerError> {
-        driver_async::asyncs::time::timeout(timeout, self.in_bearer.recv())
-            .await
-            .map_err(|_| ProvisionerError::TimedOut)?
-            .ok_or(ProvisionerError::ChannelClosed)
+        match self {
+            Bearer::Channel { in_bearer, .. } => {
+                driver_async::asyncs::time::timeout(timeout, in_bearer.recv())
+                    .await
+                    .map_err(|_| ProvisionerError::TimedOut)?
+                    .ok_or(ProvisionerError::ChannelClosed)
+            }
+            Bearer::Generic(bearer) => Ok(bearer.recv(timeout).await?),
+        }
     }
     pub async fn send(&mut self, pdu: &PDU) -> Result<(), ProvisionerError> {
-        self.out_bearer
-            .send(*pdu)
-            .await
-            .map_err(|_| ProvisionerError::ChannelClosed)
+        match self {
+            Bearer::Channel { out_bearer, .. } => out_bearer
+                .send(*pdu)
+                .await
+                .map_err(|_| ProvisionerError::ChannelClosed),
+            Bearer::Generic(bearer) => Ok(bearer.send(pdu).await?),
+        }
     }
 }
 pub struct Process {
     stage: Stage,
     last_message_time: Option<Instant>,
     pub oob_public_key: Option<PublicKey>,
+    /// Private key for the next key exchange. `None` generates a new one.
+    pub private_key: Option<ecdh::PrivateKey>,
+    /// Random for the next confirmation. `None` generates a new one.
+    pub provisioner_random: Option<Random>,
     pub attention_timer: AttentionTimer,
     pub authentication_method: AuthenticationMethod,
     pub auth_value: AuthValue,
@@ -210,6 +273,8 @@ impl Process {
             stage: Stage::Pending,
             last_message_time: None,
             oob_public_key: None,
+            private_key: None,
+            provisioner_random: None,
             attention_timer,
             authentication_method,
             auth_value,
@@ -241,14 +306,20 @@ impl Process {
             None => Ok(None),
         }
     }
+    /// Sends the encrypted `data` to the device. Call `next_stage` afterwards to wait for the
+    /// device's `Complete`.
     pub async fn distribute(&mut self, data: &ProvisioningData) -> Result<(), ProvisionerError> {
-        let sm = match &self.stage {
-            Stage::Distribute { security_materials } => security_materials,
+        let security_materials = match &self.stage {
+            Stage::Distribute { security_materials } => *security_materials,
             _ => return Err(ProvisionerError::CantDistributeYet),
         };
-        let encrypted = data.encrypt(sm);
+        let encrypted = data.encrypt(&security_materials);
         self.send(&PDU::Data(encrypted)).await?;
-        self.bearer.close().await
+        self.stage = Stage::DataSent {
+            security_materials,
+            element_address: data.element_address,
+        };
+        Ok(())
     }
     pub async fn fail(&mut self, reason: ErrorCode) -> Result<(), ProvisionerError> {
         self.stage = Stage::Failed(Failed(reason));
@@ -266,9 +337,39 @@ impl Process {
     pub fn stage(&self) -> &'_ Stage {
         &self.stage
     }
+    /// Supplies the value the device output (`Stage::OutputOOB` holds the action and size to
+    /// show the user) and moves on to sending the provisioner's confirmation.
+    pub fn await_output_oob(&mut self, value: AuthValue) -> Result<&Stage, ProvisionerError> {
+        self.bad_stage()?;
+        let (ecdh_secret, confirmation_key, confirmation_salt, provisioner_random) =
+            match &self.stage {
+                Stage::OutputOOB {
+                    ecdh_secret,
+                    confirmation_key,
+                    confirmation_salt,
+                    provisioner_random,
+                    ..
+                } => (
+                    *ecdh_secret,
+                    *confirmation_key,
+                    *confirmation_salt,
+                    *provisioner_random,
+                ),
+                _ => return Err(ProvisionerError::CantSetOutputOOB),
+            };
+        self.auth_value = value;
+        self.stage = Stage::SendConfirmation {
+            ecdh_secret,
+            confirmation_key,
+            confirmation_salt,
+            provisioner_random,
+            auth_value: value,
+        };
+        Ok(&self.stage)
+    }
     pub fn can_send(&self) -> bool {
         match self.stage {
-            Stage::Closed | Stage::Failed(_) => false,
+            Stage::Closed | Stage::Complete { .. } | Stage::Failed(_) => false,
             _ => true,
         }
     }
@@ -304,6 +405,8 @@ impl Process {
             auth_method: self.authentication_method,
         }
     }
+    /// Advances the provisioning process by one stage. `Stage::OutputOOB` is returned (without
+    /// advancing) until the value the device output is supplied with `await_output_oob`.
     pub async fn next_stage(&mut self) -> Result<&Stage, ProvisionerError> {
         let timeout = self.recv_timeout()?;
         match &mut self.stage {
@@ -359,7 +462,10 @@ impl Process {
                 let invite = *invite;
                 let capabilities = *capabilities;
                 let start = *start;
-                let private_key = ecdh::PrivateKey::new()?;
+                let private_key = match self.private_key.take() {
+                    Some(private_key) => private_key,
+                    None => ecdh::PrivateKey::new()?,
+                };
                 let public_key = (&private_key.public_key()?).into();
                 self.send(&PDU::PublicKey(public_key)).await?;
                 self.stage = Stage::PublicKeyProvisioner {
@@ -390,7 +496,10 @@ impl Process {
                 start,
                 device_public_key,
             } => {
-                let private_key = ecdh::PrivateKey::new()?;
+                let private_key = match self.private_key.take() {
+                    Some(private_key) => private_key,
+                    None => ecdh::PrivateKey::new()?,
+                };
                 let provisioner_public_key = (&private_key.public_key()?).into();
                 let invite = *invite;
                 let capabilities = *capabilities;
@@ -462,7 +571,10 @@ impl Process {
                 self.stage = Stage::Confirmation {
                     ecdh_secret,
                     confirmation_key,
-                    provisioner_random: Random::new_rand(),
+                    provisioner_random: self
+                        .provisioner_random
+                        .take()
+                        .unwrap_or_else(Random::new_rand),
                     confirmation_salt,
                     oob_type: start.auth_method,
                 }
@@ -513,20 +625,8 @@ impl Process {
                 }
             },
 
-            Stage::OutputOOB {
-                ecdh_secret,
-                confirmation_key,
-                confirmation_salt,
-                provisioner_random,
-                ..
-            } => {
-                self.stage = Stage::SendConfirmation {
-                    auth_value: self.auth_value,
-                    ecdh_secret: *ecdh_secret,
-                    confirmation_key: *confirmation_key,
-                    confirmation_salt: *confirmation_salt,
-                    provisioner_random: *provisioner_random,
-                }
+            Stage::OutputOOB { .. } => {
+                // Stays here until the user enters what the device output (`await_output_oob`).
             }
             Stage::InputOOB {
                 ecdh_secret,
@@ -535,12 +635,23 @@ impl Process {
                 provisioner_random,
                 ..
             } => {
-                self.stage = Stage::SendConfirmation {
-                    auth_value: self.auth_value,
-                    ecdh_secret: *ecdh_secret,
-                    confirmation_key: *confirmation_key,
-                    provisioner_random: *provisioner_random,
-                    confirmation_salt: *confirmation_salt,
+                // The device sends `InputComplete` once the user has entered the value
+                // we're displaying. Only then can the confirmations be exchanged.
+                let ecdh_secret = *ecdh_secret;
+                let confirmation_key = *confirmation_key;
+                let confirmation_salt = *confirmation_salt;
+                let provisioner_random = *provisioner_random;
+                match self.recv().await? {
+                    PDU::InputComplete(_) => {
+                        self.stage = Stage::SendConfirmation {
+                            auth_value: self.auth_value,
+                            ecdh_secret,
+                            confirmation_key,
+                            provisioner_random,
+                            confirmation_salt,
+                        }
+                    }
+                    _ => self.fail_with(ErrorCode::UnexpectedPDU).await?,
                 }
             }
             Stage::StaticOOB {
@@ -674,7 +785,377 @@ impl Process {
             Stage::Distribute { .. } => {
                 unimplemented!("security_materials have to manually be sent for now")
             }
+            Stage::DataSent {
+                security_materials,
+                element_address,
+            } => {
+                let security_materials = *security_materials;
+                let element_address = *element_address;
+                match self.recv().await? {
+                    PDU::Complete(_) => {
+                        self.bearer.close().await?;
+                        self.stage = Stage::Complete {
+                            security_materials,
+                            element_address,
+                        }
+                    }
+                    PDU::Failed(failed) => {
+                        self.bearer.close().await?;
+                        self.stage = Stage::Failed(failed);
+                        return Err(ProvisionerError::Failed(failed.0));
+                    }
+                    _ => self.fail_with(ErrorCode::UnexpectedPDU).await?,
+                }
+            }
+            Stage::Complete { .. } => return Err(ProvisionerError::Closed),
         }
         Ok(&self.stage)
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::UnicastAddress;
+    use crate::crypto::key::{Key, NetKey};
+    use crate::crypto::{Salt, ECDH_SECRET_LEN, MIC};
+    use crate::mesh::{bytes_str_to_buf, ElementCount, IVIndex, KeyIndex, NetKeyIndex};
+    use crate::provisioning::data::Flags;
+    use crate::provisioning::protocol::{
+        Algorithms, Confirmation, EncryptedProvisioningData, InputComplete, InputOOBOptions,
+        OutputOOBOptions, PublicKeyOption, StaticOOBOption,
+    };
+    use crate::provisioning::provisionee;
+    use crate::stack::test_helpers;
+
+    fn input_oob_process() -> (Process, mpsc::Sender<PDU>, mpsc::Receiver<PDU>) {
+        let (to_provisioner, provisioner_rx) = mpsc::channel(4);
+        let (provisioner_tx, from_provisioner) = mpsc::channel(4);
+        let mut process = Process::new(Bearer::new_channel(provisioner_rx, provisioner_tx));
+        let ecdh_secret = ECDHSecret::new_bytes([0x42_u8; ECDH_SECRET_LEN]);
+        let confirmation_salt = ConfirmationSalt(Salt::new([0x24_u8; 16]));
+        process.stage = Stage::InputOOB {
+            ecdh_secret,
+            confirmation_key: ConfirmationKey::from_salt_and_secret(
+                &confirmation_salt,
+                &ecdh_secret,
+            ),
+            confirmation_salt,
+            provisioner_random: Random::ZEROED,
+            input_oob_action: InputOOBAction::InputNumber,
+            input_oob_size: OOBSize::new(4),
+        };
+        (process, to_provisioner, from_provisioner)
+    }
+
+    #[tokio::test]
+    async fn test_input_oob_waits_for_input_complete() {
+        let (mut process, mut to_provisioner, _from_provisioner) = input_oob_process();
+        to_provisioner
+            .send(PDU::InputComplete(InputComplete()))
+            .await
+            .ok()
+            .expect("provisioner channel open");
+        match process.next_stage().await.expect("input complete handled") {
+            Stage::SendConfirmation { .. } => (),
+            _ => panic!("expected send confirmation stage"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_input_oob_unexpected_pdu() {
+        let (mut process, mut to_provisioner, mut from_provisioner) = input_oob_process();
+        to_provisioner
+            .send(PDU::Confirm(Confirmation::default()))
+            .await
+            .ok()
+            .expect("provisioner channel open");
+        assert_eq!(
+            process.next_stage().await.err(),
+            Some(ProvisionerError::Failed(ErrorCode::UnexpectedPDU))
+        );
+        assert_eq!(
+            process.stage().failed_reason(),
+            Some(ErrorCode::UnexpectedPDU)
+        );
+        match from_provisioner.recv().await {
+            Some(PDU::Failed(Failed(ErrorCode::UnexpectedPDU))) => (),
+            _ => panic!("expected failed pdu"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_output_oob_numeric() {
+        let (to_device, device_rx) = mpsc::channel(4);
+        let (device_tx, from_device) = mpsc::channel(4);
+        let output_oob_size = OOBSize::new(4);
+        let capabilities = Capabilities {
+            num_elements: ElementCount(1),
+            algorithms: Algorithms(1),
+            pub_key_option: PublicKeyOption::NoKey,
+            static_oob_option: StaticOOBOption::NoStaticOOB,
+            output_oob_size: Some(output_oob_size),
+            output_oob_action: OutputOOBOptions(1 << u8::from(OutputOOBAction::OutputNumeric)),
+            input_oob_size: None,
+            input_oob_action: InputOOBOptions(0),
+        };
+        // The number the device displays and the user types into the provisioner.
+        let displayed = AuthValue::from_numeric(1234);
+        let mut device =
+            provisionee::Process::new(Bearer::new_channel(device_rx, device_tx), capabilities);
+        device.auth_value = displayed;
+        let mut provisioner = Process::new_with(
+            Bearer::new_channel(from_device, to_device),
+            AttentionTimer::default(),
+            AuthenticationMethod::OutputOOB(OutputOOBAction::OutputNumeric, output_oob_size),
+            AuthValue::ZEROED,
+            PublicKeyType::NotAvailable,
+        );
+        let data = ProvisioningData {
+            net_key: test_helpers::net_key(),
+            net_key_index: NetKeyIndex(KeyIndex::new(0)),
+            flags: Flags::default(),
+            iv_index: IVIndex(0x1234_5678),
+            element_address: UnicastAddress::new(0x0B0C).expect("valid unicast address"),
+        };
+        let provisioner_side = async {
+            loop {
+                match provisioner.next_stage().await {
+                    Ok(Stage::Distribute { .. }) => break,
+                    Ok(Stage::OutputOOB {
+                        output_oob_action,
+                        output_oob_size,
+                        ..
+                    }) => {
+                        assert_eq!(*output_oob_action, OutputOOBAction::OutputNumeric);
+                        assert_eq!(*output_oob_size, OOBSize::new(4));
+                    }
+                    Ok(_) => continue,
+                    Err(e) => panic!("provisioner error: {:?}", e),
+                }
+                provisioner
+                    .await_output_oob(displayed)
+                    .expect("output oob value accepted");
+            }
+            provisioner
+                .distribute(&data)
+                .await
+                .expect("data distributed");
+        };
+        let device_side = async {
+            while !device.stage().is_closed() {
+                device.next_stage().await.expect("device provisioned");
+            }
+        };
+        tokio::join!(provisioner_side, device_side);
+        match device.stage() {
+            provisionee::Stage::Complete(received) => {
+                assert_eq!(received.net_key, data.net_key);
+                assert_eq!(received.iv_index, data.iv_index);
+                assert_eq!(received.element_address, data.element_address);
+            }
+            _ => panic!("expected device to be provisioned"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_provisioning() {
+        let (to_device, device_rx) = mpsc::channel(4);
+        let (device_tx, from_device) = mpsc::channel(4);
+        let capabilities = Capabilities {
+            num_elements: ElementCount(1),
+            algorithms: Algorithms(1),
+            pub_key_option: PublicKeyOption::NoKey,
+            static_oob_option: StaticOOBOption::NoStaticOOB,
+            output_oob_size: None,
+            output_oob_action: OutputOOBOptions(0),
+            input_oob_size: None,
+            input_oob_action: InputOOBOptions(0),
+        };
+        let mut device =
+            provisionee::Process::new(Bearer::new_channel(device_rx, device_tx), capabilities);
+        let mut provisioner = Process::new(Bearer::new_channel(from_device, to_device));
+        let data = ProvisioningData {
+            net_key: test_helpers::net_key(),
+            net_key_index: NetKeyIndex(KeyIndex::new(0)),
+            flags: Flags::default(),
+            iv_index: IVIndex(0x1234_5678),
+            element_address: UnicastAddress::new(0x0B0C).expect("valid unicast address"),
+        };
+        let provisioner_side = async {
+            loop {
+                match provisioner.next_stage().await {
+                    Ok(Stage::Distribute { .. }) => break,
+                    Ok(_) => (),
+                    Err(e) => panic!("provisioner error: {:?}", e),
+                }
+            }
+            provisioner
+                .distribute(&data)
+                .await
+                .expect("data distributed");
+            provisioner
+                .next_stage()
+                .await
+                .expect("device acknowledged data");
+        };
+        let device_side = async {
+            while !device.stage().is_closed() {
+                device.next_stage().await.expect("device provisioned");
+            }
+        };
+        tokio::join!(provisioner_side, device_side);
+        assert!(!provisioner.can_send());
+        match provisioner.stage() {
+            Stage::Complete {
+                security_materials,
+                element_address,
+            } => {
+                assert_eq!(*element_address, data.element_address);
+                // Both sides derive the same DevKey from the session.
+                assert_eq!(Some(security_materials.device_key()), device.device_key());
+            }
+            _ => panic!("expected provisioning to be complete"),
+        }
+        assert!(device.stage().is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_failed_during_distribution() {
+        let (mut to_provisioner, provisioner_rx) = mpsc::channel(4);
+        let (provisioner_tx, _from_provisioner) = mpsc::channel(4);
+        let mut process = Process::new(Bearer::new_channel(provisioner_rx, provisioner_tx));
+        process.stage = Stage::DataSent {
+            security_materials: SessionSecurityMaterials::from_secret_salt(
+                &ECDHSecret::new_bytes([0x42_u8; ECDH_SECRET_LEN]),
+                &ProvisioningSalt(Salt::new([0x24_u8; 16])),
+            ),
+            element_address: UnicastAddress::new(0x0B0C).expect("valid unicast address"),
+        };
+        to_provisioner
+            .send(PDU::Failed(Failed(ErrorCode::DecryptionFailed)))
+            .await
+            .ok()
+            .expect("provisioner channel open");
+        assert_eq!(
+            process.next_stage().await.err(),
+            Some(ProvisionerError::Failed(ErrorCode::DecryptionFailed))
+        );
+        assert_eq!(
+            process.stage().failed_reason(),
+            Some(ErrorCode::DecryptionFailed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sample_data_handshake() {
+        // Mesh Profile Spec 8.7 provisioning sample data.
+        fn hex<T: Default + AsMut<[u8]>>(s: &str) -> T {
+            bytes_str_to_buf(s).expect("from spec")
+        }
+        let (mut to_provisioner, provisioner_rx) = mpsc::channel(4);
+        let (provisioner_tx, mut from_provisioner) = mpsc::channel(8);
+        let mut provisioner = Process::new(Bearer::new_channel(provisioner_rx, provisioner_tx));
+        provisioner.private_key = Some(
+            ecdh::PrivateKey::from_fixed_bytes(&hex(
+                "06a516693c9aa31a6084545d0c5db641b48572b97203ddffb7ac73f7d0457663",
+            ))
+            .expect("valid private key"),
+        );
+        provisioner.provisioner_random = Some(Random(hex("8b19ac31d58b124c946209b5db1021b9")));
+        let capabilities = Capabilities {
+            num_elements: ElementCount(1),
+            algorithms: Algorithms(1),
+            pub_key_option: PublicKeyOption::NoKey,
+            static_oob_option: StaticOOBOption::NoStaticOOB,
+            output_oob_size: None,
+            output_oob_action: OutputOOBOptions(0),
+            input_oob_size: None,
+            input_oob_action: InputOOBOptions(0),
+        };
+        let device_public_key = PublicKey {
+            x: hex("f465e43ff23d3f1b9dc7dfc04da8758184dbc966204796eccf0d6cf5e16500cc"),
+            y: hex("0201d048bcbbd899eeefc424164e33c201c2b010ca6b4d43a8a155cad8ecb279"),
+        };
+        for pdu in &[
+            PDU::Capabilities(capabilities),
+            PDU::PublicKey(device_public_key),
+            PDU::Confirm(Confirmation(hex("eeba521c196b52cc2e37aa40329f554e"))),
+            PDU::Random(Random(hex("55a2a2bca04cd32ff6f346bd0a0c1a3a"))),
+        ] {
+            to_provisioner
+                .send(*pdu)
+                .await
+                .ok()
+                .expect("provisioner channel open");
+        }
+        loop {
+            match provisioner.next_stage().await {
+                Ok(Stage::Confirmation {
+                    ecdh_secret,
+                    confirmation_salt,
+                    confirmation_key,
+                    ..
+                }) => {
+                    assert_eq!(
+                        *ecdh_secret,
+                        ECDHSecret::new_bytes(hex(
+                            "ab85843a2f6d883f62e5684b38e307335fe6e1945ecd19604105c6f23221eb69"
+                        ))
+                    );
+                    assert_eq!(
+                        *confirmation_salt,
+                        ConfirmationSalt(
+                            Salt::from_hex("5faabe187337c71cc6c973369dcaa79a").expect("from spec")
+                        )
+                    );
+                    assert_eq!(
+                        *confirmation_key,
+                        ConfirmationKey(
+                            Key::from_hex("e31fe046c68ec339c425fc6629f0336f").expect("from spec")
+                        )
+                    );
+                }
+                Ok(Stage::Distribute { .. }) => break,
+                Ok(_) => (),
+                Err(e) => panic!("provisioner error: {:?}", e),
+            }
+        }
+        let data = ProvisioningData {
+            net_key: NetKey::from_hex("efb2255e6422d330088e09bb015ed707").expect("from spec"),
+            net_key_index: NetKeyIndex(KeyIndex::new(0x0567)),
+            flags: Flags::default(),
+            iv_index: IVIndex(0x0102_0304),
+            element_address: UnicastAddress::new(0x0B0C).expect("valid unicast address"),
+        };
+        provisioner
+            .distribute(&data)
+            .await
+            .expect("data distributed");
+        drop(provisioner);
+        let mut sent = Vec::new();
+        while let Some(pdu) = from_provisioner.recv().await {
+            sent.push(pdu);
+        }
+        assert_eq!(
+            sent,
+            vec![
+                PDU::Invite(Invite(AttentionTimer::default())),
+                PDU::Start(Start {
+                    algorithm: protocol::AlgorithmsFlags::FIPSP256,
+                    public_key_type: PublicKeyType::NotAvailable,
+                    auth_method: AuthenticationMethod::NoOOB,
+                }),
+                PDU::PublicKey(PublicKey {
+                    x: hex("2c31a47b5779809ef44cb5eaaf5c3e43d5f8faad4a8794cb987e9b03745c78dd"),
+                    y: hex("919512183898dfbecd52e2408e43871fd021109117bd3ed4eaf8437743715d4f"),
+                }),
+                PDU::Confirm(Confirmation(hex("b38a114dfdca1fe153bd2c1e0dc46ac2"))),
+                PDU::Random(Random(hex("8b19ac31d58b124c946209b5db1021b9"))),
+                PDU::Data(EncryptedProvisioningData {
+                    data: hex("d0bd7f4a89a2ff6222af59a90a60ad58acfe3123356f5cec29"),
+                    mic: MIC::Big(0x73e0_ec50_783b_10c7),
+                }),
+            ]
+        );
+    }
+}
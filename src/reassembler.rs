@@ -145,16 +145,18 @@ impl Context {
     pub fn mic_size(&self) -> Option<MicSize> {
         self.header.mic_size()
     }
+    /// The TransMIC trailing the reassembled data. Its size (4 or 8 bytes) comes from the SZMIC
+    /// flag in the segment header.
     pub fn mic(&self) -> Option<MIC> {
         if !self.is_ready() || self.header.lower_header.is_control() {
             None
         } else {
-            Some(
-                MIC::try_from_bytes_le(
-                    &self.data()[self.data_len..][..self.mic_size()?.byte_size()],
-                )
-                .expect("MIC should be here"),
+            let mic_size = self.mic_size()?;
+            MIC::split_from(
+                &self.data()[..self.data_len + mic_size.byte_size()],
+                mic_size == MicSize::Big,
             )
+            .map(|(_, mic)| mic)
         }
     }
     pub fn insert_data(&mut self, seg_n: SegN, data: &[u8]) -> Result<(), ReassembleError> {
@@ -178,8 +180,8 @@ impl Context {
     pub fn finish(mut self) -> Result<upper::PDU<Box<[u8]>>, Context> {
         if self.is_ready() {
             let len = self.data_len;
-            self.storage.truncate(len);
             let mic = self.mic();
+            self.storage.truncate(len);
             let header = self.header;
             let storage = self.storage.into_boxed_slice();
             match header.lower_header {
@@ -1,9 +1,256 @@
 //! Optional Relay Feature
-use crate::mesh::{IVIndex, NetKeyIndex};
+use crate::address::UnicastAddress;
+use crate::foundation::state::RelayRetransmit;
+use crate::mesh::{IVIndex, NetKeyIndex, SequenceNumber, TTL};
 use crate::net;
+use crate::timestamp::TimestampTrait;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::time::Duration;
+use driver_async::time::{Instant, InstantTrait};
 
 pub struct RelayPDU {
     pub pdu: net::PDU,
     pub iv_index: IVIndex,
     pub net_key_index: NetKeyIndex,
 }
+/// Short-term Network Message Cache of recently seen `(src, seq)` pairs so the same Network PDU
+/// isn't relayed more than once. Unlike the replay protection list (`replay::Cache`), which only
+/// keeps the highest `seq` per `src` and has to be persisted, entries here just expire after
+/// `lifetime`.
+#[derive(Clone, Debug)]
+pub struct NetworkCache {
+    entries: BTreeMap<(UnicastAddress, SequenceNumber), Instant>,
+    lifetime: Duration,
+}
+impl NetworkCache {
+    pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(10);
+    pub fn new() -> NetworkCache {
+        Self::with_lifetime(Self::DEFAULT_LIFETIME)
+    }
+    pub fn with_lifetime(lifetime: Duration) -> NetworkCache {
+        NetworkCache {
+            entries: BTreeMap::new(),
+            lifetime,
+        }
+    }
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    fn is_expired(&self, seen: Instant, now: Instant) -> bool {
+        now.checked_duration_since(seen)
+            .map_or(false, |elapsed| elapsed >= self.lifetime)
+    }
+    /// Removes every entry seen `lifetime` or longer before `now`.
+    pub fn evict_expired(&mut self, now: Instant) {
+        let expired: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, &seen)| self.is_expired(seen, now))
+            .map(|(&key, _)| key)
+            .collect();
+        for key in expired {
+            self.entries.remove(&key);
+        }
+    }
+    /// Returns `true` if `(src, seq)` is in the cache (expired entries not yet evicted included).
+    pub fn contains(&self, src: UnicastAddress, seq: SequenceNumber) -> bool {
+        self.entries.contains_key(&(src, seq))
+    }
+    /// Records `(src, seq)` as seen at `now`, refreshing it if it's already in the cache.
+    pub fn insert(&mut self, src: UnicastAddress, seq: SequenceNumber, now: Instant) {
+        self.entries.insert((src, seq), now);
+    }
+    /// Returns `true` if `(src, seq)` hasn't been seen within `lifetime` of `now` (so the PDU
+    /// should be relayed) and records it.
+    pub fn observe(&mut self, src: UnicastAddress, seq: SequenceNumber, now: Instant) -> bool {
+        self.evict_expired(now);
+        if self.contains(src, seq) {
+            false
+        } else {
+            self.insert(src, seq, now);
+            true
+        }
+    }
+}
+impl Default for NetworkCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// The node's Network Message Cache and relay. Every received PDU goes through `observe` and the
+/// ones to relay through `relay`, which records them in the same `NetworkCache` so when the
+/// relayed transmission is heard echoed back (same `src` and `seq`, lower TTL) it's dropped
+/// instead of relayed again.
+#[derive(Clone, Debug, Default)]
+pub struct Relay {
+    cache: NetworkCache,
+}
+impl Relay {
+    pub fn new() -> Relay {
+        Self::with_cache(NetworkCache::new())
+    }
+    pub fn with_cache(cache: NetworkCache) -> Relay {
+        Relay { cache }
+    }
+    pub fn cache(&self) -> &NetworkCache {
+        &self.cache
+    }
+    pub fn cache_mut(&mut self) -> &mut NetworkCache {
+        &mut self.cache
+    }
+    /// Returns `true` if a received PDU from `src` with `seq` is new (see `NetworkCache::observe`).
+    /// Repeats and echoes of relayed PDUs return `false`.
+    pub fn observe(&mut self, src: UnicastAddress, seq: SequenceNumber, now: Instant) -> bool {
+        self.cache.observe(src, seq, now)
+    }
+    /// Returns the PDU to retransmit (with its TTL decremented) or `None` if its TTL is less than
+    /// 2. The PDU is recorded in the cache at `now` so its echo doesn't get relayed again.
+    pub fn relay(&mut self, relay_pdu: RelayPDU, now: Instant) -> Option<RelayPDU> {
+        let RelayPDU {
+            mut pdu,
+            iv_index,
+            net_key_index,
+        } = relay_pdu;
+        let header = pdu.header;
+        if !header.ttl.should_relay() {
+            return None;
+        }
+        self.cache.insert(header.src, header.seq, now);
+        pdu.header.ttl = TTL::new(u8::from(header.ttl) - 1);
+        Some(RelayPDU {
+            pdu,
+            iv_index,
+            net_key_index,
+        })
+    }
+}
+/// Schedules the retransmissions of a relayed Network PDU according to `RelayRetransmit`.
+#[derive(Copy, Clone, Debug)]
+pub struct RetransmitSchedule<T: TimestampTrait> {
+    interval: Duration,
+    left: u8,
+    next: T,
+}
+impl<T: TimestampTrait> RetransmitSchedule<T> {
+    /// Starts the schedule for a PDU first relayed at `now`.
+    pub fn new(retransmit: RelayRetransmit, now: T) -> Self {
+        let interval = Duration::from_millis(u64::from(retransmit.0.steps.to_milliseconds(10)));
+        Self {
+            interval,
+            left: u8::from(retransmit.0.count),
+            next: now + interval,
+        }
+    }
+    /// Retransmissions still to be sent.
+    pub fn left(&self) -> u8 {
+        self.left
+    }
+    /// When the next retransmission is due. `None` if they've all been sent.
+    pub fn next(&self) -> Option<T> {
+        if self.left == 0 {
+            None
+        } else {
+            Some(self.next)
+        }
+    }
+    /// Returns `true` if a retransmission is due at `now` (and schedules the next one).
+    pub fn poll(&mut self, now: T) -> bool {
+        if self.left == 0 || !now.reached(self.next) {
+            return false;
+        }
+        self.left -= 1;
+        self.next = self.next + self.interval;
+        true
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::U24;
+
+    #[test]
+    fn test_network_cache_relays_once() {
+        let mut cache = NetworkCache::new();
+        let src = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let seq = SequenceNumber(U24::new(0x10));
+        let now = Instant::now();
+        assert!(cache.observe(src, seq, now));
+        assert!(!cache.observe(src, seq, now + Duration::from_secs(1)));
+        // Different seq (or src) is a different PDU.
+        assert!(cache.observe(src, SequenceNumber(U24::new(0x11)), now));
+        assert_eq!(cache.len(), 2);
+
+        cache.evict_expired(now + NetworkCache::DEFAULT_LIFETIME);
+        assert!(cache.is_empty());
+        assert!(cache.observe(src, seq, now + NetworkCache::DEFAULT_LIFETIME));
+    }
+    #[test]
+    fn test_relay_ignores_echo() {
+        use crate::address::Address;
+        use crate::lower::{self, UnsegmentedAccessPDU};
+        use crate::mesh::{KeyIndex, CTL, IVI, NID};
+        let src = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let seq = SequenceNumber(U24::new(0x10));
+        let received = |ttl: u8| RelayPDU {
+            pdu: net::PDU {
+                header: net::Header {
+                    ivi: IVI(false),
+                    nid: NID::new(0x10),
+                    ctl: CTL(false),
+                    ttl: TTL::new(ttl),
+                    seq,
+                    src,
+                    dst: Address::Unicast(
+                        UnicastAddress::new(0x0009).expect("valid unicast address"),
+                    ),
+                },
+                payload: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0_u8; 8])),
+            },
+            iv_index: IVIndex(0),
+            net_key_index: NetKeyIndex(KeyIndex::new(0)),
+        };
+        let mut relay = Relay::new();
+        let now = Instant::now();
+        assert!(relay.observe(src, seq, now));
+        let relayed = relay.relay(received(5), now).expect("pdu relayed");
+        assert_eq!(relayed.pdu.header.ttl, TTL::new(4));
+        assert!(relay.cache().contains(src, seq));
+        // Our own relayed transmission heard back from a neighbour.
+        let echo = relayed.pdu.header;
+        assert!(!relay.observe(echo.src, echo.seq, now + Duration::from_millis(50)));
+        assert_eq!(relay.cache().len(), 1);
+        // Relaying records the PDU even if it wasn't observed first.
+        let mut relay = Relay::new();
+        assert!(relay.relay(received(5), now).is_some());
+        assert!(!relay.observe(src, seq, now + Duration::from_millis(50)));
+        // TTL 1 PDUs aren't relayed.
+        assert!(Relay::new().relay(received(1), now).is_none());
+    }
+    #[test]
+    fn test_retransmit_schedule() {
+        use crate::mesh::{TransmitCount, TransmitInterval, TransmitSteps};
+        use crate::timestamp::Timestamp;
+        let retransmit = RelayRetransmit(TransmitInterval::new(
+            TransmitCount::new(2),
+            TransmitSteps::new(1),
+        ));
+        let start = Timestamp::from_micros(0);
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut schedule = RetransmitSchedule::new(retransmit, start);
+        assert_eq!(schedule.next(), Some(at(20)));
+        assert!(!schedule.poll(at(19)));
+        assert!(schedule.poll(at(20)));
+        assert!(!schedule.poll(at(21)));
+        assert!(schedule.poll(at(45)));
+        assert_eq!(schedule.left(), 0);
+        assert_eq!(schedule.next(), None);
+        assert!(!schedule.poll(at(100)));
+    }
+}
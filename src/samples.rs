@@ -33,7 +33,7 @@ fn message22() {
     let parts = AppNonceParts {
         aszmic: false,
         seq: SequenceNumber(U24::new(0x07080B)),
-        src: UnicastAddress::new(0x1234),
+        src: UnicastAddress::new(0x1234).expect("valid unicast address"),
         dst: Address::Virtual(dst),
         iv_index: IVIndex(0x12345677),
     };
@@ -1,5 +1,4 @@
 //! Transport Layer Segmenter.
-use crate::crypto::MIC;
 use crate::lower::{BlockAck, SegN, SegO, SegmentHeader, SegmentedAccessPDU, SeqAuth};
 
 use crate::crypto::materials::NetworkKeys;
@@ -8,8 +7,6 @@ use crate::mesh::{IVIndex, NetKeyIndex, SequenceNumber, CTL, NID};
 use crate::stack::NetworkHeader;
 use crate::{lower, net, upper};
 
-use core::cmp::min;
-
 pub struct UpperSegmenter<Storage: AsRef<[u8]>> {
     pub upper_pdu: upper::PDU<Storage>,
     pub seg_o: SegO,
@@ -77,58 +74,35 @@ impl<'a, Storage: AsRef<[u8]>> Iterator for SegmentIterator<'a, Storage> {
     type Item = lower::SegmentedPDU;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let seg_o = u8::from(self.segmenter.seg_o);
         // Skip acked segments.
-        while self.block_ack.get(self.seg_n) && self.seg_n < u8::from(self.segmenter.seg_o) {
+        while self.seg_n <= seg_o && self.block_ack.get(self.seg_n) {
             self.seg_n += 1;
         }
-        if self.seg_n > u8::from(self.segmenter.seg_o) {
-            None
-        } else {
-            let seg_n_out = SegN::new(self.seg_n);
-            let segment_data = self.segmenter.upper_pdu.seg_n_data(seg_n_out);
-            let header = self.segment_header();
-            match &self.segmenter.upper_pdu {
-                upper::PDU::Control(control) => {
-                    // ControlPDU
-                    let out = lower::SegmentedControlPDU::new(control.opcode, header, segment_data);
-                    self.seg_n += 1;
-                    Some(lower::SegmentedPDU::Control(out))
-                }
-                upper::PDU::Access(access) => {
-                    if segment_data.len() == SegmentedAccessPDU::max_seg_len() {
-                        let out = lower::SegmentedAccessPDU::new(
-                            access.aid(),
-                            access.mic().is_big().into(),
-                            self.segmenter.seq_auth.seq_zero(),
-                            self.segmenter.seg_o,
-                            seg_n_out,
-                            segment_data,
-                        );
-                        self.seg_n += 1;
-                        Some(lower::SegmentedPDU::Access(out))
-                    } else {
-                        let mic = access.mic();
-                        let seg_len = segment_data.len();
-                        let mut buf = [0_u8; SegmentedAccessPDU::max_seg_len() + MIC::big_size()];
-                        buf[..seg_len].copy_from_slice(segment_data);
-                        mic.be_pack_into(&mut buf[seg_len..seg_len + mic.byte_size()]);
-                        let out = lower::SegmentedAccessPDU::new(
-                            access.aid(),
-                            mic.is_big().into(),
-                            self.segmenter.seq_auth.first_seq.into(),
-                            self.segmenter.seg_o,
-                            seg_n_out,
-                            &buf[..min(
-                                seg_len + mic.byte_size(),
-                                SegmentedAccessPDU::max_seg_len(),
-                            )],
-                        );
-                        self.seg_n += 1;
-                        Some(lower::SegmentedPDU::Access(out))
-                    }
-                }
-            }
+        if self.seg_n > seg_o {
+            return None;
         }
+        let seg_n_out = SegN::new(self.seg_n);
+        let mut buf = [0_u8; SegmentedAccessPDU::max_seg_len()];
+        let seg_len = self.segmenter.upper_pdu.seg_n_data(seg_n_out, &mut buf[..]);
+        let segment_data = &buf[..seg_len];
+        let header = self.segment_header();
+        self.seg_n += 1;
+        Some(match &self.segmenter.upper_pdu {
+            upper::PDU::Control(control) => lower::SegmentedPDU::Control(
+                lower::SegmentedControlPDU::new(control.opcode, header, segment_data),
+            ),
+            upper::PDU::Access(access) => {
+                lower::SegmentedPDU::Access(lower::SegmentedAccessPDU::new(
+                    access.aid(),
+                    access.mic().is_big().into(),
+                    self.segmenter.seq_auth.seq_zero(),
+                    self.segmenter.seg_o,
+                    seg_n_out,
+                    segment_data,
+                ))
+            }
+        })
     }
 }
 
@@ -13,6 +13,36 @@ pub enum BearerError {
     Other(Box<dyn btle::error::Error + Send + 'static>),
 }
 
+/// AD Type of PB-ADV PDUs.
+pub const PB_ADV_AD_TYPE: u8 = 0x29;
+/// AD Type of Mesh Network PDUs.
+pub const MESH_PDU_AD_TYPE: u8 = 0x2A;
+/// AD Type of Mesh Beacons.
+pub const MESH_BEACON_AD_TYPE: u8 = 0x2B;
+/// Max AD structure data length in a legacy advertisement (31 bytes of advertising data minus
+/// the length and AD type octets). Bigger PDUs need extended advertising or GATT.
+pub const LEGACY_AD_DATA_MAX_LEN: usize = 29;
+
+/// Returns if `pdu` fits in the mesh message AD structure of a legacy advertisement.
+pub fn fits_legacy_adv(pdu: &[u8]) -> bool {
+    pdu.len() <= LEGACY_AD_DATA_MAX_LEN
+}
+/// Packs an Encrypted Network PDU into a legacy advertisement. Returns `PackError::BadLength` if
+/// the PDU doesn't fit (see [`fits_legacy_adv`]).
+pub fn network_pdu_advertisement<B: AsRef<[u8]>>(
+    pdu: &net::EncryptedPDU<B>,
+) -> Result<RawAdvertisement, PackError> {
+    if !fits_legacy_adv(pdu.data()) {
+        return Err(PackError::BadLength {
+            expected: LEGACY_AD_DATA_MAX_LEN,
+            got: pdu.len(),
+        });
+    }
+    let mut out = RawAdvertisement::new();
+    out.insert(pdu)?;
+    Ok(out)
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct IncomingEncryptedNetworkPDU {
     pub encrypted_pdu: net::EncryptedPDU<net::StaticEncryptedPDUBuf>,
@@ -69,15 +99,17 @@ pub enum OutgoingMessage {
     PBAdv(pb_adv::PDU<PBAdvBuf>),
 }
 impl OutgoingMessage {
+    /// Packs the message into a legacy advertisement. Returns `PackError::BadLength` if a Network
+    /// PDU doesn't fit (see [`fits_legacy_adv`]).
     pub fn to_raw_advertisement(
         &self,
     ) -> Result<(RawAdvertisement, TransmitInstructions), PackError> {
         let mut out = RawAdvertisement::new();
         Ok(match self {
-            OutgoingMessage::Network(n) => {
-                out.insert(&n.pdu)?;
-                (out, n.transmit_parameters.into())
-            }
+            OutgoingMessage::Network(n) => (
+                network_pdu_advertisement(&n.pdu)?,
+                n.transmit_parameters.into(),
+            ),
             OutgoingMessage::Beacon(b) => {
                 //TODO: TransmitInstructions
                 out.insert(b)?;
@@ -141,6 +173,30 @@ impl IncomingMessage {
             None
         }
     }
+    /// Decodes one AD structure (`ad_type` and the data following it). Returns `None` if it isn't
+    /// a mesh AD type or the data is malformed.
+    pub fn from_ad_structure(
+        ad_type: u8,
+        data: &[u8],
+        rssi: Option<RSSI>,
+    ) -> Option<IncomingMessage> {
+        match ad_type {
+            MESH_PDU_AD_TYPE => Some(IncomingMessage::Network(IncomingEncryptedNetworkPDU {
+                encrypted_pdu: net::EncryptedPDU::new(data)?.to_owned(),
+                rssi,
+                dont_relay: false,
+            })),
+            MESH_BEACON_AD_TYPE => Some(IncomingMessage::Beacon(IncomingBeacon {
+                beacon: beacon::BeaconPDU::unpack_from(data).ok()?,
+                rssi,
+            })),
+            PB_ADV_AD_TYPE => Some(IncomingMessage::PBAdv(pb_adv::IncomingPDU {
+                pdu: pb_adv::PDU::unpack_from(data).ok()?,
+                rssi,
+            })),
+            _ => None,
+        }
+    }
     pub fn network_pdu(&self) -> Option<IncomingEncryptedNetworkPDU> {
         match self {
             IncomingMessage::Network(n) => Some(*n),
@@ -178,6 +234,26 @@ impl From<IncomingMessage> for Message {
         Message::Incoming(m)
     }
 }
+/// Walks every AD structure in the advertising data `adv_data` (ex: a scan response or an
+/// extended advertisement carrying more than one AD structure) and yields each mesh message in
+/// it. Non-mesh and malformed AD structures are skipped. Stops at the first zero length (padding)
+/// or truncated AD structure.
+pub fn iter_mesh_pdus(
+    adv_data: &[u8],
+    rssi: Option<RSSI>,
+) -> impl Iterator<Item = IncomingMessage> + '_ {
+    let mut rest = adv_data;
+    core::iter::from_fn(move || {
+        let len = usize::from(*rest.first()?);
+        if len == 0 || rest.len() < 1 + len {
+            return None;
+        }
+        let (ad_struct, next) = rest[1..].split_at(len);
+        rest = next;
+        Some((ad_struct[0], &ad_struct[1..]))
+    })
+    .filter_map(move |(ad_type, data)| IncomingMessage::from_ad_structure(ad_type, data, rssi))
+}
 /*
 pub fn single_shot_advertisement<A: btle::hci::adapter::Adapter, B: AsRef<[u8]>>(
     le: &mut btle::hci::adapters::le::LEAdapter<A>,
@@ -194,11 +270,13 @@ mod tests {
     use crate::stack::bearer::IncomingMessage;
     use crate::stack::bearer::IncomingMessage::Beacon;
     use crate::uuid::UUID;
+    use alloc::vec::Vec;
     use btle::le::advertisement::RawAdvertisement;
     use btle::le::report::AddressType::RandomDevice;
     use btle::le::report::EventType::AdvNonconnInd;
     use btle::le::report::ReportInfo;
     use btle::{BTAddress, RSSI};
+    use core::convert::TryFrom;
 
     #[test]
     pub fn test_beacon() {
@@ -224,4 +302,85 @@ mod tests {
             },)
         );
     }
+    #[test]
+    fn test_fits_legacy_adv() {
+        use crate::stack::bearer::{fits_legacy_adv, LEGACY_AD_DATA_MAX_LEN};
+        let buf = [0_u8; LEGACY_AD_DATA_MAX_LEN + 1];
+        assert!(fits_legacy_adv(&buf[..28]));
+        assert!(fits_legacy_adv(&buf[..29]));
+        assert!(!fits_legacy_adv(&buf[..30]));
+    }
+    #[test]
+    fn test_oversized_network_pdu_advertisement() {
+        use crate::net;
+        use crate::stack::bearer::{network_pdu_advertisement, LEGACY_AD_DATA_MAX_LEN};
+        use btle::PackError;
+        let buf = [0_u8; LEGACY_AD_DATA_MAX_LEN + 1];
+        assert!(network_pdu_advertisement(&net::EncryptedPDU::new_unchecked(&buf[..29])).is_ok());
+        match network_pdu_advertisement(&net::EncryptedPDU::new_unchecked(&buf[..30])) {
+            Err(PackError::BadLength { expected, got }) => {
+                assert_eq!(expected, LEGACY_AD_DATA_MAX_LEN);
+                assert_eq!(got, 30);
+            }
+            _ => panic!("a 30 byte Network PDU doesn't fit in a legacy advertisement"),
+        }
+    }
+    #[test]
+    fn test_iter_mesh_pdus() {
+        use crate::address::{Address, UnicastAddress};
+        use crate::crypto::materials::NetworkKeys;
+        use crate::lower::{self, UnsegmentedAccessPDU};
+        use crate::mesh::{IVIndex, SequenceNumber, CTL, TTL, U24};
+        use crate::net;
+        use crate::stack::bearer::{iter_mesh_pdus, MESH_BEACON_AD_TYPE, MESH_PDU_AD_TYPE};
+        use crate::stack::test_helpers;
+
+        let keys = NetworkKeys::from(&test_helpers::net_key());
+        let iv_index = IVIndex(0);
+        let encrypted = net::PDU {
+            header: net::Header {
+                ivi: iv_index.ivi(),
+                nid: keys.nid(),
+                ctl: CTL(false),
+                ttl: TTL::new(3),
+                seq: SequenceNumber(U24::new(1)),
+                src: UnicastAddress::from_mask_u16(0x0005),
+                dst: Address::Unicast(UnicastAddress::from_mask_u16(0x0001)),
+            },
+            payload: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0xAA; 6])),
+        }
+        .encrypt(&keys, iv_index)
+        .expect("pdu encrypts");
+        let mut adv_data = Vec::new();
+        // Flags AD structure (not mesh).
+        adv_data.extend_from_slice(&[0x02, 0x01, 0x06]);
+        adv_data.push(u8::try_from(encrypted.len() + 1).expect("fits in an AD structure"));
+        adv_data.push(MESH_PDU_AD_TYPE);
+        adv_data.extend_from_slice(encrypted.data());
+        adv_data.extend_from_slice(&[20, MESH_BEACON_AD_TYPE, 0x00]);
+        adv_data.extend_from_slice(&[0xDD; 16]);
+        adv_data.extend_from_slice(&[0x00, 0x20]);
+        // Zero padding at the end.
+        adv_data.extend_from_slice(&[0x00, 0x00]);
+        let rssi = Some(RSSI::new(-42));
+        let messages: Vec<IncomingMessage> = iter_mesh_pdus(&adv_data, rssi).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].network_pdu().map(|pdu| pdu.encrypted_pdu),
+            Some(encrypted)
+        );
+        assert_eq!(
+            messages[1],
+            Beacon(IncomingBeacon {
+                beacon: Unprovisioned(UnprovisionedDeviceBeacon {
+                    uuid: UUID([0xDD; 16]),
+                    oob_information: OOBInformation(0x0020),
+                    uri_hash: None,
+                }),
+                rssi,
+            })
+        );
+        // A truncated AD structure ends the walk.
+        assert_eq!(iter_mesh_pdus(&adv_data[..5], rssi).count(), 0);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::stack::bearer::{IncomingMessage, OutgoingMessage, TransmitInstructions};
+use btle::error::IOError;
 use btle::hci::adapter;
 use btle::hci::adapters::buffer::HCIEventBuffer;
 use btle::hci::adapters::le::LEAdapter;
@@ -212,10 +213,15 @@ impl<A: btle::hci::adapter::Adapter> BufferedHCIAdvertiser<A> {
             }
         }
     }
+    /// Returns an `IOError` if `msg` doesn't fit in a legacy advertisement (see
+    /// [`fits_legacy_adv`](crate::stack::bearer::fits_legacy_adv)). `run_loop_send_error` passes
+    /// it on to the incoming channel and keeps going.
     async fn send(&mut self, msg: OutgoingMessage) -> Result<(), adapter::Error> {
+        // The stack already size checks Network PDUs when it encrypts them
+        // (`SendError::PayloadTooLarge`).
         let (advertisement, interval) = msg
             .to_raw_advertisement()
-            .expect("no packing errors should happen TODO: verify");
+            .map_err(|_| adapter::Error::IOError(IOError::Other))?;
         self.advertise(advertisement, interval).await
     }
     /// Same as `HCIBearer` advertise but also listens for packets while waiting
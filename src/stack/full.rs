@@ -2,22 +2,35 @@
 //! care of all the stack layer between them.
 //use crate::interface::{InputInterfaces, InterfaceSink, OutputInterfaces};
 
+use crate::access::ModelIdentifier;
+use crate::foundation::publication::ModelPublishInfo;
+use crate::mesh::ElementIndex;
 use crate::replay;
 use crate::stack::{incoming, outgoing, RecvError, SendError, StackInternals};
 
 use crate::asyncs::sync::{mpsc, Mutex, RwLock};
 use crate::stack::bearer::{IncomingEncryptedNetworkPDU, OutgoingMessage};
 use crate::stack::incoming::Incoming;
+use crate::stack::messages::IncomingMessage;
 use crate::stack::outgoing::Outgoing;
+use crate::stack::publication::PublishScheduler;
+use crate::stack::segments::SegmentationConfig;
+use crate::stack::stats::{StackStats, StatsCounters};
+use crate::upper::AppPayload;
+use alloc::boxed::Box;
 use alloc::sync::Arc;
 use core::ops::{Deref, DerefMut};
+use driver_async::time::Instant;
 pub struct FullStack {
     pub replay_cache: Arc<Mutex<replay::Cache>>,
+    pub stats: Arc<StatsCounters>,
     pub internals: Arc<RwLock<StackInternals>>,
     pub outgoing_bearer: mpsc::Receiver<OutgoingMessage>,
     pub incoming_bearer: mpsc::Sender<IncomingEncryptedNetworkPDU>,
     pub incoming: incoming::Incoming,
     pub outgoing: outgoing::Outgoing,
+    pub publications: Mutex<PublishScheduler>,
+    access_loopback: Mutex<mpsc::Sender<IncomingMessage<Box<[u8]>>>>,
     _priv: (),
 }
 pub enum FullStackError {
@@ -43,6 +56,13 @@ impl FullStack {
         let (tx_ack, rx_ack) = mpsc::channel(channel_size);
         let internals = Arc::new(RwLock::new(internals));
         let replay_cache = Arc::new(Mutex::new(replay_cache));
+        let stats = Arc::new(StatsCounters::new());
+        let outgoing = Outgoing::new(
+            internals.clone(),
+            rx_ack,
+            tx_bearer.clone(),
+            SegmentationConfig::default(),
+        );
 
         // Encrypted Incoming Network PDU Handler.
 
@@ -53,15 +73,21 @@ impl FullStack {
             incoming: Incoming::new(
                 internals.clone(),
                 replay_cache.clone(),
+                stats.clone(),
                 rx_incoming_encrypted_net,
+                tx_bearer,
                 tx_outgoing_transport,
                 tx_ack,
-                tx_access,
+                outgoing.ack_waiters.clone(),
+                tx_access.clone(),
                 tx_control,
                 channel_size,
             ),
             replay_cache,
-            outgoing: Outgoing::new(internals, rx_ack, tx_bearer),
+            stats,
+            outgoing,
+            publications: Mutex::new(PublishScheduler::new()),
+            access_loopback: Mutex::new(tx_access),
             _priv: (),
         }
     }
@@ -74,6 +100,56 @@ impl FullStack {
             .await
             .map_err(|_| RecvError::ChannelClosed)
     }
+    /// Applies the publication from a Config Model Publication Set to `model` on
+    /// `element_index`. Stores it in the `DeviceState` and restarts its publish timer.
+    pub async fn set_publication(
+        &self,
+        element_index: ElementIndex,
+        model: ModelIdentifier,
+        publication: ModelPublishInfo,
+        now: Instant,
+    ) {
+        let mut internals = self.internals.write().await;
+        let models = internals.device_state_mut().models_mut();
+        models.set_publication(element_index, model, publication);
+        self.publications.lock().await.set_publication(
+            element_index,
+            model,
+            models.publication(element_index, model),
+            now,
+        );
+    }
+    /// Sends the model publications due at `now` (see `PublishScheduler::poll`). Returns the
+    /// number of messages published.
+    pub async fn poll_publications(
+        &self,
+        now: Instant,
+        status: impl FnMut(ElementIndex, ModelIdentifier) -> Option<AppPayload<Box<[u8]>>>,
+    ) -> Result<usize, SendError> {
+        let messages = {
+            let internals = self.internals.read().await;
+            self.publications
+                .lock()
+                .await
+                .poll(internals.device_state().models(), now, status)
+        };
+        let published = messages.len();
+        let mut loopback = self.access_loopback.lock().await;
+        for msg in messages {
+            self.outgoing.send_access(msg, &mut loopback).await?;
+        }
+        Ok(published)
+    }
+    /// Snapshot of the incoming drop counters (TTL, replay, network cache and decryption).
+    pub fn stats(&self) -> StackStats {
+        self.stats.snapshot()
+    }
+    /// Turns relaying on or off (see `StackInternals::set_relay_enabled`). Returns `false` if
+    /// the node doesn't support the Relay feature.
+    pub async fn set_relay_enabled(&self, enabled: bool) -> bool {
+        self.internals_with_mut(|internals| internals.set_relay_enabled(enabled))
+            .await
+    }
     pub async fn internals_with<R>(&self, func: impl FnOnce(&StackInternals) -> R) -> R {
         func(self.internals.read().await.deref())
     }
@@ -81,3 +157,186 @@ impl FullStack {
         func(self.internals.write().await.deref_mut())
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{Address, GroupAddress, UnicastAddress};
+    use crate::asyncs::time;
+    use crate::foundation::publication::{
+        ModelPublishInfo, PublishPeriod, PublishRetransmit, StepResolution, Steps,
+    };
+    use crate::lower::{self, UnsegmentedAccessPDU};
+    use crate::mesh::{
+        AppKeyIndex, KeyIndex, ModelID, NetKeyIndex, SequenceNumber, TransmitCount,
+        TransmitInterval, TransmitSteps, CTL, TTL, U24,
+    };
+    use crate::models::generics::onoff;
+    use crate::net;
+    use crate::stack::{messages, test_helpers};
+    use core::time::Duration;
+
+    #[tokio::test]
+    async fn test_publication_sent_at_period() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let app_key_index = AppKeyIndex(KeyIndex::new(0));
+        let primary = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let device_state =
+            test_helpers::device_state_with_app_key(primary, net_key_index, app_key_index);
+        let mut stack = FullStack::new(StackInternals::new(device_state), replay::Cache::new(), 8);
+        let group = Address::Group(GroupAddress::new(0xC001).expect("valid group address"));
+        let publication = ModelPublishInfo {
+            address: group,
+            app_key_index,
+            credential_flag: false,
+            ttl: Some(TTL::new(5)),
+            period: PublishPeriod::new(StepResolution::Milliseconds100, Steps::new(5)),
+            retransmit: PublishRetransmit(TransmitInterval::new(
+                TransmitCount::new(0),
+                TransmitSteps::new(0),
+            )),
+        };
+        let onoff_server = ModelIdentifier::new_sig(ModelID(0x1000));
+        let now = Instant::now();
+        stack
+            .set_publication(ElementIndex(0), onoff_server, publication, now)
+            .await;
+        assert_eq!(
+            stack
+                .internals_with(|internals| internals
+                    .device_state()
+                    .models()
+                    .publication(ElementIndex(0), onoff_server)
+                    .copied())
+                .await,
+            Some(publication)
+        );
+        let status = |element_index, model| {
+            assert_eq!((element_index, model), (ElementIndex(0), onoff_server));
+            messages::OutgoingMessage::pack_payload(&onoff::Status {
+                present_on_off: true,
+                target: None,
+            })
+            .ok()
+        };
+        assert_eq!(
+            stack
+                .poll_publications(now + Duration::from_millis(400), status)
+                .await,
+            Ok(0)
+        );
+        assert_eq!(
+            stack
+                .poll_publications(now + Duration::from_millis(500), status)
+                .await,
+            Ok(1)
+        );
+        let outgoing = match stack.outgoing_bearer.recv().await {
+            Some(OutgoingMessage::Network(outgoing)) => outgoing,
+            _ => panic!("expected a network pdu"),
+        };
+        let (_, _, pdu) = stack
+            .internals_with(|internals| internals.decrypt_network_pdu(outgoing.pdu.as_ref()))
+            .await
+            .expect("publication decrypts");
+        assert_eq!(pdu.header.src, primary);
+        assert_eq!(pdu.header.dst, group);
+        assert_eq!(pdu.header.ttl, TTL::new(5));
+        match pdu.payload {
+            lower::PDU::UnsegmentedAccess(access) => assert!(access.aid().is_some()),
+            _ => panic!("expected an unsegmented access pdu"),
+        }
+        // The next publication is a full period later.
+        assert_eq!(
+            stack.publications.lock().await.next_publish(),
+            Some(now + Duration::from_millis(1000))
+        );
+    }
+    #[tokio::test]
+    async fn test_relay_toggled_at_runtime() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let device_state = test_helpers::device_state(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            net_key_index,
+        );
+        let mut stack = FullStack::new(StackInternals::new(device_state), replay::Cache::new(), 8);
+        // PDU between two other nodes that would be relayed.
+        let relayable = |internals: &StackInternals, seq: u32| {
+            let iv_index = internals.device_state().iv_index();
+            let pdu = net::PDU {
+                header: net::Header {
+                    ivi: iv_index.ivi(),
+                    nid: internals
+                        .net_keys()
+                        .get_keys(net_key_index)
+                        .expect("net key inserted above")
+                        .tx_key()
+                        .network_keys()
+                        .nid(),
+                    ctl: CTL(false),
+                    ttl: TTL::new(5),
+                    seq: SequenceNumber(U24::new(seq)),
+                    src: UnicastAddress::new(0x0005).expect("valid unicast address"),
+                    dst: Address::Unicast(
+                        UnicastAddress::new(0x0009).expect("valid unicast address"),
+                    ),
+                },
+                payload: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0_u8; 8])),
+            };
+            IncomingEncryptedNetworkPDU {
+                encrypted_pdu: internals
+                    .encrypt_network_pdu(pdu, net_key_index, iv_index)
+                    .expect("pdu encrypts"),
+                rssi: None,
+                dont_relay: false,
+            }
+        };
+        let pdus = stack
+            .internals_with(|internals| {
+                [
+                    relayable(internals, 0x20),
+                    relayable(internals, 0x21),
+                    relayable(internals, 0x22),
+                ]
+            })
+            .await;
+        let relayed_seq = |outgoing: Option<OutgoingMessage>, internals: &StackInternals| {
+            let outgoing = match outgoing {
+                Some(OutgoingMessage::Network(outgoing)) => outgoing,
+                _ => panic!("expected a network pdu"),
+            };
+            let (_, _, pdu) = internals
+                .decrypt_network_pdu(outgoing.pdu.as_ref())
+                .expect("relayed pdu decrypts");
+            assert_eq!(pdu.header.ttl, TTL::new(4));
+            pdu.header.seq
+        };
+
+        assert!(stack.set_relay_enabled(true).await);
+        stack.feed_network_pdu(pdus[0]).await.ok().expect("pdu fed");
+        let outgoing = stack.outgoing_bearer.recv().await;
+        assert_eq!(
+            stack
+                .internals_with(|internals| relayed_seq(outgoing, internals))
+                .await,
+            SequenceNumber(U24::new(0x20))
+        );
+
+        assert!(stack.set_relay_enabled(false).await);
+        stack.feed_network_pdu(pdus[1]).await.ok().expect("pdu fed");
+        assert!(
+            time::timeout(Duration::from_millis(100), stack.outgoing_bearer.recv())
+                .await
+                .is_err()
+        );
+
+        assert!(stack.set_relay_enabled(true).await);
+        stack.feed_network_pdu(pdus[2]).await.ok().expect("pdu fed");
+        let outgoing = stack.outgoing_bearer.recv().await;
+        assert_eq!(
+            stack
+                .internals_with(|internals| relayed_seq(outgoing, internals))
+                .await,
+            SequenceNumber(U24::new(0x22))
+        );
+    }
+}
@@ -1,60 +1,91 @@
 //! Incoming PDU message handler.
+use crate::address::{Address, FixedGroup};
 use crate::asyncs::{
     sync::{mpsc, Mutex, RwLock},
     task,
 };
 use crate::control;
-use crate::relay::RelayPDU;
-use crate::stack::bearer::IncomingEncryptedNetworkPDU;
+use crate::device_state::ConfigStates;
+use crate::foundation::state::NetworkTransmit;
+use crate::mesh::TTL;
+use crate::relay::{Relay, RelayPDU};
+use crate::stack::bearer::{
+    IncomingEncryptedNetworkPDU, OutgoingEncryptedNetworkPDU, OutgoingMessage,
+};
 use crate::stack::messages::{
     EncryptedIncomingMessage, IncomingControlMessage, IncomingMessage, IncomingNetworkPDU,
-    OutgoingLowerTransportMessage,
+    IncomingTransportPDU, OutgoingLowerTransportMessage,
 };
+use crate::stack::outgoing::AckWaiters;
 use crate::stack::segments::SegmentEvent;
+use crate::stack::stats::StatsCounters;
 use crate::stack::{segments, RecvError, StackInternals};
 use crate::{lower, replay};
 use alloc::sync::Arc;
 use core::convert::TryFrom;
+use driver_async::time::{Instant, InstantTrait};
 
 /// Asynchronous incoming message handler stack. Input Encrypted Network PDUs and it Outputs Acks,
-/// Control and Encrypted Access PDUs. This will only mutate a `replay::Cache` state but it does
-/// not mutate `StackInternals`.
+/// Control and Encrypted Access PDUs. PDUs to relay are re-encrypted and sent to the outgoing
+/// bearer. This will only mutate a `replay::Cache` state but it does not mutate `StackInternals`.
 pub struct Incoming {
     net_handler: task::JoinHandle<Result<(), RecvError>>,
+    relay_handler: task::JoinHandle<Result<(), RecvError>>,
     encrypted_net_handler: task::JoinHandle<Result<(), RecvError>>,
     encrypted_access_handler: task::JoinHandle<Result<(), RecvError>>,
+    reassembled_handler: task::JoinHandle<Result<(), RecvError>>,
 }
 impl Incoming {
     pub fn new(
         internals: Arc<RwLock<StackInternals>>,
         replay_cache: Arc<Mutex<replay::Cache>>,
+        stats: Arc<StatsCounters>,
         incoming_net: mpsc::Receiver<IncomingEncryptedNetworkPDU>,
+        outgoing_network: mpsc::Sender<OutgoingMessage>,
         outgoing_transport: mpsc::Sender<OutgoingLowerTransportMessage>,
         tx_ack: mpsc::Sender<segments::IncomingPDU<control::Ack>>,
+        ack_waiters: Arc<AckWaiters>,
         tx_access: mpsc::Sender<IncomingMessage<Box<[u8]>>>,
         tx_control: mpsc::Sender<IncomingControlMessage>,
         channel_size: usize,
     ) -> Self {
         let (tx_incoming_net, rx_incoming_net) = mpsc::channel(channel_size);
         let (tx_encrypted_access, rx_encrypted_access) = mpsc::channel(channel_size);
-        let reassembler = Arc::new(Mutex::new(segments::Reassembler::new(outgoing_transport)));
+        let (tx_reassembled, rx_reassembled) = mpsc::channel(channel_size);
+        let (tx_relay, rx_relay) = mpsc::channel(channel_size);
+        let reassembler = Arc::new(Mutex::new(segments::Reassembler::new(
+            outgoing_transport,
+            tx_reassembled,
+        )));
         Self {
+            reassembled_handler: task::spawn(Self::handle_reassembled_loop(
+                rx_reassembled,
+                tx_encrypted_access.clone(),
+            )),
             encrypted_net_handler: task::spawn(Self::handle_encrypted_net_pdu_loop(
                 internals.clone(),
                 replay_cache,
-                None,
+                stats.clone(),
+                Some(tx_relay),
                 incoming_net,
                 tx_incoming_net,
             )),
+            relay_handler: task::spawn(Self::handle_relay_loop(
+                internals.clone(),
+                rx_relay,
+                outgoing_network,
+            )),
             net_handler: task::spawn(Self::handle_net_loop(
                 reassembler,
                 tx_ack,
+                ack_waiters,
                 tx_control,
                 tx_encrypted_access,
                 rx_incoming_net,
             )),
             encrypted_access_handler: task::spawn(Self::handle_encrypted_access_loop(
                 internals,
+                stats,
                 rx_encrypted_access,
                 tx_access,
             )),
@@ -62,6 +93,7 @@ impl Incoming {
     }
     async fn handle_encrypted_access_loop(
         internals: Arc<RwLock<StackInternals>>,
+        stats: Arc<StatsCounters>,
         mut incoming_encrypted_access: mpsc::Receiver<EncryptedIncomingMessage<Box<[u8]>>>,
         outgoing_encrypted_access: mpsc::Sender<IncomingMessage<Box<[u8]>>>,
     ) -> Result<(), RecvError> {
@@ -70,11 +102,29 @@ impl Incoming {
                 .recv()
                 .await
                 .ok_or(RecvError::ChannelClosed)?;
-            if let Ok(decrypted) = internals.read().await.app_decrypt(next) {
-                outgoing_encrypted_access
+            match internals.read().await.app_decrypt(next) {
+                Ok(decrypted) => outgoing_encrypted_access
                     .send(decrypted)
                     .await
                     .ok()
+                    .ok_or(RecvError::ChannelClosed)?,
+                Err(_) => stats.record_decrypt_failure(),
+            }
+        }
+    }
+    /// Passes reassembled access messages on to be decrypted, the same as unsegmented ones.
+    async fn handle_reassembled_loop(
+        mut reassembled: mpsc::Receiver<IncomingTransportPDU<Box<[u8]>>>,
+        mut tx_access: mpsc::Sender<EncryptedIncomingMessage<Box<[u8]>>>,
+    ) -> Result<(), RecvError> {
+        loop {
+            let next = reassembled.recv().await.ok_or(RecvError::ChannelClosed)?;
+            // Segmented control messages aren't handled yet.
+            if let Some(encrypted) = next.into_encrypted_access() {
+                tx_access
+                    .send(encrypted)
+                    .await
+                    .ok()
                     .ok_or(RecvError::ChannelClosed)?;
             }
         }
@@ -82,6 +132,7 @@ impl Incoming {
     async fn handle_net_loop(
         reassembler: Arc<Mutex<segments::Reassembler>>,
         mut tx_ack: mpsc::Sender<segments::IncomingPDU<control::Ack>>,
+        ack_waiters: Arc<AckWaiters>,
         mut tx_control: mpsc::Sender<IncomingControlMessage>,
         mut tx_access: mpsc::Sender<EncryptedIncomingMessage<Box<[u8]>>>,
         mut incoming: mpsc::Receiver<IncomingNetworkPDU>,
@@ -90,6 +141,7 @@ impl Incoming {
             if let Err(RecvError::ChannelClosed) = Self::handle_net(
                 &reassembler,
                 &mut tx_ack,
+                &ack_waiters,
                 &mut tx_control,
                 &mut tx_access,
                 incoming.recv().await.ok_or(RecvError::ChannelClosed)?,
@@ -103,6 +155,7 @@ impl Incoming {
     async fn handle_net(
         reassembler: &Mutex<segments::Reassembler>,
         tx_ack: &mut mpsc::Sender<segments::IncomingPDU<control::Ack>>,
+        ack_waiters: &AckWaiters,
         tx_control: &mut mpsc::Sender<IncomingControlMessage>,
         tx_access: &mut mpsc::Sender<EncryptedIncomingMessage<Box<[u8]>>>,
         incoming: IncomingNetworkPDU,
@@ -122,12 +175,18 @@ impl Incoming {
                     Some(())
                 }
                 SegmentEvent::IncomingAck(ack) => {
-                    tx_ack
-                        .send(ack)
-                        .await
-                        .ok()
-                        .ok_or(RecvError::ChannelClosed)?;
-                    Some(())
+                    if ack_waiters.is_waiting() {
+                        // A transfer is reading acks so wait for room instead of losing its ack.
+                        tx_ack.send(ack).await.ok()
+                    } else {
+                        // Late acks (for transfers that already completed) would otherwise fill
+                        // the channel and block this loop so drop the ack if nobody is reading
+                        // them.
+                        match tx_ack.try_send(ack) {
+                            Err(mpsc::error::TrySendError::Closed(_)) => None,
+                            _ => Some(()),
+                        }
+                    }
                 }
             }
             .ok_or(RecvError::ChannelClosed)?;
@@ -170,18 +229,74 @@ impl Incoming {
             _ => Err(RecvError::MalformedNetworkPDU),
         }
     }
+    /// Returns if a PDU sent to the `fixed_group` address should be delivered to this node.
+    /// All-nodes is always delivered while the others depend on the matching feature being enabled.
+    /// PDUs not delivered may still be relayed.
+    pub fn delivers_fixed_group(fixed_group: FixedGroup, config_states: &ConfigStates) -> bool {
+        config_states.accepts_fixed_group(fixed_group)
+    }
+    /// Returns if a PDU with `ttl` should be relayed. PDUs that would have been relayed if not
+    /// for their TTL (less than 2) are counted as `ttl_drops` in `stats`.
+    pub fn relay_check(
+        dont_relay: bool,
+        ttl: TTL,
+        config_states: &ConfigStates,
+        stats: &StatsCounters,
+    ) -> bool {
+        if dont_relay || !config_states.relay_state.is_enabled() {
+            return false;
+        }
+        if !ttl.should_relay() {
+            stats.record_ttl_drop();
+            return false;
+        }
+        true
+    }
+    /// Encrypts the PDUs to relay (see `Relay::relay`) again and sends them to the bearer with the
+    /// Relay Retransmit count and interval.
+    async fn handle_relay_loop(
+        internals: Arc<RwLock<StackInternals>>,
+        mut incoming: mpsc::Receiver<RelayPDU>,
+        mut outgoing: mpsc::Sender<OutgoingMessage>,
+    ) -> Result<(), RecvError> {
+        loop {
+            let next = incoming.recv().await.ok_or(RecvError::ChannelClosed)?;
+            let relayed = {
+                let internals = internals.read().await;
+                let retransmit = internals.device_state().config_states().relay_retransmit;
+                internals
+                    .encrypt_network_pdu(next.pdu, next.net_key_index, next.iv_index)
+                    .map(|pdu| OutgoingEncryptedNetworkPDU {
+                        transmit_parameters: NetworkTransmit(retransmit.0),
+                        pdu,
+                    })
+            };
+            // PDUs that can't be encrypted again (ex: the IV Index moved on) just aren't relayed.
+            if let Ok(relayed) = relayed {
+                outgoing
+                    .send(OutgoingMessage::Network(relayed))
+                    .await
+                    .ok()
+                    .ok_or(RecvError::ChannelClosed)?;
+            }
+        }
+    }
     pub async fn handle_encrypted_net_pdu_loop(
         internals: Arc<RwLock<StackInternals>>,
         replay_cache: Arc<Mutex<replay::Cache>>,
+        stats: Arc<StatsCounters>,
         mut outgoing_relay: Option<mpsc::Sender<RelayPDU>>,
         mut incoming: mpsc::Receiver<IncomingEncryptedNetworkPDU>,
         outgoing: mpsc::Sender<IncomingNetworkPDU>,
     ) -> Result<(), RecvError> {
+        let relay = Mutex::new(Relay::new());
         loop {
             let next = incoming.recv().await.ok_or(RecvError::ChannelClosed)?;
             match Self::handle_encrypted_net_pdu(
                 &internals,
                 &replay_cache,
+                &relay,
+                &stats,
                 outgoing_relay.as_mut(),
                 next,
             )
@@ -203,6 +318,8 @@ impl Incoming {
     pub async fn handle_encrypted_net_pdu(
         internals: &RwLock<StackInternals>,
         replay_cache: &Mutex<replay::Cache>,
+        relay: &Mutex<Relay>,
+        stats: &StatsCounters,
         outgoing_relay: Option<&mut mpsc::Sender<RelayPDU>>,
         incoming: IncomingEncryptedNetworkPDU,
     ) -> Result<IncomingNetworkPDU, RecvError> {
@@ -219,32 +336,57 @@ impl Incoming {
             );
             if is_old_seq {
                 // We've already seen this PDU
+                stats.record_replay_hit();
+                return Err(RecvError::OldSeq);
+            }
+            let now = Instant::now();
+            let mut relay_guard = relay.lock().await;
+            // Bearers retransmit PDUs so the same (src, seq) can arrive several times. The replay
+            // list can't always tell (ex: the IVI doesn't match its entry) so check the network
+            // cache too before relaying or delivering it.
+            if !relay_guard.observe(header.src, header.seq, now) {
+                stats.record_network_cache_hit();
                 return Err(RecvError::OldSeq);
             }
             // Seq isn't old but SeqZero might be. Even if SeqZero is old, we still relay it to other nodes.
-            if !incoming.dont_relay
-                && pdu.header().ttl.should_relay()
-                && internals
-                    .device_state
-                    .config_states()
-                    .relay_state
-                    .is_enabled()
-            {
-                if let Some(relay_tx) = outgoing_relay {
-                    relay_tx
-                        .send(RelayPDU {
-                            pdu,
-                            iv_index,
-                            net_key_index,
-                        })
-                        .await
-                        .map_err(|_| RecvError::ChannelClosed)?;
-                }
+            let relay_pdu = if Self::relay_check(
+                incoming.dont_relay,
+                header.ttl,
+                internals.device_state.config_states(),
+                stats,
+            ) {
+                relay_guard.relay(
+                    RelayPDU {
+                        pdu,
+                        iv_index,
+                        net_key_index,
+                    },
+                    now,
+                )
+            } else {
+                None
+            };
+            drop(relay_guard);
+            if let (Some(relay_pdu), Some(relay_tx)) = (relay_pdu, outgoing_relay) {
+                relay_tx
+                    .send(relay_pdu)
+                    .await
+                    .map_err(|_| RecvError::ChannelClosed)?;
             }
             if is_old_seq_zero {
                 // We've already handle this PDU
                 return Err(RecvError::OldSeqZero);
             }
+            if let Address::Group(group) = header.dst {
+                if let Some(fixed_group) = group.fixed_group() {
+                    if !Self::delivers_fixed_group(
+                        fixed_group,
+                        internals.device_state.config_states(),
+                    ) {
+                        return Err(RecvError::InvalidDestination);
+                    }
+                }
+            }
             Ok(IncomingNetworkPDU {
                 pdu,
                 net_key_index,
@@ -252,7 +394,358 @@ impl Incoming {
                 rssi: incoming.rssi,
             })
         } else {
+            stats.record_decrypt_failure();
             Err(RecvError::NoMatchingNetKey)
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::UnicastAddress;
+    use crate::asyncs::time;
+    use crate::crypto::aes::MicSize;
+    use crate::crypto::nonce::AppNonceParts;
+    use crate::foundation::state::{FriendState, GATTProxyState, RelayState};
+    use crate::lower::{BlockAck, SegO, SeqZero, UnsegmentedAccessPDU};
+    use crate::mesh::{
+        AppKeyIndex, IVIndex, KeyIndex, NetKeyIndex, SequenceNumber, CTL, IVI, NID, U24,
+    };
+    use crate::net;
+    use crate::stack::stats::StackStats;
+    use crate::stack::test_helpers;
+    use crate::upper::SecurityMaterials;
+    use core::time::Duration;
+
+    #[tokio::test]
+    async fn test_duplicate_pdu_delivered_once() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let internals = StackInternals::new(test_helpers::device_state(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            net_key_index,
+        ));
+        let src = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let iv_index = internals.device_state().iv_index();
+        let pdu = net::PDU {
+            header: net::Header {
+                ivi: iv_index.ivi(),
+                nid: internals
+                    .net_keys()
+                    .get_keys(net_key_index)
+                    .expect("net key inserted above")
+                    .tx_key()
+                    .network_keys()
+                    .nid(),
+                ctl: CTL(false),
+                ttl: TTL::new(5),
+                seq: SequenceNumber(U24::new(0x20)),
+                src,
+                dst: Address::Unicast(UnicastAddress::new(0x0001).expect("valid unicast address")),
+            },
+            payload: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0_u8; 8])),
+        };
+        let incoming = IncomingEncryptedNetworkPDU {
+            encrypted_pdu: internals
+                .encrypt_network_pdu(pdu, net_key_index, iv_index)
+                .expect("pdu encrypts"),
+            rssi: None,
+            dont_relay: false,
+        };
+        let internals = RwLock::new(internals);
+        let replay_cache = Mutex::new(replay::Cache::new());
+        // Entry from the other IVI, so the replay list alone can't catch the repeats.
+        replay_cache.lock().await.replay_net_check(
+            src,
+            SequenceNumber(U24::new(0x100)),
+            IVI(!bool::from(iv_index.ivi())),
+            None,
+        );
+        let relay = Mutex::new(Relay::new());
+        let stats = StatsCounters::new();
+        let mut delivered = 0;
+        for _ in 0..3 {
+            if Incoming::handle_encrypted_net_pdu(
+                &internals,
+                &replay_cache,
+                &relay,
+                &stats,
+                None,
+                incoming,
+            )
+            .await
+            .is_ok()
+            {
+                delivered += 1;
+            }
+        }
+        assert_eq!(delivered, 1);
+        // The replay list entry has a different IVI so only the network cache catches these.
+        assert_eq!(stats.snapshot().network_cache_hits, 2);
+        assert_eq!(stats.snapshot().replay_hits, 0);
+    }
+
+    #[tokio::test]
+    async fn test_delivered_message_key_indexes() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(1));
+        let app_key_index = AppKeyIndex(KeyIndex::new(2));
+        let app_key = test_helpers::app_key();
+        let dst = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let internals = StackInternals::new(test_helpers::device_state_with_app_key(
+            dst,
+            net_key_index,
+            app_key_index,
+        ));
+        let src = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let seq = SequenceNumber(U24::new(0x20));
+        let iv_index = internals.device_state().iv_index();
+        // Generic OnOff Get encrypted with the app key.
+        let mut access = [0x82_u8, 0x01];
+        let nonce = AppNonceParts {
+            aszmic: false,
+            seq,
+            src,
+            dst: Address::Unicast(dst),
+            iv_index,
+        }
+        .to_nonce();
+        let mic = SecurityMaterials::App(nonce, &app_key, app_key.aid())
+            .encrypt(&mut access, MicSize::Small);
+        let mut upper = [0_u8; 6];
+        upper[..2].copy_from_slice(&access);
+        mic.be_pack_into(&mut upper[2..]);
+        let pdu = net::PDU {
+            header: net::Header {
+                ivi: iv_index.ivi(),
+                nid: internals
+                    .net_keys()
+                    .get_keys(net_key_index)
+                    .expect("net key inserted above")
+                    .tx_key()
+                    .network_keys()
+                    .nid(),
+                ctl: CTL(false),
+                ttl: TTL::new(5),
+                seq,
+                src,
+                dst: Address::Unicast(dst),
+            },
+            payload: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(
+                Some(app_key.aid()),
+                &upper,
+            )),
+        };
+        let incoming = IncomingEncryptedNetworkPDU {
+            encrypted_pdu: internals
+                .encrypt_network_pdu(pdu, net_key_index, iv_index)
+                .expect("pdu encrypts"),
+            rssi: None,
+            dont_relay: true,
+        };
+        let internals = RwLock::new(internals);
+        let net_pdu = Incoming::handle_encrypted_net_pdu(
+            &internals,
+            &Mutex::new(replay::Cache::new()),
+            &Mutex::new(Relay::new()),
+            &StatsCounters::new(),
+            None,
+            incoming,
+        )
+        .await
+        .expect("pdu accepted");
+        assert_eq!(net_pdu.net_key_index, net_key_index);
+        let (tx_lower, _rx_lower) = mpsc::channel(1);
+        let (tx_reassembled, _rx_reassembled) = mpsc::channel(1);
+        let reassembler = Mutex::new(segments::Reassembler::new(tx_lower, tx_reassembled));
+        let (mut tx_ack, _rx_ack) = mpsc::channel(1);
+        let (mut tx_control, _rx_control) = mpsc::channel(1);
+        let (mut tx_access, mut rx_access) = mpsc::channel(1);
+        Incoming::handle_net(
+            &reassembler,
+            &mut tx_ack,
+            &AckWaiters::new(),
+            &mut tx_control,
+            &mut tx_access,
+            net_pdu,
+        )
+        .await
+        .expect("access pdu forwarded");
+        let encrypted = rx_access.recv().await.expect("encrypted access message");
+        let delivered = match internals.read().await.app_decrypt(encrypted) {
+            Ok(delivered) => delivered,
+            Err(_) => panic!("app key should decrypt the message"),
+        };
+        assert_eq!(delivered.net_key_index, net_key_index);
+        assert_eq!(delivered.app_key_index, Some(app_key_index));
+        assert_eq!(&delivered.payload[..], &[0x82_u8, 0x01][..]);
+    }
+    fn ack_pdu(seq: u32) -> IncomingNetworkPDU {
+        IncomingNetworkPDU {
+            pdu: net::PDU {
+                header: net::Header {
+                    ivi: IVI(false),
+                    nid: NID::new(0x12),
+                    ctl: CTL(true),
+                    ttl: TTL::new(5),
+                    seq: SequenceNumber(U24::new(seq)),
+                    src: UnicastAddress::new(0x0005).expect("valid unicast address"),
+                    dst: Address::Unicast(
+                        UnicastAddress::new(0x0001).expect("valid unicast address"),
+                    ),
+                },
+                payload: lower::PDU::UnsegmentedControl(
+                    control::Ack {
+                        obo: false,
+                        seq_zero: SeqZero::new(0x0010),
+                        block_ack: BlockAck::new_all_acked(SegO::new(2)),
+                    }
+                    .try_to_unseg()
+                    .ok()
+                    .expect("ack fits in one pdu"),
+                ),
+            },
+            net_key_index: NetKeyIndex(KeyIndex::new(0)),
+            iv_index: IVIndex(0),
+            rssi: None,
+        }
+    }
+    #[tokio::test]
+    async fn test_late_acks_dont_block() {
+        let (tx_lower, _rx_lower) = mpsc::channel(1);
+        let (tx_reassembled, _rx_reassembled) = mpsc::channel(1);
+        let reassembler = Mutex::new(segments::Reassembler::new(tx_lower, tx_reassembled));
+        // Nobody reads acks (no transfer is waiting for them).
+        let ack_waiters = AckWaiters::new();
+        let (mut tx_ack, _rx_ack) = mpsc::channel(1);
+        let (mut tx_control, _rx_control) = mpsc::channel(1);
+        let (mut tx_access, _rx_access) = mpsc::channel(1);
+        // More acks than the channel holds.
+        for seq in 0..4 {
+            let handled = time::timeout(
+                Duration::from_millis(100),
+                Incoming::handle_net(
+                    &reassembler,
+                    &mut tx_ack,
+                    &ack_waiters,
+                    &mut tx_control,
+                    &mut tx_access,
+                    ack_pdu(seq),
+                ),
+            )
+            .await
+            .expect("late ack doesn't block the incoming loop");
+            handled.expect("late ack dropped");
+        }
+    }
+    #[tokio::test]
+    async fn test_acks_wait_for_active_transfer() {
+        let (tx_lower, _rx_lower) = mpsc::channel(1);
+        let (tx_reassembled, _rx_reassembled) = mpsc::channel(1);
+        let reassembler = Mutex::new(segments::Reassembler::new(tx_lower, tx_reassembled));
+        // A transfer is waiting for acks so none of them get dropped even with a full channel.
+        let ack_waiters = AckWaiters::new();
+        ack_waiters.start();
+        let (mut tx_ack, mut rx_ack) = mpsc::channel(1);
+        let (mut tx_control, _rx_control) = mpsc::channel(1);
+        let (mut tx_access, _rx_access) = mpsc::channel(1);
+        let feed = async {
+            for seq in 0..4 {
+                Incoming::handle_net(
+                    &reassembler,
+                    &mut tx_ack,
+                    &ack_waiters,
+                    &mut tx_control,
+                    &mut tx_access,
+                    ack_pdu(seq),
+                )
+                .await
+                .expect("ack forwarded");
+            }
+        };
+        let read = async {
+            let mut seqs = Vec::new();
+            for _ in 0..4 {
+                let ack = rx_ack.recv().await.expect("ack received");
+                seqs.push(ack.seq.0.value());
+            }
+            seqs
+        };
+        let (_, seqs) = time::timeout(Duration::from_millis(100), async {
+            tokio::join!(feed, read)
+        })
+        .await
+        .expect("acks are passed on as the transfer reads them");
+        assert_eq!(seqs, vec![0, 1, 2, 3]);
+    }
+    #[test]
+    fn test_fixed_group_delivery() {
+        let mut config_states = ConfigStates::default();
+        let delivered = |config_states: &ConfigStates| {
+            [
+                FixedGroup::AllNodes,
+                FixedGroup::AllRelays,
+                FixedGroup::AllFriends,
+                FixedGroup::AllProxies,
+            ]
+            .iter()
+            .map(|&group| Incoming::delivers_fixed_group(group, config_states))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(delivered(&config_states), vec![true, false, false, false]);
+
+        config_states.relay_state = RelayState::Enabled;
+        assert_eq!(delivered(&config_states), vec![true, true, false, false]);
+
+        config_states.friend_state = FriendState::Enabled;
+        assert_eq!(delivered(&config_states), vec![true, true, true, false]);
+
+        config_states.gatt_proxy_state = GATTProxyState::Enabled;
+        assert_eq!(delivered(&config_states), vec![true, true, true, true]);
+
+        config_states.relay_state = RelayState::NotSupported;
+        config_states.friend_state = FriendState::Disabled;
+        config_states.gatt_proxy_state = GATTProxyState::NotSupported;
+        assert_eq!(delivered(&config_states), vec![true, false, false, false]);
+    }
+    #[test]
+    fn test_relay_check_ttl_drop() {
+        let stats = StatsCounters::new();
+        let mut config_states = ConfigStates::default();
+        // Relay disabled so a low TTL isn't a drop.
+        assert!(!Incoming::relay_check(
+            false,
+            TTL::new(1),
+            &config_states,
+            &stats
+        ));
+        assert_eq!(stats.snapshot().ttl_drops, 0);
+
+        config_states.relay_state = RelayState::Enabled;
+        assert!(!Incoming::relay_check(
+            false,
+            TTL::new(1),
+            &config_states,
+            &stats
+        ));
+        assert_eq!(stats.snapshot().ttl_drops, 1);
+        assert!(Incoming::relay_check(
+            false,
+            TTL::new(2),
+            &config_states,
+            &stats
+        ));
+        assert!(!Incoming::relay_check(
+            true,
+            TTL::new(1),
+            &config_states,
+            &stats
+        ));
+        assert_eq!(
+            stats.reset(),
+            StackStats {
+                ttl_drops: 1,
+                ..StackStats::default()
+            }
+        );
+        assert_eq!(stats.snapshot(), StackStats::default());
+    }
+}
@@ -4,19 +4,34 @@
 //! has the `IVIndex`, `NetKeyIndex`, `dst`, `src`, etc. Instead of passing this extra data as
 //! parameters for every function, we just wrap the PDUs.
 
+use crate::access::Opcode;
 use crate::address::{Address, UnicastAddress};
 use crate::crypto::aes::MicSize;
+use crate::crypto::key::DevKey;
 use crate::crypto::nonce::{AppNonce, AppNonceParts, DeviceNonce, DeviceNonceParts};
 use crate::device_state::SeqRange;
+use crate::foundation::publication::ModelPublishInfo;
+use crate::friend::Credentials;
 use crate::lower::{BlockAck, SegO, SeqAuth};
 use crate::mesh::{AppKeyIndex, ElementIndex, IVIndex, NetKeyIndex, SequenceNumber, NID, TTL};
+use crate::models::{MessagePackError, PackableMessage};
 use crate::stack::segments;
 use crate::upper::{AppPayload, EncryptedAppPayload};
 use crate::{control, lower, net, segmenter, upper};
+use alloc::boxed::Box;
+use alloc::vec;
 use btle::RSSI;
+#[cfg(feature = "full_stack")]
+use core::time::Duration;
+#[cfg(feature = "full_stack")]
+use driver_async::asyncs::{sync::mpsc, time};
 
 pub enum MessageKeys {
+    /// Device key of the destination node when it was provisioned by this device (see
+    /// `NodeList::device_key`), otherwise this node's own device key.
     Device(NetKeyIndex),
+    /// Another node's device key (ex: a provisioner configuring a remote node).
+    RemoteDevice(NetKeyIndex, DevKey),
     App(AppKeyIndex),
 }
 pub struct OutgoingDestination {
@@ -33,6 +48,144 @@ pub struct OutgoingMessage<Storage: AsRef<[u8]>> {
     pub source_element_index: ElementIndex,
     pub dst: Address,
     pub ttl: Option<TTL>,
+    /// Network credentials to send with (see `friend::CredentialSelector`).
+    pub credentials: Credentials,
+}
+impl OutgoingMessage<Box<[u8]>> {
+    /// Packs `msg` (opcode + parameters) into a new `AppPayload` encrypted with the
+    /// `app_index` application key. The rest of the fields use their defaults (small MIC,
+    /// default TTL, no forced segmentation) and can be changed before sending. `iv_index` is
+    /// left zeroed because the stack encrypts with its own transmit `IVIndex`.
+    pub fn from_model_message<M: PackableMessage>(
+        src: ElementIndex,
+        app_index: AppKeyIndex,
+        dst: Address,
+        msg: &M,
+    ) -> Result<Self, MessagePackError> {
+        Self::from_message_with_keys(src, MessageKeys::App(app_index), dst, msg)
+    }
+    /// Packs `msg` for the model's `publication`: sent to the publish address with the publish
+    /// AppKey, TTL (`None` is the default TTL) and the credentials picked by the Credential Flag.
+    pub fn from_publication<M: PackableMessage>(
+        src: ElementIndex,
+        publication: &ModelPublishInfo,
+        msg: &M,
+    ) -> Result<Self, MessagePackError> {
+        Ok(Self::from_publication_payload(
+            src,
+            publication,
+            Self::pack_payload(msg)?,
+        ))
+    }
+    /// Like `from_publication` but with an already packed `app_payload` (opcode + parameters).
+    pub fn from_publication_payload(
+        src: ElementIndex,
+        publication: &ModelPublishInfo,
+        app_payload: AppPayload<Box<[u8]>>,
+    ) -> Self {
+        let mut out = Self::from_payload_with_keys(
+            src,
+            MessageKeys::App(publication.app_key_index),
+            publication.address,
+            app_payload,
+        );
+        out.ttl = publication.ttl;
+        out.credentials = publication.credentials();
+        out
+    }
+    /// Like `from_model_message` but encrypted with `keys`. Config messages to a remote node use
+    /// `MessageKeys::RemoteDevice` with that node's device key.
+    pub fn from_message_with_keys<M: PackableMessage>(
+        src: ElementIndex,
+        keys: MessageKeys,
+        dst: Address,
+        msg: &M,
+    ) -> Result<Self, MessagePackError> {
+        Ok(Self::from_payload_with_keys(
+            src,
+            keys,
+            dst,
+            Self::pack_payload(msg)?,
+        ))
+    }
+    /// Packs `msg` (opcode + parameters) into a new `AppPayload`.
+    pub fn pack_payload<M: PackableMessage>(
+        msg: &M,
+    ) -> Result<AppPayload<Box<[u8]>>, MessagePackError> {
+        let mut buffer = vec![0_u8; msg.packed_len()];
+        msg.pack_with_opcode(&mut buffer[..])?;
+        Ok(AppPayload::new(buffer.into_boxed_slice()))
+    }
+    fn from_payload_with_keys(
+        src: ElementIndex,
+        keys: MessageKeys,
+        dst: Address,
+        app_payload: AppPayload<Box<[u8]>>,
+    ) -> Self {
+        OutgoingMessage {
+            app_payload,
+            mic_size: MicSize::Small,
+            force_segment: false,
+            encryption_key: keys,
+            iv_index: IVIndex::default(),
+            source_element_index: src,
+            dst,
+            ttl: None,
+            credentials: Credentials::Master,
+        }
+    }
+}
+/// Returned by `request_status` when no matching status message was received.
+pub enum RequestError {
+    Pack(MessagePackError),
+    ChannelClosed,
+    /// Every attempt timed out without a matching response.
+    Timeout,
+}
+impl From<MessagePackError> for RequestError {
+    fn from(e: MessagePackError) -> Self {
+        RequestError::Pack(e)
+    }
+}
+/// Sends `req` to `target` and waits for a `Resp` status message from `target` (or from anyone
+/// if `target.dst` isn't a unicast address). If none arrives within `timeout`, `req` is sent again
+/// up to `retries` more times. Incoming messages that don't unpack as `Resp` are dropped.
+#[cfg(feature = "full_stack")]
+pub async fn request_status<Req: PackableMessage, Resp: PackableMessage>(
+    src: ElementIndex,
+    target: &OutgoingDestination,
+    req: &Req,
+    timeout: Duration,
+    retries: u8,
+    outgoing: &mut mpsc::Sender<OutgoingMessage<Box<[u8]>>>,
+    incoming: &mut mpsc::Receiver<IncomingMessage<Box<[u8]>>>,
+) -> Result<Resp, RequestError> {
+    for _ in 0..=retries {
+        let mut msg =
+            OutgoingMessage::from_model_message(src, target.app_key_index, target.dst, req)?;
+        msg.ttl = target.ttl;
+        outgoing
+            .send(msg)
+            .await
+            .map_err(|_| RequestError::ChannelClosed)?;
+        let wait_for_status = async {
+            loop {
+                let response = incoming.recv().await.ok_or(RequestError::ChannelClosed)?;
+                if let Address::Unicast(dst) = target.dst {
+                    if response.src != dst {
+                        continue;
+                    }
+                }
+                if let Ok(status) = Resp::unpack_with_opcode(response.payload.as_ref()) {
+                    return Ok(status);
+                }
+            }
+        };
+        if let Ok(result) = time::timeout(timeout, wait_for_status).await {
+            return result;
+        }
+    }
+    Err(RequestError::Timeout)
 }
 pub struct OutgoingLowerTransportMessage {
     pub pdu: lower::PDU,
@@ -42,6 +195,7 @@ pub struct OutgoingLowerTransportMessage {
     pub seq: Option<SequenceNumber>,
     pub iv_index: IVIndex,
     pub net_key_index: NetKeyIndex,
+    pub credentials: Credentials,
 }
 impl OutgoingLowerTransportMessage {
     pub fn net_pdu(&self, nid: NID, seq: SequenceNumber, ttl: TTL) -> net::PDU {
@@ -86,6 +240,7 @@ pub struct OutgoingUpperTransportMessage<Storage: AsRef<[u8]>> {
     pub src: UnicastAddress,
     pub dst: Address,
     pub ttl: Option<TTL>,
+    pub credentials: Credentials,
 }
 impl<Storage: AsRef<[u8]>> OutgoingUpperTransportMessage<Storage> {
     pub fn should_segment(&self) -> bool {
@@ -108,6 +263,7 @@ impl<Storage: AsRef<[u8]>> OutgoingUpperTransportMessage<Storage> {
             src: self.src,
             dst: self.dst,
             ttl: self.ttl,
+            credentials: self.credentials,
         }
     }
 }
@@ -168,6 +324,21 @@ pub struct IncomingMessage<Storage: AsRef<[u8]>> {
     pub ttl: Option<TTL>,
     pub rssi: Option<RSSI>,
 }
+impl<Storage: AsRef<[u8]>> IncomingMessage<Storage> {
+    /// Opcode of the access message. `None` if `payload` doesn't start with a valid opcode.
+    pub fn opcode(&self) -> Option<Opcode> {
+        Opcode::split_from(self.payload.as_ref())
+            .ok()
+            .map(|(opcode, _)| opcode)
+    }
+    /// Access message parameters (the bytes of `payload` after the opcode). `payload` is already
+    /// decrypted and doesn't include the TransMIC. `None` if it doesn't start with a valid opcode.
+    pub fn access_params(&self) -> Option<&[u8]> {
+        Opcode::split_from(self.payload.as_ref())
+            .ok()
+            .map(|(_, params)| params)
+    }
+}
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct IncomingNetworkPDU {
     pub pdu: net::PDU,
@@ -186,3 +357,261 @@ pub struct IncomingTransportPDU<Storage: AsRef<[u8]> + AsMut<[u8]>> {
     pub src: UnicastAddress,
     pub dst: Address,
 }
+impl<Storage: AsRef<[u8]> + AsMut<[u8]>> IncomingTransportPDU<Storage> {
+    /// Reassembled access message ready to be decrypted. `None` for control messages.
+    pub fn into_encrypted_access(self) -> Option<EncryptedIncomingMessage<Storage>> {
+        match self.upper_pdu {
+            upper::PDU::Access(encrypted_app_payload) => Some(EncryptedIncomingMessage {
+                encrypted_app_payload,
+                seq: self.seq,
+                seg_count: self.seg_count,
+                iv_index: self.iv_index,
+                net_key_index: self.net_key_index,
+                dst: self.dst,
+                src: self.src,
+                ttl: self.ttl,
+                rssi: self.rssi,
+            }),
+            upper::PDU::Control(_) => None,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::KeyIndex;
+    use crate::models::generics::onoff;
+
+    #[cfg(feature = "full_stack")]
+    #[tokio::test]
+    async fn test_request_status_retransmits() {
+        let (mut outgoing, mut requests) = mpsc::channel(4);
+        let (mut responses, mut incoming) = mpsc::channel(4);
+        let server = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let target = OutgoingDestination {
+            dst: Address::Unicast(server),
+            ttl: None,
+            app_key_index: AppKeyIndex(KeyIndex::new(0)),
+        };
+        let responder = async {
+            // Drop the first request and answer the retransmission.
+            let _dropped = requests.recv().await.expect("first request");
+            let retransmitted = requests.recv().await.expect("retransmitted request");
+            assert!(onoff::Get::unpack_with_opcode(retransmitted.app_payload.0.as_ref()).is_ok());
+            let status = onoff::Status {
+                present_on_off: true,
+                target: None,
+            };
+            let mut payload = vec![0_u8; status.packed_len()];
+            status
+                .pack_with_opcode(&mut payload[..])
+                .ok()
+                .expect("status packs");
+            responses
+                .send(IncomingMessage {
+                    payload: payload.into_boxed_slice(),
+                    src: server,
+                    dst: Address::Unicast(
+                        UnicastAddress::new(0x0001).expect("valid unicast address"),
+                    ),
+                    seq: SequenceNumber::default(),
+                    iv_index: IVIndex::default(),
+                    net_key_index: NetKeyIndex(KeyIndex::new(0)),
+                    app_key_index: Some(AppKeyIndex(KeyIndex::new(0))),
+                    ttl: None,
+                    rssi: None,
+                })
+                .await
+                .ok()
+                .expect("incoming channel open");
+        };
+        let (status, ()) = futures_util::future::join(
+            request_status::<onoff::Get, onoff::Status>(
+                ElementIndex(0),
+                &target,
+                &onoff::Get,
+                Duration::from_millis(50),
+                2,
+                &mut outgoing,
+                &mut incoming,
+            ),
+            responder,
+        )
+        .await;
+        match status {
+            Ok(status) => assert!(status.present_on_off),
+            _ => panic!("expected a status"),
+        }
+    }
+
+    #[cfg(feature = "full_stack")]
+    #[tokio::test]
+    async fn test_request_status_segmented_reply() {
+        use crate::lower::SegmentedPDU;
+        use crate::models::lighting::hsl;
+        use crate::models::transition::TransitionTime;
+        use crate::stack::{test_helpers, StackInternals};
+
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let app_key_index = AppKeyIndex(KeyIndex::new(0));
+        let client_address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let server_address = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let internals = |address| {
+            StackInternals::new(test_helpers::device_state_with_app_key(
+                address,
+                net_key_index,
+                app_key_index,
+            ))
+        };
+        let client = internals(client_address);
+        let server = internals(server_address);
+        let target = OutgoingDestination {
+            dst: Address::Unicast(server_address),
+            ttl: None,
+            app_key_index,
+        };
+        let status = hsl::Status {
+            hsl: hsl::HSL {
+                lightness: 0x1234,
+                hue: 0x5678,
+                saturation: 0x9ABC,
+            },
+            remaining_time: Some(TransitionTime(0x05)),
+        };
+        // Segments of the server's encrypted Status reply.
+        let reply_segments = || {
+            let mut reply = OutgoingMessage::from_model_message(
+                ElementIndex(0),
+                app_key_index,
+                Address::Unicast(client_address),
+                &status,
+            )
+            .ok()
+            .expect("status packs");
+            reply.force_segment = true;
+            let encrypted_reply = match server.app_encrypt::<Box<[u8]>>(reply) {
+                Ok(encrypted_reply) => encrypted_reply,
+                Err(_) => panic!("status should encrypt"),
+            };
+            test_helpers::access_segments(&encrypted_reply)
+                .into_iter()
+                .map(|(seq, segment)| segments::IncomingPDU {
+                    pdu: SegmentedPDU::Access(segment),
+                    seq,
+                    iv_index: encrypted_reply.iv_index,
+                    net_key_index,
+                    src: server_address,
+                    dst: Address::Unicast(client_address),
+                    ttl: TTL::new(5),
+                })
+                .collect::<Vec<_>>()
+        };
+        let (tx_lower, _rx_lower) = mpsc::channel(4);
+        let (tx_reassembled, mut rx_reassembled) = mpsc::channel(1);
+        let mut reassembler = segments::Reassembler::new(tx_lower, tx_reassembled);
+        let (mut outgoing, mut requests) = mpsc::channel(4);
+        let (mut responses, mut incoming) = mpsc::channel(4);
+
+        // Only the first segment arrives so nothing is matched and the request times out.
+        let responder = async {
+            let _request = requests.recv().await.expect("request sent");
+            let segments = reply_segments();
+            assert_eq!(segments.len(), 2);
+            reassembler
+                .feed_pdu(segments[0])
+                .await
+                .ok()
+                .expect("reassembler running");
+        };
+        let (timed_out, ()) = futures_util::future::join(
+            request_status::<hsl::Get, hsl::Status>(
+                ElementIndex(0),
+                &target,
+                &hsl::Get,
+                Duration::from_millis(100),
+                0,
+                &mut outgoing,
+                &mut incoming,
+            ),
+            responder,
+        )
+        .await;
+        assert!(matches!(timed_out, Err(RequestError::Timeout)));
+
+        // The Status is only matched once both segments are reassembled.
+        let responder = async {
+            let request = requests.recv().await.expect("request sent");
+            assert!(hsl::Get::unpack_with_opcode(request.app_payload.0.as_ref()).is_ok());
+            for segment in reply_segments() {
+                reassembler
+                    .feed_pdu(segment)
+                    .await
+                    .ok()
+                    .expect("reassembler running");
+            }
+            let encrypted = rx_reassembled
+                .recv()
+                .await
+                .expect("reply reassembled")
+                .into_encrypted_access()
+                .expect("access message");
+            assert_eq!(encrypted.seg_count, 2);
+            let delivered = match client.app_decrypt(encrypted) {
+                Ok(delivered) => delivered,
+                Err(_) => panic!("app key should decrypt the reply"),
+            };
+            responses
+                .send(delivered)
+                .await
+                .ok()
+                .expect("incoming channel open");
+        };
+        let (matched, ()) = futures_util::future::join(
+            request_status::<hsl::Get, hsl::Status>(
+                ElementIndex(0),
+                &target,
+                &hsl::Get,
+                Duration::from_millis(500),
+                0,
+                &mut outgoing,
+                &mut incoming,
+            ),
+            responder,
+        )
+        .await;
+        match matched {
+            Ok(matched) => assert_eq!(matched, status),
+            _ => panic!("expected the segmented status"),
+        }
+    }
+
+    #[test]
+    fn test_from_model_message() {
+        let set = onoff::Set {
+            on_off: true,
+            tid: 0x12,
+            transition: None,
+        };
+        let msg = OutgoingMessage::from_model_message(
+            ElementIndex(0),
+            AppKeyIndex(KeyIndex::new(1)),
+            Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            &set,
+        )
+        .ok()
+        .expect("message should pack");
+        let payload = msg.app_payload.payload();
+        let opcode = onoff::Set::opcode();
+        let opcode_len = opcode.byte_len();
+        assert_eq!(payload.len(), opcode_len + set.message_size());
+        let mut opcode_buf = [0_u8; 3];
+        opcode
+            .pack_into(&mut opcode_buf[..opcode_len])
+            .expect("valid opcode");
+        assert_eq!(&payload[..opcode_len], &opcode_buf[..opcode_len]);
+        assert_eq!(
+            onoff::Set::unpack_from(&payload[opcode_len..]).ok(),
+            Some(set)
+        );
+    }
+}
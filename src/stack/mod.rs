@@ -12,14 +12,24 @@ pub mod messages;
 pub mod model;
 #[cfg(feature = "full_stack")]
 pub mod outgoing;
+pub mod proxy;
+pub mod publication;
 #[cfg(feature = "std")]
 pub mod segments;
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(test)]
+pub mod test_helpers;
 
+use crate::access;
 use crate::address::{Address, UnicastAddress, VirtualAddress, VirtualAddressHash};
 
-use crate::crypto::materials::{ApplicationSecurityMaterials, NetKeyMap, NetworkSecurityMaterials};
+use crate::crypto::materials::{ApplicationSecurityMaterials, NetKeyMap, NetworkKeys};
 use crate::crypto::nonce::{AppNonceParts, DeviceNonceParts};
 use crate::device_state::{DeviceState, SeqCounter};
+use crate::foundation::state::RelayState;
+use crate::friend::{CredentialSelector, Credentials};
 use crate::lower::SegO;
 use crate::mesh::{
     AppKeyIndex, ElementCount, ElementIndex, IVIndex, IVUpdateFlag, NetKeyIndex, TTL,
@@ -58,6 +68,7 @@ pub struct NetworkHeader {
 /// The scheduling and input/output queues are handled by `FullStack`.
 pub struct StackInternals {
     device_state: device_state::DeviceState,
+    credentials: CredentialSelector,
 }
 /// Returned when an outgoing message can't be sent for some reason.
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
@@ -71,6 +82,8 @@ pub enum SendError {
     NetEncryptError,
     OutOfSeq,
     AckTimeout,
+    PayloadTooLarge,
+    LoopbackDecryptError,
 }
 /// Returned when an incoming message can't be received for some reason.
 #[derive(Debug)]
@@ -90,7 +103,10 @@ pub enum RecvError {
 impl StackInternals {
     /// Wraps a `device_state::DeviceState` and lets you perform encrypt and decryption with it.
     pub fn new(device_state: device_state::DeviceState) -> Self {
-        Self { device_state }
+        Self {
+            device_state,
+            credentials: CredentialSelector::new(),
+        }
     }
     /// Returns a reference to the Atomic `SeqCounter` pertaining to the given element.
     /// # Panics
@@ -205,6 +221,15 @@ impl StackInternals {
         &self,
         msg: OutgoingMessage<Storage>,
     ) -> Result<OutgoingUpperTransportMessage<Storage>, (SendError, OutgoingMessage<Storage>)> {
+        // Checked first because `seq_counter` panics for elements the node doesn't have.
+        let src = match self.device_state.element_address(msg.source_element_index) {
+            None => return Err((SendError::InvalidSourceElement, msg)),
+            Some(address) => address,
+        };
+        // Payloads bigger than 32 segments can't be represented by `SegO`.
+        if !access::access_payload_fits(msg.app_payload.len(), msg.mic_size) {
+            return Err((SendError::PayloadTooLarge, msg));
+        }
         // If DST is a VirtualAddress, it must have the full Label UUID.
         let dst = msg.dst;
         match &dst {
@@ -214,14 +239,22 @@ impl StackInternals {
             _ => (),
         }
         let iv_index = self.device_state.tx_iv_index();
-        let src = match self.device_state.element_address(msg.source_element_index) {
-            None => return Err((SendError::InvalidSourceElement, msg)),
-            Some(address) => address,
-        };
-        let aszmic = msg.should_segment();
+        // ASZMIC is the segmented message's SZMIC (the TransMIC size) and 0 when unsegmented.
+        let aszmic = msg.should_segment() && msg.mic_size.is_big();
         let seg_count = u8::from(msg.seg_o().unwrap_or_else(|| SegO::new(0))) + 1;
+        let dev_key = match (&msg.encryption_key, dst) {
+            (MessageKeys::RemoteDevice(_, dev_key), _) => *dev_key,
+            // Messages to a node this device provisioned use that node's device key.
+            (MessageKeys::Device(_), Address::Unicast(unicast)) => self
+                .device_state
+                .nodes()
+                .device_key(unicast)
+                .copied()
+                .unwrap_or(self.device_state.security_materials().dev_key),
+            _ => self.device_state.security_materials().dev_key,
+        };
         let (sm, net_key_index, seq) = match msg.encryption_key {
-            MessageKeys::Device(net_key_index) => {
+            MessageKeys::Device(net_key_index) | MessageKeys::RemoteDevice(net_key_index, _) => {
                 // Check for a valid net_key
                 match self
                     .device_state
@@ -250,32 +283,41 @@ impl StackInternals {
                             iv_index,
                         }
                         .to_nonce(),
-                        &self.device_state.security_materials().dev_key,
+                        &dev_key,
                     ),
                     net_key_index,
                     seq_range,
                 )
             }
             MessageKeys::App(app_key_index) => {
-                let app_sm = match self
+                let net_key_index = match self
                     .device_state
                     .security_materials()
                     .app_key_map
                     .get_key(app_key_index)
                 {
                     None => return Err((SendError::InvalidAppKeyIndex, msg)),
-                    Some(app_sm) => app_sm,
+                    Some(app_sm) => app_sm.net_key_index,
                 };
-                let net_key_index = app_sm.net_key_index;
                 // Check for a valid net_key
-                match self
+                let phase = match self
                     .device_state
                     .security_materials()
                     .net_key_map
                     .get_keys(net_key_index)
                 {
                     None => return Err((SendError::InvalidNetKeyIndex, msg)),
-                    Some(_) => (),
+                    Some(phase) => phase.phase(),
+                };
+                // During Key Refresh, the old application key is used until the new one is.
+                let app_sm = match self
+                    .device_state
+                    .security_materials()
+                    .app_key_map
+                    .tx_key(app_key_index, phase)
+                {
+                    None => return Err((SendError::InvalidAppKeyIndex, msg)),
+                    Some(app_sm) => app_sm,
                 };
                 let seq_range = match self
                     .seq_counter(msg.source_element_index)
@@ -316,14 +358,69 @@ impl StackInternals {
         Ok(OutgoingUpperTransportMessage {
             upper_pdu: upper::PDU::Access(encrypted),
             seq,
-            seg_count: SegO::new(seg_count),
+            seg_count: SegO::new(seg_count - 1),
             net_key_index,
             src,
             dst,
             ttl: Some(ttl),
             iv_index,
+            credentials: msg.credentials,
         })
     }
+    /// Returns if an access message sent to `dst` is for this node: one of its element addresses
+    /// or a fixed group it accepts. Other group subscriptions aren't tracked yet.
+    pub fn is_local_destination(&self, dst: &Address) -> bool {
+        match dst {
+            Address::Unicast(unicast) => self.device_state.element_index(*unicast).is_some(),
+            Address::Group(group) => group.fixed_group().map_or(false, |fixed_group| {
+                self.device_state
+                    .config_states()
+                    .accepts_fixed_group(fixed_group)
+            }),
+            Address::Unassigned | Address::Virtual(_) | Address::VirtualHash(_) => false,
+        }
+    }
+    /// Decrypts an access message encrypted by `app_encrypt` like it was received over the air so
+    /// messages sent to this node don't have to leave it. Returns `None` for control messages.
+    pub fn loopback<Storage: AsRef<[u8]> + AsMut<[u8]> + Clone>(
+        &self,
+        msg: &OutgoingUpperTransportMessage<Storage>,
+    ) -> Option<Result<IncomingMessage<Storage>, RecvError>> {
+        let encrypted_app_payload = match &msg.upper_pdu {
+            upper::PDU::Access(access) => access.clone(),
+            upper::PDU::Control(_) => return None,
+        };
+        Some(self.app_decrypt(EncryptedIncomingMessage {
+            encrypted_app_payload,
+            seq: msg.seq.start(),
+            seg_count: u8::from(msg.seg_count),
+            iv_index: msg.iv_index,
+            net_key_index: msg.net_key_index,
+            dst: msg.dst,
+            src: msg.src,
+            ttl: msg.ttl,
+            rssi: None,
+        }))
+    }
+    /// Returns if received Network PDUs are relayed (`RelayState::Enabled`).
+    pub fn relay_enabled(&self) -> bool {
+        self.device_state.config_states().relay_state.is_enabled()
+    }
+    /// Turns relaying on or off at runtime. Every received PDU checks the `RelayState` so the
+    /// change applies to the next one. Returns `false` (and changes nothing) if the node doesn't
+    /// support the Relay feature.
+    pub fn set_relay_enabled(&mut self, enabled: bool) -> bool {
+        let relay_state = &mut self.device_state.config_states_mut().relay_state;
+        if !relay_state.is_supported() {
+            return false;
+        }
+        *relay_state = if enabled {
+            RelayState::Enabled
+        } else {
+            RelayState::Disabled
+        };
+        true
+    }
     /// Returns the default `TTL`.
     pub fn default_ttl(&self) -> TTL {
         self.device_state.default_ttl()
@@ -339,6 +436,34 @@ impl StackInternals {
     pub fn net_keys(&self) -> &NetKeyMap {
         &self.device_state.security_materials().net_key_map
     }
+    /// Returns the `CredentialSelector` that picks master or friendship credentials for
+    /// outgoing Network PDUs.
+    pub fn credential_selector(&self) -> &CredentialSelector {
+        &self.credentials
+    }
+    /// Returns a mutable reference to the `CredentialSelector` to add or remove friendships.
+    pub fn credential_selector_mut(&mut self) -> &mut CredentialSelector {
+        &mut self.credentials
+    }
+    /// `NetworkKeys` to encrypt a Network PDU from `src` to `dst` with. Uses the transmit key of
+    /// the `net_key_index` subnet and lets the `CredentialSelector` pick master or friendship
+    /// credentials.
+    pub fn tx_network_keys(
+        &self,
+        net_key_index: NetKeyIndex,
+        src: UnicastAddress,
+        dst: Address,
+        credentials: Credentials,
+    ) -> Result<NetworkKeys, SendError> {
+        let net_sm = self
+            .net_keys()
+            .get_keys(net_key_index)
+            .ok_or(SendError::InvalidNetKeyIndex)?
+            .tx_key();
+        Ok(self
+            .credentials
+            .network_keys(net_key_index, net_sm, src, dst, credentials))
+    }
     /// Returns a mutable reference to `device_state::DeviceState`. If you take a mutable reference,
     /// you essential lock out the rest of the stack from using `device_state::DeviceState` to
     /// encrypt and decrypt messages.
@@ -363,7 +488,23 @@ impl StackInternals {
                 return Some((index, iv_index, decrypted_pdu));
             }
         }
-
+        // Friend Queue messages (and a Low Power Node's messages to its Friend) are encrypted
+        // with the friendship credentials instead.
+        for friendship in self.credentials.friendships() {
+            let (first, second) = match self.net_keys().get_keys(friendship.net_key_index) {
+                Some(phase) => phase.rx_keys(),
+                None => continue,
+            };
+            for sm in core::iter::once(first).chain(second) {
+                let keys = friendship.network_keys(sm);
+                if keys.nid() != pdu.nid() {
+                    continue;
+                }
+                if let Ok(decrypted_pdu) = pdu.try_decrypt(&keys, iv_index) {
+                    return Some((friendship.net_key_index, iv_index, decrypted_pdu));
+                }
+            }
+        }
         None
     }
     /// Returns if the given `IVIndex` is a valid `IVIndex` (Based on IVI).
@@ -393,10 +534,12 @@ impl StackInternals {
             net_keys: net_sm.network_keys(),
         })
     }
+    /// Converts `msg` to a Network PDU and returns it with the `NetworkKeys` (master or
+    /// friendship, see `tx_network_keys`) it has to be encrypted with.
     pub fn lower_to_net(
         &self,
         msg: &OutgoingLowerTransportMessage,
-    ) -> Result<(net::PDU, &NetworkSecurityMaterials), SendError> {
+    ) -> Result<(net::PDU, NetworkKeys), SendError> {
         if !self.is_valid_iv_index(msg.iv_index) {
             return Err(SendError::InvalidIVIndex);
         }
@@ -404,11 +547,8 @@ impl StackInternals {
             .device_state
             .element_index(msg.src)
             .ok_or(SendError::InvalidSourceElement)?;
-        let net_sm = self
-            .net_keys()
-            .get_keys(msg.net_key_index)
-            .ok_or(SendError::InvalidNetKeyIndex)?
-            .tx_key();
+        let net_keys =
+            self.tx_network_keys(msg.net_key_index, msg.src, msg.dst, msg.credentials)?;
         let seq = match msg.seq {
             Some(seq) => seq,
             None => self
@@ -420,15 +560,17 @@ impl StackInternals {
         };
         Ok((
             msg.net_pdu(
-                net_sm.network_keys().nid(),
+                net_keys.nid(),
                 seq,
                 msg.ttl.unwrap_or_else(|| self.device_state.default_ttl()),
             ),
-            net_sm,
+            net_keys,
         ))
     }
     /// Encrypt a single [`net::PDU`]. Use `Self::encrypted_network_pdus` instead if you have
     /// more than one Network PDU.
+    /// Returns `SendError::PayloadTooLarge` if the encrypted PDU doesn't fit in a legacy
+    /// advertisement (see [`bearer::fits_legacy_adv`]).
     pub fn encrypt_network_pdu(
         &self,
         pdu: net::PDU,
@@ -438,7 +580,8 @@ impl StackInternals {
         if !self.is_valid_iv_index(iv_index) {
             return Err(SendError::InvalidIVIndex);
         }
-        pdu.encrypt(
+        Self::encrypt_with_keys(
+            &pdu,
             self.net_keys()
                 .get_keys(net_key_index)
                 .ok_or(SendError::InvalidNetKeyIndex)?
@@ -446,7 +589,22 @@ impl StackInternals {
                 .network_keys(),
             iv_index,
         )
-        .map_err(|_| SendError::NetEncryptError)
+    }
+    /// Encrypt a single [`net::PDU`] with `net_keys`. Every Network PDU sent goes through here so
+    /// returns `SendError::PayloadTooLarge` if the encrypted PDU doesn't fit in a legacy
+    /// advertisement (see [`bearer::fits_legacy_adv`]).
+    pub fn encrypt_with_keys(
+        pdu: &net::PDU,
+        net_keys: &NetworkKeys,
+        iv_index: IVIndex,
+    ) -> Result<net::EncryptedPDU<net::StaticEncryptedPDUBuf>, SendError> {
+        let encrypted = pdu
+            .encrypt(net_keys, iv_index)
+            .map_err(|_| SendError::NetEncryptError)?;
+        if !bearer::fits_legacy_adv(encrypted.data()) {
+            return Err(SendError::PayloadTooLarge);
+        }
+        Ok(encrypted)
     }
 }
 
@@ -457,6 +615,7 @@ pub trait Stack: Sized {
         ElementRef::new(&self, element_index)
     }
     fn element_count(&self) -> ElementCount;
+    /// Returns `SendError::InvalidSourceElement` if `source_element >= element_count()`.
     fn send_message<Storage: AsRef<[u8]> + AsMut<[u8]>>(
         &self,
         source_element: ElementIndex,
@@ -465,3 +624,402 @@ pub trait Stack: Sized {
         payload: AppPayload<Storage>,
     ) -> Result<(), SendError>;
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::{Opcode, VendorOpcode};
+    use crate::crypto::aes::MicSize;
+    use crate::crypto::key::DevKey;
+    use crate::crypto::MIC;
+    use crate::device_state::NodeInfo;
+    use crate::foundation::state::DefaultTTLState;
+    use crate::friend::Friendship;
+    use crate::lower;
+    use crate::lower::UnsegmentedAccessPDU;
+    use crate::mesh::{CompanyID, KeyIndex};
+    use crate::models::config::messages::default_ttl;
+    use crate::models::{MessagePackError, PackableMessage};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    #[test]
+    pub fn test_app_encrypt_payload_too_large() {
+        let internals = StackInternals::new(DeviceState::new(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            ElementCount(1),
+        ));
+        let msg = OutgoingMessage {
+            app_payload: AppPayload::new(vec![0_u8; 400]),
+            mic_size: MicSize::Small,
+            force_segment: false,
+            encryption_key: MessageKeys::App(AppKeyIndex(KeyIndex::new(0))),
+            iv_index: IVIndex(0),
+            source_element_index: ElementIndex(0),
+            dst: Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            ttl: None,
+            credentials: Credentials::Master,
+        };
+        match internals.app_encrypt::<Vec<u8>>(msg) {
+            Err((SendError::PayloadTooLarge, msg)) => assert_eq!(msg.app_payload.len(), 400),
+            _ => panic!("expected PayloadTooLarge"),
+        }
+    }
+    struct LargeMessage(Vec<u8>);
+    impl PackableMessage for LargeMessage {
+        fn opcode() -> Opcode {
+            Opcode::Vendor(VendorOpcode::new(0x01), CompanyID(0x05F1))
+        }
+        fn message_size(&self) -> usize {
+            self.0.len()
+        }
+        fn pack_into(&self, buffer: &mut [u8]) -> Result<(), MessagePackError> {
+            buffer[..self.0.len()].copy_from_slice(&self.0);
+            Ok(())
+        }
+        fn unpack_from(buffer: &[u8]) -> Result<Self, MessagePackError> {
+            Ok(LargeMessage(buffer.to_vec()))
+        }
+    }
+    #[test]
+    pub fn test_large_model_message_payload_too_large() {
+        let internals = StackInternals::new(DeviceState::new(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+            ElementCount(1),
+        ));
+        let app_index = AppKeyIndex(KeyIndex::new(0));
+        let dst = Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address"));
+        // 3 byte vendor opcode + 378 bytes = 381 > 380
+        let large = LargeMessage(vec![0xAA; 378]);
+        assert_eq!(large.packed_len(), access::max_access_payload_len() + 1);
+        let msg = OutgoingMessage::from_model_message(ElementIndex(0), app_index, dst, &large)
+            .ok()
+            .expect("message packs");
+        match internals.app_encrypt::<Box<[u8]>>(msg) {
+            Err((SendError::PayloadTooLarge, _)) => (),
+            _ => panic!("expected PayloadTooLarge"),
+        }
+    }
+    #[test]
+    pub fn test_opcode_only_message_unsegmented() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let internals = StackInternals::new(test_helpers::device_state(address, net_key_index));
+        // Config Default TTL Get is only an opcode.
+        let mut payload = vec![0_u8; default_ttl::Get.packed_len()];
+        default_ttl::Get
+            .pack_with_opcode(&mut payload[..])
+            .ok()
+            .expect("get packs");
+        let msg = OutgoingMessage {
+            app_payload: AppPayload::new(payload.clone()),
+            mic_size: MicSize::Small,
+            force_segment: false,
+            encryption_key: MessageKeys::Device(net_key_index),
+            iv_index: IVIndex(0),
+            source_element_index: ElementIndex(0),
+            dst: Address::Unicast(address),
+            ttl: None,
+            credentials: Credentials::Master,
+        };
+        assert!(!msg.should_segment());
+        let outgoing = match internals.app_encrypt::<Vec<u8>>(msg) {
+            Ok(outgoing) => outgoing,
+            Err(_) => panic!("device key message should encrypt"),
+        };
+        let unsegmented = match &outgoing.upper_pdu {
+            upper::PDU::Access(access) => access.as_unsegmented().expect("fits unsegmented"),
+            upper::PDU::Control(_) => panic!("expected an access pdu"),
+        };
+        assert_eq!(
+            unsegmented.upper_pdu_len(),
+            payload.len() + MIC::small_size()
+        );
+        let mut bytes = [0_u8; UnsegmentedAccessPDU::max_len()];
+        unsegmented.pack_into(&mut bytes[..]);
+        let received = UnsegmentedAccessPDU::unpack_from(&bytes[..unsegmented.len()])
+            .expect("device key pdu unpacks");
+        assert_eq!(received, unsegmented);
+        let encrypted = EncryptedIncomingMessage {
+            encrypted_app_payload: (&received).into(),
+            seq: outgoing.seq.start(),
+            seg_count: 0,
+            iv_index: outgoing.iv_index,
+            net_key_index,
+            dst: Address::Unicast(address),
+            src: address,
+            ttl: outgoing.ttl,
+            rssi: None,
+        };
+        let delivered = match internals.app_decrypt(encrypted) {
+            Ok(delivered) => delivered,
+            Err(_) => panic!("device key should decrypt the message"),
+        };
+        assert_eq!(&delivered.payload[..], &payload[..]);
+        assert!(default_ttl::Get::unpack_with_opcode(&delivered.payload[..]).is_ok());
+    }
+    #[test]
+    pub fn test_incoming_config_status_params() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let internals = StackInternals::new(test_helpers::device_state(address, net_key_index));
+        let status = default_ttl::Status(DefaultTTLState::new(0x0A));
+        let msg = OutgoingMessage::from_message_with_keys(
+            ElementIndex(0),
+            MessageKeys::Device(net_key_index),
+            Address::Unicast(address),
+            &status,
+        )
+        .ok()
+        .expect("status packs");
+        let outgoing = match internals.app_encrypt::<Box<[u8]>>(msg) {
+            Ok(outgoing) => outgoing,
+            Err(_) => panic!("device key message should encrypt"),
+        };
+        let unsegmented = match &outgoing.upper_pdu {
+            upper::PDU::Access(access) => access.as_unsegmented().expect("fits unsegmented"),
+            upper::PDU::Control(_) => panic!("expected an access pdu"),
+        };
+        let delivered = match internals.app_decrypt(EncryptedIncomingMessage {
+            encrypted_app_payload: unsegmented.into(),
+            seq: outgoing.seq.start(),
+            seg_count: 0,
+            iv_index: outgoing.iv_index,
+            net_key_index,
+            dst: Address::Unicast(address),
+            src: address,
+            ttl: outgoing.ttl,
+            rssi: None,
+        }) {
+            Ok(delivered) => delivered,
+            Err(_) => panic!("device key should decrypt the message"),
+        };
+        // Opcode (0x800E) and the TTL without the TransMIC.
+        assert_eq!(&delivered.payload[..], &[0x80, 0x0E, 0x0A][..]);
+        assert_eq!(delivered.opcode(), Some(default_ttl::Status::opcode()));
+        let params = delivered.access_params().expect("valid opcode");
+        assert_eq!(params, &[0x0A][..]);
+        assert_eq!(default_ttl::Status::unpack_from(params).ok(), Some(status));
+    }
+    #[test]
+    pub fn test_remote_device_key_encrypt() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let remote_dev_key = DevKey::new_bytes([0x42_u8; 16]);
+        let provisioner_address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let node_address = UnicastAddress::new(0x0005).expect("valid unicast address");
+        let provisioner = StackInternals::new(test_helpers::device_state(
+            provisioner_address,
+            net_key_index,
+        ));
+        let mut node_state = test_helpers::device_state(node_address, net_key_index);
+        node_state.security_materials_mut().dev_key = remote_dev_key;
+        let node = StackInternals::new(node_state);
+        let node_receive = |keys: MessageKeys| {
+            let msg = OutgoingMessage::from_message_with_keys(
+                ElementIndex(0),
+                keys,
+                Address::Unicast(node_address),
+                &default_ttl::Get,
+            )
+            .ok()
+            .expect("get packs");
+            let outgoing = match provisioner.app_encrypt::<Box<[u8]>>(msg) {
+                Ok(outgoing) => outgoing,
+                Err(_) => panic!("device key message should encrypt"),
+            };
+            let unsegmented = match &outgoing.upper_pdu {
+                upper::PDU::Access(access) => access.as_unsegmented().expect("fits unsegmented"),
+                upper::PDU::Control(_) => panic!("expected an access pdu"),
+            };
+            node.app_decrypt(EncryptedIncomingMessage {
+                encrypted_app_payload: (&unsegmented).into(),
+                seq: outgoing.seq.start(),
+                seg_count: 0,
+                iv_index: outgoing.iv_index,
+                net_key_index,
+                dst: Address::Unicast(node_address),
+                src: provisioner_address,
+                ttl: outgoing.ttl,
+                rssi: None,
+            })
+        };
+        match node_receive(MessageKeys::RemoteDevice(net_key_index, remote_dev_key)) {
+            Ok(delivered) => {
+                assert!(default_ttl::Get::unpack_with_opcode(&delivered.payload[..]).is_ok())
+            }
+            Err(_) => panic!("node should decrypt with its own device key"),
+        }
+        // The provisioner's own device key isn't the node's.
+        assert!(node_receive(MessageKeys::Device(net_key_index)).is_err());
+    }
+    #[test]
+    pub fn test_config_message_to_provisioned_node() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let provisioner_address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let node_address = UnicastAddress::new(0x0005).expect("valid unicast address");
+        // Mock provisioning: both sides end up with the same device key.
+        let node_dev_key = DevKey::new_bytes([0x42_u8; 16]);
+        let mut provisioner_state = test_helpers::device_state(provisioner_address, net_key_index);
+        provisioner_state.nodes_mut().insert(
+            node_address,
+            NodeInfo {
+                element_count: ElementCount(2),
+                net_keys: vec![net_key_index],
+                dev_key: Some(node_dev_key),
+            },
+        );
+        let second_element = UnicastAddress::new(0x0006).expect("valid unicast address");
+        assert_eq!(
+            provisioner_state.nodes().device_key(second_element),
+            Some(&node_dev_key)
+        );
+        assert_eq!(
+            provisioner_state
+                .nodes()
+                .device_key(UnicastAddress::new(0x0007).expect("valid unicast address")),
+            None
+        );
+        let provisioner = StackInternals::new(provisioner_state);
+        let mut node_state = DeviceState::new(node_address, ElementCount(2));
+        node_state
+            .security_materials_mut()
+            .net_key_map
+            .insert(net_key_index, &test_helpers::net_key());
+        node_state.security_materials_mut().dev_key = node_dev_key;
+        let node = StackInternals::new(node_state);
+
+        let msg = OutgoingMessage::from_message_with_keys(
+            ElementIndex(0),
+            MessageKeys::Device(net_key_index),
+            Address::Unicast(node_address),
+            &default_ttl::Get,
+        )
+        .ok()
+        .expect("get packs");
+        let outgoing = match provisioner.app_encrypt::<Box<[u8]>>(msg) {
+            Ok(outgoing) => outgoing,
+            Err(_) => panic!("device key message should encrypt"),
+        };
+        let unsegmented = match &outgoing.upper_pdu {
+            upper::PDU::Access(access) => access.as_unsegmented().expect("fits unsegmented"),
+            upper::PDU::Control(_) => panic!("expected an access pdu"),
+        };
+        let delivered = node.app_decrypt(EncryptedIncomingMessage {
+            encrypted_app_payload: (&unsegmented).into(),
+            seq: outgoing.seq.start(),
+            seg_count: 0,
+            iv_index: outgoing.iv_index,
+            net_key_index,
+            dst: Address::Unicast(node_address),
+            src: provisioner_address,
+            ttl: outgoing.ttl,
+            rssi: None,
+        });
+        match delivered {
+            Ok(delivered) => {
+                assert!(default_ttl::Get::unpack_with_opcode(&delivered.payload[..]).is_ok())
+            }
+            Err(_) => panic!("node should decrypt with its device key"),
+        }
+    }
+    #[test]
+    pub fn test_friendship_credentials_selected_per_destination() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let friend = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let lpn = UnicastAddress::new(0x0002).expect("valid unicast address");
+        let other = UnicastAddress::new(0x0003).expect("valid unicast address");
+        let mut internals = StackInternals::new(test_helpers::device_state(lpn, net_key_index));
+        let friendship = Friendship {
+            net_key_index,
+            lpn,
+            friend,
+            lpn_counter: 0x0001,
+            friend_counter: 0x0002,
+        };
+        internals
+            .credential_selector_mut()
+            .add_friendship(friendship);
+        let net_sm = internals
+            .net_keys()
+            .get_keys(net_key_index)
+            .expect("net key inserted")
+            .tx_key();
+        let master_keys = *net_sm.network_keys();
+        let friendship_keys = friendship.network_keys(net_sm);
+        assert_ne!(master_keys, friendship_keys);
+        let iv_index = internals.device_state().iv_index();
+        let msg_to = |dst: UnicastAddress| OutgoingLowerTransportMessage {
+            pdu: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0x42_u8; 8])),
+            src: lpn,
+            dst: Address::Unicast(dst),
+            ttl: None,
+            seq: None,
+            iv_index,
+            net_key_index,
+            credentials: Credentials::Master,
+        };
+        let (pdu, keys) = internals
+            .lower_to_net(&msg_to(friend))
+            .expect("message to the friend lowers");
+        assert_eq!(keys, friendship_keys);
+        assert_eq!(pdu.header.nid, friendship_keys.nid());
+        let (pdu, keys) = internals
+            .lower_to_net(&msg_to(other))
+            .expect("message to another node lowers");
+        assert_eq!(keys, master_keys);
+        assert_eq!(pdu.header.nid, master_keys.nid());
+        // With the Credential Flag set, messages to other nodes go through the friend.
+        let mut flagged = msg_to(other);
+        flagged.credentials = Credentials::Friendship;
+        let (_, keys) = internals
+            .lower_to_net(&flagged)
+            .expect("flagged message lowers");
+        assert_eq!(keys, friendship_keys);
+    }
+    #[test]
+    pub fn test_friend_queue_pdu_decrypts_with_friendship_credentials() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let friend = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let lpn = UnicastAddress::new(0x0002).expect("valid unicast address");
+        let friendship = Friendship {
+            net_key_index,
+            lpn,
+            friend,
+            lpn_counter: 0x0001,
+            friend_counter: 0x0002,
+        };
+        let mut friend_node =
+            StackInternals::new(test_helpers::device_state(friend, net_key_index));
+        friend_node
+            .credential_selector_mut()
+            .add_friendship(friendship);
+        let iv_index = friend_node.device_state().iv_index();
+        let (pdu, keys) = friend_node
+            .lower_to_net(&OutgoingLowerTransportMessage {
+                pdu: lower::PDU::UnsegmentedAccess(UnsegmentedAccessPDU::new(None, &[0x42_u8; 8])),
+                src: friend,
+                dst: Address::Unicast(lpn),
+                ttl: None,
+                seq: None,
+                iv_index,
+                net_key_index,
+                credentials: Credentials::Master,
+            })
+            .expect("friend queue message lowers");
+        let encrypted = StackInternals::encrypt_with_keys(&pdu, &keys, iv_index)
+            .ok()
+            .expect("pdu fits in an advertisement");
+
+        let mut lpn_node = StackInternals::new(test_helpers::device_state(lpn, net_key_index));
+        // Without the friendship only the master credentials are tried.
+        assert!(lpn_node.decrypt_network_pdu(encrypted.as_ref()).is_none());
+        lpn_node
+            .credential_selector_mut()
+            .add_friendship(friendship);
+        let (index, _, decrypted) = lpn_node
+            .decrypt_network_pdu(encrypted.as_ref())
+            .expect("friendship credentials decrypt the pdu");
+        assert_eq!(index, net_key_index);
+        assert_eq!(decrypted.header.src, friend);
+        assert_eq!(decrypted.header.dst, Address::Unicast(lpn));
+    }
+}
@@ -1,45 +1,156 @@
 //! Outgoing PDU handler.
 use crate::asyncs::{
-    sync::{mpsc, Mutex, RwLock},
+    sync::{mpsc, Mutex, MutexGuard, RwLock},
     time,
 };
 use crate::device_state::SeqRange;
 use crate::mesh::{SequenceNumber, CTL};
 use crate::net::Header;
 use crate::stack::bearer::{OutgoingEncryptedNetworkPDU, OutgoingMessage};
-use crate::stack::messages::{OutgoingLowerTransportMessage, OutgoingUpperTransportMessage};
-use crate::stack::segments::{IncomingPDU, OutgoingSegments};
-use crate::stack::{segments, SendError, StackInternals};
-use crate::{control, net};
+use crate::stack::messages::{
+    IncomingMessage, OutgoingLowerTransportMessage, OutgoingUpperTransportMessage,
+};
+use crate::stack::segments::{IncomingPDU, OutgoingSegments, SegmentationConfig};
+use crate::stack::{messages, segments, SendError, StackInternals};
+use crate::{control, lower, net, upper};
+use alloc::boxed::Box;
 use alloc::sync::Arc;
-use core::time::Duration;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the unicast segmented transfers currently waiting for acks. `Incoming` only drops acks
+/// when nobody is waiting for them.
+#[derive(Default)]
+pub struct AckWaiters(AtomicUsize);
+impl AckWaiters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns `true` if a transfer is reading acks.
+    pub fn is_waiting(&self) -> bool {
+        self.0.load(Ordering::Acquire) > 0
+    }
+    pub(crate) fn start(&self) {
+        self.0.fetch_add(1, Ordering::AcqRel);
+    }
+    pub(crate) fn finish(&self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+/// Holds the ack receiver for a unicast segmented transfer and counts it as waiting until dropped.
+struct AckWait<'a> {
+    waiters: &'a AckWaiters,
+    ack_rx: MutexGuard<'a, mpsc::Receiver<IncomingPDU<control::Ack>>>,
+}
+impl<'a> AckWait<'a> {
+    fn new(
+        waiters: &'a AckWaiters,
+        ack_rx: MutexGuard<'a, mpsc::Receiver<IncomingPDU<control::Ack>>>,
+    ) -> Self {
+        waiters.start();
+        Self { waiters, ack_rx }
+    }
+}
+impl<'a> Drop for AckWait<'a> {
+    fn drop(&mut self) {
+        self.waiters.finish();
+        // `Incoming` may be waiting to send an ack that arrived after the transfer ended so drain
+        // the channel to let it move on.
+        while self.ack_rx.try_recv().is_ok() {}
+    }
+}
 
 pub struct Outgoing {
     pub outgoing_network: Mutex<mpsc::Sender<OutgoingMessage>>,
     pub internals: Arc<RwLock<StackInternals>>,
     pub ack_rx: Mutex<mpsc::Receiver<IncomingPDU<control::Ack>>>,
+    pub ack_waiters: Arc<AckWaiters>,
+    pub segmentation: SegmentationConfig,
 }
-pub const SEND_TIMEOUT_SECS: u64 = 10;
 impl Outgoing {
     pub fn new(
         internals: Arc<RwLock<StackInternals>>,
         ack_rx: mpsc::Receiver<IncomingPDU<control::Ack>>,
         outgoing: mpsc::Sender<OutgoingMessage>,
+        segmentation: SegmentationConfig,
     ) -> Self {
         Self {
             outgoing_network: Mutex::new(outgoing),
             internals,
             ack_rx: Mutex::new(ack_rx),
+            ack_waiters: Arc::new(AckWaiters::new()),
+            segmentation,
         }
     }
-    pub async fn send_upper_transport<Storage: AsRef<[u8]>>(
+    /// Encrypts `msg` and sends it. Messages for this node (see
+    /// `StackInternals::is_local_destination`) are decrypted again and delivered to `loopback`
+    /// without going over the air. Messages sent to a local group are sent over the air as well
+    /// because other nodes may be listening to the group. Returns
+    /// `SendError::LoopbackDecryptError` if a local message can't be decrypted again.
+    pub async fn send_access(
         &self,
-        _msg: OutgoingUpperTransportMessage<Storage>,
+        msg: messages::OutgoingMessage<Box<[u8]>>,
+        loopback: &mut mpsc::Sender<IncomingMessage<Box<[u8]>>>,
     ) -> Result<(), SendError> {
-        todo!("implement sending upper transport PDU")
+        let internals = self.internals.read().await;
+        let upper = internals.app_encrypt(msg).map_err(|(e, _)| e)?;
+        let is_local = internals.is_local_destination(&upper.dst);
+        let looped_back = if is_local {
+            internals
+                .loopback(&upper)
+                .transpose()
+                .map_err(|_| SendError::LoopbackDecryptError)?
+        } else {
+            None
+        };
+        // Release the lock on StackInternals.
+        drop(internals);
+        if let Some(incoming) = looped_back {
+            loopback
+                .send(incoming)
+                .await
+                .ok()
+                .ok_or(SendError::ChannelClosed)?;
+        }
+        if is_local && upper.dst.is_unicast() {
+            return Ok(());
+        }
+        self.send_upper_transport(upper).await
     }
-    pub fn send_timeout(&self) -> Duration {
-        Duration::from_secs(SEND_TIMEOUT_SECS)
+    /// Sends `msg` as an unsegmented PDU if it fits in one or as segments otherwise.
+    pub async fn send_upper_transport<Storage: AsRef<[u8]>>(
+        &self,
+        msg: OutgoingUpperTransportMessage<Storage>,
+    ) -> Result<(), SendError> {
+        let unsegmented = match &msg.upper_pdu {
+            upper::PDU::Access(access) => {
+                access.as_unsegmented().map(lower::PDU::UnsegmentedAccess)
+            }
+            upper::PDU::Control(control)
+                if control.payload.as_ref().len()
+                    <= lower::UnsegmentedControlPDU::max_parameters_size() =>
+            {
+                Some(lower::PDU::UnsegmentedControl(
+                    lower::UnsegmentedControlPDU::new(control.opcode, control.payload.as_ref()),
+                ))
+            }
+            upper::PDU::Control(_) => None,
+        };
+        match unsegmented {
+            Some(pdu) => {
+                self.send_unsegmented(OutgoingLowerTransportMessage {
+                    pdu,
+                    src: msg.src,
+                    dst: msg.dst,
+                    ttl: msg.ttl,
+                    seq: Some(msg.seq.start()),
+                    iv_index: msg.iv_index,
+                    net_key_index: msg.net_key_index,
+                    credentials: msg.credentials,
+                })
+                .await
+            }
+            None => self.send_segments(msg.into_outgoing_segments()).await,
+        }
     }
     pub async fn next_ack<Storage: AsRef<[u8]>>(
         segments: &OutgoingSegments<Storage>,
@@ -70,79 +181,281 @@ impl Outgoing {
         msg: OutgoingLowerTransportMessage,
     ) -> Result<(), SendError> {
         let internals = self.internals.read().await;
-        let (pdu, net_sm) = internals.lower_to_net(&msg)?;
+        let (pdu, net_keys) = internals.lower_to_net(&msg)?;
         let transmit_parameters = internals.device_state.config_states().network_transmit;
         // Release the lock on StackInternals.
         self.send_encrypted_network_pdu(OutgoingEncryptedNetworkPDU {
             transmit_parameters,
-            pdu: pdu
-                .encrypt(net_sm.network_keys(), msg.iv_index)
-                .map_err(|_| SendError::NetEncryptError)?,
+            pdu: StackInternals::encrypt_with_keys(&pdu, &net_keys, msg.iv_index)?,
         })
         .await
     }
+    /// Sends the segments of `msg`. Segments sent to a unicast address are resent (with fresh
+    /// sequence numbers) every time the segment transmission timer expires until they're all
+    /// acked or `max_retransmits` timers expire without progress (`SendError::AckTimeout`).
+    /// Group and virtual addresses never ack so their segments are just sent
+    /// `max_retransmits + 1` times.
     pub async fn send_segments<Storage: AsRef<[u8]>>(
         &self,
-        msg: segments::OutgoingSegments<Storage>,
+        mut msg: segments::OutgoingSegments<Storage>,
     ) -> Result<(), SendError> {
-        //todo check element_index (src address?)
         //todo Lock out SeqCounter
         let seq = msg.segments.seq_auth().first_seq;
-        let internals = self.internals.read().await;
         let iv_index = msg.segments.seq_auth().iv_index;
-        if !internals.is_valid_iv_index(iv_index) {
-            return Err(SendError::InvalidIVIndex);
-        }
+        let (net_keys, transmit_parameters, ttl, element_index) = {
+            let internals = self.internals.read().await;
+            if !internals.is_valid_iv_index(iv_index) {
+                return Err(SendError::InvalidIVIndex);
+            }
+            let net_keys =
+                internals.tx_network_keys(msg.net_key_index, msg.src, msg.dst, msg.credentials)?;
+            let element_index = internals
+                .device_state()
+                .element_index(msg.src)
+                .ok_or(SendError::InvalidSourceElement)?;
+            (
+                net_keys,
+                internals.device_state().config_states().network_transmit,
+                msg.ttl.unwrap_or_else(|| internals.default_ttl()),
+                element_index,
+            )
+        };
+        // The lock on StackInternals is released so it isn't held while waiting for acks.
         let ivi = iv_index.ivi();
-        let net_sm = internals
-            .net_keys()
-            .get_keys(msg.net_key_index)
-            .ok_or(SendError::InvalidNetKeyIndex)?
-            .tx_key();
-        let nid = net_sm.network_keys().nid();
+        let nid = net_keys.nid();
         let ctl = CTL(msg.segments.upper_pdu.is_control());
-        let transmit_parameters = internals.device_state().config_states().network_transmit;
-        let ttl = msg.ttl.unwrap_or_else(|| internals.default_ttl());
-        let mut ack_rx = self.ack_rx.lock().await;
+        let (src, dst) = (msg.src, msg.dst);
         let make_net_header = |seq: SequenceNumber| Header {
             ivi,
             nid,
             ctl,
             ttl,
             seq,
-            src: msg.src,
-            dst: msg.dst,
+            src,
+            dst,
         };
-        // Immediately send out the PDUs with the acquired seq range.
-        for (seg, seq) in msg
-            .segments
-            .iter(msg.block_ack)
-            .zip(SeqRange::new_segs(seq, msg.segments.seg_o()))
-        {
-            self.send_encrypted_network_pdu(OutgoingEncryptedNetworkPDU {
-                transmit_parameters,
-                pdu: net::PDU {
-                    header: make_net_header(seq),
-                    payload: seg.into(),
+        let mut ack_wait = if dst.is_unicast() {
+            Some(AckWait::new(&self.ack_waiters, self.ack_rx.lock().await))
+        } else {
+            None
+        };
+        let segment_timeout = self.segmentation.segment_timeout(ttl);
+        let mut seqs = SeqRange::new_segs(seq, msg.segments.seg_o());
+        let mut retransmits_left = self.segmentation.max_retransmits;
+        loop {
+            for (seg, seq) in msg.segments.iter(msg.block_ack).zip(&mut seqs) {
+                self.send_encrypted_network_pdu(OutgoingEncryptedNetworkPDU {
+                    transmit_parameters,
+                    pdu: StackInternals::encrypt_with_keys(
+                        &net::PDU {
+                            header: make_net_header(seq),
+                            payload: seg.into(),
+                        },
+                        &net_keys,
+                        iv_index,
+                    )?,
+                })
+                .await?;
+            }
+            match ack_wait.as_mut() {
+                None if retransmits_left == 0 => return Ok(()),
+                None => {
+                    // Nobody acks group or virtual segments so just resend all of them every
+                    // segment transmission interval.
+                    retransmits_left -= 1;
+                    time::sleep(segment_timeout).await;
                 }
-                .encrypt(net_sm.network_keys(), iv_index)
-                .map_err(|_| SendError::NetEncryptError)?,
-            })
-            .await?;
+                Some(AckWait { ack_rx, .. }) => {
+                    // Wait for the segment transmission timer to expire or an ack acknowledging
+                    // more segments to come in.
+                    let new_ack =
+                        time::timeout(segment_timeout, Self::next_ack(&msg, &mut *ack_rx))
+                            .await
+                            .ok();
+                    match new_ack {
+                        Some(ack) => {
+                            msg.block_ack = ack?.pdu.block_ack;
+                            if msg.block_ack.all_acked(msg.segments.seg_o()) {
+                                return Ok(());
+                            }
+                            // The receiver is making progress so restart the retransmit count.
+                            retransmits_left = self.segmentation.max_retransmits;
+                        }
+                        None if retransmits_left == 0 => return Err(SendError::AckTimeout),
+                        None => retransmits_left -= 1,
+                    }
+                }
+            }
+            // Retransmit the unacked segments with fresh sequence numbers.
+            let seg_left = u32::from(msg.block_ack.seg_left(msg.segments.seg_o())) + 1;
+            seqs = self
+                .internals
+                .read()
+                .await
+                .seq_counter(element_index)
+                .inc_seq(seg_left)
+                .ok_or(SendError::OutOfSeq)?;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{Address, GroupAddress, UnicastAddress};
+    use crate::control::{ControlOpcode, ControlPayload};
+    use crate::friend::Credentials;
+    use crate::lower::{BlockAck, SegO, SeqAuth};
+    use crate::mesh::AppKeyIndex;
+    use crate::mesh::{ElementIndex, KeyIndex, NetKeyIndex, TTL};
+    use crate::models::generics::onoff;
+    use crate::models::PackableMessage;
+    use crate::segmenter::UpperSegmenter;
+    use crate::stack::test_helpers;
+    use core::time::Duration;
+    use driver_async::time::{Instant, InstantTrait};
+
+    #[tokio::test]
+    async fn test_unacked_segments_time_out() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let src = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let internals = StackInternals::new(test_helpers::device_state(src, net_key_index));
+        let iv_index = internals.device_state().iv_index();
+        let first_seq = internals
+            .seq_counter(ElementIndex(0))
+            .inc_seq(2)
+            .expect("fresh seq counter")
+            .start();
+        let (_ack_tx, ack_rx) = mpsc::channel(2);
+        let (network_tx, mut network_rx) = mpsc::channel(16);
+        let config = SegmentationConfig::new(2, Duration::from_millis(10));
+        let internals = Arc::new(RwLock::new(internals));
+        let outgoing = Outgoing::new(internals.clone(), ack_rx, network_tx, config);
+        let segments = OutgoingSegments {
+            segments: UpperSegmenter {
+                upper_pdu: upper::PDU::Control(ControlPayload {
+                    opcode: ControlOpcode::FriendPoll,
+                    payload: [0x42_u8; 16],
+                }),
+                seg_o: SegO::new(1),
+                seq_auth: SeqAuth::new(first_seq, iv_index),
+            },
+            block_ack: BlockAck::ZERO,
+            net_key_index,
+            src,
+            dst: Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            ttl: Some(TTL::new(0)),
+            credentials: Credentials::Master,
+        };
+        // StackInternals isn't locked while waiting for acks.
+        let (sent, write_locked) = tokio::join!(outgoing.send_segments(segments), async {
+            time::timeout(Duration::from_millis(5), internals.write())
+                .await
+                .is_ok()
+        });
+        assert_eq!(sent, Err(SendError::AckTimeout));
+        assert!(write_locked);
+        drop(outgoing);
+        let mut sent = 0;
+        while network_rx.recv().await.is_some() {
+            sent += 1;
         }
-        time::timeout(self.send_timeout(), async {
-            loop {
-                let _first_ack = Self::next_ack(&msg, &mut ack_rx).await?;
+        // Both segments get sent once and then retransmitted `max_retransmits` times.
+        assert_eq!(sent, 2 * (1 + usize::from(config.max_retransmits)));
+    }
 
-                // Check for a valid ack
-                todo!()
+    #[tokio::test]
+    async fn test_group_segments_spaced_by_retransmit_interval() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let src = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let internals = StackInternals::new(test_helpers::device_state(src, net_key_index));
+        let iv_index = internals.device_state().iv_index();
+        let first_seq = internals
+            .seq_counter(ElementIndex(0))
+            .inc_seq(2)
+            .expect("fresh seq counter")
+            .start();
+        let (_ack_tx, ack_rx) = mpsc::channel(2);
+        let (network_tx, mut network_rx) = mpsc::channel(16);
+        let interval = Duration::from_millis(20);
+        let config = SegmentationConfig::new(2, interval);
+        let outgoing = Outgoing::new(Arc::new(RwLock::new(internals)), ack_rx, network_tx, config);
+        let segments = OutgoingSegments {
+            segments: UpperSegmenter {
+                upper_pdu: upper::PDU::Control(ControlPayload {
+                    opcode: ControlOpcode::FriendPoll,
+                    payload: [0x42_u8; 16],
+                }),
+                seg_o: SegO::new(1),
+                seq_auth: SeqAuth::new(first_seq, iv_index),
+            },
+            block_ack: BlockAck::ZERO,
+            net_key_index,
+            src,
+            dst: Address::Group(GroupAddress::new(0xC001).expect("valid group address")),
+            ttl: Some(TTL::new(0)),
+            credentials: Credentials::Master,
+        };
+        // Both segments get sent once and then again `max_retransmits` times.
+        let rounds = 1 + usize::from(config.max_retransmits);
+        let receive = async {
+            let mut sent_at = Vec::new();
+            for _ in 0..2 * rounds {
+                network_rx.recv().await.expect("segment sent");
+                sent_at.push(Instant::now());
             }
-            // Allow unreachable_code so we can annotate the async result type.
-            #[allow(unreachable_code)]
-            Ok::<(), SendError>(())
-        })
-        .await
-        .ok()
-        .ok_or(SendError::AckTimeout)?
+            sent_at
+        };
+        let (result, sent_at) = tokio::join!(
+            time::timeout(Duration::from_secs(1), outgoing.send_segments(segments)),
+            receive
+        );
+        // No acks are waited for.
+        assert_eq!(result.ok(), Some(Ok(())));
+        // Each round is sent one segment transmission interval after the previous one.
+        for round in 1..rounds {
+            let gap = sent_at[2 * round]
+                .checked_duration_since(sent_at[2 * round - 1])
+                .expect("sent in order");
+            assert!(gap >= interval, "rounds sent back to back ({:?})", gap);
+        }
+        drop(outgoing);
+        assert!(network_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_to_own_address_loops_back() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let app_key_index = AppKeyIndex(KeyIndex::new(0));
+        let primary = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let internals = StackInternals::new(test_helpers::device_state_with_app_key(
+            primary,
+            net_key_index,
+            app_key_index,
+        ));
+        let (_ack_tx, ack_rx) = mpsc::channel(1);
+        let (network_tx, mut network_rx) = mpsc::channel(4);
+        let outgoing = Outgoing::new(
+            Arc::new(RwLock::new(internals)),
+            ack_rx,
+            network_tx,
+            SegmentationConfig::default(),
+        );
+        let (mut loopback_tx, mut loopback_rx) = mpsc::channel(1);
+        let msg = messages::OutgoingMessage::from_model_message(
+            ElementIndex(0),
+            app_key_index,
+            Address::Unicast(primary),
+            &onoff::Get,
+        )
+        .expect("get packs");
+        assert_eq!(outgoing.send_access(msg, &mut loopback_tx).await, Ok(()));
+        let incoming = loopback_rx.recv().await.expect("looped back message");
+        assert_eq!(incoming.src, primary);
+        assert_eq!(incoming.dst, Address::Unicast(primary));
+        assert_eq!(incoming.app_key_index, Some(app_key_index));
+        assert!(onoff::Get::unpack_with_opcode(incoming.payload.as_ref()).is_ok());
+        // Nothing went over the air.
+        drop(outgoing);
+        assert!(network_rx.recv().await.is_none());
     }
 }
@@ -0,0 +1,228 @@
+//! Proxy Client connection state. A GATT Proxy Server forgets the proxy filter every time the
+//! GATT connection drops so `ProxyConnection` remembers the filter the client configured and
+//! replays it after a reconnect.
+use crate::address::{Address, UnicastAddress};
+use crate::mesh::NetKeyIndex;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use btle::PackError;
+
+/// Proxy filter type. A new connection always starts with an empty white list.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum FilterType {
+    /// Only forward messages sent to addresses in the filter.
+    WhiteList = 0x00,
+    /// Forward every message except the ones sent to addresses in the filter.
+    BlackList = 0x01,
+}
+impl Default for FilterType {
+    fn default() -> Self {
+        FilterType::WhiteList
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum ProxyConfigOpcode {
+    SetFilterType = 0x00,
+    AddAddresses = 0x01,
+    RemoveAddresses = 0x02,
+    FilterStatus = 0x03,
+}
+/// Proxy Configuration message sent by the Proxy Client to configure the server's filter.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum ProxyConfigMessage {
+    SetFilterType(FilterType),
+    AddAddresses(Vec<Address>),
+    RemoveAddresses(Vec<Address>),
+}
+impl ProxyConfigMessage {
+    pub fn opcode(&self) -> ProxyConfigOpcode {
+        match self {
+            ProxyConfigMessage::SetFilterType(_) => ProxyConfigOpcode::SetFilterType,
+            ProxyConfigMessage::AddAddresses(_) => ProxyConfigOpcode::AddAddresses,
+            ProxyConfigMessage::RemoveAddresses(_) => ProxyConfigOpcode::RemoveAddresses,
+        }
+    }
+    /// Length in bytes including the opcode.
+    pub fn byte_len(&self) -> usize {
+        1 + match self {
+            ProxyConfigMessage::SetFilterType(_) => 1,
+            ProxyConfigMessage::AddAddresses(addresses)
+            | ProxyConfigMessage::RemoveAddresses(addresses) => addresses.len() * 2,
+        }
+    }
+    /// Packs the opcode and parameters (addresses are big endian) into `buf`.
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self.opcode() as u8;
+        match self {
+            ProxyConfigMessage::SetFilterType(filter_type) => buf[1] = *filter_type as u8,
+            ProxyConfigMessage::AddAddresses(addresses)
+            | ProxyConfigMessage::RemoveAddresses(addresses) => {
+                for (address, out) in addresses.iter().zip(buf[1..].chunks_exact_mut(2)) {
+                    out.copy_from_slice(&u16::from(address).to_be_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+/// Proxy filter the client wants the Proxy Server to use.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ProxyFilter {
+    pub filter_type: FilterType,
+    pub addresses: BTreeSet<Address>,
+}
+impl ProxyFilter {
+    /// Returns if the filter is the one every new connection starts with (empty white list).
+    pub fn is_default(&self) -> bool {
+        self.filter_type == FilterType::WhiteList && self.addresses.is_empty()
+    }
+    /// Messages that configure a freshly connected Proxy Server with this filter.
+    pub fn config_messages(&self) -> Vec<ProxyConfigMessage> {
+        let mut messages = Vec::new();
+        if self.filter_type != FilterType::default() {
+            messages.push(ProxyConfigMessage::SetFilterType(self.filter_type));
+        }
+        if !self.addresses.is_empty() {
+            messages.push(ProxyConfigMessage::AddAddresses(
+                self.addresses.iter().copied().collect(),
+            ));
+        }
+        messages
+    }
+}
+/// Proxy Client's connection to a Proxy Server node. Filter changes are recorded even while
+/// disconnected and every method returns the `ProxyConfigMessage`s that have to be sent to the
+/// server now (none while disconnected).
+#[derive(Clone, Debug)]
+pub struct ProxyConnection {
+    node: UnicastAddress,
+    net_key_index: NetKeyIndex,
+    filter: ProxyFilter,
+    connected: bool,
+}
+impl ProxyConnection {
+    /// New (disconnected) connection to the Proxy Server `node` on the `net_key_index` subnet.
+    pub fn new(node: UnicastAddress, net_key_index: NetKeyIndex) -> Self {
+        Self {
+            node,
+            net_key_index,
+            filter: ProxyFilter::default(),
+            connected: false,
+        }
+    }
+    /// Primary address of the Proxy Server node.
+    pub fn node(&self) -> UnicastAddress {
+        self.node
+    }
+    /// Subnet the connection is using.
+    pub fn net_key_index(&self) -> NetKeyIndex {
+        self.net_key_index
+    }
+    pub fn filter(&self) -> &ProxyFilter {
+        &self.filter
+    }
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+    /// Called when the GATT connection is (re)established. The server starts with an empty white
+    /// list so the returned messages reapply the whole filter.
+    pub fn connected(&mut self) -> Vec<ProxyConfigMessage> {
+        self.connected = true;
+        self.filter.config_messages()
+    }
+    /// Called when the GATT connection drops. The filter is kept for the next connection.
+    pub fn disconnected(&mut self) {
+        self.connected = false;
+    }
+    /// Switches to another subnet. The server forgets the filter when the client reconnects so
+    /// this is only expected to change while disconnected.
+    pub fn set_net_key_index(&mut self, net_key_index: NetKeyIndex) {
+        self.net_key_index = net_key_index;
+    }
+    fn if_connected(&self, message: ProxyConfigMessage) -> Option<ProxyConfigMessage> {
+        if self.connected {
+            Some(message)
+        } else {
+            None
+        }
+    }
+    /// Sets the filter type. Like the server, this clears the filter's addresses.
+    pub fn set_filter_type(&mut self, filter_type: FilterType) -> Option<ProxyConfigMessage> {
+        self.filter.filter_type = filter_type;
+        self.filter.addresses.clear();
+        self.if_connected(ProxyConfigMessage::SetFilterType(filter_type))
+    }
+    /// Adds `addresses` to the filter. Only the addresses not already in the filter are sent.
+    pub fn add_addresses(
+        &mut self,
+        addresses: impl IntoIterator<Item = Address>,
+    ) -> Option<ProxyConfigMessage> {
+        let filter = &mut self.filter;
+        let added: Vec<Address> = addresses
+            .into_iter()
+            .filter(|&address| filter.addresses.insert(address))
+            .collect();
+        if added.is_empty() {
+            None
+        } else {
+            self.if_connected(ProxyConfigMessage::AddAddresses(added))
+        }
+    }
+    /// Removes `addresses` from the filter. Only the addresses that were in the filter are sent.
+    pub fn remove_addresses(
+        &mut self,
+        addresses: impl IntoIterator<Item = Address>,
+    ) -> Option<ProxyConfigMessage> {
+        let filter = &mut self.filter;
+        let removed: Vec<Address> = addresses
+            .into_iter()
+            .filter(|address| filter.addresses.remove(address))
+            .collect();
+        if removed.is_empty() {
+            None
+        } else {
+            self.if_connected(ProxyConfigMessage::RemoveAddresses(removed))
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::GroupAddress;
+    use crate::mesh::KeyIndex;
+
+    #[test]
+    fn test_filter_reapplied_after_reconnect() {
+        let node = UnicastAddress::new(0x0010).expect("valid unicast address");
+        let group = Address::Group(GroupAddress::new(0xC001).expect("valid group address"));
+        let unicast = Address::Unicast(UnicastAddress::new(0x0002).expect("valid address"));
+        let mut connection = ProxyConnection::new(node, NetKeyIndex(KeyIndex::new(0)));
+        // Nothing to reapply to a server with the default filter.
+        assert_eq!(connection.connected(), Vec::new());
+        assert_eq!(
+            connection.set_filter_type(FilterType::BlackList),
+            Some(ProxyConfigMessage::SetFilterType(FilterType::BlackList))
+        );
+        assert_eq!(
+            connection.add_addresses(vec![group, unicast]),
+            Some(ProxyConfigMessage::AddAddresses(vec![group, unicast]))
+        );
+        connection.disconnected();
+        assert!(!connection.is_connected());
+        // Changes while disconnected are only recorded.
+        assert_eq!(connection.remove_addresses(vec![unicast]), None);
+        let reapply = connection.connected();
+        assert_eq!(
+            reapply,
+            vec![
+                ProxyConfigMessage::SetFilterType(FilterType::BlackList),
+                ProxyConfigMessage::AddAddresses(vec![group]),
+            ]
+        );
+        let mut buf = [0_u8; 3];
+        reapply[1].pack_into(&mut buf).expect("buffer fits");
+        assert_eq!(buf, [0x01, 0xC0, 0x01]);
+        assert_eq!(connection.node(), node);
+    }
+}
@@ -0,0 +1,82 @@
+//! Periodic model publication. Keeps a `PublishTimer` for every model with a periodic
+//! publication and builds the publish messages once their period elapses. The publications
+//! themselves live in `device_state::Models`.
+use crate::access::ModelIdentifier;
+use crate::device_state::Models;
+use crate::foundation::publication::{ModelPublishInfo, PublishTimer};
+use crate::mesh::ElementIndex;
+use crate::stack::messages::OutgoingMessage;
+use crate::upper::AppPayload;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use driver_async::time::{Instant, InstantTrait};
+
+/// Publication timers of every model on the node keyed by `(element, model)`.
+#[derive(Default)]
+pub struct PublishScheduler {
+    timers: BTreeMap<(ElementIndex, ModelIdentifier), PublishTimer>,
+}
+impl PublishScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Restarts the timer of `model` on `element_index` after its publication changed (see
+    /// `FullStack::set_publication`). `None` or an `Unassigned` publish address removes it.
+    pub fn set_publication(
+        &mut self,
+        element_index: ElementIndex,
+        model: ModelIdentifier,
+        publication: Option<&ModelPublishInfo>,
+        now: Instant,
+    ) {
+        match publication.filter(|publication| publication.is_enabled()) {
+            Some(publication) => {
+                let mut timer = PublishTimer::new();
+                timer.set_publication(Some(publication), now);
+                self.timers.insert((element_index, model), timer);
+            }
+            None => {
+                self.timers.remove(&(element_index, model));
+            }
+        }
+    }
+    /// When the next periodic publication is due. `None` if no model publishes periodically.
+    pub fn next_publish(&self) -> Option<Instant> {
+        self.timers
+            .values()
+            .filter_map(PublishTimer::next_publish)
+            .fold(None, |earliest, next| match earliest {
+                Some(earliest) if next.checked_duration_since(earliest).is_some() => Some(earliest),
+                _ => Some(next),
+            })
+    }
+    /// Returns the messages to publish at `now`. `status` packs the current status message
+    /// (opcode + parameters) of the model or returns `None` to skip this period. Each message is
+    /// sent to the publish address with the publish AppKey, TTL and credentials from `models`.
+    pub fn poll(
+        &mut self,
+        models: &Models,
+        now: Instant,
+        mut status: impl FnMut(ElementIndex, ModelIdentifier) -> Option<AppPayload<Box<[u8]>>>,
+    ) -> Vec<OutgoingMessage<Box<[u8]>>> {
+        let mut out = Vec::new();
+        for (&(element_index, model), timer) in self.timers.iter_mut() {
+            if !timer.poll(now) {
+                continue;
+            }
+            let publication = match models.publication(element_index, model) {
+                Some(publication) => publication,
+                None => continue,
+            };
+            if let Some(payload) = status(element_index, model) {
+                out.push(OutgoingMessage::from_publication_payload(
+                    element_index,
+                    publication,
+                    payload,
+                ));
+            }
+        }
+        out
+    }
+}
@@ -2,7 +2,9 @@
 use crate::address::{Address, UnicastAddress};
 use crate::asyncs::{sync::mpsc, task, time};
 use crate::control::ControlMessage;
-use crate::lower::{BlockAck, SegmentedPDU, SeqAuth, SeqZero};
+use crate::crypto::aes::MicSize;
+use crate::friend::Credentials;
+use crate::lower::{BlockAck, SegN, SegO, SegmentedPDU, SeqAuth, SeqZero};
 use crate::mesh::{IVIndex, NetKeyIndex, SequenceNumber, TTL};
 use crate::reassembler;
 use crate::reassembler::LowerHeader;
@@ -15,6 +17,7 @@ use alloc::collections::btree_map::Entry;
 use alloc::collections::BTreeMap;
 use core::convert::{TryFrom, TryInto};
 use core::fmt::{Debug, Error, Formatter};
+use driver_async::time::{Instant, InstantTrait};
 
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
 pub struct SegmentsConversionError(());
@@ -34,6 +37,7 @@ pub struct OutgoingSegments<Storage: AsRef<[u8]>> {
     pub src: UnicastAddress,
     pub dst: Address,
     pub ttl: Option<TTL>,
+    pub credentials: Credentials,
 }
 impl<Storage: AsRef<[u8]>> OutgoingSegments<Storage> {
     pub fn is_new_ack(&self, ack: IncomingPDU<control::Ack>) -> Result<bool, AckError> {
@@ -65,6 +69,7 @@ impl<Storage: AsRef<[u8]>> OutgoingSegments<Storage> {
             seq,
             iv_index: self.segments.seq_auth().iv_index,
             net_key_index: self.net_key_index,
+            credentials: self.credentials,
         }
     }
 }
@@ -84,12 +89,19 @@ impl IncomingSegments {
                 SegmentedPDU::Access(a) => LowerHeader::AID(a.aid()),
                 SegmentedPDU::Control(c) => LowerHeader::ControlOpcode(c.opcode()),
             };
+            // Only access segments have SZMIC. It picks the TransMIC size (4 or 8 bytes) trailing
+            // the reassembled upper transport PDU.
+            let szmic = first_seg.pdu.szmic().unwrap_or(false);
+            let mut context = reassembler::Context::new(reassembler::ContextHeader::new(
+                lower_header,
+                seg_header.seg_o,
+                szmic,
+            ));
+            context
+                .insert_data(seg_header.seg_n, first_seg.pdu.seg_data())
+                .ok()?;
             Some(IncomingSegments {
-                context: reassembler::Context::new(reassembler::ContextHeader::new(
-                    lower_header,
-                    seg_header.seg_o,
-                    first_seg.pdu.szmic().unwrap_or(false),
-                )),
+                context,
                 segs_src: first_seg.src,
                 segs_dst: first_seg.dst,
                 seq_auth: SeqAuth::from_seq_zero(
@@ -118,6 +130,10 @@ impl IncomingSegments {
     pub fn is_access(&self) -> bool {
         self.context.header().lower_header().is_access()
     }
+    /// TransMIC size from the first segment's SZMIC. `None` for control messages.
+    pub fn mic_size(&self) -> Option<MicSize> {
+        self.context.mic_size()
+    }
     pub fn is_ready(&self) -> bool {
         self.context.is_ready()
     }
@@ -125,13 +141,52 @@ impl IncomingSegments {
     pub fn seq_auth(&self) -> SeqAuth {
         self.seq_auth
     }
+    /// Adds another segment of the message. Segments with a sequence number outside of the
+    /// `SeqAuth` window return `ReassemblyError::Canceled`.
+    pub fn insert_segment(
+        &mut self,
+        seg: &IncomingPDU<lower::SegmentedPDU>,
+    ) -> Result<(), ReassemblyError> {
+        if !self.seq_auth.valid_seq(seg.seq) {
+            return Err(ReassemblyError::Canceled);
+        }
+        self.context
+            .insert_data(seg.pdu.segment_header().seg_n, seg.pdu.seg_data())
+            .map_err(ReassemblyError::Reassemble)
+    }
+    /// Segment Acknowledgment sent from the destination element back to the source. `None` if the
+    /// segments weren't sent to a unicast address because those aren't acked.
+    pub fn ack_message(&self, block_ack: BlockAck) -> Option<OutgoingLowerTransportMessage> {
+        let src = match self.segs_dst {
+            Address::Unicast(unicast) => unicast,
+            _ => return None,
+        };
+        Some(OutgoingLowerTransportMessage {
+            pdu: lower::PDU::UnsegmentedControl(
+                control::Ack {
+                    obo: false,
+                    seq_zero: self.seq_auth.first_seq.into(),
+                    block_ack,
+                }
+                .try_to_unseg()
+                .expect("correctly formatted PDU"),
+            ),
+            src,
+            dst: Address::Unicast(self.segs_src),
+            ttl: self.ack_ttl,
+            seq: None,
+            iv_index: self.seq_auth.iv_index,
+            net_key_index: self.net_key_index,
+            credentials: Credentials::Master,
+        })
+    }
     pub fn finish(self) -> Result<IncomingTransportPDU<Box<[u8]>>, Self> {
         if self.is_ready() {
             let seq_auth = self.seq_auth();
             Ok(IncomingTransportPDU {
+                seg_count: u8::from(self.context.header().seg_o()) + 1,
                 upper_pdu: self.context.finish().expect("context is ensured ready"),
                 iv_index: seq_auth.iv_index,
-                seg_count: 0,
                 seq: seq_auth.first_seq,
                 net_key_index: self.net_key_index,
                 ttl: None,
@@ -190,7 +245,7 @@ impl TryFrom<&IncomingNetworkPDU> for SegmentEvent {
         if let Ok(lower) = IncomingPDU::try_from(pdu) {
             Ok(SegmentEvent::IncomingSegment(lower))
         } else {
-            Ok(SegmentEvent::IncomingSegment(pdu.try_into()?))
+            Ok(SegmentEvent::IncomingAck(pdu.try_into()?))
         }
     }
 }
@@ -274,20 +329,129 @@ impl<Storage: AsRef<[u8]> + AsMut<[u8]> + Send + 'static> Segments<Storage> {
             src: pdu.src,
             dst: pdu.dst,
             ttl: pdu.ttl,
+            credentials: pdu.credentials,
         };
         todo!()
     }
 }
 
+/// Retransmission parameters for outgoing segmented messages.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SegmentationConfig {
+    /// How many times unacked segments are resent before giving up with `SendError::AckTimeout`.
+    pub max_retransmits: u8,
+    /// Base segment transmission timer interval. `50ms * TTL` is added on top of it per message.
+    pub retransmit_interval: time::Duration,
+}
+impl SegmentationConfig {
+    /// Segments are retransmitted at least twice as recommended by the Mesh Spec.
+    pub const DEFAULT_MAX_RETRANSMITS: u8 = 2;
+    /// Minimum segment transmission timer interval (`200ms + 50ms * TTL`) from the Mesh Spec.
+    pub const DEFAULT_RETRANSMIT_INTERVAL: time::Duration = time::Duration::from_millis(200);
+    pub const fn new(max_retransmits: u8, retransmit_interval: time::Duration) -> Self {
+        Self {
+            max_retransmits,
+            retransmit_interval,
+        }
+    }
+    /// Segment transmission timer interval for segments sent with `ttl`.
+    pub fn segment_timeout(&self, ttl: TTL) -> time::Duration {
+        self.retransmit_interval + time::Duration::from_millis(50 * u64::from(u8::from(ttl)))
+    }
+}
+impl Default for SegmentationConfig {
+    fn default() -> Self {
+        Self::new(
+            Self::DEFAULT_MAX_RETRANSMITS,
+            Self::DEFAULT_RETRANSMIT_INTERVAL,
+        )
+    }
+}
+/// Segment acknowledgment timer. Instead of acking every segment, received segments are coalesced
+/// into one `BlockAck` sent when the timer (started by the first unacked segment) fires or once
+/// every segment has been received.
+#[derive(Copy, Clone, Debug)]
+pub struct AckScheduler {
+    seg_o: SegO,
+    block_ack: BlockAck,
+    ack_timeout: time::Duration,
+    deadline: Option<Instant>,
+}
+impl AckScheduler {
+    /// `seg_o` is the last segment index of the message and `ttl` the TTL of the segments.
+    pub fn new(seg_o: SegO, ttl: TTL) -> Self {
+        Self {
+            seg_o,
+            block_ack: BlockAck::ZERO,
+            ack_timeout: Self::ack_timeout(ttl),
+            deadline: None,
+        }
+    }
+    /// Segment acknowledgment timer interval (`150ms + 50ms * TTL`).
+    pub fn ack_timeout(ttl: TTL) -> time::Duration {
+        time::Duration::from_millis(150 + 50 * u64::from(u8::from(ttl)))
+    }
+    pub fn block_ack(&self) -> BlockAck {
+        self.block_ack
+    }
+    /// When the pending ack is due (if any segments are waiting to be acked).
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+    /// Records segment `seg_n` received at `now`, starting the timer if it isn't running.
+    /// Returns the `BlockAck` to send right away if that completed the message.
+    pub fn segment_received(&mut self, seg_n: SegN, now: Instant) -> Option<BlockAck> {
+        self.block_ack.set(seg_n.into());
+        if self.block_ack.all_acked(self.seg_o) {
+            self.deadline = None;
+            return Some(self.block_ack);
+        }
+        if self.deadline.is_none() {
+            self.deadline = Some(now + self.ack_timeout);
+        }
+        None
+    }
+    /// Returns the coalesced `BlockAck` if the timer has fired by `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<BlockAck> {
+        match self.deadline {
+            Some(deadline) if now.checked_duration_since(deadline).is_some() => {
+                self.deadline = None;
+                Some(self.block_ack)
+            }
+            _ => None,
+        }
+    }
+}
+/// Identifies a segmented transfer being reassembled. `SeqZero` alone isn't unique: different
+/// sources pick their own sequence numbers and the same `SeqZero` repeats after an IV Index change.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReassemblyKey {
+    pub src: UnicastAddress,
+    pub seq_zero: SeqZero,
+    pub iv_index: IVIndex,
+}
+impl From<&IncomingPDU<lower::SegmentedPDU>> for ReassemblyKey {
+    fn from(pdu: &IncomingPDU<lower::SegmentedPDU>) -> Self {
+        ReassemblyKey {
+            src: pdu.src,
+            seq_zero: pdu.pdu.seq_zero(),
+            iv_index: pdu.iv_index,
+        }
+    }
+}
 pub struct ReassemblerHandle {
     pub src: UnicastAddress,
     pub seq_zero: SeqZero,
+    pub iv_index: IVIndex,
     pub sender: mpsc::Sender<IncomingPDU<lower::SegmentedPDU>>,
-    pub handle: task::JoinHandle<Result<IncomingTransportPDU<Box<[u8]>>, ReassemblyError>>,
+    pub handle: task::JoinHandle<Result<(), ReassemblyError>>,
 }
+/// Reassembles incoming segmented PDUs. Acks go out through `outgoing_pdus` and each upper
+/// transport PDU is sent to `reassembled` once all of its segments are received.
 pub struct Reassembler {
-    incoming_channels: BTreeMap<(UnicastAddress, lower::SeqZero), ReassemblerHandle>,
+    incoming_channels: BTreeMap<ReassemblyKey, ReassemblerHandle>,
     outgoing_pdus: mpsc::Sender<OutgoingLowerTransportMessage>,
+    reassembled: mpsc::Sender<IncomingTransportPDU<Box<[u8]>>>,
 }
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 pub enum ReassemblyError {
@@ -299,17 +463,22 @@ pub enum ReassemblyError {
 }
 pub const REASSEMBLER_CHANNEL_LEN: usize = 8;
 impl Reassembler {
-    pub fn new(outgoing_pdus: mpsc::Sender<OutgoingLowerTransportMessage>) -> Self {
+    pub fn new(
+        outgoing_pdus: mpsc::Sender<OutgoingLowerTransportMessage>,
+        reassembled: mpsc::Sender<IncomingTransportPDU<Box<[u8]>>>,
+    ) -> Self {
         Self {
             incoming_channels: BTreeMap::new(),
             outgoing_pdus,
+            reassembled,
         }
     }
     pub async fn feed_pdu(
         &mut self,
         pdu: IncomingPDU<lower::SegmentedPDU>,
     ) -> Result<(), ReassemblyError> {
-        match self.incoming_channels.entry((pdu.src, pdu.pdu.seq_zero())) {
+        let key = ReassemblyKey::from(&pdu);
+        match self.incoming_channels.entry(key) {
             Entry::Occupied(mut o) => o
                 .get_mut()
                 .sender
@@ -318,11 +487,16 @@ impl Reassembler {
                 .map_err(|_| ReassemblyError::ChannelClosed),
             Entry::Vacant(v) => {
                 let (tx, rx) = mpsc::channel(REASSEMBLER_CHANNEL_LEN);
-                let handle =
-                    task::spawn(Self::reassemble_segs(pdu, self.outgoing_pdus.clone(), rx));
+                let handle = task::spawn(Self::reassemble_segs(
+                    pdu,
+                    self.outgoing_pdus.clone(),
+                    self.reassembled.clone(),
+                    rx,
+                ));
                 v.insert(ReassemblerHandle {
-                    src: pdu.src,
-                    seq_zero: pdu.pdu.seq_zero(),
+                    src: key.src,
+                    seq_zero: key.seq_zero,
+                    iv_index: key.iv_index,
                     sender: tx,
                     handle,
                 });
@@ -335,27 +509,14 @@ impl Reassembler {
         outgoing: &mut mpsc::Sender<OutgoingLowerTransportMessage>,
         ack: BlockAck,
     ) -> Result<(), ReassemblyError> {
-        outgoing
-            .send(OutgoingLowerTransportMessage {
-                pdu: lower::PDU::UnsegmentedControl(
-                    control::Ack {
-                        obo: false,
-                        seq_zero: segs.seq_auth.first_seq.into(),
-                        block_ack: ack,
-                    }
-                    .try_to_unseg()
-                    .expect("correctly formatted PDU"),
-                ),
-                src: segs.segs_src,
-                dst: segs.segs_dst,
-                ttl: segs.ack_ttl,
-                seq: None,
-                iv_index: segs.seq_auth.iv_index,
-                net_key_index: segs.net_key_index,
-            })
-            .await
-            .ok()
-            .ok_or(ReassemblyError::ChannelClosed)
+        match segs.ack_message(ack) {
+            Some(msg) => outgoing
+                .send(msg)
+                .await
+                .ok()
+                .ok_or(ReassemblyError::ChannelClosed),
+            None => Ok(()),
+        }
     }
     async fn cancel_ack(
         segs: &IncomingSegments,
@@ -366,30 +527,220 @@ impl Reassembler {
     async fn reassemble_segs(
         first_seg: IncomingPDU<lower::SegmentedPDU>,
         mut outgoing: mpsc::Sender<OutgoingLowerTransportMessage>,
+        mut reassembled: mpsc::Sender<IncomingTransportPDU<Box<[u8]>>>,
         mut rx: mpsc::Receiver<IncomingPDU<lower::SegmentedPDU>>,
-    ) -> Result<IncomingTransportPDU<Box<[u8]>>, ReassemblyError> {
+    ) -> Result<(), ReassemblyError> {
+        let first_header = first_seg.pdu.segment_header();
+        let mut acks = AckScheduler::new(first_header.seg_o, first_seg.ttl);
         let mut segments =
             IncomingSegments::new(first_seg).ok_or(ReassemblyError::InvalidFirstSegment)?;
-
+        let mut ack = acks.segment_received(first_header.seg_n, Instant::now());
+        let mut incomplete_deadline = Instant::now() + segments.recv_timeout();
         while !segments.is_ready() {
-            let next = time::timeout(segments.recv_timeout(), rx.recv())
-                .await
-                .map_err(|_| ReassemblyError::Timeout)?
-                .ok_or(ReassemblyError::ChannelClosed)?;
-            if !segments.seq_auth.valid_seq(next.seq) {
-                // bad sequence number for segment.
-                Self::cancel_ack(&segments, &mut outgoing).await?;
-                return Err(ReassemblyError::Canceled);
+            if let Some(block_ack) = ack.take() {
+                Self::send_ack(&segments, &mut outgoing, block_ack).await?;
+            }
+            let now = Instant::now();
+            let incomplete_left = now
+                .checked_duration_until(incomplete_deadline)
+                .ok_or(ReassemblyError::Timeout)?;
+            let wait = acks.deadline().map_or(incomplete_left, |deadline| {
+                now.checked_duration_until(deadline)
+                    .unwrap_or_default()
+                    .min(incomplete_left)
+            });
+            let next = match time::timeout(wait, rx.recv()).await {
+                Ok(next) => next.ok_or(ReassemblyError::ChannelClosed)?,
+                Err(_) => {
+                    // Either the ack timer fired or we'll find out the message is incomplete.
+                    ack = acks.poll(Instant::now());
+                    continue;
+                }
+            };
+            match segments.insert_segment(&next) {
+                Err(ReassemblyError::Canceled) => {
+                    // bad sequence number for segment.
+                    Self::cancel_ack(&segments, &mut outgoing).await?;
+                    return Err(ReassemblyError::Canceled);
+                }
+                result => result?,
             }
             let seg_header = next.pdu.segment_header();
-            segments
-                .context
-                .insert_data(seg_header.seg_n, next.pdu.seg_data())
-                .map_err(ReassemblyError::Reassemble)?;
+            let now = Instant::now();
+            incomplete_deadline = now + segments.recv_timeout();
+            ack = acks.segment_received(seg_header.seg_n, now);
         }
-        match segments.finish() {
-            Ok(msg) => Ok(msg),
+        if let Some(block_ack) = ack {
+            Self::send_ack(&segments, &mut outgoing, block_ack).await?;
+        }
+        let msg = match segments.finish() {
+            Ok(msg) => msg,
             Err(_) => unreachable!("segments is ensured to be is_ready() by the loop above"),
+        };
+        reassembled
+            .send(msg)
+            .await
+            .ok()
+            .ok_or(ReassemblyError::ChannelClosed)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::MIC;
+    use crate::lower::SegmentedAccessPDU;
+    use crate::mesh::{KeyIndex, U24};
+    use crate::upper;
+
+    #[test]
+    fn test_ack_scheduler_coalesces() {
+        let ttl = TTL::new(4);
+        assert_eq!(
+            AckScheduler::ack_timeout(ttl),
+            time::Duration::from_millis(350)
+        );
+        let mut acks = AckScheduler::new(SegO::new(4), ttl);
+        let start = Instant::now();
+        let at = |ms: u64| start + time::Duration::from_millis(ms);
+        assert_eq!(acks.segment_received(SegN::new(0), at(0)), None);
+        assert_eq!(acks.deadline(), Some(at(350)));
+        assert_eq!(acks.segment_received(SegN::new(1), at(100)), None);
+        assert_eq!(acks.segment_received(SegN::new(3), at(200)), None);
+        // Later segments don't push the timer back.
+        assert_eq!(acks.deadline(), Some(at(350)));
+        assert_eq!(acks.poll(at(300)), None);
+        let mut expected = BlockAck::ZERO;
+        expected.set(0);
+        expected.set(1);
+        expected.set(3);
+        assert_eq!(acks.poll(at(350)), Some(expected));
+        // Only one ack per timer.
+        assert_eq!(acks.poll(at(400)), None);
+        assert_eq!(acks.deadline(), None);
+        // The last segment completes the block and acks right away.
+        assert_eq!(acks.segment_received(SegN::new(2), at(450)), None);
+        assert_eq!(
+            acks.segment_received(SegN::new(4), at(460)),
+            Some(BlockAck::new_all_acked(SegO::new(4)))
+        );
+        assert_eq!(acks.deadline(), None);
+    }
+    #[test]
+    fn test_reassemble_big_mic() {
+        // 14 bytes of encrypted access payload followed by an 8 byte TransMIC (SZMIC = 1).
+        let mut upper_pdu = [0xA5_u8; 22];
+        upper_pdu[14..].copy_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        let segment = |seg_n: u8, data: &[u8]| IncomingPDU {
+            pdu: SegmentedPDU::Access(SegmentedAccessPDU::new(
+                None,
+                true.into(),
+                SeqZero::new(0x10),
+                SegO::new(1),
+                SegN::new(seg_n),
+                data,
+            )),
+            seq: SequenceNumber(U24::new(0x10 + u32::from(seg_n))),
+            iv_index: IVIndex(0),
+            net_key_index: NetKeyIndex(KeyIndex::new(0)),
+            src: UnicastAddress::new(0x0001).expect("valid unicast address"),
+            dst: Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            ttl: TTL::new(5),
+        };
+        let mut segments =
+            IncomingSegments::new(segment(0, &upper_pdu[..12])).expect("valid first segment");
+        assert_eq!(segments.mic_size(), Some(MicSize::Big));
+        assert!(!segments.is_ready());
+        let last = segment(1, &upper_pdu[12..]);
+        segments
+            .context
+            .insert_data(SegN::new(1), last.pdu.seg_data())
+            .expect("segment fits");
+        match segments
+            .finish()
+            .ok()
+            .expect("all segments received")
+            .upper_pdu
+        {
+            upper::PDU::Access(payload) => {
+                assert_eq!(payload.data(), &upper_pdu[..14]);
+                assert_eq!(payload.mic, MIC::Big(0x0102_0304_0506_0708));
+            }
+            upper::PDU::Control(_) => panic!("expected access payload"),
+        }
+    }
+    #[tokio::test]
+    async fn test_interleaved_transfers_same_seq_zero() {
+        let (outgoing_tx, mut outgoing_rx) = mpsc::channel(8);
+        let (reassembled_tx, mut reassembled_rx) = mpsc::channel(2);
+        let mut reassembler = Reassembler::new(outgoing_tx, reassembled_tx);
+        let first_src = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let second_src = UnicastAddress::new(0x0003).expect("valid unicast address");
+        let segment = |src: UnicastAddress, seg_n: u8, data: &[u8]| IncomingPDU {
+            pdu: SegmentedPDU::Access(SegmentedAccessPDU::new(
+                None,
+                false.into(),
+                SeqZero::new(0x10),
+                SegO::new(1),
+                SegN::new(seg_n),
+                data,
+            )),
+            seq: SequenceNumber(U24::new(0x10 + u32::from(seg_n))),
+            iv_index: IVIndex(0),
+            net_key_index: NetKeyIndex(KeyIndex::new(0)),
+            src,
+            dst: Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            ttl: TTL::new(5),
+        };
+        let first = [0x11_u8; 12];
+        let second = [0x22_u8; 12];
+        for pdu in [
+            segment(first_src, 0, &first),
+            segment(second_src, 0, &second),
+            segment(first_src, 1, &first[..4]),
+            segment(second_src, 1, &second[..4]),
+        ]
+        .iter()
+        {
+            reassembler
+                .feed_pdu(*pdu)
+                .await
+                .expect("reassembler running");
+        }
+        assert_eq!(reassembler.incoming_channels.len(), 2);
+        assert!(reassembler
+            .incoming_channels
+            .contains_key(&ReassemblyKey::from(&segment(second_src, 0, &second))));
+        // Each transfer completes on its own and gets its own full block ack.
+        let mut acked = Vec::new();
+        for _ in 0..2 {
+            let ack = outgoing_rx.recv().await.expect("ack sent");
+            match ack.pdu {
+                lower::PDU::UnsegmentedControl(pdu) => assert_eq!(
+                    control::Ack::try_from_pdu(&pdu)
+                        .ok()
+                        .map(|ack| ack.block_ack),
+                    Some(BlockAck::new_all_acked(SegO::new(1)))
+                ),
+                _ => panic!("expected segment ack"),
+            }
+            assert_eq!(
+                ack.src,
+                UnicastAddress::new(0x0002).expect("valid unicast address")
+            );
+            acked.push(match ack.dst {
+                Address::Unicast(dst) => dst,
+                _ => panic!("acks are sent back to the source"),
+            });
+        }
+        acked.sort();
+        assert_eq!(acked, vec![first_src, second_src]);
+        let mut reassembled = Vec::new();
+        for _ in 0..2 {
+            let msg = reassembled_rx.recv().await.expect("transfer reassembled");
+            assert_eq!(msg.seg_count, 2);
+            reassembled.push(msg.src);
         }
+        reassembled.sort();
+        assert_eq!(reassembled, vec![first_src, second_src]);
     }
 }
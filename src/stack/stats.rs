@@ -0,0 +1,57 @@
+//! Drop counters for debugging why messages aren't propagating through the stack.
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Snapshot of the `StatsCounters` at some point in time.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct StackStats {
+    /// PDUs that would have been relayed but had a TTL less than 2.
+    pub ttl_drops: u32,
+    /// PDUs dropped by the replay protection list (old `seq`).
+    pub replay_hits: u32,
+    /// PDUs dropped by the network cache (already received, usually a bearer retransmission).
+    pub network_cache_hits: u32,
+    /// Network PDUs with no matching NetKey and access payloads with no matching AppKey/DevKey.
+    pub decrypt_failures: u32,
+}
+/// Counters shared between the stack's incoming handlers. Counters wrap on overflow.
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    ttl_drops: AtomicU32,
+    replay_hits: AtomicU32,
+    network_cache_hits: AtomicU32,
+    decrypt_failures: AtomicU32,
+}
+impl StatsCounters {
+    pub fn new() -> StatsCounters {
+        StatsCounters::default()
+    }
+    pub fn record_ttl_drop(&self) {
+        self.ttl_drops.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn record_replay_hit(&self) {
+        self.replay_hits.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn record_network_cache_hit(&self) {
+        self.network_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn record_decrypt_failure(&self) {
+        self.decrypt_failures.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn snapshot(&self) -> StackStats {
+        StackStats {
+            ttl_drops: self.ttl_drops.load(Ordering::Relaxed),
+            replay_hits: self.replay_hits.load(Ordering::Relaxed),
+            network_cache_hits: self.network_cache_hits.load(Ordering::Relaxed),
+            decrypt_failures: self.decrypt_failures.load(Ordering::Relaxed),
+        }
+    }
+    /// Zeros every counter and returns the values from before the reset.
+    pub fn reset(&self) -> StackStats {
+        StackStats {
+            ttl_drops: self.ttl_drops.swap(0, Ordering::Relaxed),
+            replay_hits: self.replay_hits.swap(0, Ordering::Relaxed),
+            network_cache_hits: self.network_cache_hits.swap(0, Ordering::Relaxed),
+            decrypt_failures: self.decrypt_failures.swap(0, Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,783 @@
+//! Synchronous (sans-IO) stack for targets without an async runtime. `SyncStack` never touches a
+//! radio or a timer itself. The user feeds it received Network PDUs and the current time from
+//! their own event loop and carries out the returned `Action`s (transmitting PDUs, delivering
+//! messages to models).
+use crate::address::Address;
+use crate::device_state::SeqRange;
+use crate::foundation::state::NetworkTransmit;
+use crate::lower;
+use crate::mesh::ElementIndex;
+use crate::relay::NetworkCache;
+use crate::replay;
+use crate::stack::bearer::{IncomingEncryptedNetworkPDU, OutgoingEncryptedNetworkPDU};
+use crate::stack::messages::{
+    EncryptedIncomingMessage, IncomingMessage, IncomingNetworkPDU, OutgoingLowerTransportMessage,
+    OutgoingMessage,
+};
+use crate::stack::segments::{
+    AckScheduler, IncomingPDU, IncomingSegments, OutgoingSegments, ReassemblyError, ReassemblyKey,
+    SegmentEvent, SegmentationConfig,
+};
+use crate::stack::{SendError, StackInternals};
+use crate::{control, upper};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::time::Duration;
+use driver_async::time::Instant;
+
+/// When to transmit an outgoing Network PDU. It's sent at `start` and then repeated `count` more
+/// times, `interval` apart (from the Network Transmit state).
+#[derive(Copy, Clone, Debug)]
+pub struct TransmitSchedule {
+    pub start: Instant,
+    pub count: u8,
+    pub interval: Duration,
+}
+impl TransmitSchedule {
+    pub fn new(network_transmit: NetworkTransmit, start: Instant) -> Self {
+        Self {
+            start,
+            count: u8::from(network_transmit.0.count),
+            interval: network_transmit.interval(),
+        }
+    }
+    /// Every time the PDU has to be transmitted (`count + 1` times).
+    pub fn transmissions(&self) -> impl Iterator<Item = Instant> {
+        let schedule = *self;
+        (0..=u32::from(schedule.count)).map(move |i| schedule.start + schedule.interval * i)
+    }
+}
+/// Network PDU for the bearer and when to transmit it.
+#[derive(Copy, Clone, Debug)]
+pub struct OutgoingAction {
+    pub pdu: OutgoingEncryptedNetworkPDU,
+    pub transmit_schedule: TransmitSchedule,
+}
+/// Something the user of `SyncStack` has to do.
+pub enum Action {
+    /// Transmit the encrypted Network PDU on the bearer.
+    Send(OutgoingAction),
+    /// Deliver the decrypted access message to the local models.
+    Deliver(IncomingMessage<Box<[u8]>>),
+}
+/// Segmented message being received.
+struct IncomingTransfer {
+    segments: IncomingSegments,
+    acks: AckScheduler,
+    incomplete_deadline: Instant,
+}
+/// Segmented message being sent to a unicast address and waiting for acks.
+struct OutgoingTransfer {
+    segments: OutgoingSegments<Box<[u8]>>,
+    element_index: ElementIndex,
+    retransmits_left: u8,
+    /// Segment transmission timer. Unacked segments are sent again when it fires.
+    deadline: Instant,
+}
+/// Poll based stack driven by the user's event loop. Call `poll_incoming` for every received
+/// Network PDU and `poll_timers` when `next_timer` is reached. Relaying isn't supported yet.
+pub struct SyncStack {
+    internals: StackInternals,
+    replay_cache: replay::Cache,
+    network_cache: NetworkCache,
+    segmentation: SegmentationConfig,
+    incoming_transfers: BTreeMap<ReassemblyKey, IncomingTransfer>,
+    outgoing_transfers: Vec<OutgoingTransfer>,
+}
+impl SyncStack {
+    pub fn new(internals: StackInternals) -> Self {
+        Self {
+            internals,
+            replay_cache: replay::Cache::new(),
+            network_cache: NetworkCache::new(),
+            segmentation: SegmentationConfig::default(),
+            incoming_transfers: BTreeMap::new(),
+            outgoing_transfers: Vec::new(),
+        }
+    }
+    pub fn internals(&self) -> &StackInternals {
+        &self.internals
+    }
+    pub fn internals_mut(&mut self) -> &mut StackInternals {
+        &mut self.internals
+    }
+    /// Handles a Network PDU received at `now`. PDUs that fail to decrypt, replays and PDUs not
+    /// for this node return no actions.
+    pub fn poll_incoming(&mut self, pdu: IncomingEncryptedNetworkPDU, now: Instant) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let incoming = match self.check_network_pdu(pdu, now) {
+            Some(incoming) => incoming,
+            None => return actions,
+        };
+        match SegmentEvent::try_from(&incoming) {
+            Ok(SegmentEvent::IncomingSegment(seg)) => self.handle_segment(seg, now, &mut actions),
+            Ok(SegmentEvent::IncomingAck(ack)) => self.handle_ack(ack, now, &mut actions),
+            Err(_) => match &incoming.pdu.payload {
+                lower::PDU::UnsegmentedAccess(unseg_access) => {
+                    self.deliver(
+                        EncryptedIncomingMessage {
+                            encrypted_app_payload: unseg_access.into(),
+                            seq: incoming.pdu.header.seq,
+                            seg_count: 0,
+                            iv_index: incoming.iv_index,
+                            net_key_index: incoming.net_key_index,
+                            dst: incoming.pdu.header.dst,
+                            src: incoming.pdu.header.src,
+                            ttl: Some(incoming.pdu.header.ttl),
+                            rssi: incoming.rssi,
+                        },
+                        &mut actions,
+                    );
+                }
+                // Control messages aren't handled yet.
+                _ => (),
+            },
+        }
+        actions
+    }
+    /// Encrypts `msg` and returns the Network PDUs to transmit starting at `now`. Segmented
+    /// messages to unicast addresses are kept until they're acked and `poll_timers` sends the
+    /// unacked segments again.
+    pub fn send_message(
+        &mut self,
+        msg: OutgoingMessage<Box<[u8]>>,
+        now: Instant,
+    ) -> Result<Vec<OutgoingAction>, SendError> {
+        let upper = self.internals.app_encrypt(msg).map_err(|(e, _)| e)?;
+        let unsegmented = match &upper.upper_pdu {
+            upper::PDU::Access(access) => {
+                access.as_unsegmented().map(lower::PDU::UnsegmentedAccess)
+            }
+            upper::PDU::Control(control)
+                if control.payload.as_ref().len()
+                    <= lower::UnsegmentedControlPDU::max_parameters_size() =>
+            {
+                Some(lower::PDU::UnsegmentedControl(
+                    lower::UnsegmentedControlPDU::new(control.opcode, control.payload.as_ref()),
+                ))
+            }
+            upper::PDU::Control(_) => None,
+        };
+        if let Some(pdu) = unsegmented {
+            let msg = OutgoingLowerTransportMessage {
+                pdu,
+                src: upper.src,
+                dst: upper.dst,
+                ttl: upper.ttl,
+                seq: Some(upper.seq.start()),
+                iv_index: upper.iv_index,
+                net_key_index: upper.net_key_index,
+                credentials: upper.credentials,
+            };
+            return Ok(vec![Self::lower_action(&self.internals, &msg, now)?]);
+        }
+        let element_index = self
+            .internals
+            .device_state()
+            .element_index(upper.src)
+            .ok_or(SendError::InvalidSourceElement)?;
+        let mut transfer = OutgoingTransfer {
+            segments: upper.into_outgoing_segments(),
+            element_index,
+            retransmits_left: self.segmentation.max_retransmits,
+            deadline: now,
+        };
+        let seqs = SeqRange::new_segs(
+            transfer.segments.segments.seq_auth().first_seq,
+            transfer.segments.segments.seg_o(),
+        );
+        let actions = self.send_segments(&mut transfer, seqs, now)?;
+        // Group and virtual destinations don't ack.
+        if transfer.segments.dst.is_unicast() {
+            self.outgoing_transfers.push(transfer);
+        }
+        Ok(actions)
+    }
+    /// Sends the segment acks that are due, retransmits unacked outgoing segments and drops
+    /// segmented messages that timed out.
+    pub fn poll_timers(&mut self, now: Instant) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let mut timed_out = Vec::new();
+        for (key, transfer) in self.incoming_transfers.iter_mut() {
+            if now
+                .checked_duration_since(transfer.incomplete_deadline)
+                .is_some()
+            {
+                timed_out.push(*key);
+            } else if let Some(block_ack) = transfer.acks.poll(now) {
+                if let Some(ack) = transfer.segments.ack_message(block_ack) {
+                    actions.extend(
+                        Self::lower_action(&self.internals, &ack, now)
+                            .ok()
+                            .map(Action::Send),
+                    );
+                }
+            }
+        }
+        for key in timed_out {
+            self.incoming_transfers.remove(&key);
+        }
+        let mut outgoing_transfers = core::mem::take(&mut self.outgoing_transfers);
+        // Gives up on messages that ran out of retransmissions without being acked.
+        outgoing_transfers.retain(|transfer| {
+            now.checked_duration_since(transfer.deadline).is_none() || transfer.retransmits_left > 0
+        });
+        for transfer in outgoing_transfers.iter_mut() {
+            if now.checked_duration_since(transfer.deadline).is_some() {
+                transfer.retransmits_left -= 1;
+                if let Ok(sent) = self.resend_unacked(transfer, now) {
+                    actions.extend(sent.into_iter().map(Action::Send));
+                }
+            }
+        }
+        self.outgoing_transfers = outgoing_transfers;
+        actions
+    }
+    /// When `poll_timers` should be called next. `None` if no timers are running.
+    pub fn next_timer(&self) -> Option<Instant> {
+        let incoming = self.incoming_transfers.values().map(|transfer| {
+            transfer
+                .acks
+                .deadline()
+                .map_or(transfer.incomplete_deadline, |deadline| {
+                    deadline.min(transfer.incomplete_deadline)
+                })
+        });
+        let outgoing = self
+            .outgoing_transfers
+            .iter()
+            .map(|transfer| transfer.deadline);
+        incoming.chain(outgoing).min()
+    }
+    fn check_network_pdu(
+        &mut self,
+        pdu: IncomingEncryptedNetworkPDU,
+        now: Instant,
+    ) -> Option<IncomingNetworkPDU> {
+        let (net_key_index, iv_index, net_pdu) = self
+            .internals
+            .decrypt_network_pdu(pdu.encrypted_pdu.as_ref())?;
+        let header = net_pdu.header();
+        let (is_old_seq, is_old_seq_zero) = self.replay_cache.replay_net_check(
+            header.src,
+            header.seq,
+            header.ivi,
+            net_pdu.payload.seq_zero(),
+        );
+        if is_old_seq || is_old_seq_zero || !self.network_cache.observe(header.src, header.seq, now)
+        {
+            return None;
+        }
+        if let Address::Group(group) = header.dst {
+            if let Some(fixed_group) = group.fixed_group() {
+                if !self
+                    .internals
+                    .device_state()
+                    .config_states()
+                    .accepts_fixed_group(fixed_group)
+                {
+                    return None;
+                }
+            }
+        }
+        Some(IncomingNetworkPDU {
+            pdu: net_pdu,
+            net_key_index,
+            iv_index,
+            rssi: pdu.rssi,
+        })
+    }
+    fn handle_segment(
+        &mut self,
+        seg: IncomingPDU<lower::SegmentedPDU>,
+        now: Instant,
+        actions: &mut Vec<Action>,
+    ) {
+        let key = ReassemblyKey::from(&seg);
+        let seg_header = seg.pdu.segment_header();
+        let is_new = !self.incoming_transfers.contains_key(&key);
+        if is_new {
+            // The first segment is inserted by `IncomingSegments::new`.
+            let segments = match IncomingSegments::new(seg) {
+                Some(segments) => segments,
+                None => return,
+            };
+            self.incoming_transfers.insert(
+                key,
+                IncomingTransfer {
+                    incomplete_deadline: now + segments.recv_timeout(),
+                    segments,
+                    acks: AckScheduler::new(seg_header.seg_o, seg.ttl),
+                },
+            );
+        }
+        let transfer = self
+            .incoming_transfers
+            .get_mut(&key)
+            .expect("transfer inserted above");
+        if !is_new {
+            match transfer.segments.insert_segment(&seg) {
+                Ok(()) => (),
+                Err(ReassemblyError::Canceled) => {
+                    if let Some(ack) = transfer.segments.ack_message(lower::BlockAck::cancel()) {
+                        actions.extend(
+                            Self::lower_action(&self.internals, &ack, now)
+                                .ok()
+                                .map(Action::Send),
+                        );
+                    }
+                    self.incoming_transfers.remove(&key);
+                    return;
+                }
+                Err(_) => return,
+            }
+        }
+        transfer.incomplete_deadline = now + transfer.segments.recv_timeout();
+        if let Some(block_ack) = transfer.acks.segment_received(seg_header.seg_n, now) {
+            if let Some(ack) = transfer.segments.ack_message(block_ack) {
+                actions.extend(
+                    Self::lower_action(&self.internals, &ack, now)
+                        .ok()
+                        .map(Action::Send),
+                );
+            }
+        }
+        if transfer.segments.is_ready() {
+            let transfer = self
+                .incoming_transfers
+                .remove(&key)
+                .expect("transfer is being reassembled");
+            if let Ok(reassembled) = transfer.segments.finish() {
+                if let Some(encrypted) = reassembled.into_encrypted_access() {
+                    self.deliver(encrypted, actions);
+                }
+            }
+        }
+    }
+    /// Applies `ack` to the outgoing transfer it acknowledges. Acks without a matching transfer
+    /// (ex: a late ack for a transfer that's already complete) are dropped.
+    fn handle_ack(
+        &mut self,
+        ack: IncomingPDU<control::Ack>,
+        now: Instant,
+        actions: &mut Vec<Action>,
+    ) {
+        let index = match self
+            .outgoing_transfers
+            .iter()
+            .position(|transfer| transfer.segments.is_new_ack(ack) == Ok(true))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        let mut transfer = self.outgoing_transfers.remove(index);
+        transfer.segments.block_ack = ack.pdu.block_ack;
+        if transfer
+            .segments
+            .block_ack
+            .all_acked(transfer.segments.segments.seg_o())
+        {
+            return;
+        }
+        // The receiver is making progress so the retransmissions start over.
+        transfer.retransmits_left = self.segmentation.max_retransmits;
+        if let Ok(sent) = self.resend_unacked(&mut transfer, now) {
+            actions.extend(sent.into_iter().map(Action::Send));
+        }
+        self.outgoing_transfers.push(transfer);
+    }
+    /// Sends the unacked segments again with new sequence numbers.
+    fn resend_unacked(
+        &self,
+        transfer: &mut OutgoingTransfer,
+        now: Instant,
+    ) -> Result<Vec<OutgoingAction>, SendError> {
+        let segments = &transfer.segments;
+        let unacked = u32::from(segments.block_ack.seg_left(segments.segments.seg_o())) + 1;
+        let seqs = self
+            .internals
+            .seq_counter(transfer.element_index)
+            .inc_seq(unacked)
+            .ok_or(SendError::OutOfSeq)?;
+        self.send_segments(transfer, seqs, now)
+    }
+    /// Sends the unacked segments with `seqs` and restarts the segment transmission timer.
+    fn send_segments(
+        &self,
+        transfer: &mut OutgoingTransfer,
+        seqs: SeqRange,
+        now: Instant,
+    ) -> Result<Vec<OutgoingAction>, SendError> {
+        let segments = &transfer.segments;
+        let mut actions = Vec::new();
+        for (seg, seq) in segments.segments.iter(segments.block_ack).zip(seqs) {
+            let msg = segments.seg_to_outgoing(seg, Some(seq));
+            actions.push(Self::lower_action(&self.internals, &msg, now)?);
+        }
+        let ttl = segments.ttl.unwrap_or_else(|| self.internals.default_ttl());
+        transfer.deadline = now + self.segmentation.segment_timeout(ttl);
+        Ok(actions)
+    }
+    fn deliver(&self, encrypted: EncryptedIncomingMessage<Box<[u8]>>, actions: &mut Vec<Action>) {
+        if let Ok(msg) = self.internals.app_decrypt(encrypted) {
+            actions.push(Action::Deliver(msg));
+        }
+    }
+    fn lower_action(
+        internals: &StackInternals,
+        msg: &OutgoingLowerTransportMessage,
+        now: Instant,
+    ) -> Result<OutgoingAction, SendError> {
+        let (pdu, net_keys) = internals.lower_to_net(msg)?;
+        let transmit_parameters = internals.device_state().config_states().network_transmit;
+        Ok(OutgoingAction {
+            pdu: OutgoingEncryptedNetworkPDU {
+                transmit_parameters,
+                pdu: StackInternals::encrypt_with_keys(&pdu, &net_keys, msg.iv_index)?,
+            },
+            transmit_schedule: TransmitSchedule::new(transmit_parameters, now),
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::UnicastAddress;
+    use crate::control::ControlMessage;
+    use crate::crypto::aes::MicSize;
+    use crate::friend::Credentials;
+    use crate::lower::{BlockAck, SegO, SeqZero};
+    use crate::mesh::{AppKeyIndex, IVIndex, KeyIndex, NetKeyIndex, SequenceNumber, CTL, TTL};
+    use crate::models::lighting::hsl;
+    use crate::models::PackableMessage;
+    use crate::net;
+    use crate::stack::messages::MessageKeys;
+    use crate::stack::test_helpers;
+    use crate::upper::AppPayload;
+
+    fn internals(address: UnicastAddress) -> StackInternals {
+        StackInternals::new(test_helpers::device_state_with_app_key(
+            address,
+            NetKeyIndex(KeyIndex::new(0)),
+            AppKeyIndex(KeyIndex::new(0)),
+        ))
+    }
+    #[test]
+    fn test_send_from_missing_element() {
+        let sender_address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let mut sender = SyncStack::new(internals(sender_address));
+        let msg = OutgoingMessage {
+            app_payload: AppPayload::new(vec![0xA5_u8; 4].into_boxed_slice()),
+            mic_size: MicSize::Small,
+            force_segment: false,
+            encryption_key: MessageKeys::App(AppKeyIndex(KeyIndex::new(0))),
+            iv_index: IVIndex(0),
+            // The node only has element 0.
+            source_element_index: ElementIndex(1),
+            dst: Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            ttl: None,
+            credentials: Credentials::Master,
+        };
+        match sender.send_message(msg, Instant::now()) {
+            Err(SendError::InvalidSourceElement) => (),
+            _ => panic!("expected InvalidSourceElement"),
+        }
+    }
+    #[test]
+    fn test_network_transmit_set_applies_to_sends() {
+        use crate::mesh::{TransmitCount, TransmitInterval, TransmitSteps};
+        use crate::models::config::messages::network_transmit;
+        let mut sender = SyncStack::new(internals(
+            UnicastAddress::new(0x0001).expect("valid unicast address"),
+        ));
+        let network_transmit = NetworkTransmit(TransmitInterval::new(
+            TransmitCount::new(5),
+            TransmitSteps::new(7),
+        ));
+        let status = network_transmit::Set(network_transmit).apply(
+            sender
+                .internals_mut()
+                .device_state_mut()
+                .config_states_mut(),
+        );
+        assert_eq!(status, network_transmit::Status(network_transmit));
+        let msg = OutgoingMessage {
+            app_payload: AppPayload::new(vec![0xA5_u8; 4].into_boxed_slice()),
+            mic_size: MicSize::Small,
+            force_segment: false,
+            encryption_key: MessageKeys::App(AppKeyIndex(KeyIndex::new(0))),
+            iv_index: IVIndex(0),
+            source_element_index: ElementIndex(0),
+            dst: Address::Unicast(UnicastAddress::new(0x0002).expect("valid unicast address")),
+            ttl: None,
+            credentials: Credentials::Master,
+        };
+        let start = Instant::now();
+        let sent = sender.send_message(msg, start).ok().expect("message sends");
+        assert_eq!(sent.len(), 1);
+        let schedule = sent[0].transmit_schedule;
+        assert_eq!(schedule.count, 5);
+        assert_eq!(schedule.interval, Duration::from_millis(80));
+        assert_eq!(schedule.transmissions().count(), 6);
+        assert_eq!(sent[0].pdu.transmit_parameters, network_transmit);
+    }
+    #[test]
+    fn test_segmented_receive_deliver() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let app_key_index = AppKeyIndex(KeyIndex::new(0));
+        let sender_address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let node_address = UnicastAddress::new(0x0002).expect("valid unicast address");
+        let sender = internals(sender_address);
+        let mut node = SyncStack::new(internals(node_address));
+
+        let set = hsl::Set {
+            hsl: hsl::HSL {
+                lightness: 0x1234,
+                hue: 0x5678,
+                saturation: 0x9ABC,
+            },
+            tid: 0x42,
+            transition: None,
+        };
+        let mut msg = OutgoingMessage::from_model_message(
+            ElementIndex(0),
+            app_key_index,
+            Address::Unicast(node_address),
+            &set,
+        )
+        .ok()
+        .expect("set packs");
+        msg.force_segment = true;
+        let upper = match sender.app_encrypt::<Box<[u8]>>(msg) {
+            Ok(upper) => upper,
+            Err(_) => panic!("set should encrypt"),
+        };
+        let nid = sender
+            .net_keys()
+            .get_keys(net_key_index)
+            .expect("net key inserted above")
+            .tx_key()
+            .network_keys()
+            .nid();
+        let segments: Vec<IncomingEncryptedNetworkPDU> = test_helpers::access_segments(&upper)
+            .into_iter()
+            .map(|(seq, segment)| {
+                let pdu = net::PDU {
+                    header: net::Header {
+                        ivi: upper.iv_index.ivi(),
+                        nid,
+                        ctl: CTL(false),
+                        ttl: TTL::new(5),
+                        seq,
+                        src: sender_address,
+                        dst: Address::Unicast(node_address),
+                    },
+                    payload: lower::PDU::SegmentedAccess(segment),
+                };
+                IncomingEncryptedNetworkPDU {
+                    encrypted_pdu: sender
+                        .encrypt_network_pdu(pdu, net_key_index, upper.iv_index)
+                        .expect("pdu encrypts"),
+                    rssi: None,
+                    dont_relay: false,
+                }
+            })
+            .collect();
+        assert_eq!(segments.len(), 2);
+        // Acks sent by the node, decrypted by the sender.
+        let block_ack = |action: &Action| match action {
+            Action::Send(outgoing) => {
+                let (_, _, pdu) = sender
+                    .decrypt_network_pdu(outgoing.pdu.pdu.as_ref())
+                    .expect("ack decrypts");
+                assert_eq!(pdu.header.src, node_address);
+                assert_eq!(pdu.header.dst, Address::Unicast(sender_address));
+                match pdu.payload {
+                    lower::PDU::UnsegmentedControl(ack) => {
+                        control::Ack::try_from_pdu(&ack)
+                            .ok()
+                            .expect("segment ack")
+                            .block_ack
+                    }
+                    _ => panic!("expected a segment ack"),
+                }
+            }
+            Action::Deliver(_) => panic!("expected an ack"),
+        };
+
+        let start = Instant::now();
+        assert!(node.poll_incoming(segments[0], start).is_empty());
+        let ack_due = start + AckScheduler::ack_timeout(TTL::new(5));
+        assert_eq!(node.next_timer(), Some(ack_due));
+        let actions = node.poll_timers(ack_due);
+        assert_eq!(actions.len(), 1);
+        let mut first_acked = BlockAck::ZERO;
+        first_acked.set(0);
+        assert_eq!(block_ack(&actions[0]), first_acked);
+
+        let actions = node.poll_incoming(segments[1], ack_due + Duration::from_millis(100));
+        assert_eq!(actions.len(), 2);
+        assert_eq!(
+            block_ack(&actions[0]),
+            BlockAck::new_all_acked(SegO::new(1))
+        );
+        match &actions[1] {
+            Action::Deliver(delivered) => {
+                assert_eq!(delivered.src, sender_address);
+                assert_eq!(delivered.app_key_index, Some(app_key_index));
+                assert_eq!(
+                    hsl::Set::unpack_with_opcode(&delivered.payload[..]).ok(),
+                    Some(set)
+                );
+            }
+            Action::Send(_) => panic!("expected the reassembled message"),
+        }
+        assert_eq!(node.next_timer(), None);
+        // Retransmitted segments are replays.
+        assert!(node.poll_incoming(segments[1], ack_due).is_empty());
+    }
+    #[test]
+    fn test_segmented_send_retransmit() {
+        let net_key_index = NetKeyIndex(KeyIndex::new(0));
+        let sender_address = UnicastAddress::new(0x0001).expect("valid unicast address");
+        let node_address = UnicastAddress::new(0x0002).expect("valid unicast address");
+        let mut sender = SyncStack::new(internals(sender_address));
+        let node = internals(node_address);
+        // 30 bytes + 4 byte TransMIC is 3 segments.
+        let msg = OutgoingMessage {
+            app_payload: AppPayload::new(vec![0xA5_u8; 30].into_boxed_slice()),
+            mic_size: MicSize::Small,
+            force_segment: false,
+            encryption_key: MessageKeys::App(AppKeyIndex(KeyIndex::new(0))),
+            iv_index: IVIndex(0),
+            source_element_index: ElementIndex(0),
+            dst: Address::Unicast(node_address),
+            ttl: None,
+            credentials: Credentials::Master,
+        };
+        // Segments received by the node as (seq, seg_n).
+        let segments = |actions: &[Action]| -> Vec<(SequenceNumber, u8)> {
+            actions
+                .iter()
+                .map(|action| match action {
+                    Action::Send(outgoing) => {
+                        let (_, _, pdu) = node
+                            .decrypt_network_pdu(outgoing.pdu.pdu.as_ref())
+                            .expect("segment decrypts");
+                        match pdu.payload {
+                            lower::PDU::SegmentedAccess(seg) => (
+                                pdu.header.seq,
+                                u8::from(lower::SegmentedPDU::Access(seg).segment_header().seg_n),
+                            ),
+                            _ => panic!("expected an access segment"),
+                        }
+                    }
+                    Action::Deliver(_) => panic!("expected a segment"),
+                })
+                .collect()
+        };
+        let nid = node
+            .net_keys()
+            .get_keys(net_key_index)
+            .expect("net key inserted above")
+            .tx_key()
+            .network_keys()
+            .nid();
+        let ack = |seq_zero, block_ack| {
+            let seq = node
+                .seq_counter(ElementIndex(0))
+                .inc_seq(1)
+                .expect("seq left")
+                .start();
+            let pdu = net::PDU {
+                header: net::Header {
+                    ivi: IVIndex(0).ivi(),
+                    nid,
+                    ctl: CTL(true),
+                    ttl: TTL::new(5),
+                    seq,
+                    src: node_address,
+                    dst: Address::Unicast(sender_address),
+                },
+                payload: lower::PDU::UnsegmentedControl(
+                    control::Ack {
+                        obo: false,
+                        seq_zero,
+                        block_ack,
+                    }
+                    .try_to_unseg()
+                    .ok()
+                    .expect("ack fits in one pdu"),
+                ),
+            };
+            IncomingEncryptedNetworkPDU {
+                encrypted_pdu: node
+                    .encrypt_network_pdu(pdu, net_key_index, IVIndex(0))
+                    .expect("pdu encrypts"),
+                rssi: None,
+                dont_relay: false,
+            }
+        };
+
+        let start = Instant::now();
+        let sent = sender.send_message(msg, start).ok().expect("message sends");
+        let network_transmit = NetworkTransmit::default();
+        for action in &sent {
+            assert_eq!(action.transmit_schedule.start, start);
+            assert_eq!(action.transmit_schedule.count, 3);
+            assert_eq!(
+                action.transmit_schedule.interval,
+                network_transmit.interval()
+            );
+            assert_eq!(action.transmit_schedule.transmissions().count(), 4);
+        }
+        let sent: Vec<Action> = sent.into_iter().map(Action::Send).collect();
+        let first = segments(&sent);
+        assert_eq!(
+            first.iter().map(|&(_, seg_n)| seg_n).collect::<Vec<u8>>(),
+            vec![0, 1, 2]
+        );
+        let seq_zero = SeqZero::from(first[0].0);
+
+        // Nothing acked before the segment transmission timer fires so everything is resent.
+        let timeout =
+            start + SegmentationConfig::default().segment_timeout(sender.internals().default_ttl());
+        assert_eq!(sender.next_timer(), Some(timeout));
+        let resent = segments(&sender.poll_timers(timeout));
+        assert_eq!(
+            resent.iter().map(|&(_, seg_n)| seg_n).collect::<Vec<u8>>(),
+            vec![0, 1, 2]
+        );
+        assert!(resent.iter().all(|&(seq, _)| seq > first[2].0));
+
+        // Only the segment that's still missing is resent after a partial ack.
+        let mut partial = BlockAck::ZERO;
+        partial.set(0);
+        partial.set(1);
+        let resent = segments(&sender.poll_incoming(ack(seq_zero, partial), timeout));
+        assert_eq!(
+            resent.iter().map(|&(_, seg_n)| seg_n).collect::<Vec<u8>>(),
+            vec![2]
+        );
+        assert!(sender
+            .poll_incoming(
+                ack(seq_zero, BlockAck::new_all_acked(SegO::new(2))),
+                timeout
+            )
+            .is_empty());
+        assert_eq!(sender.next_timer(), None);
+
+        // The transfer is gone so a late (repeated) ack for it is silently ignored.
+        let late = timeout + Duration::from_millis(500);
+        assert!(sender
+            .poll_incoming(ack(seq_zero, BlockAck::new_all_acked(SegO::new(2))), late)
+            .is_empty());
+        assert!(sender
+            .poll_incoming(ack(seq_zero, partial), late)
+            .is_empty());
+        assert_eq!(sender.next_timer(), None);
+        assert!(sender.poll_timers(late).is_empty());
+    }
+}
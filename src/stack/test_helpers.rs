@@ -0,0 +1,76 @@
+//! Fixtures shared by the stack tests.
+use crate::address::UnicastAddress;
+use crate::crypto::key::{AppKey, NetKey};
+use crate::device_state::DeviceState;
+use crate::lower::{SegN, SegmentedAccessPDU, SeqZero};
+use crate::mesh::{AppKeyIndex, ElementCount, NetKeyIndex, SequenceNumber, U24};
+use crate::stack::messages::OutgoingUpperTransportMessage;
+use crate::upper;
+use alloc::vec::Vec;
+
+/// Network key every test node shares.
+pub fn net_key() -> NetKey {
+    NetKey::new_bytes([0x7D_u8; 16])
+}
+/// Application key every test node shares.
+pub fn app_key() -> AppKey {
+    AppKey::new_bytes([0x3F_u8; 16])
+}
+/// Single element `DeviceState` at `primary` with `net_key()` under `net_key_index`.
+pub fn device_state(primary: UnicastAddress, net_key_index: NetKeyIndex) -> DeviceState {
+    let mut device_state = DeviceState::new(primary, ElementCount(1));
+    device_state
+        .security_materials_mut()
+        .net_key_map
+        .insert(net_key_index, &net_key());
+    device_state
+}
+/// `device_state()` with `app_key()` bound to the subnet under `app_key_index`.
+pub fn device_state_with_app_key(
+    primary: UnicastAddress,
+    net_key_index: NetKeyIndex,
+    app_key_index: AppKeyIndex,
+) -> DeviceState {
+    let mut device_state = device_state(primary, net_key_index);
+    device_state.security_materials_mut().app_key_map.insert(
+        net_key_index,
+        app_key_index,
+        app_key(),
+    );
+    device_state
+}
+/// Splits the encrypted access message `upper` into its Segmented Access PDUs (like the sender's
+/// lower transport layer would) along with the sequence number of each segment.
+/// # Panics
+/// Panics if `upper` isn't an access message.
+pub fn access_segments<Storage: AsRef<[u8]>>(
+    upper: &OutgoingUpperTransportMessage<Storage>,
+) -> Vec<(SequenceNumber, SegmentedAccessPDU)> {
+    let payload = match &upper.upper_pdu {
+        upper::PDU::Access(payload) => payload,
+        upper::PDU::Control(_) => panic!("expected an access pdu"),
+    };
+    let mut upper_pdu = payload.data().to_vec();
+    let mic = payload.mic();
+    upper_pdu.resize(upper_pdu.len() + mic.byte_size(), 0);
+    let mic_start = upper_pdu.len() - mic.byte_size();
+    mic.be_pack_into(&mut upper_pdu[mic_start..]);
+    let first_seq = upper.seq.start();
+    upper_pdu
+        .chunks(SegmentedAccessPDU::max_seg_len())
+        .enumerate()
+        .map(|(seg_n, data)| {
+            (
+                SequenceNumber(first_seq.0 + U24::new(seg_n as u32)),
+                SegmentedAccessPDU::new(
+                    payload.aid(),
+                    false.into(),
+                    SeqZero::from(first_seq),
+                    upper.seg_count,
+                    SegN::new(seg_n as u8),
+                    data,
+                ),
+            )
+        })
+        .collect()
+}
@@ -1 +1,108 @@
+//! Monotonic timestamps for timers (Friend Poll Timeout, Relay Retransmit, etc) that also work
+//! without `std`. Timers are generic over `TimestampTrait` and read the time from a `Clock`. `std`
+//! builds use `AsyncClock` (the `driver_async` clock) while embedded targets can implement `Clock`
+//! on top of a hardware timer counting `Timestamp`s.
+use core::convert::TryFrom;
+use core::fmt::Debug;
+use core::ops::Add;
+use core::time::Duration;
+use driver_async::time::{Instant, InstantTrait};
 
+/// Point in time from a monotonic clock.
+pub trait TimestampTrait: Copy + Debug + Add<Duration, Output = Self> {
+    /// Time elapsed from `earlier` to `self`. `None` if `earlier` is after `self`.
+    fn elapsed_since(self, earlier: Self) -> Option<Duration>;
+    /// Time left from `self` until `later`. `None` if `later` is before `self`.
+    fn until(self, later: Self) -> Option<Duration> {
+        later.elapsed_since(self)
+    }
+    /// Returns if `self` is at or after `deadline`.
+    fn reached(self, deadline: Self) -> bool {
+        self.elapsed_since(deadline).is_some()
+    }
+}
+/// Source of `TimestampTrait` timestamps.
+pub trait Clock {
+    type Timestamp: TimestampTrait;
+    fn now(&self) -> Self::Timestamp;
+}
+/// `no_std` timestamp counted in microseconds from an arbitrary epoch (usually when the hardware
+/// timer started).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct Timestamp(u64);
+impl Timestamp {
+    pub const fn from_micros(micros: u64) -> Timestamp {
+        Timestamp(micros)
+    }
+    pub const fn as_micros(self) -> u64 {
+        self.0
+    }
+}
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        let micros = u64::try_from(rhs.as_micros()).unwrap_or(u64::MAX);
+        Timestamp(self.0.saturating_add(micros))
+    }
+}
+impl TimestampTrait for Timestamp {
+    fn elapsed_since(self, earlier: Self) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(Duration::from_micros)
+    }
+}
+impl TimestampTrait for Instant {
+    fn elapsed_since(self, earlier: Self) -> Option<Duration> {
+        self.checked_duration_since(earlier)
+    }
+}
+/// `Clock` backed by the `driver_async` clock (`Instant::now()`).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct AsyncClock;
+impl Clock for AsyncClock {
+    type Timestamp = Instant;
+
+    fn now(&self) -> Self::Timestamp {
+        Instant::now()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// Clock that only moves when told to.
+    #[derive(Default)]
+    struct MockClock(Cell<Timestamp>);
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration)
+        }
+    }
+    impl Clock for MockClock {
+        type Timestamp = Timestamp;
+
+        fn now(&self) -> Self::Timestamp {
+            self.0.get()
+        }
+    }
+    #[test]
+    fn test_timestamp_elapsed() {
+        let clock = MockClock::default();
+        let start = clock.now();
+        clock.advance(Duration::from_millis(250));
+        let now = clock.now();
+        assert_eq!(now.elapsed_since(start), Some(Duration::from_millis(250)));
+        assert_eq!(start.elapsed_since(now), None);
+        assert_eq!(start.until(now), Some(Duration::from_millis(250)));
+        assert!(now.reached(start + Duration::from_millis(250)));
+        assert!(!now.reached(start + Duration::from_millis(251)));
+    }
+    #[test]
+    fn test_timestamp_add_saturates() {
+        assert_eq!(
+            Timestamp::from_micros(u64::MAX - 1) + Duration::from_secs(1),
+            Timestamp::from_micros(u64::MAX)
+        );
+    }
+}
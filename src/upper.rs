@@ -29,32 +29,37 @@ impl<Storage: AsRef<[u8]>> PDU<Storage> {
     pub fn should_segment(&self) -> bool {
         self.total_len() > self.max_seg_len()
     }
+    /// Index of the last segment.
     pub fn seg_o(&self) -> SegO {
         assert!(
-            self.total_len() < ENCRYPTED_APP_PAYLOAD_MAX_LEN,
+            self.total_len() <= ENCRYPTED_APP_PAYLOAD_MAX_LEN,
             "payload overflow"
         );
-        let l = self.total_len();
-        let n = l / self.max_seg_len();
-        SegO::new(
-            u8::try_from(if n * self.max_seg_len() == l {
-                n
-            } else {
-                n + 1
-            })
-            .expect("can't send this much data"),
-        )
+        calculate_seg_o(self.total_len(), self.max_seg_len())
     }
-    /// Gets Segment N's data to be sent. !! THE MIC WON'T BE INCLUDED !!. Access Messages
-    /// include a MIC and will have to be append to the end of the payload manually.
+    /// Copies Segment N's data (a `max_seg_len()` chunk of the payload followed by the big endian
+    /// MIC for access messages) into `buf` and returns the segment length.
     /// # Panics
-    /// Panics if seg_n > seg_o
-    pub fn seg_n_data(&self, seg_n: SegN) -> &[u8] {
+    /// Panics if seg_n > seg_o or if `buf` is shorter than the segment.
+    pub fn seg_n_data(&self, seg_n: SegN, buf: &mut [u8]) -> usize {
         let seg_i = u8::from(seg_n);
         assert!(seg_i <= u8::from(self.seg_o()));
-        let seg_i = usize::from(seg_i);
         let max_seg = self.max_seg_len();
-        &self.payload()[seg_i * max_seg..(seg_i + 1) * max_seg]
+        let start = usize::from(seg_i) * max_seg;
+        let end = (start + max_seg).min(self.total_len());
+        let payload = self.payload();
+        let mut mic_bytes = [0_u8; MIC::big_size()];
+        if let Some(mic) = self.mic() {
+            mic.be_pack_into(&mut mic_bytes[..mic.byte_size()]);
+        }
+        for (i, out) in (start..end).zip(buf[..end - start].iter_mut()) {
+            *out = if i < payload.len() {
+                payload[i]
+            } else {
+                mic_bytes[i - payload.len()]
+            };
+        }
+        end - start
     }
     pub fn is_control(&self) -> bool {
         match self {
@@ -94,7 +99,8 @@ impl<Storage: Clone + AsRef<[u8]>> Clone for PDU<Storage> {
 }
 impl From<lower::UnsegmentedAccessPDU> for EncryptedAppPayload<Box<[u8]>> {
     fn from(pdu: UnsegmentedAccessPDU) -> Self {
-        Self::new(pdu.upper_pdu().into(), pdu.mic(), pdu.aid())
+        // The TransMIC is kept separate from the encrypted data.
+        Self::from(&pdu)
     }
 }
 /// Application Security Materials used to encrypt and decrypt at the application layer.
@@ -274,13 +280,12 @@ impl<'a, Storage: AsRef<[u8]>> AppPayload<Storage> {
     }
     #[must_use]
     pub fn should_segment(&self, mic_size: MicSize) -> bool {
-        self.0.as_ref().len() + mic_size.byte_size() > UnsegmentedAccessPDU::max_len()
+        self.0.as_ref().len() + mic_size.byte_size() > UnsegmentedAccessPDU::max_upper_pdu_len()
     }
 }
+/// Index of the last segment (`SegO`) when `data_len` bytes are split into `pdu_size` segments.
 pub fn calculate_seg_o(data_len: usize, pdu_size: usize) -> SegO {
-    let l = data_len;
-    let n = data_len / pdu_size;
-    let n = if n * pdu_size * n == l { n } else { n + 1 };
+    let n = data_len.saturating_sub(1) / pdu_size;
     SegO::new(u8::try_from(n).expect("data_len longer than ENCRYPTED_APP_PAYLOAD_MAX_LEN"))
 }
 pub struct EncryptedAppPayload<Storage: AsRef<[u8]>> {
@@ -334,13 +339,22 @@ impl<Storage: AsRef<[u8]>> EncryptedAppPayload<Storage> {
         calculate_seg_o(self.len(), SegmentedAccessPDU::max_seg_len())
     }
     pub fn should_segment(&self) -> bool {
-        self.len() > UnsegmentedAccessPDU::max_len()
+        self.len() > UnsegmentedAccessPDU::max_upper_pdu_len()
     }
+    /// Returns the Unsegmented Access PDU (encrypted payload + TransMIC) if the payload doesn't
+    /// have to be segmented. Unsegmented Access PDUs always use a 32-bit TransMIC.
     pub fn as_unsegmented(&self) -> Option<UnsegmentedAccessPDU> {
-        if self.should_segment() {
-            Some(UnsegmentedAccessPDU::new(self.aid(), self.data()))
-        } else {
+        if self.should_segment()
+            || self.mic.is_big()
+            || self.len() < UnsegmentedAccessPDU::min_upper_pdu_len()
+        {
             None
+        } else {
+            let mut buf = [0_u8; UnsegmentedAccessPDU::max_upper_pdu_len()];
+            let data_len = self.data_len();
+            buf[..data_len].copy_from_slice(self.data());
+            self.mic.be_pack_into(&mut buf[data_len..self.len()]);
+            Some(UnsegmentedAccessPDU::new(self.aid(), &buf[..self.len()]))
         }
     }
     pub fn into_storage(self) -> Storage {
@@ -376,3 +390,50 @@ impl From<&UnsegmentedAccessPDU> for EncryptedAppPayload<Box<[u8]>> {
         Self::new(upper_pdu, mic, pdu.aid())
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_seg_o() {
+        let max_seg = SegmentedAccessPDU::max_seg_len();
+        assert_eq!(calculate_seg_o(1, max_seg), SegO::new(0));
+        assert_eq!(calculate_seg_o(max_seg, max_seg), SegO::new(0));
+        assert_eq!(calculate_seg_o(max_seg + 1, max_seg), SegO::new(1));
+        assert_eq!(calculate_seg_o(2 * max_seg, max_seg), SegO::new(1));
+        assert_eq!(calculate_seg_o(2 * max_seg + 1, max_seg), SegO::new(2));
+        assert_eq!(
+            calculate_seg_o(ENCRYPTED_APP_PAYLOAD_MAX_LEN, max_seg),
+            SegO::new(31)
+        );
+    }
+    #[test]
+    fn test_seg_n_data_splits_mic() {
+        let mut buf = [0_u8; SegmentedAccessPDU::max_seg_len()];
+        // 10 bytes of payload + 4 bytes of TransMIC. The MIC is split across both segments.
+        let payload: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let pdu = PDU::Access(EncryptedAppPayload::new(
+            &payload[..],
+            MIC::Small(0xAABB_CCDD),
+            None,
+        ));
+        assert_eq!(pdu.seg_o(), SegO::new(1));
+        assert_eq!(pdu.seg_n_data(SegN::new(0), &mut buf), 12);
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0xAA, 0xBB]);
+        assert_eq!(pdu.seg_n_data(SegN::new(1), &mut buf), 2);
+        assert_eq!(buf[..2], [0xCC, 0xDD]);
+
+        // A full segment of payload puts the whole (big) MIC in the last segment.
+        let payload = [0x11_u8; 12];
+        let pdu = PDU::Access(EncryptedAppPayload::new(
+            &payload[..],
+            MIC::Big(0x0102_0304_0506_0708),
+            None,
+        ));
+        assert_eq!(pdu.seg_o(), SegO::new(1));
+        assert_eq!(pdu.seg_n_data(SegN::new(0), &mut buf), 12);
+        assert_eq!(buf, [0x11_u8; 12]);
+        assert_eq!(pdu.seg_n_data(SegN::new(1), &mut buf), 8);
+        assert_eq!(buf[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
@@ -0,0 +1,239 @@
+//! URI encoding used by the URI AD type and the URI Hash of Unprovisioned Device Beacons. The URI
+//! scheme is compressed into a single code point from the Bluetooth URI Scheme Name String
+//! Mapping (`https:` becomes `U+0017`).
+use crate::beacon::URIHash;
+use alloc::vec::Vec;
+
+/// Scheme code for a URI with no compressed scheme (the full URI follows).
+pub const EMPTY_SCHEME: char = '\u{01}';
+/// URI Scheme Name String Mapping codes (`0x02..=0xA7`) and the scheme (including the `:`)
+/// they replace.
+pub const SCHEMES: [(char, &str); 166] = [
+    ('\u{02}', "aaa:"),
+    ('\u{03}', "aaas:"),
+    ('\u{04}', "about:"),
+    ('\u{05}', "acap:"),
+    ('\u{06}', "acct:"),
+    ('\u{07}', "cap:"),
+    ('\u{08}', "cid:"),
+    ('\u{09}', "coap:"),
+    ('\u{0A}', "coaps:"),
+    ('\u{0B}', "crid:"),
+    ('\u{0C}', "data:"),
+    ('\u{0D}', "dav:"),
+    ('\u{0E}', "dict:"),
+    ('\u{0F}', "dns:"),
+    ('\u{10}', "file:"),
+    ('\u{11}', "ftp:"),
+    ('\u{12}', "geo:"),
+    ('\u{13}', "go:"),
+    ('\u{14}', "gopher:"),
+    ('\u{15}', "h323:"),
+    ('\u{16}', "http:"),
+    ('\u{17}', "https:"),
+    ('\u{18}', "iax:"),
+    ('\u{19}', "icap:"),
+    ('\u{1A}', "im:"),
+    ('\u{1B}', "imap:"),
+    ('\u{1C}', "info:"),
+    ('\u{1D}', "ipp:"),
+    ('\u{1E}', "ipps:"),
+    ('\u{1F}', "iris:"),
+    ('\u{20}', "iris.beep:"),
+    ('\u{21}', "iris.xpc:"),
+    ('\u{22}', "iris.xpcs:"),
+    ('\u{23}', "iris.lwz:"),
+    ('\u{24}', "jabber:"),
+    ('\u{25}', "ldap:"),
+    ('\u{26}', "mailto:"),
+    ('\u{27}', "mid:"),
+    ('\u{28}', "msrp:"),
+    ('\u{29}', "msrps:"),
+    ('\u{2A}', "mtqp:"),
+    ('\u{2B}', "mupdate:"),
+    ('\u{2C}', "news:"),
+    ('\u{2D}', "nfs:"),
+    ('\u{2E}', "ni:"),
+    ('\u{2F}', "nih:"),
+    ('\u{30}', "nntp:"),
+    ('\u{31}', "opaquelocktoken:"),
+    ('\u{32}', "pop:"),
+    ('\u{33}', "pres:"),
+    ('\u{34}', "reload:"),
+    ('\u{35}', "rtsp:"),
+    ('\u{36}', "rtsps:"),
+    ('\u{37}', "rtspu:"),
+    ('\u{38}', "service:"),
+    ('\u{39}', "session:"),
+    ('\u{3A}', "shttp:"),
+    ('\u{3B}', "sieve:"),
+    ('\u{3C}', "sip:"),
+    ('\u{3D}', "sips:"),
+    ('\u{3E}', "sms:"),
+    ('\u{3F}', "snmp:"),
+    ('\u{40}', "soap.beep:"),
+    ('\u{41}', "soap.beeps:"),
+    ('\u{42}', "stun:"),
+    ('\u{43}', "stuns:"),
+    ('\u{44}', "tag:"),
+    ('\u{45}', "tel:"),
+    ('\u{46}', "telnet:"),
+    ('\u{47}', "tftp:"),
+    ('\u{48}', "thismessage:"),
+    ('\u{49}', "tn3270:"),
+    ('\u{4A}', "tip:"),
+    ('\u{4B}', "turn:"),
+    ('\u{4C}', "turns:"),
+    ('\u{4D}', "tv:"),
+    ('\u{4E}', "urn:"),
+    ('\u{4F}', "vemmi:"),
+    ('\u{50}', "ws:"),
+    ('\u{51}', "wss:"),
+    ('\u{52}', "xcon:"),
+    ('\u{53}', "xcon-userid:"),
+    ('\u{54}', "xmlrpc.beep:"),
+    ('\u{55}', "xmlrpc.beeps:"),
+    ('\u{56}', "xmpp:"),
+    ('\u{57}', "z39.50r:"),
+    ('\u{58}', "z39.50s:"),
+    ('\u{59}', "acr:"),
+    ('\u{5A}', "adiumxtra:"),
+    ('\u{5B}', "afp:"),
+    ('\u{5C}', "afs:"),
+    ('\u{5D}', "aim:"),
+    ('\u{5E}', "apt:"),
+    ('\u{5F}', "attachment:"),
+    ('\u{60}', "aw:"),
+    ('\u{61}', "barion:"),
+    ('\u{62}', "beshare:"),
+    ('\u{63}', "bitcoin:"),
+    ('\u{64}', "bolo:"),
+    ('\u{65}', "callto:"),
+    ('\u{66}', "chrome:"),
+    ('\u{67}', "chrome-extension:"),
+    ('\u{68}', "com-eventbrite-attendee:"),
+    ('\u{69}', "content:"),
+    ('\u{6A}', "cvs:"),
+    ('\u{6B}', "dlna-playsingle:"),
+    ('\u{6C}', "dlna-playcontainer:"),
+    ('\u{6D}', "dtn:"),
+    ('\u{6E}', "dvb:"),
+    ('\u{6F}', "ed2k:"),
+    ('\u{70}', "facetime:"),
+    ('\u{71}', "feed:"),
+    ('\u{72}', "feedready:"),
+    ('\u{73}', "finger:"),
+    ('\u{74}', "fish:"),
+    ('\u{75}', "gg:"),
+    ('\u{76}', "git:"),
+    ('\u{77}', "gizmoproject:"),
+    ('\u{78}', "gtalk:"),
+    ('\u{79}', "ham:"),
+    ('\u{7A}', "hcp:"),
+    ('\u{7B}', "icon:"),
+    ('\u{7C}', "ipn:"),
+    ('\u{7D}', "irc:"),
+    ('\u{7E}', "irc6:"),
+    ('\u{7F}', "ircs:"),
+    ('\u{80}', "itms:"),
+    ('\u{81}', "jar:"),
+    ('\u{82}', "jms:"),
+    ('\u{83}', "keyparc:"),
+    ('\u{84}', "lastfm:"),
+    ('\u{85}', "ldaps:"),
+    ('\u{86}', "magnet:"),
+    ('\u{87}', "maps:"),
+    ('\u{88}', "market:"),
+    ('\u{89}', "message:"),
+    ('\u{8A}', "mms:"),
+    ('\u{8B}', "ms-help:"),
+    ('\u{8C}', "ms-settings-power:"),
+    ('\u{8D}', "msnim:"),
+    ('\u{8E}', "mumble:"),
+    ('\u{8F}', "mvn:"),
+    ('\u{90}', "notes:"),
+    ('\u{91}', "oid:"),
+    ('\u{92}', "palm:"),
+    ('\u{93}', "paparazzi:"),
+    ('\u{94}', "pkcs11:"),
+    ('\u{95}', "platform:"),
+    ('\u{96}', "proxy:"),
+    ('\u{97}', "psyc:"),
+    ('\u{98}', "query:"),
+    ('\u{99}', "res:"),
+    ('\u{9A}', "resource:"),
+    ('\u{9B}', "rmi:"),
+    ('\u{9C}', "rsync:"),
+    ('\u{9D}', "rtmp:"),
+    ('\u{9E}', "secondlife:"),
+    ('\u{9F}', "sftp:"),
+    ('\u{A0}', "sgn:"),
+    ('\u{A1}', "skype:"),
+    ('\u{A2}', "smb:"),
+    ('\u{A3}', "soldat:"),
+    ('\u{A4}', "spotify:"),
+    ('\u{A5}', "ssh:"),
+    ('\u{A6}', "steam:"),
+    ('\u{A7}', "svn:"),
+];
+/// Returns the scheme code for `uri` and the rest of the URI after the scheme. URIs without a
+/// known scheme use `EMPTY_SCHEME` and keep the whole URI.
+pub fn split_scheme(uri: &str) -> (char, &str) {
+    SCHEMES
+        .iter()
+        .find_map(|&(code, scheme)| match uri.get(..scheme.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(scheme) => {
+                Some((code, &uri[scheme.len()..]))
+            }
+            _ => None,
+        })
+        .unwrap_or((EMPTY_SCHEME, uri))
+}
+/// Encodes `uri` (UTF-8) with its scheme compressed.
+pub fn encode(uri: &str) -> Vec<u8> {
+    let (code, rest) = split_scheme(uri);
+    let mut code_buf = [0_u8; 4];
+    let code = code.encode_utf8(&mut code_buf).as_bytes();
+    let mut out = Vec::with_capacity(code.len() + rest.len());
+    out.extend_from_slice(code);
+    out.extend_from_slice(rest.as_bytes());
+    out
+}
+/// URI Hash (first 4 bytes of `s1` over the encoded URI) advertised in Unprovisioned Device
+/// Beacons.
+pub fn uri_hash(uri: &str) -> u32 {
+    URIHash::hash_data(&encode(uri)).0
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_schemes() {
+        assert_eq!(encode("http://example.com"), b"\x16//example.com".as_ref());
+        assert_eq!(
+            encode("https://www.example.com/mesh"),
+            b"\x17//www.example.com/mesh".as_ref()
+        );
+        assert_eq!(
+            encode("mailto:mesh@example.com"),
+            b"\x26mesh@example.com".as_ref()
+        );
+        assert_eq!(encode("tel:+15555550100"), b"\x45+15555550100".as_ref());
+        // Codes past `0x7F` take two bytes in UTF-8.
+        assert_eq!(
+            encode("ssh://example.com"),
+            b"\xC2\xA5//example.com".as_ref()
+        );
+        // Unknown scheme is kept as is behind the empty scheme code.
+        assert_eq!(encode("zzz:abc"), b"\x01zzz:abc".as_ref());
+    }
+    #[test]
+    fn test_uri_hash() {
+        // Mesh Profile Spec 8.4.2 sample data.
+        assert_eq!(
+            uri_hash("https://www.example.com/mesh/products/light-switch-v3"),
+            0xD974_78B3
+        );
+    }
+}